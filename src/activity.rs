@@ -0,0 +1,98 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// One progress line per Rayon worker showing the file it is hashing.
+struct Activity {
+    multi: MultiProgress,
+    workers: Vec<ProgressBar>,
+}
+
+static ACTIVITY: OnceLock<Activity> = OnceLock::new();
+
+/// Width the per-worker path is shortened to.
+const PATH_WIDTH: usize = 48;
+
+/// Turn on the per-thread display (`--thread-activity`). Call once the Rayon
+/// pool is configured; does nothing unless stderr is a terminal.
+pub fn enable() {
+    use std::io::IsTerminal;
+    if !std::io::stderr().is_terminal() {
+        return;
+    }
+    ACTIVITY.get_or_init(|| {
+        let multi =
+            MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::stderr_with_hz(10));
+        let style = ProgressStyle::default_bar()
+            .template("  [{prefix:>2}] {bar:20.cyan/blue} {percent:>3}% {msg}")
+            .expect("static template");
+        let workers = (0..rayon::current_num_threads())
+            .map(|i| {
+                let bar = multi.add(ProgressBar::new(0).with_style(style.clone()));
+                bar.set_prefix(i.to_string());
+                bar.set_message("idle");
+                bar
+            })
+            .collect();
+        Activity { multi, workers }
+    });
+}
+
+/// Route an overall progress bar through the display so the two do not
+/// overdraw each other. Returns the bar unchanged when the display is off.
+pub fn attach(pb: ProgressBar) -> ProgressBar {
+    match ACTIVITY.get() {
+        Some(a) => a.multi.insert(0, pb),
+        None => pb,
+    }
+}
+
+fn current_bar() -> Option<&'static ProgressBar> {
+    ACTIVITY.get()?.workers.get(rayon::current_thread_index()?)
+}
+
+/// Marks the calling worker busy with `path` until dropped.
+pub struct Tracked(Option<&'static ProgressBar>);
+
+/// Show that the calling worker started hashing `len` bytes of `path`.
+pub fn track(path: &Path, len: u64) -> Tracked {
+    let bar = current_bar();
+    if let Some(bar) = bar {
+        bar.set_length(len);
+        bar.set_position(0);
+        bar.set_message(shorten(&path.to_string_lossy()));
+    }
+    Tracked(bar)
+}
+
+impl Tracked {
+    pub fn advance(&self, bytes: u64) {
+        if let Some(bar) = self.0 {
+            bar.inc(bytes);
+        }
+    }
+
+    /// Whether anyone is watching; lets callers skip splitting work just for progress.
+    pub fn is_shown(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        if let Some(bar) = self.0 {
+            bar.set_length(0);
+            bar.set_message("idle");
+        }
+    }
+}
+
+/// Keep the tail of `path`, which names the file, within `PATH_WIDTH` characters.
+pub fn shorten(path: &str) -> String {
+    let count = path.chars().count();
+    if count <= PATH_WIDTH {
+        return path.to_string();
+    }
+    let tail: String = path.chars().skip(count - (PATH_WIDTH - 1)).collect();
+    format!("…{}", tail)
+}
@@ -0,0 +1,397 @@
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use globset::GlobSet;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufReader, Cursor, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tar::{Archive, EntryType};
+
+use crate::compare::CompareConfig;
+use crate::models::{ErrorEntry, FileEntry, HashResult, Mode, SymlinkMode};
+use crate::sandbox;
+use crate::utils::{build_globset, hash_reader};
+use crate::ziplisting::{for_each_member, is_zip_path};
+
+/// Read a tar stream as one side of a comparison. Members are hashed while the
+/// stream passes by, since nothing can be read back later, and come out as
+/// entries under `root` carrying their digests, like snapshot entries.
+///
+/// The scan options of a folder walk (`--depth`, `--hidden`, `--type`,
+/// `--ignore`, `--symlinks`, `--owner-filter`) are applied to member paths so both sides list
+/// the same files; `--strip-components` drops leading path components first.
+pub fn read_tar(
+    reader: impl Read,
+    root: &Path,
+    config: &CompareConfig,
+) -> Result<(Vec<FileEntry>, Vec<ErrorEntry>)> {
+    let filter = ListingFilter::new(config)?;
+
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    // Hard-link members carry no data; they reuse the size and digest of their target.
+    let mut contents: HashMap<PathBuf, (u64, Option<HashResult>)> = HashMap::new();
+
+    let mut archive = Archive::new(reader);
+    for member in archive.entries().context("Failed to read tar stream")? {
+        let mut member = member.context("Failed to read tar stream")?;
+        let header = member.header();
+        let kind = header.entry_type();
+        let raw_path = member.path().context("Invalid path in tar stream")?;
+        let Some(rel_path) = member_path(&raw_path, config.strip_components) else {
+            if raw_path.components().any(|c| c == Component::ParentDir) {
+                errors.push(ErrorEntry::new(
+                    raw_path.into_owned(),
+                    "tar member path escapes the archive root".to_string(),
+                ));
+            }
+            continue;
+        };
+
+        let wanted = match kind {
+            EntryType::Regular | EntryType::Continuous | EntryType::Link => true,
+            EntryType::Symlink => config.symlinks == SymlinkMode::Compare,
+            _ => false,
+        };
+        if !wanted
+            || filter.excludes(&rel_path)
+            || config
+                .owner_filter
+                .is_some_and(|uid| header.uid().ok() != Some(u64::from(uid)))
+        {
+            continue;
+        }
+
+        let modified = header
+            .mtime()
+            .ok()
+            .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        let mut entry = FileEntry {
+            path: root.join(&rel_path),
+            size: header.size().unwrap_or(0),
+            modified,
+            permissions: header.mode().ok().map(|m| m & 0o7777),
+            uid: header.uid().ok().map(|u| u as u32),
+            gid: header.gid().ok().map(|g| g as u32),
+            ..FileEntry::default()
+        };
+
+        match kind {
+            EntryType::Symlink => {
+                let target = member.link_name().ok().flatten().unwrap_or_default();
+                entry.symlink_target = Some(target.to_string_lossy().to_string());
+            }
+            EntryType::Link => {
+                let target = member
+                    .link_name()
+                    .ok()
+                    .flatten()
+                    .and_then(|t| member_path(&t, config.strip_components));
+                match target.and_then(|t| contents.get(&t)) {
+                    Some((size, hashes)) => {
+                        entry.size = *size;
+                        entry.hashes = hashes.clone();
+                    }
+                    None => {
+                        errors.push(ErrorEntry::new(
+                            rel_path,
+                            "hard link to a member not in the stream".to_string(),
+                        ));
+                        continue;
+                    }
+                }
+            }
+            _ => {
+                if config.mode != Mode::Metadata {
+                    let hashes = hash_reader(&mut member, config.algo).with_context(|| {
+                        format!("Failed to read {} from tar stream", entry.path.display())
+                    })?;
+                    entry.hashes = Some(hashes);
+                }
+                contents.insert(rel_path, (entry.size, entry.hashes.clone()));
+            }
+        }
+        files.push(entry);
+    }
+    Ok((files, errors))
+}
+
+/// Archive formats `--archives recurse` opens.
+#[derive(Debug, Clone, Copy)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+/// The format of an archive, by file name, and the virtual directory its
+/// members are listed under: the archive's path without the extension, so
+/// `data/logs.tar.gz` lines up with an unpacked `data/logs/`.
+fn archive_kind(path: &Path) -> Option<(ArchiveKind, PathBuf)> {
+    let name = path.file_name()?.to_str()?;
+    let lower = name.to_ascii_lowercase();
+    let (kind, ext_len) = if lower.ends_with(".tar.gz") {
+        (ArchiveKind::TarGz, 7)
+    } else if lower.ends_with(".tgz") {
+        (ArchiveKind::TarGz, 4)
+    } else if lower.ends_with(".tar") {
+        (ArchiveKind::Tar, 4)
+    } else if is_zip_path(path) {
+        (ArchiveKind::Zip, path.extension()?.len() + 1)
+    } else {
+        return None;
+    };
+    let stem = &name[..name.len() - ext_len];
+    (!stem.is_empty())
+        .then(|| path.with_file_name(stem))
+        .map(|dir| (kind, dir))
+}
+
+type Visit<'a> = dyn FnMut(&Path, u64, Option<SystemTime>, &mut dyn Read) -> io::Result<()> + 'a;
+
+/// Call `visit` with the name, size, modification time and content of each
+/// regular file in an archive. Links and special members are skipped.
+fn visit_members<R: Read + Seek>(
+    kind: ArchiveKind,
+    mut source: R,
+    visit: &mut Visit,
+) -> io::Result<()> {
+    let tar_members = |reader: &mut dyn Read, visit: &mut Visit| -> io::Result<()> {
+        for member in Archive::new(reader).entries()? {
+            let mut member = member?;
+            let header = member.header();
+            if !matches!(
+                header.entry_type(),
+                EntryType::Regular | EntryType::Continuous
+            ) {
+                continue;
+            }
+            let size = header.size()?;
+            let modified = header
+                .mtime()
+                .ok()
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+            let name = member.path()?.into_owned();
+            visit(&name, size, modified, &mut member)?;
+        }
+        Ok(())
+    };
+    match kind {
+        ArchiveKind::Zip => for_each_member(&mut source, |member, modified, reader| {
+            let name = PathBuf::from(String::from_utf8_lossy(&member.name).as_ref());
+            visit(&name, member.size, modified, reader)
+        }),
+        ArchiveKind::Tar => tar_members(&mut source, visit),
+        ArchiveKind::TarGz => tar_members(&mut GzDecoder::new(source), visit),
+    }
+}
+
+/// The members of an archive as entries under `dir`, hashed as they are read.
+/// Archives among them are opened in turn while `depth` allows.
+fn archive_entries<R: Read + Seek>(
+    kind: ArchiveKind,
+    source: R,
+    dir: &Path,
+    depth: usize,
+    root: &Path,
+    filter: &ListingFilter,
+    config: &CompareConfig,
+) -> io::Result<Vec<FileEntry>> {
+    let mut entries = Vec::new();
+    visit_members(kind, source, &mut |name, size, modified, reader| {
+        let rel = member_path(name, 0).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("member path {} escapes the archive", name.display()),
+            )
+        })?;
+        let path = dir.join(rel);
+        if let Some((inner_kind, inner_dir)) = archive_kind(&path).filter(|_| depth > 1) {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            match archive_entries(
+                inner_kind,
+                Cursor::new(&data),
+                &inner_dir,
+                depth - 1,
+                root,
+                filter,
+                config,
+            ) {
+                Ok(inner) => entries.extend(inner),
+                // Not readable as an archive: compared as the file it is.
+                Err(_) => entries.extend(member_entry(
+                    path,
+                    size,
+                    modified,
+                    &mut data.as_slice(),
+                    root,
+                    filter,
+                    config,
+                )?),
+            }
+        } else {
+            entries.extend(member_entry(
+                path, size, modified, reader, root, filter, config,
+            )?);
+        }
+        Ok(())
+    })?;
+    Ok(entries)
+}
+
+fn member_entry(
+    path: PathBuf,
+    size: u64,
+    modified: Option<SystemTime>,
+    reader: &mut dyn Read,
+    root: &Path,
+    filter: &ListingFilter,
+    config: &CompareConfig,
+) -> io::Result<Option<FileEntry>> {
+    if filter.excludes(path.strip_prefix(root).unwrap_or(&path)) {
+        return Ok(None);
+    }
+    let hashes = if config.mode == Mode::Metadata {
+        None
+    } else {
+        Some(hash_reader(reader, config.algo)?)
+    };
+    Ok(Some(FileEntry {
+        path,
+        size,
+        modified,
+        hashes,
+        ..FileEntry::default()
+    }))
+}
+
+/// `--archives recurse`: replace the zip and tar archives among the walked
+/// `files` of `root` by their members, listed under a directory named like the
+/// archive without its extension, so an archive matches the folder it was made
+/// from. Members are hashed while read, like those of a tar stream.
+///
+/// An archive that cannot be read, or whose directory would overlap paths
+/// already listed, stays in the listing as an ordinary file.
+pub fn expand_archives(
+    files: Vec<FileEntry>,
+    root: &Path,
+    config: &CompareConfig,
+) -> Result<Vec<FileEntry>> {
+    let filter = ListingFilter::new(config)?;
+    let (archives, mut kept): (Vec<FileEntry>, Vec<FileEntry>) = files
+        .into_iter()
+        .partition(|e| e.symlink_target.is_none() && archive_kind(&e.path).is_some());
+
+    let expanded: Vec<(FileEntry, io::Result<Vec<FileEntry>>)> = archives
+        .into_par_iter()
+        .map(|archive| {
+            let (kind, dir) = archive_kind(&archive.path).expect("partitioned on archive_kind");
+            let members = sandbox::open(&archive.path).and_then(|file| {
+                archive_entries(
+                    kind,
+                    BufReader::new(file),
+                    &dir,
+                    config.archive_depth,
+                    root,
+                    &filter,
+                    config,
+                )
+            });
+            (archive, members)
+        })
+        .collect();
+
+    // Listed files, and the directories holding them.
+    let mut files: HashSet<PathBuf> = HashSet::new();
+    let mut dirs: HashSet<PathBuf> = HashSet::new();
+    let list = |files: &mut HashSet<PathBuf>, dirs: &mut HashSet<PathBuf>, path: &Path| {
+        files.insert(path.to_path_buf());
+        dirs.extend(path.ancestors().skip(1).map(Path::to_path_buf));
+    };
+    for entry in &kept {
+        list(&mut files, &mut dirs, &entry.path);
+    }
+    for (archive, members) in expanded {
+        let (_, dir) = archive_kind(&archive.path).expect("partitioned on archive_kind");
+        let overlaps = dirs.contains(&dir) || dir.ancestors().any(|a| files.contains(a));
+        match members {
+            Ok(members) if !overlaps => {
+                for member in &members {
+                    list(&mut files, &mut dirs, &member.path);
+                }
+                kept.extend(members);
+            }
+            _ => {
+                list(&mut files, &mut dirs, &archive.path);
+                kept.push(archive);
+            }
+        }
+    }
+    Ok(kept)
+}
+
+/// The scan options that apply to a listing of relative paths, for sides that
+/// are not walked on disk: tar streams and rclone remotes.
+pub(crate) struct ListingFilter {
+    max_depth: Option<usize>,
+    hidden: bool,
+    ignore_set: Option<GlobSet>,
+    type_filter: Option<HashSet<String>>,
+}
+
+impl ListingFilter {
+    pub(crate) fn new(config: &CompareConfig) -> Result<Self> {
+        Ok(Self {
+            max_depth: if config.no_recursive {
+                Some(1)
+            } else {
+                config.depth
+            },
+            hidden: config.hidden,
+            ignore_set: build_globset(&config.ignore)?,
+            type_filter: config.types.as_ref().map(|exts| {
+                exts.iter()
+                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                    .collect()
+            }),
+        })
+    }
+
+    /// Whether `--depth`, `--hidden`, `--ignore` or `--type` leave `rel_path` out.
+    pub(crate) fn excludes(&self, rel_path: &Path) -> bool {
+        self.max_depth
+            .is_some_and(|d| rel_path.components().count() > d)
+            || (!self.hidden
+                && rel_path
+                    .components()
+                    .any(|c| c.as_os_str().to_string_lossy().starts_with('.')))
+            || self
+                .ignore_set
+                .as_ref()
+                .is_some_and(|set| set.is_match(rel_path))
+            || self.type_filter.as_ref().is_some_and(|exts| {
+                !rel_path
+                    .extension()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|s| exts.contains(&s.to_lowercase()))
+            })
+    }
+}
+
+/// A member's path relative to the compared root: `.` and leading `/` dropped,
+/// then `strip` leading components removed. `None` for paths with `..`, and for
+/// members consumed entirely by the strip (such as the top directory).
+fn member_path(path: &Path, strip: usize) -> Option<PathBuf> {
+    let mut parts = Vec::new();
+    for c in path.components() {
+        match c {
+            Component::Normal(part) => parts.push(part),
+            Component::ParentDir => return None,
+            _ => {}
+        }
+    }
+    let rel: PathBuf = parts.into_iter().skip(strip).collect();
+    (!rel.as_os_str().is_empty()).then_some(rel)
+}
@@ -0,0 +1,275 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::models::{FileEntry, HashAlgo, HashResult, Status, SymlinkMode};
+
+/// Size and mtime of both files at the time a verdict was reached.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct PairKey {
+    size1: u64,
+    modified1: Option<SystemTime>,
+    size2: u64,
+    modified2: Option<SystemTime>,
+}
+
+impl PairKey {
+    fn of(entry1: &FileEntry, entry2: &FileEntry) -> Self {
+        Self {
+            size1: entry1.size,
+            modified1: entry1.modified,
+            size2: entry2.size,
+            modified2: entry2.modified,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedVerdict {
+    #[serde(flatten)]
+    key: PairKey,
+    status: Status,
+    /// When the pair was first seen with this verdict and key; absent in
+    /// caches written before ages were kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    since: Option<SystemTime>,
+}
+
+/// Comparison options that can turn a DIFF into a MATCH or back; a cache
+/// written under other options is discarded like one of another algorithm.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VerdictOptions {
+    /// `--mode bytes` rather than hashing.
+    pub bytes: bool,
+    pub quick: Option<u64>,
+    pub zip_members: bool,
+    pub float_tolerance: Option<f64>,
+    pub symlinks: SymlinkMode,
+    pub normalize_symlinks: bool,
+    pub compare_permissions: bool,
+}
+
+/// Pairwise MATCH/DIFF verdicts from a previous run, keyed by relative path.
+///
+/// A verdict is reused only while both files keep the size and mtime they had
+/// when it was computed, so a re-run after a partial fix re-hashes only the
+/// pairs that were touched.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerdictCache {
+    algo: HashAlgo,
+    /// Absent in caches written before options were recorded.
+    #[serde(default)]
+    options: Option<VerdictOptions>,
+    entries: HashMap<PathBuf, CachedVerdict>,
+}
+
+fn content_verdict(status: Status) -> Status {
+    match status {
+        Status::AllowedDiff => Status::Diff,
+        other => other,
+    }
+}
+
+impl VerdictCache {
+    pub fn new(algo: HashAlgo, options: VerdictOptions) -> Self {
+        Self {
+            algo,
+            options: Some(options),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Load the cache at `path`. A missing file, or one written for another
+    /// algorithm or other options, yields an empty cache.
+    pub fn load(path: &Path, algo: HashAlgo, options: VerdictOptions) -> Result<Self> {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::new(algo, options)),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to open verdict cache {}", path.display()));
+            }
+        };
+        let cache: Self = serde_json::from_reader(io::BufReader::new(file))
+            .with_context(|| format!("Failed to parse verdict cache {}", path.display()))?;
+        Ok(
+            if cache.algo == algo && cache.options.as_ref() == Some(&options) {
+                cache
+            } else {
+                Self::new(algo, options)
+            },
+        )
+    }
+
+    pub fn lookup(
+        &self,
+        rel_path: &Path,
+        entry1: &FileEntry,
+        entry2: &FileEntry,
+    ) -> Option<Status> {
+        self.entries
+            .get(rel_path)
+            .filter(|v| v.key == PairKey::of(entry1, entry2))
+            .map(|v| v.status)
+    }
+
+    /// Since when the pair has had `status` without either file changing.
+    pub fn since(
+        &self,
+        rel_path: &Path,
+        entry1: &FileEntry,
+        entry2: &FileEntry,
+        status: Status,
+    ) -> Option<SystemTime> {
+        self.entries
+            .get(rel_path)
+            .filter(|v| v.key == PairKey::of(entry1, entry2) && v.status == content_verdict(status))
+            .and_then(|v| v.since)
+    }
+
+    /// Remember a content verdict reached no later than `since`. Only MATCH
+    /// and DIFF of regular files are kept; an ALLOWED_DIFF is stored as the
+    /// DIFF it was before the rules applied.
+    pub fn record(
+        &mut self,
+        rel_path: PathBuf,
+        entry1: &FileEntry,
+        entry2: &FileEntry,
+        status: Status,
+        since: SystemTime,
+    ) {
+        let status = content_verdict(status);
+        if !matches!(status, Status::Match | Status::Diff)
+            || entry1.symlink_target.is_some()
+            || entry2.symlink_target.is_some()
+        {
+            return;
+        }
+        self.entries.insert(
+            rel_path,
+            CachedVerdict {
+                key: PairKey::of(entry1, entry2),
+                status,
+                since: Some(since),
+            },
+        );
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Failed to write verdict cache {}", path.display()))?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedHash {
+    size: u64,
+    modified: SystemTime,
+    /// Absent in caches written before inodes were recorded, and off Unix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inode: Option<u64>,
+    hashes: HashResult,
+}
+
+/// Digests of individual files, keyed by absolute path.
+///
+/// Unlike the verdict cache it does not depend on what a file is compared
+/// with, so `prime` can fill it ahead of time and any comparison touching the
+/// same files reuses it. A digest is reused only while the file keeps the size,
+/// mtime and inode it had when hashed, so a file replaced by another one with
+/// the same size and mtime (e.g. restored by `cp -p` or `rsync -t`) is hashed
+/// again; files without an mtime are never cached.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashCache {
+    algo: HashAlgo,
+    entries: HashMap<PathBuf, CachedHash>,
+}
+
+impl HashCache {
+    pub fn new(algo: HashAlgo) -> Self {
+        Self {
+            algo,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Load the cache at `path`. A missing file, or one written for another
+    /// algorithm, yields an empty cache.
+    pub fn load(path: &Path, algo: HashAlgo) -> Result<Self> {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::new(algo)),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to open hash cache {}", path.display()));
+            }
+        };
+        let cache: Self = serde_json::from_reader(io::BufReader::new(file))
+            .with_context(|| format!("Failed to parse hash cache {}", path.display()))?;
+        Ok(if cache.algo == algo {
+            cache
+        } else {
+            Self::new(algo)
+        })
+    }
+
+    pub fn lookup(&self, entry: &FileEntry) -> Option<HashResult> {
+        let modified = entry.modified?;
+        let path = std::path::absolute(&entry.path).ok()?;
+        self.entries
+            .get(&path)
+            .filter(|c| c.size == entry.size && c.modified == modified && c.inode == entry.inode)
+            .map(|c| c.hashes.clone())
+    }
+
+    pub fn algo(&self) -> HashAlgo {
+        self.algo
+    }
+
+    /// Remember the digest of a regular file read from disk.
+    pub fn record(&mut self, entry: &FileEntry, hashes: HashResult) {
+        let (Some(modified), Ok(path)) = (entry.modified, std::path::absolute(&entry.path)) else {
+            return;
+        };
+        if entry.symlink_target.is_some() || !entry.on_disk() {
+            return;
+        }
+        self.entries.insert(
+            path,
+            CachedHash {
+                size: entry.size,
+                modified,
+                inode: entry.inode,
+                hashes,
+            },
+        );
+    }
+
+    /// Drop entries under `root` whose file no longer exists.
+    pub fn prune(&mut self, root: &Path) {
+        let Ok(root) = std::path::absolute(root) else {
+            return;
+        };
+        self.entries
+            .retain(|path, _| !path.starts_with(&root) || path.exists());
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Failed to write hash cache {}", path.display()))?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, Once};
+
+type Teardown = Box<dyn FnOnce() + Send>;
+
+/// Teardowns of system state created for the run, by registration number.
+static PENDING: Mutex<Vec<(usize, Teardown)>> = Mutex::new(Vec::new());
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+static HANDLER: Once = Once::new();
+
+/// Undoes something the run created outside its own process (a filesystem
+/// snapshot, a shadow copy) when dropped, or when the process is stopped by
+/// Ctrl-C or SIGTERM first, which would otherwise skip every `Drop`.
+pub struct Cleanup {
+    id: usize,
+}
+
+impl Cleanup {
+    pub fn register(teardown: impl FnOnce() + Send + 'static) -> Self {
+        HANDLER.call_once(|| {
+            if let Err(e) = ctrlc::set_handler(interrupted) {
+                eprintln!(
+                    "Warning: cannot catch Ctrl-C ({}); an interrupted run leaves its snapshots behind",
+                    e
+                );
+            }
+        });
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        PENDING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((id, Box::new(teardown)));
+        Cleanup { id }
+    }
+}
+
+impl Drop for Cleanup {
+    fn drop(&mut self) {
+        let teardown = {
+            let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+            pending
+                .iter()
+                .position(|(id, _)| *id == self.id)
+                .map(|i| pending.remove(i).1)
+        };
+        if let Some(teardown) = teardown {
+            teardown();
+        }
+    }
+}
+
+/// Run every pending teardown, newest first, and exit as a shell would
+/// after SIGINT.
+fn interrupted() {
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+    if !pending.is_empty() {
+        eprintln!("Interrupted; removing the snapshots created for this run");
+    }
+    for (_, teardown) in pending.into_iter().rev() {
+        teardown();
+    }
+    std::process::exit(130);
+}
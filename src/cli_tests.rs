@@ -0,0 +1,323 @@
+#[cfg(test)]
+mod cmpf_cli_tests {
+    use crate::{
+        Cli, Commands, SnapshotCommands, apply_env, compare_config, dispatch, readonly_rules,
+        run_baseline, sandbox_paths,
+    };
+    use clap::Parser;
+    use clap::{CommandFactory, FromArgMatches};
+    use cmpf::compare::ExitStatus;
+    use cmpf::models::{HashAlgo, OutputFormat};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sync_delete_flags_conflict_on_cli() {
+        let err = Cli::try_parse_from([
+            "cmpf",
+            "sync",
+            "src",
+            "dst",
+            "--delete-extraneous",
+            "--no-delete",
+        ])
+        .err()
+        .expect("conflicting flags must be rejected");
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_porcelain_selects_its_output_format() {
+        let mut cli = Cli::try_parse_from(["cmpf", "compare", "a", "b", "--porcelain"]).unwrap();
+        let Some(Commands::Compare {
+            folder1, folder2, ..
+        }) = cli.command.take()
+        else {
+            unreachable!()
+        };
+        assert_eq!(
+            compare_config(cli, folder1, folder2).output_format,
+            OutputFormat::Porcelain
+        );
+        let err = Cli::try_parse_from(["cmpf", "--porcelain", "-f", "json", "compare", "a", "b"])
+            .err()
+            .expect("--porcelain is an output format of its own");
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_interleave_needs_sorting() {
+        let err = Cli::try_parse_from([
+            "cmpf",
+            "-m",
+            "realtime",
+            "--interleave",
+            "--no-sort",
+            "compare",
+            "a",
+            "b",
+        ])
+        .err()
+        .expect("--interleave merges sorted lists");
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        let mut cli = Cli::try_parse_from([
+            "cmpf",
+            "compare",
+            "a",
+            "b",
+            "--interleave",
+            "-m",
+            "realtime",
+        ])
+        .unwrap();
+        let Some(Commands::Compare {
+            folder1, folder2, ..
+        }) = cli.command.take()
+        else {
+            unreachable!()
+        };
+        assert!(compare_config(cli, folder1, folder2).interleave);
+    }
+
+    #[test]
+    fn test_assert_readonly_refuses_sync() {
+        let cli = Cli::try_parse_from(["cmpf", "--assert-readonly", "sync", "a", "b"]).unwrap();
+        assert!(readonly_rules(&cli).is_err());
+        let cli = Cli::try_parse_from(["cmpf", "--assert-readonly", "compare", "a", "b"]).unwrap();
+        assert!(readonly_rules(&cli).is_ok());
+    }
+
+    #[test]
+    fn test_assert_readonly_compares_but_never_records_a_baseline() {
+        let dir = tempdir().unwrap();
+        let data = dir.path().join("data");
+        let store = dir.path().join("baselines");
+        fs::create_dir_all(&data).unwrap();
+        fs::write(data.join("a"), "one").unwrap();
+        let cli = |readonly: bool| {
+            let mut args = vec![
+                "cmpf".as_ref(),
+                "--baseline".as_ref(),
+                data.as_os_str(),
+                "--baseline-dir".as_ref(),
+                store.as_os_str(),
+            ];
+            if readonly {
+                args.push("--assert-readonly".as_ref());
+            }
+            Cli::try_parse_from(args).unwrap()
+        };
+
+        assert!(readonly_rules(&cli(true)).is_ok());
+        assert!(run_baseline(cli(true), data.clone()).is_err());
+        assert!(!store.exists());
+
+        assert_eq!(
+            run_baseline(cli(false), data.clone()).unwrap(),
+            ExitStatus::Success
+        );
+        assert_eq!(
+            run_baseline(cli(true), data.clone()).unwrap(),
+            ExitStatus::Success
+        );
+    }
+
+    #[test]
+    fn test_read_ahead_compares_through_the_pipeline() {
+        let dir = tempdir().unwrap();
+        let f1 = dir.path().join("f1");
+        let f2 = dir.path().join("f2");
+        fs::create_dir_all(&f1).unwrap();
+        fs::create_dir_all(&f2).unwrap();
+        // Spans several 8 MiB buffers and ends mid-buffer.
+        let mut data: Vec<u8> = (0..20 * 1024 * 1024 + 123)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        fs::write(f1.join("big.bin"), &data).unwrap();
+        fs::write(f2.join("big.bin"), &data).unwrap();
+        let compare = || {
+            let cli = Cli::try_parse_from([
+                "cmpf".as_ref(),
+                "--read-ahead".as_ref(),
+                "2".as_ref(),
+                "compare".as_ref(),
+                f1.as_os_str(),
+                f2.as_os_str(),
+            ])
+            .unwrap();
+            dispatch(cli).unwrap()
+        };
+
+        assert_eq!(compare(), ExitStatus::Success);
+        *data.last_mut().unwrap() ^= 1;
+        fs::write(f2.join("big.bin"), &data).unwrap();
+        assert_eq!(compare(), ExitStatus::Diff);
+    }
+
+    #[test]
+    fn test_readonly_and_sandbox_refuse_fs_snapshot() {
+        let cli = Cli::try_parse_from(["cmpf", "--fs-snapshot", "compare", "a", "b"]).unwrap();
+        assert!(readonly_rules(&cli).is_err());
+        assert!(sandbox_paths(&cli).is_err());
+    }
+
+    #[test]
+    fn test_sandbox_refuses_remote_folders_in_both_forms() {
+        for folder in ["gdrive:backup", "user@host:/srv", "s3://bucket/data"] {
+            let cli = Cli::try_parse_from(["cmpf", "--sandbox", "compare", folder, "b"]).unwrap();
+            assert!(sandbox_paths(&cli).is_err(), "{}", folder);
+            let cli = Cli::try_parse_from(["cmpf", "--sandbox", folder, "b"]).unwrap();
+            assert!(sandbox_paths(&cli).is_err(), "{}", folder);
+        }
+    }
+
+    #[test]
+    fn test_readonly_and_sandbox_refuse_vss() {
+        let cli = Cli::try_parse_from(["cmpf", "--vss", "compare", "a", "b"]).unwrap();
+        assert!(readonly_rules(&cli).is_err());
+        assert!(sandbox_paths(&cli).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_subcommands_share_global_flags() {
+        let cli =
+            Cli::try_parse_from(["cmpf", "snapshot", "dir", "--output", "s.json", "-H"]).unwrap();
+        assert!(cli.hidden);
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Snapshot { action: None, create: Some(ref args) })
+                if args.folder.as_os_str() == "dir"
+        ));
+        let cli =
+            Cli::try_parse_from(["cmpf", "snapshot", "create", "dir", "-a", "sha256"]).unwrap();
+        assert_eq!(cli.algo, HashAlgo::Sha256);
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Snapshot {
+                action: Some(SnapshotCommands::Create(_)),
+                create: None
+            })
+        ));
+        let cli =
+            Cli::try_parse_from(["cmpf", "snapshot", "verify", "dir", "s.json", "-f", "json"])
+                .unwrap();
+        assert_eq!(cli.output_format, OutputFormat::Json);
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Snapshot {
+                action: Some(SnapshotCommands::Verify(_)),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_snapshot_diff_compares_two_snapshots() {
+        let dir = tempdir().unwrap();
+        let mut snapshots = Vec::new();
+        for (name, content) in [("a", "one"), ("b", "two")] {
+            let folder = dir.path().join(name);
+            fs::create_dir_all(&folder).unwrap();
+            fs::write(folder.join("f"), content).unwrap();
+            let snapshot = dir.path().join(format!("{}.json", name));
+            let cli = Cli::try_parse_from([
+                "cmpf".as_ref(),
+                "snapshot".as_ref(),
+                folder.as_os_str(),
+                "--output".as_ref(),
+                snapshot.as_os_str(),
+            ])
+            .unwrap();
+            assert_eq!(dispatch(cli).unwrap(), ExitStatus::Success);
+            snapshots.push(snapshot);
+        }
+        let diff = |second: &std::path::Path| {
+            dispatch(
+                Cli::try_parse_from([
+                    "cmpf".as_ref(),
+                    "snapshot".as_ref(),
+                    "diff".as_ref(),
+                    snapshots[0].as_os_str(),
+                    second.as_os_str(),
+                    "-o".as_ref(),
+                    dir.path().join("out").as_os_str(),
+                ])
+                .unwrap(),
+            )
+        };
+        assert_eq!(diff(&snapshots[1]).unwrap(), ExitStatus::Diff);
+        assert_eq!(diff(&snapshots[0]).unwrap(), ExitStatus::Success);
+        assert!(diff(&dir.path().join("a")).is_err());
+    }
+
+    #[test]
+    fn test_env_layer_below_command_line() {
+        // Only variables whose values cannot change the other CLI tests' outcomes.
+        unsafe {
+            std::env::set_var("CMPF_PRESENCE_ONLY", "*.iso, *.img");
+            std::env::set_var("CMPF_BLOCK_DIFF", "1");
+            std::env::set_var("CMPF_BLOCK_SIZE", "1024");
+        }
+        let parse = |args: &[&str]| {
+            let matches = Cli::command().try_get_matches_from(args).unwrap();
+            let mut cli = Cli::from_arg_matches(&matches).unwrap();
+            apply_env(&mut cli, &matches).unwrap();
+            cli
+        };
+
+        let cli = parse(&["cmpf", "compare", "a", "b"]);
+        assert_eq!(
+            cli.presence_only,
+            Some(vec!["*.iso".to_string(), "*.img".to_string()])
+        );
+        assert!(cli.block_diff);
+        assert_eq!(cli.block_size, 1024);
+
+        let cli = parse(&[
+            "cmpf",
+            "--block-size",
+            "2048",
+            "--presence-only",
+            "*.a,b",
+            "compare",
+            "a",
+            "b",
+        ]);
+        assert_eq!(cli.block_size, 2048);
+        assert_eq!(cli.presence_only, Some(vec!["*.a,b".to_string()]));
+        unsafe {
+            std::env::remove_var("CMPF_PRESENCE_ONLY");
+            std::env::remove_var("CMPF_BLOCK_DIFF");
+            std::env::remove_var("CMPF_BLOCK_SIZE");
+        }
+    }
+
+    #[test]
+    fn test_baseline_records_then_compares() {
+        let dir = tempdir().unwrap();
+        let data = dir.path().join("data");
+        let store = dir.path().join("baselines");
+        fs::create_dir_all(&data).unwrap();
+        fs::write(data.join("a"), "one").unwrap();
+        let cli = || {
+            Cli::try_parse_from([
+                "cmpf".as_ref(),
+                "--baseline".as_ref(),
+                data.as_os_str(),
+                "--baseline-dir".as_ref(),
+                store.as_os_str(),
+                "-o".as_ref(),
+                dir.path().join("out").as_os_str(),
+            ])
+            .unwrap()
+        };
+        let run = || run_baseline(cli(), data.clone()).unwrap();
+
+        assert_eq!(run(), ExitStatus::Success);
+        assert_eq!(fs::read_dir(&store).unwrap().count(), 1);
+        assert_eq!(run(), ExitStatus::Success);
+
+        fs::write(data.join("b"), "two").unwrap();
+        assert_eq!(run(), ExitStatus::Diff);
+    }
+}
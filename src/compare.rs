@@ -1,32 +1,70 @@
 use anyhow::Result;
 use chrono::{DateTime, Local};
 use colored::*;
+use globset::GlobSet;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
-use std::collections::{HashMap, HashSet};
-use std::io::{self, IsTerminal};
-use std::path::PathBuf;
-use std::time::Instant;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
+use crate::activity;
+use crate::archive::{expand_archives, read_tar};
+use crate::cache::{HashCache, VerdictCache, VerdictOptions};
+use crate::config::{Expect, FileConfig, Severities, StatusRule, SubtreeRule};
+use crate::drift::report_drift;
+use crate::filetype;
+use crate::fssnapshot::FsSnapshot;
 use crate::models::{
-    ComparisonResult, FileEntry, HashAlgo, Mode, OutputFormat, Status, SymlinkMode,
+    ArchiveMode, BlockDiff, ComparisonResult, ErrorEntry, FileEntry, HashAlgo, HashResult, Mode,
+    OutputFormat, RootAttrs, RootCheck, SampleParams, Severity, Status, StdinFormat, SymlinkMode,
 };
+use crate::probe::{format_granularity, probe};
+use crate::rclone::{is_remote, read_remote};
+use crate::replicas::run_replicas;
 use crate::report::{
-    ReportConfig, SummaryData, generate_json_report, generate_summary_text, generate_text_report,
-    print_error_entry, print_realtime_missing, write_report,
+    Breakdown, DirTotals, PrecheckData, PreviousSummary, ReportConfig, ResultsPage,
+    SampleConfidence, SummaryData, error_result, generate_code_quality_report, generate_csv_report,
+    generate_html_report, generate_json_report, generate_ndjson_report, generate_porcelain_report,
+    generate_precheck_json, generate_precheck_text, generate_summary_text, generate_text_report,
+    load_previous_summary, ndjson_line, ndjson_summary_line, porcelain_line, print_error_entries,
+    print_realtime_missing, print_skips, print_status_line, write_report, write_status_lists,
 };
-use crate::utils::{collect_files, compute_hashes};
+use crate::resources::ResourceUsage;
+use crate::s3::{is_s3, object_hashes, read_s3};
+use crate::sandbox;
+use crate::snapshot::{LoadedSnapshots, SnapshotScanParams, load_snapshot, write_manifest};
+use crate::ssh::{is_ssh, read_ssh};
+use crate::stream::run_stream;
+use crate::tabular;
+use crate::utils::{
+    build_globset, collect_files, compute_hashes, compute_hashes_noting, diff_blocks,
+    explain_skips, first_difference, key_by_relative_path, link_dir_mismatches, parse_size,
+    quick_hashes, read_root_attributes, resolve_link_target, split_command, unified_diff,
+};
+use crate::vss::Shadows;
+use crate::ziplisting::{is_zip_path, zip_members};
 
 #[derive(Debug, PartialEq)]
 pub enum ExitStatus {
     Success,
     Diff,
     Error,
+    /// Some paths could not be read for lack of permission (`--permission-exit`).
+    PermissionDenied,
 }
 
+#[derive(Default, Clone)]
 pub struct CompareConfig {
     pub folder1: PathBuf,
     pub folder2: PathBuf,
+    /// Further replicas compared along with folder1 and folder2 (`compare A B C...`).
+    pub replicas: Vec<PathBuf>,
     pub mode: Mode,
     pub algo: HashAlgo,
     pub output_folder: Option<PathBuf>,
@@ -39,13 +77,417 @@ pub struct CompareConfig {
     pub types: Option<Vec<String>>,
     pub ignore: Option<Vec<String>>,
     pub threads: Option<usize>,
+    /// Buffers the reader fills ahead of the hasher (`--read-ahead`); when set,
+    /// every file past the small-file path is streamed.
+    pub read_ahead: Option<usize>,
     pub no_sort: bool,
+    /// Realtime mode: print EXTRA entries in path order among the others.
+    pub interleave: bool,
     pub diff_cmd: Option<String>,
+    pub block_diff: bool,
+    pub block_size: u64,
+    /// Context lines of the unified diffs added to differing text files under
+    /// `verbose`.
+    pub diff_context: usize,
+    /// Files larger than this get no unified diff; 0 turns them off.
+    pub diff_max_size: u64,
+    pub size_only: bool,
+    pub report_checksum: bool,
+    /// Compare per-directory file counts and bytes before hashing.
+    pub precheck: bool,
+    /// Stop after the precheck when its divergence (in percent) exceeds this.
+    pub precheck_threshold: Option<f64>,
+    /// File holding MATCH/DIFF verdicts reused while both files are unchanged.
+    pub verdict_cache: Option<PathBuf>,
+    /// Per-file digest cache read before hashing and updated by batch runs.
+    pub hash_cache: Option<PathBuf>,
+    /// Run was started with `--assert-readonly`; recorded in the report.
+    pub assert_readonly: bool,
+    /// Read folders on disk from Volume Shadow Copies of their volumes (`--vss`, Windows).
+    pub vss: bool,
+    /// Read folder1 from a temporary btrfs or LVM snapshot (`--fs-snapshot`, Linux).
+    pub fs_snapshot: bool,
+    /// Globs (matched against the relative path) for files checked for existence only.
+    pub presence_only: Option<Vec<String>>,
+    /// Hash only this percentage of common files; the rest get a size/mtime check.
+    pub sample: Option<f64>,
+    /// Seed of the run's random choices, such as the `sample`, recorded in
+    /// `run_info`; a time-based one is used when absent.
+    pub seed: Option<u64>,
+    /// Print a one-line machine-readable outcome to stderr at the end.
+    pub status_line: bool,
+    /// Hash MISSING/EXTRA files and report where their content lives on the other side.
+    pub relocated: bool,
+    /// Merge MISSING/EXTRA pairs with identical content into RENAMED results.
+    pub detect_renames: bool,
+    /// Detect a constant whole-interval mtime offset between the sides and ignore it.
+    pub auto_mtime_skew: bool,
+    /// Rules from `--config` overriding the verdict of matching paths.
+    pub status_rules: Vec<StatusRule>,
+    /// `[[subtree]]` tables from `--config`: options for matching paths.
+    pub subtrees: Vec<SubtreeRule>,
+    /// Pairs whose permission bits differ are DIFF (`permissions = true` in a subtree).
+    pub compare_permissions: bool,
+    /// Per-class severities from `--config`; the defaults when absent.
+    pub severities: Option<Severities>,
+    /// Directory receiving plain `diff.txt`/`missing.txt`/`extra.txt` path lists.
+    pub emit_list_dir: Option<PathBuf>,
+    /// Group DIFF/MISSING/EXTRA entries by extension and size in the report.
+    pub breakdown: bool,
+    /// Write a snapshot of folder1 here, reusing the digests of the comparison.
+    pub emit_manifest: Option<PathBuf>,
+    /// Add peak memory, CPU time, storage reads and open files to the summary.
+    pub resource_usage: bool,
+    /// Hash only this many bytes at each end of files longer than twice that.
+    pub quick: Option<u64>,
+    /// Compare zip and jar files by member names, CRCs and sizes, ignoring
+    /// timestamps and compression.
+    pub zip_members: bool,
+    /// Compare csv and tsv files field by field, numbers being equal within
+    /// this relative difference.
+    pub float_tolerance: Option<f64>,
+    /// Hash `s3://` objects by downloading them even where the ETag is an MD5.
+    pub s3_download: bool,
+    /// Count `s3://` objects of equal size and ETag as matching without
+    /// downloading them.
+    pub trust_etags: bool,
+    /// Keep `--algo` even when a snapshot side records another algorithm,
+    /// failing instead of switching to the recorded one.
+    pub force_algo: bool,
+    /// Format of a side given as `-`, read from stdin.
+    pub stdin_format: StdinFormat,
+    /// Open archives in the folders and compare their members (`--archives recurse`).
+    pub archives: ArchiveMode,
+    /// Levels of archives opened under `--archives recurse`; 1 opens only the
+    /// archives in the folders, not those inside them.
+    pub archive_depth: usize,
+    /// Leading path components dropped from members of a stdin archive.
+    pub strip_components: usize,
+    /// Probe both folders' filesystems and tolerate their mtime granularity.
+    pub probe_fs: bool,
+    /// Merge two sorted walks and compare pairs as they arrive (`--stream`).
+    pub stream: bool,
+    /// `--max-memory` budget of the hashing buffers, which `main` sets
+    /// process-wide. A comparison under it runs as with `stream`, so neither
+    /// the file lists nor the results grow with the trees.
+    pub max_memory: Option<u64>,
+    /// Exit with a dedicated code when paths could not be read for lack of permission.
+    pub permission_exit: bool,
+    /// Symlinks whose targets differ as written still match when both resolve
+    /// to the same place.
+    pub normalize_symlinks: bool,
+    /// JSON report of an earlier run; only the changes against it are reported.
+    pub baseline_report: Option<PathBuf>,
+    /// Leave the permissions, owner and xattrs of the two roots uncompared.
+    pub no_root_check: bool,
+    /// JSON or ndjson report of an earlier run; summary counts show how far
+    /// they moved since it.
+    pub previous_report: Option<PathBuf>,
+    /// Only files owned by this uid are compared (`--owner-filter`).
+    pub owner_filter: Option<u32>,
+    /// Do not descend into other mounted filesystems (`--one-file-system`).
+    pub one_file_system: bool,
+    /// Print each path the walks leave out and the rule that excludes it.
+    pub explain_skips: bool,
+    /// Record the MIME type of each side's content in the results.
+    pub detect_type: bool,
+    /// Report DIFF verdicts the verdict cache has seen unchanged for more
+    /// than this many days as ALLOWED_DIFF.
+    pub ignore_known_diffs: Option<u64>,
+    /// Status filter, offset and limit of the results listed in JSON reports.
+    pub results_page: ResultsPage,
+}
+
+impl CompareConfig {
+    /// Severity shown for a result, only when severities are configured so that
+    /// the default output is unchanged.
+    pub(crate) fn severity_of(&self, status: Status) -> Option<Severity> {
+        self.severities.and_then(|s| s.of(status))
+    }
+
+    /// The option that makes the comparison run as `--stream`, if any.
+    pub(crate) fn stream_flag(&self) -> Option<&'static str> {
+        if self.stream {
+            Some("--stream")
+        } else if self.max_memory.is_some() {
+            Some("--max-memory (which compares as --stream)")
+        } else {
+            None
+        }
+    }
+
+    /// Options a cached verdict depends on besides the algorithm.
+    fn verdict_options(&self) -> VerdictOptions {
+        VerdictOptions {
+            bytes: self.mode == Mode::Bytes,
+            quick: self.quick,
+            zip_members: self.zip_members,
+            float_tolerance: self.float_tolerance,
+            symlinks: self.symlinks,
+            normalize_symlinks: self.normalize_symlinks,
+            compare_permissions: self.compare_permissions,
+        }
+    }
+
+    /// Whether the two roots' own metadata is compared: unless
+    /// `--no-root-check` turns it off, wherever a batch report is made.
+    pub(crate) fn root_check(&self) -> bool {
+        !self.no_root_check && (self.mode != Mode::Realtime || self.baseline_report.is_some())
+    }
+
+    pub(crate) fn report_config(&self, rules: &Rules) -> ReportConfig {
+        ReportConfig {
+            mode: self.mode,
+            algo: self.algo,
+            threads: self.threads,
+            verbose: self.verbose,
+            report_checksum: self.report_checksum,
+            assert_readonly: self.assert_readonly,
+            seed: Some(rules.seed),
+            sample: rules.sample,
+            mtime_skew: rules.mtime_skew,
+            mtime_tolerance: rules.mtime_tolerance,
+            folders: Some(self.absolute_folders()),
+            results_page: self.results_page.clone(),
+        }
+    }
+
+    /// Both roots made absolute where possible, so a report can be replayed elsewhere.
+    fn absolute_folders(&self) -> (PathBuf, PathBuf) {
+        let abs = |p: &PathBuf| fs::canonicalize(p).unwrap_or_else(|_| p.clone());
+        (abs(&self.folder1), abs(&self.folder2))
+    }
+}
+
+/// Per-run matchers derived from the config once, before any file is compared.
+#[derive(Default, Clone)]
+pub(crate) struct Rules {
+    presence: Option<GlobSet>,
+    /// Paths whose content differences are expected (`expect = "diff-ok"`).
+    allowed_diff: Option<GlobSet>,
+    seed: u64,
+    sample: Option<SampleParams>,
+    /// Seconds folder2's mtimes run ahead of folder1's (`--auto-mtime-skew`).
+    mtime_skew: Option<i64>,
+    /// Mtimes closer than this are equal; the coarser granularity of the two
+    /// filesystems when `--probe-fs` found them to differ.
+    mtime_tolerance: Option<Duration>,
+    /// Digests from `--hash-cache`, used instead of reading unchanged files.
+    hash_cache: Option<Arc<HashCache>>,
+    subtrees: Vec<Subtree>,
+    /// Summary counts of `--previous-report`.
+    pub(crate) previous: Option<PreviousSummary>,
+    /// Snapshot sides already read while negotiating `--algo`.
+    snapshots: LoadedSnapshots,
+}
+
+/// A `[[subtree]]` of `--config`: the options its files are compared with.
+#[derive(Clone)]
+struct Subtree {
+    matcher: GlobSet,
+    config: Arc<CompareConfig>,
+    sample: Option<SampleParams>,
+}
+
+impl Subtree {
+    fn new(rule: &SubtreeRule, config: &CompareConfig, seed: u64) -> Result<Self> {
+        let matcher = build_globset(&Some(vec![rule.pattern.clone()]))?
+            .expect("one pattern always builds a set");
+        let mut scoped = CompareConfig {
+            subtrees: Vec::new(),
+            ..config.clone()
+        };
+        scoped.mode = rule.mode.unwrap_or(config.mode);
+        scoped.algo = rule.algo.unwrap_or(config.algo);
+        scoped.size_only = rule.size_only.unwrap_or(config.size_only);
+        scoped.compare_permissions = rule.permissions.unwrap_or(config.compare_permissions);
+        if let Some(quick) = &rule.quick {
+            scoped.quick = Some(parse_size(quick).map_err(anyhow::Error::msg)?).filter(|&n| n > 0);
+        }
+        let sample = match rule.sample {
+            Some(p) if !(p > 0.0 && p <= 100.0) => anyhow::bail!(
+                "subtree {}: sample must be a percentage in (0, 100], got {}",
+                rule.pattern,
+                p
+            ),
+            Some(p) => (p < 100.0).then_some(SampleParams { percent: p, seed }),
+            None => config.sample.map(|percent| SampleParams { percent, seed }),
+        };
+        Ok(Self {
+            matcher,
+            config: Arc::new(scoped),
+            sample,
+        })
+    }
+}
+
+impl Rules {
+    pub(crate) fn new(config: &CompareConfig) -> Result<Self> {
+        if let Some(p) = config.sample
+            && !(p > 0.0 && p <= 100.0)
+        {
+            anyhow::bail!("--sample must be a percentage in (0, 100], got {}", p);
+        }
+        if let Some(t) = config.float_tolerance
+            && !(t.is_finite() && t >= 0.0)
+        {
+            anyhow::bail!("--float-tolerance must be a non-negative number, got {}", t);
+        }
+        let seed = config.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or_default()
+        });
+        let sample = config.sample.map(|percent| SampleParams { percent, seed });
+        Ok(Self {
+            presence: build_globset(&config.presence_only)?,
+            allowed_diff: build_globset(&FileConfig::patterns(
+                &config.status_rules,
+                Expect::DiffOk,
+            ))?,
+            seed,
+            sample,
+            mtime_skew: None,
+            mtime_tolerance: None,
+            hash_cache: match &config.hash_cache {
+                Some(path) if config.mode != Mode::Metadata => {
+                    Some(Arc::new(HashCache::load(path, config.algo)?))
+                }
+                _ => None,
+            },
+            subtrees: config
+                .subtrees
+                .iter()
+                .map(|rule| Subtree::new(rule, config, seed))
+                .collect::<Result<_>>()?,
+            previous: config
+                .previous_report
+                .as_deref()
+                .map(load_previous_summary)
+                .transpose()?,
+            snapshots: LoadedSnapshots::new(),
+        })
+    }
+
+    fn subtree(&self, rel_path: &Path) -> Option<&Subtree> {
+        self.subtrees.iter().find(|s| s.matcher.is_match(rel_path))
+    }
+
+    /// The options `rel_path` is compared with: those of its subtree, if any.
+    fn config_for<'a>(&'a self, rel_path: &Path, config: &'a CompareConfig) -> &'a CompareConfig {
+        self.subtree(rel_path).map_or(config, |s| &s.config)
+    }
+
+    /// Modification times agree once the detected skew is taken out.
+    fn same_mtime(&self, entry1: &FileEntry, entry2: &FileEntry) -> bool {
+        let (Some(t1), Some(t2)) = (entry1.modified, entry2.modified) else {
+            return entry1.modified == entry2.modified;
+        };
+        let t2 = match self.mtime_skew {
+            Some(skew) => {
+                let shift = Duration::from_secs(skew.unsigned_abs());
+                if skew > 0 {
+                    t2.checked_sub(shift)
+                } else {
+                    t2.checked_add(shift)
+                }
+            }
+            None => Some(t2),
+        };
+        match (t2, self.mtime_tolerance) {
+            (Some(t2), Some(tolerance)) => {
+                t1.duration_since(t2).unwrap_or_else(|e| e.duration()) < tolerance
+            }
+            (t2, None) => t2 == Some(t1),
+            (None, Some(_)) => false,
+        }
+    }
+
+    /// Final status of a pair once the `--config` rules are applied.
+    fn settle(&self, rel_path: &Path, status: Status) -> Status {
+        match status {
+            Status::Diff
+                if self
+                    .allowed_diff
+                    .as_ref()
+                    .is_some_and(|set| set.is_match(rel_path)) =>
+            {
+                Status::AllowedDiff
+            }
+            _ => status,
+        }
+    }
+
+    fn presence_only(&self, rel_path: &Path) -> bool {
+        self.presence
+            .as_ref()
+            .is_some_and(|set| set.is_match(rel_path))
+    }
+
+    /// Whether the content of `rel_path` is actually examined in this run.
+    fn hashes(&self, rel_path: &Path) -> bool {
+        let sample = self.subtree(rel_path).map_or(self.sample, |s| s.sample);
+        !self.presence_only(rel_path) && sample.is_none_or(|s| s.selects(rel_path))
+    }
+
+    /// Count a compared pair toward the `--sample` estimate, if its size and
+    /// mtime are ones a metadata check would accept.
+    fn tally_sample(
+        &self,
+        confidence: &mut SampleConfidence,
+        rel_path: &Path,
+        (entry1, entry2): (&FileEntry, &FileEntry),
+        status: Status,
+        size_only: bool,
+    ) {
+        let Some(sample) = self.sample else {
+            return;
+        };
+        if self.presence_only(rel_path)
+            || entry1.size != entry2.size
+            || !(size_only || self.same_mtime(entry1, entry2))
+        {
+            return;
+        }
+        let differs = match status {
+            Status::Match | Status::QuickMatch => false,
+            Status::Diff | Status::AllowedDiff => true,
+            _ => return,
+        };
+        confidence.record(sample.selects(rel_path), differs);
+    }
 }
 
-pub fn run_compare(config: CompareConfig) -> Result<ExitStatus> {
+pub fn run_compare(mut config: CompareConfig) -> Result<ExitStatus> {
     let start_time = Instant::now();
 
+    if config.folder1 == Path::new("-") && config.folder2 == Path::new("-") {
+        anyhow::bail!("Only one side can be read from stdin");
+    }
+
+    // Kept until the run ends; dropping it deletes the shadow copies.
+    let _shadows = if config.vss {
+        Some(read_from_shadows(&mut config)?)
+    } else {
+        None
+    };
+    let _fs_snapshot = if config.fs_snapshot {
+        if config.folder1 == Path::new("-")
+            || is_snapshot_file(&config.folder1)
+            || is_remote(&config.folder1)
+            || is_ssh(&config.folder1)
+            || is_s3(&config.folder1)
+        {
+            anyhow::bail!("--fs-snapshot needs folder1 to be a folder");
+        }
+        let snapshot = FsSnapshot::create(&config.folder1)?;
+        config.folder1 = snapshot.folder().to_path_buf();
+        Some(snapshot)
+    } else {
+        None
+    };
+
     // Fix #5: thread pool configuration is done once here, not repeated in every
     // subcommand entry point. Subsequent calls are harmless (global pool already set).
     if let Some(num_threads) = config.threads {
@@ -54,9 +496,83 @@ pub fn run_compare(config: CompareConfig) -> Result<ExitStatus> {
             .build_global();
     }
 
+    let snapshots = load_snapshot_sides(&config)?;
+    negotiate_algo(&mut config, &snapshots)?;
+
+    if !config.replicas.is_empty() {
+        return run_replicas(&config, &snapshots, start_time);
+    }
+
+    let mut rules = Rules::new(&config)?;
+    rules.snapshots = snapshots;
+    if config.probe_fs {
+        rules.mtime_tolerance = probe_filesystems(&config, false);
+    }
+    if config.explain_skips {
+        for (folder, side) in [(&config.folder1, "folder1"), (&config.folder2, "folder2")] {
+            if folder.is_dir() {
+                print_skips(
+                    &explain_skips(
+                        folder,
+                        config.depth,
+                        config.no_recursive,
+                        config.hidden,
+                        &config.types,
+                        &config.ignore,
+                        config.symlinks,
+                        config.owner_filter,
+                        config.one_file_system,
+                    )?,
+                    side,
+                );
+            }
+        }
+    }
+
+    if config.emit_manifest.is_some()
+        && (config.folder1 == Path::new("-")
+            || is_snapshot_file(&config.folder1)
+            || is_remote(&config.folder1)
+            || is_ssh(&config.folder1)
+            || is_s3(&config.folder1))
+    {
+        anyhow::bail!("--emit-manifest needs folder1 to be a folder");
+    }
+
+    if config.baseline_report.is_some()
+        && !matches!(config.output_format, OutputFormat::Txt | OutputFormat::Json)
+    {
+        anyhow::bail!("--baseline-report writes txt or json reports only");
+    }
+
+    if !config.results_page.is_all()
+        && (config.output_format != OutputFormat::Json
+            || config.mode == Mode::Realtime
+            || config.stream_flag().is_some()
+            || config.baseline_report.is_some())
+    {
+        anyhow::bail!(
+            "--results-status, --results-offset and --results-limit apply to batch json reports only"
+        );
+    }
+
+    if let Some(flag) = config.stream_flag() {
+        if config.archives == ArchiveMode::Recurse {
+            anyhow::bail!(
+                "--archives recurse lists archive members and cannot be combined with {}",
+                flag
+            );
+        }
+        return run_stream(&config, &rules, start_time);
+    }
+
     match config.mode {
-        Mode::Realtime => run_realtime(&config, start_time),
-        Mode::Batch | Mode::Metadata => run_batch(&config, start_time),
+        // The changes need every result, so a baseline comparison always batches.
+        Mode::Realtime if config.baseline_report.is_none() => {
+            run_realtime(&config, &rules, start_time)
+        }
+        Mode::Realtime => run_batch(&config, &rules, start_time),
+        Mode::Batch | Mode::Metadata | Mode::Bytes => run_batch(&config, &rules, start_time),
     }
 }
 
@@ -65,136 +581,602 @@ pub(crate) fn compare_files_core(
     entry1: &FileEntry,
     entry2: &FileEntry,
     config: &CompareConfig,
+    rules: &Rules,
 ) -> Result<ComparisonResult> {
-    let size1 = Some(entry1.size);
-    let size2 = Some(entry2.size);
-    let mut time1_str = None;
-    let mut time2_str = None;
-
-    if let Some(t1) = entry1.modified {
-        time1_str = Some(
-            DateTime::<Local>::from(t1)
-                .format("%Y-%m-%d %H:%M:%S")
-                .to_string(),
-        );
+    let config = rules.config_for(&rel_path, config);
+    let mut result = compare_pair(rel_path, entry1, entry2, config, rules)?;
+    if config.compare_permissions
+        && matches!(result.status, Status::Match | Status::QuickMatch)
+        && entry1.permissions.is_some()
+        && entry1.permissions != entry2.permissions
+    {
+        result.status = Status::Diff;
     }
-    if let Some(t2) = entry2.modified {
-        time2_str = Some(
-            DateTime::<Local>::from(t2)
-                .format("%Y-%m-%d %H:%M:%S")
-                .to_string(),
+    result.status = rules.settle(&result.file, result.status);
+    result.severity = config.severity_of(result.status);
+    if config.verbose
+        && matches!(result.status, Status::Diff | Status::AllowedDiff)
+        && entry1.size.max(entry2.size) <= config.diff_max_size
+        && [entry1, entry2]
+            .iter()
+            .all(|e| e.on_disk() && e.symlink_target.is_none())
+    {
+        result.unified_diff = unified_diff(
+            &entry1.path,
+            &entry2.path,
+            &result.file,
+            config.diff_context,
         );
     }
+    Ok(result)
+}
+
+fn compare_pair(
+    rel_path: PathBuf,
+    entry1: &FileEntry,
+    entry2: &FileEntry,
+    config: &CompareConfig,
+    rules: &Rules,
+) -> Result<ComparisonResult> {
+    let size1 = Some(entry1.size);
+    let size2 = Some(entry2.size);
+    let time1_str = format_mtime(entry1.modified);
+    let time2_str = format_mtime(entry2.modified);
+
+    // --presence-only: existing on both sides is all that is checked.
+    if rules.presence_only(&rel_path) {
+        return Ok(ComparisonResult {
+            size1,
+            size2,
+            modified1: time1_str,
+            modified2: time2_str,
+            ..ComparisonResult::new(rel_path, Status::Match)
+        });
+    }
 
     if config.symlinks == SymlinkMode::Compare {
         let s1 = entry1.symlink_target.as_deref();
         let s2 = entry2.symlink_target.as_deref();
 
-        if s1.is_some() && s2.is_some() {
-            let matches = s1 == s2;
-            return Ok(ComparisonResult {
-                file: rel_path,
-                status: if matches { Status::Match } else { Status::Diff },
-                hash1: None,
-                hash2: None,
-                size1: None,
-                size2: None,
-                modified1: time1_str,
-                modified2: time2_str,
-                symlink1: entry1.symlink_target.clone(),
-                symlink2: entry2.symlink_target.clone(),
-            });
-        }
-        if s1.is_some() != s2.is_some() {
+        if s1.is_some() || s2.is_some() {
+            let same_place = || match (s1, s2) {
+                (Some(t1), Some(t2)) => {
+                    resolve_link_target(&entry1.path, t1) == resolve_link_target(&entry2.path, t2)
+                }
+                _ => false,
+            };
+            let status = if s1 == s2 || (config.normalize_symlinks && same_place()) {
+                Status::Match
+            } else {
+                Status::Diff
+            };
             return Ok(ComparisonResult {
-                file: rel_path,
-                status: Status::Diff,
-                hash1: None,
-                hash2: None,
-                size1: None,
-                size2: None,
                 modified1: time1_str,
                 modified2: time2_str,
                 symlink1: entry1.symlink_target.clone(),
                 symlink2: entry2.symlink_target.clone(),
+                ..ComparisonResult::new(rel_path, status)
             });
         }
     }
 
+    // --zip-members: archives that differ only in member timestamps, order or
+    // compression match. Anything that cannot be read as a zip archive falls
+    // through to a plain comparison.
+    if config.zip_members
+        && config.mode != Mode::Metadata
+        && rules.hashes(&rel_path)
+        && is_zip_path(&rel_path)
+        && [entry1, entry2]
+            .iter()
+            .all(|e| e.on_disk() && e.symlink_target.is_none())
+        && let (Ok(m1), Ok(m2)) =
+            rayon::join(|| zip_members(&entry1.path), || zip_members(&entry2.path))
+    {
+        let status = if m1 == m2 {
+            Status::Match
+        } else {
+            Status::Diff
+        };
+        return Ok(ComparisonResult {
+            size1,
+            size2,
+            modified1: time1_str,
+            modified2: time2_str,
+            ..ComparisonResult::new(rel_path, status)
+        });
+    }
+
+    // --float-tolerance: data files regenerated with last-digit differences in
+    // their numbers match. Files that are not UTF-8 text fall through.
+    if let Some(tolerance) = config.float_tolerance
+        && config.mode != Mode::Metadata
+        && rules.hashes(&rel_path)
+        && let Some(delimiter) = tabular::delimiter(&rel_path)
+        && [entry1, entry2]
+            .iter()
+            .all(|e| e.on_disk() && e.symlink_target.is_none())
+        && let Ok(same) = tabular::same_values(&entry1.path, &entry2.path, delimiter, tolerance)
+    {
+        let status = if same { Status::Match } else { Status::Diff };
+        return Ok(ComparisonResult {
+            size1,
+            size2,
+            modified1: time1_str,
+            modified2: time2_str,
+            ..ComparisonResult::new(rel_path, status)
+        });
+    }
+
     if entry1.size != entry2.size {
+        let block_diff = block_diff_for(entry1, entry2, config);
         return Ok(ComparisonResult {
-            file: rel_path,
-            status: Status::Diff,
-            hash1: None,
-            hash2: None,
             size1,
             size2,
             modified1: time1_str,
             modified2: time2_str,
-            symlink1: None,
-            symlink2: None,
+            block_diff,
+            ..ComparisonResult::new(rel_path, Status::Diff)
         });
-    } else if config.mode == Mode::Metadata {
-        let status = if entry1.modified != entry2.modified {
+    } else if config.mode == Mode::Metadata || !rules.hashes(&rel_path) {
+        // Metadata mode, or a file left out of --sample.
+        let status = if !config.size_only && !rules.same_mtime(entry1, entry2) {
             Status::Diff
         } else {
             Status::Match
         };
         return Ok(ComparisonResult {
-            file: rel_path,
-            status,
-            hash1: None,
-            hash2: None,
             size1,
             size2,
             modified1: time1_str,
             modified2: time2_str,
-            symlink1: None,
-            symlink2: None,
+            ..ComparisonResult::new(rel_path, status)
         });
     }
 
-    let (h1_res, h2_res) = rayon::join(
-        || compute_hashes(&entry1.path, config.algo),
-        || compute_hashes(&entry2.path, config.algo),
-    );
+    // --trust-etags: objects of the same size and ETag hold the same bytes,
+    // so only pairs whose ETags differ are downloaded.
+    if config.trust_etags && entry1.etag.is_some() && entry1.etag == entry2.etag {
+        return Ok(ComparisonResult {
+            size1,
+            size2,
+            modified1: time1_str,
+            modified2: time2_str,
+            ..ComparisonResult::new(rel_path, Status::Match)
+        });
+    }
 
-    let (status, h1, h2) = match (h1_res, h2_res) {
-        (Ok(h1), Ok(h2)) => {
-            let is_match = match config.algo {
-                HashAlgo::Sha256 => h1.sha256 == h2.sha256,
-                HashAlgo::Blake3 => h1.blake3 == h2.blake3,
-                HashAlgo::Both => h1.sha256 == h2.sha256 && h1.blake3 == h2.blake3,
-            };
-            (
-                if is_match {
-                    Status::Match
+    // --quick: on-disk pairs longer than the two sampled ends only have those
+    // ends compared. Their digests cover part of the file, so they are not
+    // reported or cached.
+    if let Some(n) = config.quick
+        && entry1.on_disk()
+        && entry2.on_disk()
+        && entry1.size > n.saturating_mul(2)
+    {
+        let (q1, q2) = rayon::join(
+            || quick_hashes(&entry1.path, entry1.size, n, config.algo),
+            || quick_hashes(&entry2.path, entry2.size, n, config.algo),
+        );
+        let status = match (&q1, &q2) {
+            (Ok(h1), Ok(h2)) if h1.matches(h2, config.algo) => Status::QuickMatch,
+            (Ok(_), Ok(_)) => Status::Diff,
+            (Err(e), _) | (_, Err(e)) => {
+                let side = if q1.is_err() { "folder1" } else { "folder2" };
+                return Ok(ComparisonResult {
+                    size1,
+                    size2,
+                    modified1: time1_str,
+                    modified2: time2_str,
+                    ..ComparisonResult::failed(rel_path, side, e)
+                });
+            }
+        };
+        let status = unless_changed(status, entry1, entry2);
+        let block_diff = if status == Status::Diff {
+            block_diff_for(entry1, entry2, config)
+        } else {
+            None
+        };
+        return Ok(ComparisonResult {
+            size1,
+            size2,
+            modified1: time1_str,
+            modified2: time2_str,
+            block_diff,
+            ..ComparisonResult::new(rel_path, status)
+        });
+    }
+
+    // --mode bytes: on-disk pairs are read side by side up to the first
+    // differing byte. Snapshot and remote entries only have digests and are
+    // hashed as usual.
+    if config.mode == Mode::Bytes && entry1.on_disk() && entry2.on_disk() {
+        let first_diff = match first_difference(&entry1.path, &entry2.path) {
+            Ok(first_diff) => first_diff,
+            Err(e) => {
+                let side = if sandbox::open(&entry1.path).is_err() {
+                    "folder1"
                 } else {
-                    Status::Diff
-                },
-                Some(h1),
-                Some(h2),
-            )
-        }
-        _ => (Status::Error, None, None),
+                    "folder2"
+                };
+                return Ok(ComparisonResult {
+                    size1,
+                    size2,
+                    modified1: time1_str,
+                    modified2: time2_str,
+                    ..ComparisonResult::failed(rel_path, side, &e)
+                });
+            }
+        };
+        let status = match first_diff {
+            Some(_) => Status::Diff,
+            None => Status::Match,
+        };
+        let (status, block_diff) = match unless_changed(status, entry1, entry2) {
+            Status::Diff => (Status::Diff, block_diff_for(entry1, entry2, config)),
+            status => (status, None),
+        };
+        return Ok(ComparisonResult {
+            size1,
+            size2,
+            modified1: time1_str,
+            modified2: time2_str,
+            block_diff,
+            first_diff,
+            ..ComparisonResult::new(rel_path, status)
+        });
+    }
+
+    // Snapshot-backed entries carry their recorded digest instead of a readable file.
+    let hash = |entry: &FileEntry| match &entry.hashes {
+        Some(h) => Ok((h.clone(), None)),
+        None => match rules
+            .hash_cache
+            .as_ref()
+            .filter(|c| c.algo() == config.algo)
+            .and_then(|c| c.lookup(entry))
+        {
+            Some(h) => Ok((h, None)),
+            None if is_s3(&entry.path) => {
+                object_hashes(&entry.path, config.algo).map(|h| (h, None))
+            }
+            None => compute_hashes_noting(&entry.path, config.algo, config.read_ahead),
+        },
+    };
+    let (h1_res, h2_res) = rayon::join(|| hash(entry1), || hash(entry2));
+
+    let failure = match (&h1_res, &h2_res) {
+        (Err(e), _) => Some(("folder1", e)),
+        (_, Err(e)) => Some(("folder2", e)),
+        _ => None,
+    };
+    if let Some((side, e)) = failure {
+        return Ok(ComparisonResult {
+            size1,
+            size2,
+            modified1: time1_str,
+            modified2: time2_str,
+            ..ComparisonResult::failed(rel_path, side, e)
+        });
+    }
+    let ((h1, note1), (h2, note2)) = (h1_res?, h2_res?);
+    let read_fallback = [("folder1", note1), ("folder2", note2)]
+        .into_iter()
+        .filter_map(|(side, note)| Some(format!("{}: {}", side, note?)))
+        .reduce(|a, b| format!("{}; {}", a, b));
+    let status = if h1.matches(&h2, config.algo) {
+        Status::Match
+    } else {
+        Status::Diff
+    };
+    let status = unless_changed(status, entry1, entry2);
+
+    let block_diff = if status == Status::Diff {
+        block_diff_for(entry1, entry2, config)
+    } else {
+        None
     };
 
     Ok(ComparisonResult {
-        file: rel_path,
-        status,
-        hash1: h1,
-        hash2: h2,
+        hash1: Some(h1),
+        hash2: Some(h2),
         size1,
         size2,
         modified1: time1_str,
         modified2: time2_str,
-        symlink1: None,
-        symlink2: None,
+        block_diff,
+        read_fallback,
+        ..ComparisonResult::new(rel_path, status)
+    })
+}
+
+/// Whether a file read from disk no longer has the size or modification time
+/// the walk saw, i.e. it was written to while being compared.
+fn changed_since_walk(entry: &FileEntry) -> bool {
+    entry.on_disk()
+        && entry.symlink_target.is_none()
+        && fs::metadata(&entry.path).is_ok_and(|m| {
+            m.len() != entry.size
+                || (entry.modified.is_some() && m.modified().ok() != entry.modified)
+        })
+}
+
+/// `status`, unless either file changed while it was read.
+fn unless_changed(status: Status, entry1: &FileEntry, entry2: &FileEntry) -> Status {
+    if changed_since_walk(entry1) || changed_since_walk(entry2) {
+        Status::Unstable
+    } else {
+        status
+    }
+}
+
+fn format_mtime(modified: Option<SystemTime>) -> Option<String> {
+    modified.map(|t| {
+        DateTime::<Local>::from(t)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    })
+}
+
+/// Chunk-level breakdown for a differing pair, when `--block-diff` is on and
+/// at least one side spans more than a single block. Failures to read are not
+/// fatal here: the file is already known to differ, so the breakdown is dropped.
+fn block_diff_for(
+    entry1: &FileEntry,
+    entry2: &FileEntry,
+    config: &CompareConfig,
+) -> Option<BlockDiff> {
+    if !config.block_diff
+        || config.mode == Mode::Metadata
+        || !entry1.on_disk()
+        || !entry2.on_disk()
+        || entry1.size.max(entry2.size) <= config.block_size
+    {
+        return None;
+    }
+    diff_blocks(&entry1.path, &entry2.path, config.block_size).ok()
+}
+
+/// `--vss`: point the folders on disk at shadow copies of their volumes.
+fn read_from_shadows(config: &mut CompareConfig) -> Result<Shadows> {
+    let on_disk = |f: &PathBuf| {
+        f != Path::new("-") && !is_snapshot_file(f) && !is_remote(f) && !is_ssh(f) && !is_s3(f)
+    };
+    let folders: Vec<PathBuf> = [&config.folder1, &config.folder2]
+        .into_iter()
+        .chain(&config.replicas)
+        .filter(|f| on_disk(f))
+        .cloned()
+        .collect();
+    let shadows = Shadows::create(&folders)?;
+    for folder in [&mut config.folder1, &mut config.folder2]
+        .into_iter()
+        .chain(config.replicas.iter_mut())
+    {
+        if on_disk(folder) {
+            *folder = shadows.translate(folder);
+        }
+    }
+    Ok(shadows)
+}
+
+/// One side of a comparison: a live folder, or a snapshot file or stdin archive
+/// standing in for one.
+pub(crate) struct Side {
+    pub(crate) files: Vec<FileEntry>,
+    pub(crate) errors: Vec<ErrorEntry>,
+    /// Prefix stripped from `files` paths to get relative paths.
+    pub(crate) root: PathBuf,
+    /// Entries come from a snapshot file or stdin; nothing under `root` is read.
+    from_snapshot: bool,
+    /// Metadata of the root itself, read from disk or recorded in the snapshot.
+    root_attrs: Option<RootAttrs>,
+}
+
+impl Side {
+    /// The folder on disk, unless this side is a snapshot.
+    fn live_root(&self) -> Option<&Path> {
+        (!self.from_snapshot).then_some(self.root.as_path())
+    }
+}
+
+/// A regular file passed where a folder is expected is read as a snapshot
+/// when it has a `.json` extension or starts with a JSON object.
+pub fn is_snapshot_file(path: &Path) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+    {
+        return true;
+    }
+    let mut head = [0u8; 64];
+    File::open(path)
+        .and_then(|mut f| f.read(&mut head))
+        .is_ok_and(|n| head[..n].iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{'))
+}
+
+/// Read every side given as a snapshot file.
+fn load_snapshot_sides(config: &CompareConfig) -> Result<LoadedSnapshots> {
+    let mut snapshots = LoadedSnapshots::new();
+    for path in [&config.folder1, &config.folder2]
+        .into_iter()
+        .chain(&config.replicas)
+    {
+        if path != Path::new("-") && is_snapshot_file(path) && !snapshots.contains_key(path) {
+            snapshots.insert(path.clone(), Arc::new(load_snapshot(path)?));
+        }
+    }
+    Ok(snapshots)
+}
+
+/// Make `--algo` one that the snapshot sides record digests for, so their
+/// entries are not compared on digests they lack. A snapshot made with
+/// `both` covers `sha256` and `blake3`; otherwise the recorded algorithm is
+/// used, with a warning, unless `--force-algo` asks to fail instead.
+pub(crate) fn negotiate_algo(
+    config: &mut CompareConfig,
+    snapshots: &LoadedSnapshots,
+) -> Result<()> {
+    if config.mode == Mode::Metadata {
+        return Ok(());
+    }
+    let recorded: Vec<(PathBuf, HashAlgo)> = [&config.folder1, &config.folder2]
+        .into_iter()
+        .chain(&config.replicas)
+        .filter_map(|path| Some((path.clone(), snapshots.get(path)?.algo)))
+        .collect();
+    let Some((path, stored)) = recorded
+        .iter()
+        .find(|(_, stored)| !stored.covers(config.algo))
+    else {
+        return Ok(());
+    };
+    if config.force_algo {
+        anyhow::bail!(
+            "{} records {:?} digests, not {:?} (--force-algo); drop --force-algo to compare with {:?}",
+            path.display(),
+            stored,
+            config.algo,
+            stored
+        );
+    }
+    // The narrowest recorded algorithm every snapshot side covers.
+    let Some(algo) = recorded
+        .iter()
+        .map(|(_, stored)| *stored)
+        .filter(|&a| recorded.iter().all(|(_, stored)| stored.covers(a)))
+        .min_by_key(|&a| a == HashAlgo::Both)
+    else {
+        anyhow::bail!(
+            "{} record digests of different algorithms ({}) and cannot be compared by \
+             content; use --mode metadata or snapshot them again with one --algo",
+            recorded
+                .iter()
+                .map(|(p, _)| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            recorded
+                .iter()
+                .map(|(_, a)| format!("{:?}", a).to_lowercase())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    };
+    eprintln!(
+        "{} {} records {:?} digests; comparing with --algo {} instead of {} (--force-algo keeps --algo)",
+        "Warning:".yellow(),
+        path.display(),
+        stored,
+        format!("{:?}", algo).to_lowercase(),
+        format!("{:?}", config.algo).to_lowercase()
+    );
+    config.algo = algo;
+    Ok(())
+}
+
+pub(crate) fn scan_side(
+    path: &Path,
+    config: &CompareConfig,
+    snapshots: &LoadedSnapshots,
+) -> Result<Side> {
+    if path == Path::new("-") {
+        let (files, errors) = match config.stdin_format {
+            StdinFormat::Tar => read_tar(io::stdin().lock(), path, config)?,
+        };
+        return Ok(Side {
+            files,
+            errors,
+            root: path.to_path_buf(),
+            from_snapshot: true,
+            root_attrs: None,
+        });
+    }
+    if is_s3(path) {
+        return Ok(Side {
+            files: read_s3(path, config)?,
+            errors: Vec::new(),
+            root: path.to_path_buf(),
+            from_snapshot: true,
+            root_attrs: None,
+        });
+    }
+    if is_ssh(path) {
+        let (files, errors) = read_ssh(path, config)?;
+        return Ok(Side {
+            files,
+            errors,
+            root: path.to_path_buf(),
+            from_snapshot: true,
+            root_attrs: None,
+        });
+    }
+    if is_remote(path) {
+        let (files, errors) = read_remote(path, config)?;
+        return Ok(Side {
+            files,
+            errors,
+            root: path.to_path_buf(),
+            from_snapshot: true,
+            root_attrs: None,
+        });
+    }
+    if !is_snapshot_file(path) {
+        let (files, mut errors) = collect_files(
+            path,
+            config.depth,
+            config.no_recursive,
+            config.hidden,
+            &config.types,
+            &config.ignore,
+            config.symlinks,
+            config.owner_filter,
+            config.one_file_system,
+        )?;
+        let files = match config.archives {
+            ArchiveMode::File => files,
+            ArchiveMode::Recurse => expand_archives(files, path, config)?,
+        };
+        let root_attrs = config
+            .root_check()
+            .then(|| read_root_attributes(path, &mut errors))
+            .flatten();
+        return Ok(Side {
+            files,
+            errors,
+            root: path.to_path_buf(),
+            from_snapshot: false,
+            root_attrs,
+        });
+    }
+
+    let snapshot = match snapshots.get(path) {
+        Some(snapshot) => Arc::clone(snapshot),
+        None => Arc::new(load_snapshot(path)?),
+    };
+    if config.mode != Mode::Metadata && !snapshot.algo.covers(config.algo) {
+        anyhow::bail!(
+            "{} records {:?} digests; compare it with --algo {}",
+            path.display(),
+            snapshot.algo,
+            format!("{:?}", snapshot.algo).to_lowercase()
+        );
+    }
+    Ok(Side {
+        files: snapshot.entries(),
+        errors: Vec::new(),
+        root: PathBuf::from(&snapshot.root_path),
+        from_snapshot: true,
+        root_attrs: snapshot.root.clone(),
     })
 }
 
-fn run_realtime(config: &CompareConfig, start_time: Instant) -> Result<ExitStatus> {
-    if io::stdout().is_terminal() {
+fn run_realtime(config: &CompareConfig, rules: &Rules, start_time: Instant) -> Result<ExitStatus> {
+    let line_based = config.output_format.is_line_based();
+    if io::stdout().is_terminal() && !line_based {
         println!(
             "{}",
             "==============================================".bright_blue()
@@ -206,140 +1188,538 @@ fn run_realtime(config: &CompareConfig, start_time: Instant) -> Result<ExitStatu
         );
     }
 
-    let (mut files1, errors1) = collect_files(
-        &config.folder1,
-        config.depth,
-        config.no_recursive,
-        config.hidden,
-        &config.types,
-        &config.ignore,
-        config.symlinks,
-    )?;
+    let Side {
+        files: mut files1,
+        errors: errors1,
+        root: root1,
+        ..
+    } = scan_side(&config.folder1, config, &rules.snapshots)?;
 
-    for e in &errors1 {
-        print_error_entry(e, "folder1");
-    }
+    print_walk_errors(&errors1, "folder1", config)?;
 
-    let (files2, errors2) = collect_files(
-        &config.folder2,
-        config.depth,
-        config.no_recursive,
-        config.hidden,
-        &config.types,
-        &config.ignore,
-        config.symlinks,
-    )?;
+    let Side {
+        files: files2,
+        errors: errors2,
+        root: root2,
+        ..
+    } = scan_side(&config.folder2, config, &rules.snapshots)?;
 
-    for e in &errors2 {
-        print_error_entry(e, "folder2");
-    }
+    print_walk_errors(&errors2, "folder2", config)?;
 
     if !config.no_sort {
         files1.sort_by(|a, b| a.path.cmp(&b.path));
     }
 
-    let mut files2_map: HashMap<PathBuf, FileEntry> = files2
-        .into_iter()
-        .map(|f| {
-            let rel = f.path.strip_prefix(&config.folder2).unwrap().to_path_buf();
-            (rel, f)
-        })
-        .collect();
+    let (mut files2_map, out_of_tree2) = key_by_relative_path(files2, &root2);
+
+    // Links facing a real directory are reported once, where the first path
+    // under them comes up; the folder2 entries involved are set aside so they
+    // are not listed as EXTRA.
+    let type_mismatches = if config.symlinks == SymlinkMode::Compare {
+        link_dir_mismatches(
+            files1
+                .iter()
+                .filter_map(|e| Some((e.path.strip_prefix(&root1).ok()?, e))),
+            files2_map.iter().map(|(p, e)| (p.as_path(), e)),
+        )
+    } else {
+        Vec::new()
+    };
+    let mut links2: HashMap<PathBuf, FileEntry> = HashMap::new();
+    for link in &type_mismatches {
+        if let Some(entry2) = files2_map.remove(link) {
+            links2.insert(link.clone(), entry2);
+        }
+        files2_map.retain(|p, _| !p.starts_with(link));
+    }
+    let mut reported_mismatches: HashSet<&Path> = HashSet::new();
+
+    // With --interleave, the paths only in folder2 are found before anything
+    // is printed, so each can be printed just before the first folder1 path
+    // sorting after it.
+    let mut pending_extra: VecDeque<PathBuf> = VecDeque::new();
+    if config.interleave {
+        let rel1: HashSet<&Path> = files1
+            .iter()
+            .filter_map(|e| e.path.strip_prefix(&root1).ok())
+            .collect();
+        let mut only2: Vec<PathBuf> = files2_map
+            .keys()
+            .filter(|p| !rel1.contains(p.as_path()))
+            .cloned()
+            .collect();
+        only2.sort();
+        pending_extra = only2.into();
+    }
+    let mut extra = 0;
 
     let mut matches = 0;
+    let mut quick_matches = 0;
+    let mut unstable = 0;
     let mut diffs = 0;
+    let mut allowed_diffs = 0;
     let mut missing = 0;
+    let mut hash_errors = 0;
+    let mut permission_denied = count_permission_denied(&errors1, &errors2);
+    let mut out_of_tree = out_of_tree2.len();
+    let mut transfer_bytes = 0;
+    let mut sample_confidence = SampleConfidence::default();
+
+    for entry1 in &files1 {
+        let rel_path = match entry1.path.strip_prefix(&root1) {
+            Ok(rel) => rel.to_path_buf(),
+            Err(_) => {
+                out_of_tree += 1;
+                print_one_sided(Status::OutOfTree, &entry1.path, None, config)?;
+                continue;
+            }
+        };
+
+        while pending_extra.front().is_some_and(|p| *p < rel_path)
+            && let Some(path) = pending_extra.pop_front()
+        {
+            if let Some(entry2) = files2_map.remove(&path) {
+                extra += 1;
+                print_one_sided(Status::Extra, &path, Some(entry2.size), config)?;
+            }
+        }
+
+        if let Some(link) = type_mismatches.iter().find(|l| rel_path.starts_with(l)) {
+            if reported_mismatches.insert(link) {
+                diffs += 1;
+                let result = ComparisonResult {
+                    symlink1: entry1.symlink_target.clone().filter(|_| rel_path == *link),
+                    symlink2: links2.get(link).and_then(|e| e.symlink_target.clone()),
+                    severity: config.severity_of(Status::TypeMismatch),
+                    ..ComparisonResult::new(link.clone(), Status::TypeMismatch)
+                };
+                print_live_result(&result, config)?;
+            }
+            continue;
+        }
+
+        if let Some(entry2) = files2_map.remove(&rel_path) {
+            let result = compare_files_core(rel_path.clone(), entry1, &entry2, config, rules)?;
+            rules.tally_sample(
+                &mut sample_confidence,
+                &rel_path,
+                (entry1, &entry2),
+                result.status,
+                config.size_only,
+            );
+
+            match result.status {
+                Status::Match => matches += 1,
+                Status::QuickMatch => quick_matches += 1,
+                Status::Diff => {
+                    diffs += 1;
+                    transfer_bytes += entry1.size;
+                }
+                Status::AllowedDiff => allowed_diffs += 1,
+                Status::Unstable => unstable += 1,
+                Status::Error => {
+                    hash_errors += 1;
+                    permission_denied += usize::from(result.permission_denied());
+                }
+                _ => (),
+            }
+
+            print_live_result(&result, config)?;
+
+            if result.status == Status::Diff
+                && let Some(mut command) = diff_command(&rel_path, config)
+            {
+                let _ = command.spawn();
+            }
+        } else {
+            missing += 1;
+            transfer_bytes += entry1.size;
+            print_one_sided(Status::Missing, &rel_path, Some(entry1.size), config)?;
+        }
+    }
+
+    extra += files2_map.len();
+    // Sorted even under --no-sort so the EXTRA section is stable between runs.
+    let mut sorted_extra: Vec<_> = files2_map.into_iter().collect();
+    sorted_extra.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (rel_path, entry2) in sorted_extra {
+        print_one_sided(Status::Extra, &rel_path, Some(entry2.size), config)?;
+    }
+    for path in &out_of_tree2 {
+        print_one_sided(Status::OutOfTree, path, None, config)?;
+    }
+
+    let elapsed = start_time.elapsed();
+    let total = files1.len() + extra + out_of_tree2.len();
+    let total_errors = errors1.len() + errors2.len();
+
+    let report_conf = config.report_config(rules);
+
+    let severities = config.severities.unwrap_or_default();
+    let mut summary_data = SummaryData {
+        total,
+        matches,
+        quick_matches,
+        diffs,
+        unstable,
+        missing,
+        extra,
+        errors: total_errors,
+        hash_errors,
+        permission_denied,
+        out_of_tree,
+        allowed_diffs,
+        transfer_bytes,
+        elapsed,
+        resource_usage: config.resource_usage.then(ResourceUsage::measure).flatten(),
+        sample_confidence: (rules.sample.is_some() && config.mode != Mode::Metadata)
+            .then_some(sample_confidence),
+        previous: rules.previous.clone(),
+        ..Default::default()
+    };
+    summary_data.warnings = severities.count(&summary_data, Severity::Warning);
+
+    match config.output_format {
+        OutputFormat::Ndjson => print!("{}", ndjson_summary_line(&summary_data)?),
+        OutputFormat::Porcelain => (),
+        _ => {
+            for line in generate_summary_text(&summary_data, &report_conf) {
+                println!("{}", line);
+            }
+        }
+    }
+
+    let status = summary_data.exit_status(&severities, config.permission_exit);
+    if config.status_line {
+        print_status_line(&status, &summary_data.status_fields());
+    }
+    Ok(status)
+}
+
+/// `--diff-cmd` run on the two sides of `rel_path`, announced on stderr.
+fn diff_command(rel_path: &Path, config: &CompareConfig) -> Option<std::process::Command> {
+    let diff_cmd_str = config.diff_cmd.as_ref()?;
+    // Fix #11: use shlex-style splitting to support paths-with-spaces in diff_cmd
+    let parts = split_command(diff_cmd_str);
+    let (command, args) = parts.split_first()?;
+    let file1_path = config.folder1.join(rel_path);
+    let file2_path = config.folder2.join(rel_path);
+
+    eprintln!(
+        "Launching diff: {} {} {}",
+        diff_cmd_str,
+        file1_path.display(),
+        file2_path.display()
+    );
+
+    let mut command = std::process::Command::new(command);
+    command.args(args).arg(&file1_path).arg(&file2_path);
+    Some(command)
+}
+
+/// After a batch report, offer each DIFF result in turn for `--diff-cmd`,
+/// asking on `input` and waiting for the tool to close before the next one.
+/// Prompts go to stderr, so a report printed on stdout stays clean. Returns
+/// how many diffs were opened.
+pub(crate) fn review_diffs(
+    results: &[ComparisonResult],
+    config: &CompareConfig,
+    mut input: impl BufRead,
+) -> Result<usize> {
+    let diffs: Vec<&ComparisonResult> = results
+        .iter()
+        .filter(|r| r.status == Status::Diff)
+        .collect();
+    let mut opened = 0;
+    for (n, result) in diffs.iter().enumerate() {
+        let answer = loop {
+            eprint!(
+                "Open diff {}/{} {}? {} yes, {} next, {} quit: ",
+                n + 1,
+                diffs.len(),
+                result.file.display(),
+                "[y]".bold(),
+                "[n]".bold(),
+                "[q]".bold()
+            );
+            io::stderr().flush()?;
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                eprintln!();
+                return Ok(opened);
+            }
+            match answer.trim().to_ascii_lowercase().as_str() {
+                "y" | "yes" | "" => break true,
+                "n" | "no" | "next" => break false,
+                "q" | "quit" => return Ok(opened),
+                other => eprintln!("Unknown answer {:?}", other),
+            }
+        };
+        if answer && let Some(mut command) = diff_command(&result.file, config) {
+            match command.status() {
+                Ok(_) => opened += 1,
+                Err(e) => eprintln!("{} could not run --diff-cmd: {}", "Warning:".yellow(), e),
+            }
+        }
+    }
+    Ok(opened)
+}
+
+/// Print a result as soon as it is known, in realtime mode and under
+/// `--stream`: its text line, or its JSON or porcelain line.
+pub(crate) fn print_live_result(result: &ComparisonResult, config: &CompareConfig) -> Result<()> {
+    let line = match config.output_format {
+        OutputFormat::Ndjson => ndjson_line(result)?,
+        OutputFormat::Porcelain => porcelain_line(result),
+        _ => result.format_text(config.verbose, config.algo)?,
+    };
+    io::stdout().lock().write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// [`print_live_result`] for a path found on one side only, with its size on
+/// that side when known.
+pub(crate) fn print_one_sided(
+    status: Status,
+    path: &Path,
+    size: Option<u64>,
+    config: &CompareConfig,
+) -> Result<()> {
+    let severity = config.severity_of(status);
+    if !config.output_format.is_line_based() {
+        return print_realtime_missing(status, path, severity);
+    }
+    let (size1, size2) = if status == Status::Extra {
+        (None, size)
+    } else {
+        (size, None)
+    };
+    print_live_result(
+        &ComparisonResult {
+            size1,
+            size2,
+            severity,
+            ..ComparisonResult::new(path.to_path_buf(), status)
+        },
+        config,
+    )
+}
+
+/// Walk errors of a run that prints as it goes: on stderr, or as `ERROR`
+/// lines on stdout under ndjson and porcelain output.
+pub(crate) fn print_walk_errors(
+    errors: &[ErrorEntry],
+    source: &str,
+    config: &CompareConfig,
+) -> Result<()> {
+    if !config.output_format.is_line_based() {
+        print_error_entries(errors, source);
+        return Ok(());
+    }
+    for e in errors {
+        print_live_result(&error_result(e, source), config)?;
+    }
+    Ok(())
+}
 
-    for entry1 in &files1 {
-        let rel_path = entry1.path.strip_prefix(&config.folder1)?.to_path_buf();
+/// Everything one batch comparison found, as returned by [`compare_folders`].
+pub struct Comparison {
+    /// One result per path, sorted by path unless `no_sort` is set.
+    pub results: Vec<ComparisonResult>,
+    /// Entries of folder1 that could not be read.
+    pub errors1: Vec<ErrorEntry>,
+    /// Entries of folder2 that could not be read.
+    pub errors2: Vec<ErrorEntry>,
+    pub summary: SummaryData,
+    /// The verdict the command line would exit with.
+    pub status: ExitStatus,
+}
 
-        if let Some(entry2) = files2_map.remove(&rel_path) {
-            let result = compare_files_core(rel_path.clone(), entry1, &entry2, config)?;
+// Built once per run, so the size of `Compared` does not matter.
+#[allow(clippy::large_enum_variant)]
+enum Batch {
+    /// `--precheck-threshold` was exceeded; nothing was compared.
+    Stopped(ExitStatus),
+    /// Compared, with the rules after `--auto-mtime-skew` calibration.
+    Compared(Comparison, Rules),
+}
 
-            match result.status {
-                Status::Match => matches += 1,
-                Status::Diff => diffs += 1,
-                _ => (),
-            }
+/// Compare two folders, snapshots or a stdin archive and return every result
+/// without printing anything or writing a report.
+///
+/// `config` is read as for a batch run, except that reports, `--precheck`,
+/// `--stream` and `--baseline-report` are left to the caller, and the rayon
+/// thread pool is not configured. Files the configuration names are still
+/// written: the `verdict_cache` and `hash_cache` are saved with this run's
+/// digests, `emit_manifest` is written, and `probe_fs` creates and removes a
+/// scratch file in each folder unless `assert_readonly` is set.
+pub fn compare_folders(config: &CompareConfig) -> Result<Comparison> {
+    if config.folder1 == Path::new("-") && config.folder2 == Path::new("-") {
+        anyhow::bail!("Only one side can be read from stdin");
+    }
+    let mut rules = Rules::new(config)?;
+    if config.probe_fs {
+        rules.mtime_tolerance = probe_filesystems(config, true);
+    }
+    match compare_batch(config, &rules, Instant::now(), true)? {
+        Batch::Compared(comparison, _) => Ok(comparison),
+        Batch::Stopped(_) => unreachable!("quiet comparisons skip the precheck"),
+    }
+}
 
-            print!("{}", result.format_text(config.verbose, config.algo)?);
+fn run_batch(config: &CompareConfig, rules: &Rules, start_time: Instant) -> Result<ExitStatus> {
+    let (comparison, rules) = match compare_batch(config, rules, start_time, false)? {
+        Batch::Stopped(status) => return Ok(status),
+        Batch::Compared(comparison, rules) => (comparison, rules),
+    };
+    let Comparison {
+        results: all_results,
+        errors1,
+        errors2,
+        summary: summary_data,
+        status,
+    } = comparison;
+    let report_conf = config.report_config(&rules);
 
-            // Fix #11: use shlex-style splitting to support paths-with-spaces in diff_cmd
-            if let Some(diff_cmd_str) = &config.diff_cmd
-                && result.status == Status::Diff
-            {
-                let parts = split_command(diff_cmd_str);
-                if let Some((command, args)) = parts.split_first() {
-                    let file1_path = config.folder1.join(&rel_path);
-                    let file2_path = config.folder2.join(&rel_path);
-
-                    eprintln!(
-                        "Launching diff: {} {} {}",
-                        diff_cmd_str,
-                        file1_path.display(),
-                        file2_path.display()
-                    );
-
-                    let _ = std::process::Command::new(command)
-                        .args(args)
-                        .arg(&file1_path)
-                        .arg(&file2_path)
-                        .spawn();
-                }
-            }
-        } else {
-            missing += 1;
-            print_realtime_missing(Status::Missing, &rel_path, config.verbose)?;
+    if let Some(baseline) = &config.baseline_report {
+        if let Some(dir) = &config.emit_list_dir {
+            write_status_lists(&all_results, dir)?;
         }
+        return report_drift(
+            baseline,
+            &all_results,
+            &summary_data,
+            config,
+            &report_conf,
+            start_time,
+        );
     }
 
-    let extra = files2_map.len();
-    let mut sorted_extra: Vec<_> = files2_map.into_keys().collect();
-    if !config.no_sort {
-        sorted_extra.sort();
+    match config.output_format {
+        OutputFormat::Txt => {
+            let output = generate_text_report(
+                &all_results,
+                &errors1,
+                &errors2,
+                &summary_data,
+                &report_conf,
+            )?;
+            write_report(
+                output,
+                &config.output_folder,
+                "report.txt",
+                config.report_checksum,
+            )?;
+        }
+        OutputFormat::Json => {
+            let output = generate_json_report(
+                &all_results,
+                &errors1,
+                &errors2,
+                &summary_data,
+                &report_conf,
+            )?;
+            write_report(
+                output,
+                &config.output_folder,
+                "report.json",
+                config.report_checksum,
+            )?;
+        }
+        OutputFormat::CodeQuality => {
+            let output = generate_code_quality_report(
+                &all_results,
+                &errors1,
+                &errors2,
+                (&config.folder1, &config.folder2),
+            )?;
+            write_report(
+                output,
+                &config.output_folder,
+                "gl-code-quality-report.json",
+                config.report_checksum,
+            )?;
+        }
+        OutputFormat::Html => {
+            let output = generate_html_report(
+                &all_results,
+                &errors1,
+                &errors2,
+                &summary_data,
+                &report_conf,
+            );
+            write_report(
+                output,
+                &config.output_folder,
+                "report.html",
+                config.report_checksum,
+            )?;
+        }
+        OutputFormat::Ndjson => {
+            let output = generate_ndjson_report(&all_results, &errors1, &errors2, &summary_data)?;
+            write_report(
+                output,
+                &config.output_folder,
+                "report.ndjson",
+                config.report_checksum,
+            )?;
+        }
+        OutputFormat::Porcelain => {
+            let output = generate_porcelain_report(&all_results, &errors1, &errors2);
+            write_report(
+                output,
+                &config.output_folder,
+                "report.porcelain",
+                config.report_checksum,
+            )?;
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let (separator, filename) = if config.output_format == OutputFormat::Csv {
+                (',', "report.csv")
+            } else {
+                ('\t', "report.tsv")
+            };
+            let output = generate_csv_report(&all_results, &errors1, &errors2, separator);
+            write_report(
+                output,
+                &config.output_folder,
+                filename,
+                config.report_checksum,
+            )?;
+        }
     }
 
-    for rel_path in sorted_extra {
-        print_realtime_missing(Status::Extra, &rel_path, config.verbose)?;
+    if let Some(dir) = &config.emit_list_dir {
+        write_status_lists(&all_results, dir)?;
     }
 
-    let elapsed = start_time.elapsed();
-    let total = files1.len() + extra;
-    let total_errors = errors1.len() + errors2.len();
-
-    let report_conf = ReportConfig {
-        mode: config.mode,
-        algo: config.algo,
-        threads: config.threads,
-        verbose: config.verbose,
-    };
-
-    let summary_data = SummaryData {
-        total,
-        matches,
-        diffs,
-        missing,
-        extra,
-        errors: total_errors,
-        elapsed,
-    };
-
-    let summary_lines = generate_summary_text(&summary_data, &report_conf);
-    for line in summary_lines {
-        println!("{}", line);
+    if config.diff_cmd.is_some() && status == ExitStatus::Diff {
+        if io::stdin().is_terminal() {
+            review_diffs(&all_results, config, io::stdin().lock())?;
+        } else {
+            eprintln!(
+                "{} stdin is not a terminal, so --diff-cmd is not offered for the differences",
+                "Note:".yellow()
+            );
+        }
     }
 
-    if total_errors > 0 {
-        Ok(ExitStatus::Error)
-    } else if diffs > 0 || missing > 0 || extra > 0 {
-        Ok(ExitStatus::Diff)
-    } else {
-        Ok(ExitStatus::Success)
+    if config.status_line {
+        print_status_line(&status, &summary_data.status_fields());
     }
+    Ok(status)
 }
 
-fn run_batch(config: &CompareConfig, start_time: Instant) -> Result<ExitStatus> {
-    if io::stdout().is_terminal() {
+/// Scan and compare both sides. With `quiet` nothing is printed: no banner,
+/// precheck, calibration notes or progress bar.
+fn compare_batch(
+    config: &CompareConfig,
+    rules: &Rules,
+    start_time: Instant,
+    quiet: bool,
+) -> Result<Batch> {
+    if !quiet && io::stdout().is_terminal() && config.output_format != OutputFormat::Porcelain {
         println!(
             "{}",
             "==============================================".bright_blue()
@@ -354,53 +1734,107 @@ fn run_batch(config: &CompareConfig, start_time: Instant) -> Result<ExitStatus>
 
     // Both folder scans run in parallel (already correct in original batch mode)
     let (res1, res2) = rayon::join(
-        || {
-            collect_files(
-                &config.folder1,
-                config.depth,
-                config.no_recursive,
-                config.hidden,
-                &config.types,
-                &config.ignore,
-                config.symlinks,
-            )
-        },
-        || {
-            collect_files(
-                &config.folder2,
-                config.depth,
-                config.no_recursive,
-                config.hidden,
-                &config.types,
-                &config.ignore,
-                config.symlinks,
-            )
-        },
+        || scan_side(&config.folder1, config, &rules.snapshots),
+        || scan_side(&config.folder2, config, &rules.snapshots),
     );
-    let (files1, errors1) = res1?;
-    let (files2, errors2) = res2?;
+    let mut side1 = res1?;
+    let mut side2 = res2?;
+    let (errors1, errors2) = (mem::take(&mut side1.errors), mem::take(&mut side2.errors));
 
     let total_errors = errors1.len() + errors2.len();
+    let root = match (side1.root_attrs.take(), side2.root_attrs.take()) {
+        (Some(a), Some(b)) => Some(RootCheck::new(a, b)),
+        _ => None,
+    };
 
     // Fix #4: build maps without double-cloning all keys
-    let files1_map: HashMap<PathBuf, FileEntry> = files1
-        .into_par_iter()
-        .map(|f| {
-            (
-                f.path.strip_prefix(&config.folder1).unwrap().to_path_buf(),
-                f,
-            )
-        })
-        .collect();
-    let files2_map: HashMap<PathBuf, FileEntry> = files2
-        .into_par_iter()
-        .map(|f| {
-            (
-                f.path.strip_prefix(&config.folder2).unwrap().to_path_buf(),
-                f,
-            )
-        })
-        .collect();
+    let (files1_map, out_of_tree1) = key_by_relative_path(mem::take(&mut side1.files), &side1.root);
+    let (files2_map, out_of_tree2) = key_by_relative_path(mem::take(&mut side2.files), &side2.root);
+
+    if !quiet && config.precheck {
+        let data = precheck(
+            &files1_map,
+            &files2_map,
+            side1.live_root(),
+            side2.live_root(),
+        );
+        // Keep a JSON report on stdout parseable.
+        let to_stderr = config.output_format.is_document() && config.output_folder.is_none();
+        for line in generate_precheck_text(&data) {
+            if to_stderr {
+                eprintln!("{}", line);
+            } else {
+                println!("{}", line);
+            }
+        }
+        if let Some(limit) = config.precheck_threshold
+            && data.divergence() > limit
+        {
+            eprintln!(
+                "{} divergence exceeds {}%; skipping content comparison",
+                "Precheck:".yellow(),
+                limit
+            );
+            // The precheck is all this run has to show, so it takes the report's place.
+            if config.output_folder.is_some() {
+                let (output, filename) = if config.output_format == OutputFormat::Json {
+                    (generate_precheck_json(&data, limit)?, "precheck.json")
+                } else {
+                    let mut text = generate_precheck_text(&data).join("\n");
+                    text.push('\n');
+                    (text, "precheck.txt")
+                };
+                write_report(
+                    output,
+                    &config.output_folder,
+                    filename,
+                    config.report_checksum,
+                )?;
+            }
+            let status = if total_errors > 0 {
+                ExitStatus::Error
+            } else {
+                ExitStatus::Diff
+            };
+            if config.status_line {
+                let data = SummaryData {
+                    errors: total_errors,
+                    elapsed: start_time.elapsed(),
+                    ..Default::default()
+                };
+                print_status_line(&status, &data.status_fields());
+            }
+            return Ok(Batch::Stopped(status));
+        }
+    }
+
+    // Calibration pass: find the offset before any mtime is compared.
+    let calibrated;
+    let rules = if config.auto_mtime_skew {
+        let skew = detect_mtime_skew(&files1_map, &files2_map);
+        let note = match skew {
+            Some(s) => format!(
+                "{} folder2 timestamps are offset by {:+}s; compensating",
+                "Mtime skew:".yellow(),
+                s
+            ),
+            None => format!("{} no constant offset detected", "Mtime skew:".bold()),
+        };
+        if !quiet {
+            if config.output_format.is_document() && config.output_folder.is_none() {
+                eprintln!("{}", note);
+            } else {
+                println!("{}", note);
+            }
+        }
+        calibrated = Rules {
+            mtime_skew: skew,
+            ..rules.clone()
+        };
+        &calibrated
+    } else {
+        rules
+    };
 
     // Fix #4: use reference sets to avoid cloning every key twice
     let set1_paths: HashSet<&PathBuf> = files1_map.keys().collect();
@@ -411,28 +1845,55 @@ fn run_batch(config: &CompareConfig, start_time: Instant) -> Result<ExitStatus>
         .map(|p| (*p).clone())
         .collect();
 
-    let pb = if io::stderr().is_terminal() {
+    let pb = if !quiet && io::stderr().is_terminal() {
         let pb = ProgressBar::new(common_paths.len() as u64);
         pb.set_style(
             ProgressStyle::default_bar()
-                .template("{spinner:.green} [Elap>{elapsed_precise}] [ {bar:40.cyan/blue} ] {pos}/{len} (Rema>{eta})")?
+                .template("{spinner:.green} [Elap>{elapsed_precise}] [ {bar:40.cyan/blue} ] {pos}/{len} (Rema>{eta}) {msg}")?
                 .progress_chars("#>- ")
         );
         pb.set_draw_target(indicatif::ProgressDrawTarget::stderr_with_hz(10));
-        Some(pb)
+        Some(activity::attach(pb))
     } else {
         None
     };
 
+    // Metadata mode never hashes, so there is nothing worth caching.
+    let cache = match &config.verdict_cache {
+        Some(path) if config.mode != Mode::Metadata => Some(VerdictCache::load(
+            path,
+            config.algo,
+            config.verdict_options(),
+        )?),
+        _ => None,
+    };
+
+    let live = LiveCounts::default();
     let mut all_results: Vec<ComparisonResult> = common_paths
         .par_iter()
         .map(|rel_path| {
+            let entry1 = files1_map.get(rel_path).unwrap();
+            let entry2 = files2_map.get(rel_path).unwrap();
+            // Only pairs whose content this run examines have a verdict to reuse.
+            let result = match cache
+                .as_ref()
+                .filter(|_| rules.hashes(rel_path))
+                .and_then(|c| c.lookup(rel_path, entry1, entry2))
+            {
+                Some(status) => ComparisonResult {
+                    size1: Some(entry1.size),
+                    size2: Some(entry2.size),
+                    modified1: format_mtime(entry1.modified),
+                    modified2: format_mtime(entry2.modified),
+                    ..ComparisonResult::new(rel_path.clone(), rules.settle(rel_path, status))
+                },
+                None => compare_files_core(rel_path.clone(), entry1, entry2, config, rules)?,
+            };
             if let Some(ref p) = pb {
                 p.inc(1);
+                live.record(result.status, p);
             }
-            let entry1 = files1_map.get(rel_path).unwrap();
-            let entry2 = files2_map.get(rel_path).unwrap();
-            compare_files_core(rel_path.clone(), entry1, entry2, config)
+            Ok(result)
         })
         .collect::<Result<Vec<_>>>()?;
 
@@ -440,13 +1901,243 @@ fn run_batch(config: &CompareConfig, start_time: Instant) -> Result<ExitStatus>
         p.finish_with_message("Comparison complete");
     }
 
+    let now = SystemTime::now();
+    let known_since = |r: &ComparisonResult| {
+        cache.as_ref().and_then(|c| {
+            c.since(
+                &r.file,
+                &files1_map[&r.file],
+                &files2_map[&r.file],
+                r.status,
+            )
+        })
+    };
+
+    if let (Some(days), Some(_)) = (config.ignore_known_diffs, &cache)
+        && let Some(cutoff) =
+            now.checked_sub(Duration::from_secs(days.saturating_mul(24 * 60 * 60)))
+    {
+        let mut downgraded = 0;
+        for r in &mut all_results {
+            if r.status == Status::Diff && known_since(r).is_some_and(|since| since <= cutoff) {
+                r.status = Status::AllowedDiff;
+                downgraded += 1;
+            }
+        }
+        if !quiet && downgraded > 0 {
+            let note = format!(
+                "{} {} difference(s) unchanged for over {} day(s) reported as ALLOWED_DIFF",
+                "Known diffs:".yellow(),
+                downgraded,
+                days
+            );
+            if config.output_format.is_document() && config.output_folder.is_none() {
+                eprintln!("{}", note);
+            } else {
+                println!("{}", note);
+            }
+        }
+    }
+
+    // Rebuilt from this run's verdicts so entries for vanished pairs drop out.
+    if let Some(path) = config.verdict_cache.as_ref().filter(|_| cache.is_some()) {
+        let mut fresh = VerdictCache::new(config.algo, config.verdict_options());
+        // Presence-only and unsampled matches are not content verdicts.
+        for r in all_results.iter().filter(|r| rules.hashes(&r.file)) {
+            fresh.record(
+                r.file.clone(),
+                &files1_map[&r.file],
+                &files2_map[&r.file],
+                r.status,
+                known_since(r).unwrap_or(now),
+            );
+        }
+        fresh.save(path)?;
+    }
+
+    // Digests read in this run join the ones already cached.
+    if let (Some(path), Some(cached)) = (&config.hash_cache, &rules.hash_cache) {
+        let mut cache = HashCache::clone(cached);
+        // Digests of subtrees hashed with another algorithm do not fit the cache.
+        for r in all_results.iter().filter(|r| {
+            r.status != Status::Unstable && rules.config_for(&r.file, config).algo == config.algo
+        }) {
+            for (map, hash) in [(&files1_map, &r.hash1), (&files2_map, &r.hash2)] {
+                if let (Some(entry), Some(hash)) = (map.get(&r.file), hash) {
+                    cache.record(entry, hash.clone());
+                }
+            }
+        }
+        cache.save(path)?;
+    }
+
+    if let Some(path) = &config.emit_manifest {
+        let known: HashMap<&Path, &HashResult> = all_results
+            .iter()
+            .filter(|r| {
+                r.status != Status::Unstable
+                    && rules.config_for(&r.file, config).algo == config.algo
+            })
+            .filter_map(|r| Some((r.file.as_path(), r.hash1.as_ref()?)))
+            .collect();
+        let hashed = write_manifest(
+            path,
+            &config.folder1,
+            &files1_map,
+            &known,
+            config.algo,
+            config.read_ahead,
+            SnapshotScanParams {
+                depth: config.depth,
+                no_recursive: config.no_recursive,
+                hidden: config.hidden,
+                types: config.types.clone(),
+                ignore: config.ignore.clone(),
+                symlinks: config.symlinks,
+                owner_filter: config.owner_filter,
+                one_file_system: config.one_file_system,
+            },
+        )?;
+        if !quiet {
+            eprintln!(
+                "Manifest of folder1 saved to {} ({} of {} files hashed for it)",
+                path.display(),
+                hashed,
+                files1_map.len()
+            );
+        }
+    }
+
+    // MISSING, EXTRA and OUT_OF_TREE sections are sorted even under --no-sort:
+    // they are usually small, and a stable order lets consumers correlate runs.
+    let mut missing_paths: Vec<&PathBuf> = set1_paths.difference(&set2_paths).copied().collect();
+    let mut extra_paths: Vec<&PathBuf> = set2_paths.difference(&set1_paths).copied().collect();
+    let mut out_of_tree_paths: Vec<PathBuf> =
+        out_of_tree1.into_iter().chain(out_of_tree2).collect();
+    missing_paths.sort();
+    extra_paths.sort();
+    out_of_tree_paths.sort();
+
+    // A link on one side and a real directory on the other would otherwise be
+    // a MISSING link plus an EXTRA entry per file in the directory (or the
+    // reverse); report it once instead.
+    let type_mismatches = if config.symlinks == SymlinkMode::Compare {
+        link_dir_mismatches(
+            files1_map.iter().map(|(p, e)| (p.as_path(), e)),
+            files2_map.iter().map(|(p, e)| (p.as_path(), e)),
+        )
+    } else {
+        Vec::new()
+    };
+    for link in &type_mismatches {
+        missing_paths.retain(|p| !p.starts_with(link));
+        extra_paths.retain(|p| !p.starts_with(link));
+    }
+
+    let renames = if config.detect_renames {
+        pair_renames(
+            &missing_paths,
+            &extra_paths,
+            &files1_map,
+            &files2_map,
+            config.algo,
+            config.read_ahead,
+        )
+    } else {
+        Vec::new()
+    };
+    if !renames.is_empty() {
+        let old: HashSet<&PathBuf> = renames.iter().map(|(old, _)| old).collect();
+        let new: HashSet<&PathBuf> = renames.iter().map(|(_, new)| new).collect();
+        missing_paths.retain(|p| !old.contains(p));
+        extra_paths.retain(|p| !new.contains(p));
+    }
+
+    let (mut moved_from1, mut moved_from2) = if config.relocated {
+        rayon::join(
+            || {
+                relocated_content(
+                    &missing_paths,
+                    &files1_map,
+                    &files2_map,
+                    config.algo,
+                    config.read_ahead,
+                )
+            },
+            || {
+                relocated_content(
+                    &extra_paths,
+                    &files2_map,
+                    &files1_map,
+                    config.algo,
+                    config.read_ahead,
+                )
+            },
+        )
+    } else {
+        Default::default()
+    };
+    let relocated = moved_from1.len() + moved_from2.len();
+
     // Fix #12: use constructor helpers instead of large None-filled struct literals
-    for rel_path in set1_paths.difference(&set2_paths) {
-        all_results.push(ComparisonResult::missing((*rel_path).clone()));
+    for rel_path in missing_paths {
+        all_results.push(ComparisonResult {
+            size1: Some(files1_map[rel_path].size),
+            relocated: moved_from1.remove(rel_path),
+            ..ComparisonResult::missing(rel_path.clone())
+        });
+    }
+
+    for rel_path in extra_paths {
+        all_results.push(ComparisonResult {
+            size2: Some(files2_map[rel_path].size),
+            relocated: moved_from2.remove(rel_path),
+            ..ComparisonResult::extra(rel_path.clone())
+        });
+    }
+
+    for (old, new) in renames {
+        all_results.push(ComparisonResult {
+            size1: Some(files1_map[&old].size),
+            size2: Some(files2_map[&new].size),
+            renamed_to: Some(new),
+            ..ComparisonResult::new(old, Status::Renamed)
+        });
+    }
+
+    for link in type_mismatches {
+        all_results.push(ComparisonResult {
+            symlink1: files1_map.get(&link).and_then(|e| e.symlink_target.clone()),
+            symlink2: files2_map.get(&link).and_then(|e| e.symlink_target.clone()),
+            ..ComparisonResult::new(link, Status::TypeMismatch)
+        });
     }
 
-    for rel_path in set2_paths.difference(&set1_paths) {
-        all_results.push(ComparisonResult::extra((*rel_path).clone()));
+    for path in out_of_tree_paths {
+        all_results.push(ComparisonResult::new(path, Status::OutOfTree));
+    }
+
+    // Also covers verdicts taken from the cache, which skip compare_files_core.
+    for r in &mut all_results {
+        r.severity = config.severity_of(r.status);
+    }
+
+    if config.detect_type {
+        // Snapshot and stdin sides have no file on disk and stay unknown.
+        let detect = |map: &HashMap<PathBuf, FileEntry>, side: &Side, file: &Path| {
+            map.get(file)
+                .filter(|e| !side.from_snapshot && e.symlink_target.is_none())
+                .and_then(|e| filetype::detect(&e.path))
+                .map(str::to_string)
+        };
+        all_results.par_iter_mut().for_each(|r| {
+            r.mime_type1 = detect(&files1_map, &side1, &r.file);
+            r.mime_type2 = detect(
+                &files2_map,
+                &side2,
+                r.renamed_to.as_ref().unwrap_or(&r.file),
+            );
+        });
     }
 
     if !config.no_sort {
@@ -454,88 +2145,426 @@ fn run_batch(config: &CompareConfig, start_time: Instant) -> Result<ExitStatus>
     }
 
     let mut matches = 0;
+    let mut quick_matches = 0;
+    let mut unstable = 0;
     let mut diffs = 0;
+    let mut allowed_diffs = 0;
     let mut missing = 0;
     let mut extra = 0;
+    let mut renamed = 0;
+    let mut hash_errors = 0;
+    let mut permission_denied = count_permission_denied(&errors1, &errors2);
+    let mut out_of_tree = 0;
+    let mut transfer_bytes = 0;
+    let mut sample_confidence = SampleConfidence::default();
     for r in &all_results {
+        if let (Some(entry1), Some(entry2)) = (files1_map.get(&r.file), files2_map.get(&r.file)) {
+            rules.tally_sample(
+                &mut sample_confidence,
+                &r.file,
+                (entry1, entry2),
+                r.status,
+                config.size_only,
+            );
+        }
+        if matches!(r.status, Status::Diff | Status::Missing) {
+            transfer_bytes += r.size1.unwrap_or(0);
+        }
         match r.status {
             Status::Match => matches += 1,
-            Status::Diff => diffs += 1,
+            Status::QuickMatch => quick_matches += 1,
+            Status::Diff | Status::TypeMismatch => diffs += 1,
+            Status::AllowedDiff => allowed_diffs += 1,
             Status::Missing => missing += 1,
             Status::Extra => extra += 1,
+            Status::Renamed => renamed += 1,
+            Status::OutOfTree => out_of_tree += 1,
+            Status::Unstable => unstable += 1,
+            Status::Error => {
+                hash_errors += 1;
+                permission_denied += usize::from(r.permission_denied());
+            }
             _ => (),
         }
     }
     let total = all_results.len();
     let elapsed = start_time.elapsed();
 
-    let report_conf = ReportConfig {
-        mode: config.mode,
-        algo: config.algo,
-        threads: config.threads,
-        verbose: config.verbose,
-    };
-
-    let summary_data = SummaryData {
+    let severities = config.severities.unwrap_or_default();
+    let mut summary_data = SummaryData {
         total,
         matches,
+        quick_matches,
         diffs,
+        unstable,
+        allowed_diffs,
         missing,
         extra,
+        renamed,
         errors: total_errors,
+        hash_errors,
+        permission_denied,
+        out_of_tree,
+        transfer_bytes,
+        relocated,
+        root,
+        breakdown: config.breakdown.then(|| Breakdown::of(&all_results)),
         elapsed,
+        resource_usage: config.resource_usage.then(ResourceUsage::measure).flatten(),
+        sample_confidence: (rules.sample.is_some() && config.mode != Mode::Metadata)
+            .then_some(sample_confidence),
+        previous: rules.previous.clone(),
+        ..Default::default()
     };
+    summary_data.warnings = severities.count(&summary_data, Severity::Warning);
+    let status = summary_data.exit_status(&severities, config.permission_exit);
 
-    match config.output_format {
-        OutputFormat::Txt => {
-            let output = generate_text_report(
-                &all_results,
-                &errors1,
-                &errors2,
-                &summary_data,
-                &report_conf,
-            )?;
-            write_report(output, &config.output_folder, "report.txt")?;
+    Ok(Batch::Compared(
+        Comparison {
+            results: all_results,
+            errors1,
+            errors2,
+            summary: summary_data,
+            status,
+        },
+        rules.clone(),
+    ))
+}
+
+/// Running verdict counts shown on the batch progress bar, so trouble is
+/// visible before the run ends.
+#[derive(Default)]
+pub(crate) struct LiveCounts {
+    matches: AtomicUsize,
+    diffs: AtomicUsize,
+    errors: AtomicUsize,
+}
+
+impl LiveCounts {
+    /// Matches only refresh the message every so often; anything else at once.
+    const MATCH_REFRESH: usize = 256;
+
+    pub(crate) fn record(&self, status: Status, pb: &ProgressBar) {
+        let refresh = match status {
+            Status::Match | Status::QuickMatch | Status::AllowedDiff => self
+                .matches
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(Self::MATCH_REFRESH),
+            Status::Error => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            _ => {
+                self.diffs.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+        };
+        if refresh {
+            let diffs = self.diffs.load(Ordering::Relaxed);
+            let errors = self.errors.load(Ordering::Relaxed);
+            pb.set_message(format!(
+                "{} {} {} {} {} {}",
+                "match".green(),
+                self.matches.load(Ordering::Relaxed),
+                "diff".red(),
+                diffs,
+                "error".red(),
+                errors
+            ));
         }
-        OutputFormat::Json => {
-            let output = generate_json_report(&all_results, &errors1, &errors2, &summary_data)?;
-            write_report(output, &config.output_folder, "report.json")?;
+    }
+}
+
+fn count_permission_denied(errors1: &[ErrorEntry], errors2: &[ErrorEntry]) -> usize {
+    errors1
+        .iter()
+        .chain(errors2)
+        .filter(|e| e.permission_denied())
+        .count()
+}
+
+/// Granularity of the offsets `--auto-mtime-skew` recognises: time zones and
+/// DST shifts are whole quarter hours.
+const SKEW_STEP_SECS: u64 = 15 * 60;
+
+/// The constant offset, in seconds, by which folder2's mtimes differ from
+/// folder1's, if one exists.
+///
+/// Only pairs of equal size are considered. The offset must be a non-zero
+/// multiple of `SKEW_STEP_SECS` shared by more than half of those pairs, so
+/// genuinely edited files cannot masquerade as skew.
+pub(crate) fn detect_mtime_skew(
+    files1: &HashMap<PathBuf, FileEntry>,
+    files2: &HashMap<PathBuf, FileEntry>,
+) -> Option<i64> {
+    let mut pairs = 0usize;
+    let mut offsets: HashMap<i64, usize> = HashMap::new();
+    for (rel_path, e1) in files1 {
+        let Some(e2) = files2.get(rel_path) else {
+            continue;
+        };
+        let (Some(t1), Some(t2)) = (e1.modified, e2.modified) else {
+            continue;
+        };
+        if e1.size != e2.size {
+            continue;
+        }
+        pairs += 1;
+        let (delta, sign) = match t2.duration_since(t1) {
+            Ok(d) => (d, 1),
+            Err(e) => (e.duration(), -1),
+        };
+        if delta.subsec_nanos() == 0 && delta.as_secs() % SKEW_STEP_SECS == 0 {
+            *offsets.entry(sign * delta.as_secs() as i64).or_default() += 1;
         }
     }
+    let (offset, count) = offsets
+        .into_iter()
+        .max_by_key(|&(offset, count)| (count, std::cmp::Reverse(offset.unsigned_abs())))?;
+    (offset != 0 && count * 2 > pairs).then_some(offset)
+}
 
-    if total_errors > 0 {
-        Ok(ExitStatus::Error)
-    } else if diffs > 0 || missing > 0 || extra > 0 {
-        Ok(ExitStatus::Diff)
-    } else {
-        Ok(ExitStatus::Success)
-    }
-}
-
-/// Fix #11: split a command string respecting single- and double-quoted segments
-/// so that paths containing spaces (e.g. "/my tools/code --diff") are handled
-/// correctly instead of being naively split on every whitespace character.
-fn split_command(cmd: &str) -> Vec<String> {
-    let mut parts = Vec::new();
-    let mut current = String::new();
-    let mut in_single = false;
-    let mut in_double = false;
-
-    for ch in cmd.chars() {
-        match ch {
-            '\'' if !in_double => in_single = !in_single,
-            '"' if !in_single => in_double = !in_double,
-            ' ' | '\t' if !in_single && !in_double => {
-                if !current.is_empty() {
-                    parts.push(current.clone());
-                    current.clear();
+/// For each path in `lost` (present only in `own`), the paths in `other` whose
+/// content is identical, as far as `algo` can tell. Files are hashed with
+/// `read_ahead` buffers, as in the comparison.
+///
+/// Only files of a size some lost file has are hashed on the other side.
+/// Empty files and symlinks are left out: they would match indiscriminately.
+fn relocated_content(
+    lost: &[&PathBuf],
+    own: &HashMap<PathBuf, FileEntry>,
+    other: &HashMap<PathBuf, FileEntry>,
+    algo: HashAlgo,
+    read_ahead: Option<usize>,
+) -> HashMap<PathBuf, Vec<PathBuf>> {
+    let eligible = |e: &FileEntry| e.size > 0 && e.symlink_target.is_none();
+    let digest = |entry: &FileEntry| {
+        let h = match &entry.hashes {
+            Some(h) => h.clone(),
+            None if is_s3(&entry.path) => object_hashes(&entry.path, algo).ok()?,
+            None => compute_hashes(&entry.path, algo, read_ahead).ok()?,
+        };
+        match algo {
+            HashAlgo::Sha256 => h.sha256,
+            HashAlgo::Blake3 | HashAlgo::Both => h.blake3,
+            HashAlgo::Xxh3 => h.xxh3,
+            HashAlgo::Sha512 => h.sha512,
+            HashAlgo::Blake2b => h.blake2b,
+            HashAlgo::Md5 => h.md5,
+            HashAlgo::Sha1 => h.sha1,
+        }
+        .map(|d| (entry.size, d))
+    };
+
+    let lost: Vec<(&PathBuf, &FileEntry)> = lost
+        .iter()
+        .map(|p| (*p, &own[*p]))
+        .filter(|(_, e)| eligible(e))
+        .collect();
+    let sizes: HashSet<u64> = lost.iter().map(|(_, e)| e.size).collect();
+    if sizes.is_empty() {
+        return HashMap::new();
+    }
+
+    let mut by_content: HashMap<(u64, String), Vec<PathBuf>> = HashMap::new();
+    let candidates: Vec<((u64, String), &PathBuf)> = other
+        .par_iter()
+        .filter(|(_, e)| eligible(e) && sizes.contains(&e.size))
+        .filter_map(|(p, e)| Some((digest(e)?, p)))
+        .collect();
+    for (key, path) in candidates {
+        by_content.entry(key).or_default().push(path.clone());
+    }
+
+    lost.par_iter()
+        .filter_map(|(path, entry)| {
+            let mut found = by_content.get(&digest(entry)?)?.clone();
+            found.sort();
+            Some(((*path).clone(), found))
+        })
+        .collect()
+}
+
+/// Pair MISSING paths with EXTRA paths of identical content, one to one, as
+/// `(folder1 path, folder2 path)`. When several EXTRA files share the content,
+/// each MISSING path in sorted order takes the first one still free.
+fn pair_renames(
+    missing: &[&PathBuf],
+    extra: &[&PathBuf],
+    files1: &HashMap<PathBuf, FileEntry>,
+    files2: &HashMap<PathBuf, FileEntry>,
+    algo: HashAlgo,
+    read_ahead: Option<usize>,
+) -> Vec<(PathBuf, PathBuf)> {
+    if missing.is_empty() || extra.is_empty() {
+        return Vec::new();
+    }
+    let extras: HashMap<PathBuf, FileEntry> = extra
+        .iter()
+        .map(|p| ((*p).clone(), files2[*p].clone()))
+        .collect();
+    let mut found: Vec<(PathBuf, Vec<PathBuf>)> =
+        relocated_content(missing, files1, &extras, algo, read_ahead)
+            .into_iter()
+            .collect();
+    found.sort();
+
+    let mut taken: HashSet<PathBuf> = HashSet::new();
+    let mut pairs = Vec::new();
+    for (old, candidates) in found {
+        if let Some(new) = candidates.into_iter().find(|c| !taken.contains(c)) {
+            taken.insert(new.clone());
+            pairs.push((old, new));
+        }
+    }
+    pairs
+}
+
+/// Aggregate both sides into per-directory file counts, byte totals and
+/// immediate child counts.
+///
+/// Immediate child counts include subdirectories, which are inferred from the
+/// collected file paths. Directory mtimes are compared only when both roots
+/// are live folders.
+pub(crate) fn precheck(
+    files1: &HashMap<PathBuf, FileEntry>,
+    files2: &HashMap<PathBuf, FileEntry>,
+    root1: Option<&Path>,
+    root2: Option<&Path>,
+) -> PrecheckData {
+    fn totals_for<'a>(
+        totals: &'a mut BTreeMap<PathBuf, DirTotals>,
+        dir: &Path,
+    ) -> &'a mut DirTotals {
+        totals
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| DirTotals {
+                dir: dir.to_path_buf(),
+                ..Default::default()
+            })
+    }
+
+    let mut totals: BTreeMap<PathBuf, DirTotals> = BTreeMap::new();
+    for (side, files) in [(1, files1), (2, files2)] {
+        let mut dirs: HashSet<PathBuf> = HashSet::new();
+        for (rel_path, entry) in files {
+            let dir = rel_path.parent().unwrap_or(Path::new(""));
+            let t = totals_for(&mut totals, dir);
+            if side == 1 {
+                t.files1 += 1;
+                t.bytes1 += entry.size;
+                t.children1 += 1;
+            } else {
+                t.files2 += 1;
+                t.bytes2 += entry.size;
+                t.children2 += 1;
+            }
+            let mut ancestor = Some(dir);
+            while let Some(d) = ancestor {
+                if !dirs.insert(d.to_path_buf()) {
+                    break;
+                }
+                ancestor = d.parent();
+            }
+        }
+        for dir in &dirs {
+            totals_for(&mut totals, dir);
+            if let Some(parent) = dir.parent() {
+                let t = totals_for(&mut totals, parent);
+                if side == 1 {
+                    t.children1 += 1;
+                } else {
+                    t.children2 += 1;
                 }
             }
-            _ => current.push(ch),
         }
     }
-    if !current.is_empty() {
-        parts.push(current);
+
+    if let (Some(root1), Some(root2)) = (root1, root2) {
+        let mtime =
+            |root: &Path, dir: &Path| fs::metadata(root.join(dir)).and_then(|m| m.modified()).ok();
+        for t in totals.values_mut() {
+            if let (Some(m1), Some(m2)) = (mtime(root1, &t.dir), mtime(root2, &t.dir)) {
+                t.mtime_differs = m1 != m2;
+            }
+        }
+    }
+
+    PrecheckData {
+        dirs: totals.into_values().collect(),
+    }
+}
+
+/// `--probe-fs`: report what each folder's filesystem supports and return the
+/// mtime tolerance to use. Snapshot and stdin sides have no filesystem to probe.
+fn probe_filesystems(config: &CompareConfig, quiet: bool) -> Option<Duration> {
+    let to_stderr = config.output_format.is_document() && config.output_folder.is_none();
+    let note = |line: String| {
+        if quiet {
+            return;
+        }
+        if to_stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    };
+
+    note(format!("{}", "Filesystem probe:".bold()));
+    let mut caps = Vec::new();
+    for (name, folder) in [("folder1", &config.folder1), ("folder2", &config.folder2)] {
+        if folder == Path::new("-")
+            || is_snapshot_file(folder)
+            || is_remote(folder)
+            || is_ssh(folder)
+            || is_s3(folder)
+        {
+            note(format!("  {}  not a folder, skipped", name));
+            continue;
+        }
+        let c = probe(folder, config.assert_readonly);
+        note(format!("  {}  {}", name, c.describe()));
+        caps.push((name, c));
+    }
+    let [(name1, c1), (name2, c2)] = caps.as_slice() else {
+        return None;
+    };
+
+    if let (Some(a), Some(b)) = (c1.case_sensitive, c2.case_sensitive)
+        && a != b
+    {
+        let (sensitive, insensitive) = if a { (name1, name2) } else { (name2, name1) };
+        note(format!(
+            "  {} {} is case-sensitive but {} is not: names differing only in case cannot \
+             coexist in {} and show up as MISSING/EXTRA",
+            "Suggestion:".yellow(),
+            sensitive,
+            insensitive,
+            insensitive
+        ));
+    }
+    if config.symlinks == SymlinkMode::Compare {
+        for (name, c) in [(name1, c1), (name2, c2)] {
+            if c.symlinks == Some(false) {
+                note(format!(
+                    "  {} {} cannot hold symlinks; consider --symlinks follow or ignore",
+                    "Suggestion:".yellow(),
+                    name
+                ));
+            }
+        }
+    }
+
+    match (c1.mtime_granularity, c2.mtime_granularity) {
+        (Some(g1), Some(g2)) if g1 != g2 => {
+            let tolerance = g1.max(g2);
+            note(format!(
+                "  {} treating mtimes less than {} apart as equal",
+                "Tolerance:".yellow(),
+                format_granularity(tolerance)
+            ));
+            Some(tolerance)
+        }
+        _ => None,
     }
-    parts
 }
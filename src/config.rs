@@ -0,0 +1,153 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::models::{HashAlgo, Mode, Severity, Status};
+use crate::profile::{Preset, Profile};
+use crate::report::SummaryData;
+
+/// Settings read from the TOML file given with `--config`.
+///
+/// ```toml
+/// [[rule]]
+/// pattern = "*.generated"
+/// expect = "diff-ok"
+///
+/// [[subtree]]
+/// pattern = "finance/**"
+/// algo = "sha256"
+/// permissions = true
+///
+/// [severity]
+/// extra = "warning"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<StatusRule>,
+    #[serde(default, rename = "subtree")]
+    pub subtrees: Vec<SubtreeRule>,
+    #[serde(default)]
+    pub severity: Option<Severities>,
+    /// `[profile.<name>]` tables adjusting the built-in `--profile` presets.
+    #[serde(default, rename = "profile")]
+    pub profiles: HashMap<Profile, Preset>,
+}
+
+/// Overrides the verdict for files whose relative path matches `pattern`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatusRule {
+    pub pattern: String,
+    pub expect: Expect,
+}
+
+/// Options for the files whose relative path matches `pattern`, replacing
+/// those of the run. Unset fields keep the run's value; the first matching
+/// subtree applies.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubtreeRule {
+    pub pattern: String,
+    pub mode: Option<Mode>,
+    pub algo: Option<HashAlgo>,
+    /// `--quick` size such as `"1M"`; `"0"` turns a run-wide `--quick` off.
+    pub quick: Option<String>,
+    /// `--sample` percentage; `100` hashes every file.
+    pub sample: Option<f64>,
+    pub size_only: Option<bool>,
+    /// Files whose permission bits differ are DIFF even with equal content.
+    pub permissions: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Expect {
+    /// Content differences are known and accepted: DIFF becomes ALLOWED_DIFF.
+    DiffOk,
+}
+
+impl FileConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Patterns of all rules expecting `expect`, in the shape `build_globset` takes.
+    pub fn patterns(rules: &[StatusRule], expect: Expect) -> Option<Vec<String>> {
+        let patterns: Vec<String> = rules
+            .iter()
+            .filter(|r| r.expect == expect)
+            .map(|r| r.pattern.clone())
+            .collect();
+        (!patterns.is_empty()).then_some(patterns)
+    }
+}
+
+/// Severity of each result class. Unlisted classes keep their default: every
+/// kind of difference is an error, except ALLOWED_DIFF, which is informational.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Severities {
+    pub diff: Severity,
+    pub allowed_diff: Severity,
+    pub missing: Severity,
+    pub extra: Severity,
+    pub renamed: Severity,
+    pub out_of_tree: Severity,
+}
+
+impl Default for Severities {
+    fn default() -> Self {
+        Self {
+            diff: Severity::Error,
+            allowed_diff: Severity::Info,
+            missing: Severity::Error,
+            extra: Severity::Error,
+            renamed: Severity::Error,
+            out_of_tree: Severity::Error,
+        }
+    }
+}
+
+impl Severities {
+    /// Severity of a result; `None` for matches and for read errors, which
+    /// always fail the run.
+    pub fn of(&self, status: Status) -> Option<Severity> {
+        match status {
+            Status::Diff | Status::TypeMismatch => Some(self.diff),
+            Status::AllowedDiff => Some(self.allowed_diff),
+            Status::Missing => Some(self.missing),
+            Status::Extra => Some(self.extra),
+            Status::Renamed => Some(self.renamed),
+            Status::OutOfTree => Some(self.out_of_tree),
+            _ => None,
+        }
+    }
+
+    /// Number of summarized results at `level`.
+    pub fn count(&self, data: &SummaryData, level: Severity) -> usize {
+        [
+            (self.diff, data.diffs),
+            (self.allowed_diff, data.allowed_diffs),
+            (self.missing, data.missing),
+            (self.extra, data.extra),
+            (self.renamed, data.renamed),
+            (self.out_of_tree, data.out_of_tree),
+        ]
+        .iter()
+        .filter(|(severity, _)| *severity == level)
+        .map(|(_, n)| n)
+        .sum()
+    }
+
+    /// Whether the summarized run counts as differing (exit code 1): any
+    /// error-level result, or root metadata that differs.
+    pub fn fails(&self, data: &SummaryData) -> bool {
+        self.count(data, Severity::Error) > 0 || data.root_differs()
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::Result;
+use colored::*;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::compare::{CompareConfig, ExitStatus};
+use crate::models::{ComparisonResult, DriftEntry, Mode, OutputFormat, Status};
+use crate::recheck::load_report;
+use crate::report::{
+    DriftSummary, ReportConfig, SummaryData, generate_drift_json, generate_drift_text,
+    print_status_line, write_report,
+};
+
+/// Paths whose status differs between a baseline report's results and this
+/// run's, in path order.
+pub fn drift_entries(
+    previous: Vec<ComparisonResult>,
+    current: &[ComparisonResult],
+) -> Vec<DriftEntry> {
+    let mut statuses: BTreeMap<PathBuf, (Option<Status>, Option<Status>)> = BTreeMap::new();
+    for r in previous {
+        statuses.entry(r.file).or_default().0 = Some(r.status);
+    }
+    for r in current {
+        statuses.entry(r.file.clone()).or_default().1 = Some(r.status);
+    }
+    statuses
+        .into_iter()
+        .filter(|(_, (previous, current))| previous != current)
+        .map(|(file, (previous, current))| DriftEntry {
+            file,
+            previous,
+            current,
+        })
+        .collect()
+}
+
+/// `--baseline-report`: write only what changed since the given JSON report
+/// instead of the full results of this run.
+pub(crate) fn report_drift(
+    baseline: &Path,
+    results: &[ComparisonResult],
+    summary_data: &SummaryData,
+    config: &CompareConfig,
+    report_conf: &ReportConfig,
+    start_time: Instant,
+) -> Result<ExitStatus> {
+    let report = load_report(baseline)?;
+    // Realtime and batch reach the same verdicts; metadata mode does not.
+    let verdicts_of = |mode: Mode| mode == Mode::Metadata;
+    if let Some(mode) = report.run_info.mode
+        && verdicts_of(mode) != verdicts_of(config.mode)
+    {
+        let note = format!(
+            "{} the baseline report was made in {:?} mode, so some changes may only reflect the mode",
+            "Note:".yellow(),
+            mode
+        );
+        if config.output_format.is_document() && config.output_folder.is_none() {
+            eprintln!("{}", note);
+        } else {
+            println!("{}", note);
+        }
+    }
+
+    let baseline_paths = report.results.len();
+    let entries = drift_entries(report.results, results);
+    let count = |f: fn(Option<Status>) -> bool| entries.iter().filter(|e| f(e.current)).count();
+    let summary = DriftSummary {
+        baseline: baseline_paths,
+        current: results.len(),
+        changed: entries.len(),
+        newly_differing: count(|s| s == Some(Status::Diff)),
+        newly_matching: entries.iter().filter(|e| e.improved()).count(),
+        newly_missing: count(|s| s == Some(Status::Missing)),
+        newly_extra: count(|s| s == Some(Status::Extra)),
+        errors: summary_data.errors + summary_data.hash_errors,
+        elapsed: start_time.elapsed(),
+    };
+
+    match config.output_format {
+        OutputFormat::Txt => write_report(
+            generate_drift_text(&entries, &summary, report_conf),
+            &config.output_folder,
+            "drift_report.txt",
+            config.report_checksum,
+        )?,
+        OutputFormat::Json => write_report(
+            generate_drift_json(&entries, &summary, baseline, report_conf)?,
+            &config.output_folder,
+            "drift_report.json",
+            config.report_checksum,
+        )?,
+        OutputFormat::CodeQuality
+        | OutputFormat::Html
+        | OutputFormat::Csv
+        | OutputFormat::Tsv
+        | OutputFormat::Ndjson
+        | OutputFormat::Porcelain => {
+            unreachable!("refused by run_compare")
+        }
+    }
+
+    let status = if summary.errors > 0 {
+        ExitStatus::Error
+    } else if entries.iter().any(DriftEntry::worsened) {
+        ExitStatus::Diff
+    } else {
+        ExitStatus::Success
+    };
+    if config.status_line {
+        print_status_line(&status, &summary.status_fields());
+    }
+    Ok(status)
+}
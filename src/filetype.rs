@@ -0,0 +1,69 @@
+use std::io::Read;
+use std::path::Path;
+
+/// Bytes read from the start of a file; the same limit `infer` reads itself.
+const HEAD_LEN: usize = 8192;
+
+/// MIME type of the file at `path` from its leading bytes, or `None` when it
+/// cannot be read or is not recognised.
+pub fn detect(path: &Path) -> Option<&'static str> {
+    let mut head = Vec::with_capacity(HEAD_LEN);
+    crate::sandbox::open(path)
+        .ok()?
+        .take(HEAD_LEN as u64)
+        .read_to_end(&mut head)
+        .ok()?;
+    sniff(&head)
+}
+
+/// MIME type of content starting with `head`. Unrecognised content that is
+/// valid UTF-8 without NUL bytes counts as plain text.
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    if head.is_empty() {
+        return None;
+    }
+    if let Some(kind) = infer::get(head).filter(|kind| plausible(kind, head)) {
+        return Some(kind.mime_type());
+    }
+    if head.contains(&0) {
+        return None;
+    }
+    // A multi-byte character may be cut off at the end of the head.
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+    let start = text.trim_start();
+    Some(if start.starts_with("<?xml") {
+        "text/xml"
+    } else if start.starts_with("<!DOCTYPE html") || start.starts_with("<html") {
+        "text/html"
+    } else {
+        "text/plain"
+    })
+}
+
+/// `infer` matches executables on `MZ` and icons on `00 00 01 00` alone,
+/// which plenty of text and data files start with; check the structure
+/// behind those two before trusting them.
+fn plausible(kind: &infer::Type, head: &[u8]) -> bool {
+    match kind.extension() {
+        // The DOS header points at the `PE\0\0` signature.
+        "exe" | "dll" => head
+            .get(0x3C..0x40)
+            .map(|at| u32::from_le_bytes([at[0], at[1], at[2], at[3]]) as usize)
+            .and_then(|at| head.get(at..at.checked_add(4)?))
+            .is_some_and(|sig| sig == b"PE\0\0"),
+        // At least one image, and the first directory entry's reserved byte
+        // and colour planes are zero or one.
+        "ico" => {
+            head.len() >= 22
+                && u16::from_le_bytes([head[4], head[5]]) > 0
+                && head[9] == 0
+                && head[10] <= 1
+                && head[11] == 0
+        }
+        _ => true,
+    }
+}
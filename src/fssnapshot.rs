@@ -0,0 +1,274 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::cleanup::Cleanup;
+
+/// A line of `/proc/self/mountinfo`: where a filesystem is mounted, its type
+/// and the device it was mounted from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mount {
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    pub source: String,
+}
+
+/// Undo the octal escapes (`\040` for a space) mountinfo writes into paths.
+fn unescape(field: &str) -> String {
+    let mut out = String::new();
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let code: String = chars.by_ref().take(3).collect();
+            match u8::from_str_radix(&code, 8) {
+                Ok(b) => out.push(char::from(b)),
+                Err(_) => {
+                    out.push(c);
+                    out.push_str(&code);
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// The mount in `mountinfo` whose mount point is the longest prefix of the
+/// absolute `path`; the last one mounted wins when several share it.
+pub fn mount_of(path: &Path, mountinfo: &str) -> Option<Mount> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let (mount, fs) = line.split_once(" - ")?;
+            let mount_point = PathBuf::from(unescape(mount.split(' ').nth(4)?));
+            let mut fs = fs.split(' ');
+            Some(Mount {
+                mount_point,
+                fs_type: fs.next()?.to_string(),
+                source: unescape(fs.next()?),
+            })
+        })
+        .filter(|m| path.starts_with(&m.mount_point))
+        .max_by_key(|m| m.mount_point.components().count())
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to run {}; is it installed and on PATH?", program))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} {} failed ({}): {}",
+            program,
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// What was created, so it can be removed again.
+enum Kind {
+    /// A read-only btrfs snapshot of a subvolume.
+    Btrfs { snapshot: PathBuf },
+    /// An LVM snapshot volume, mounted read-only on a temporary directory.
+    Lvm {
+        volume: String,
+        mount_point: PathBuf,
+    },
+}
+
+impl Kind {
+    fn remove(&self) -> Result<()> {
+        match self {
+            Kind::Btrfs { snapshot } => run(
+                "btrfs",
+                &["subvolume", "delete", &snapshot.to_string_lossy()],
+            )
+            .map(drop),
+            Kind::Lvm {
+                volume,
+                mount_point,
+            } => run("umount", &[&mount_point.to_string_lossy()])
+                .and_then(|_| Ok(fs::remove_dir(mount_point)?))
+                .and_then(|_| run("lvremove", &["--yes", volume]).map(drop)),
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Kind::Btrfs { .. } => "btrfs",
+            Kind::Lvm { .. } => "LVM",
+        }
+    }
+}
+
+/// A temporary snapshot of the filesystem holding a folder (`--fs-snapshot`),
+/// so a long comparison reads one frozen state even while the folder is being
+/// written to. Removed again when dropped, or when the run is interrupted.
+pub struct FsSnapshot {
+    /// The folder as seen inside the snapshot.
+    folder: PathBuf,
+    _cleanup: Cleanup,
+}
+
+impl FsSnapshot {
+    fn new(kind: Kind, folder: PathBuf) -> Self {
+        FsSnapshot {
+            folder,
+            _cleanup: Cleanup::register(move || {
+                if let Err(e) = kind.remove() {
+                    eprintln!(
+                        "Warning: could not remove the {} snapshot: {:#}",
+                        kind.label(),
+                        e
+                    );
+                }
+            }),
+        }
+    }
+}
+
+impl FsSnapshot {
+    /// Snapshot the btrfs subvolume or LVM logical volume holding `folder`.
+    /// Needs Linux, the `btrfs` or LVM tools, and the privileges they ask for.
+    pub fn create(folder: &Path) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            anyhow::bail!("--fs-snapshot snapshots btrfs or LVM volumes and needs Linux");
+        }
+        let folder = fs::canonicalize(folder)
+            .with_context(|| format!("Cannot access {}", folder.display()))?;
+        let mountinfo = fs::read_to_string("/proc/self/mountinfo")
+            .context("Failed to read /proc/self/mountinfo")?;
+        let mount = mount_of(&folder, &mountinfo)
+            .with_context(|| format!("No mount holds {}", folder.display()))?;
+        let (kind, snapshot_folder) = if mount.fs_type == "btrfs" {
+            btrfs_snapshot(&folder, &mount)?
+        } else {
+            lvm_snapshot(&folder, &mount)?
+        };
+        eprintln!(
+            "Reading {} from a {} snapshot at {}",
+            folder.display(),
+            kind.label(),
+            snapshot_folder.display()
+        );
+        Ok(FsSnapshot::new(kind, snapshot_folder))
+    }
+
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+}
+
+/// The root of the btrfs subvolume holding `folder`: the nearest ancestor
+/// with inode 256, which every subvolume root has.
+#[cfg(target_os = "linux")]
+fn subvolume_root(folder: &Path, mount: &Mount) -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+    folder
+        .ancestors()
+        .take_while(|p| p.starts_with(&mount.mount_point))
+        .find(|p| fs::metadata(p).is_ok_and(|m| m.ino() == 256))
+        .unwrap_or(&mount.mount_point)
+        .to_path_buf()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn subvolume_root(_folder: &Path, mount: &Mount) -> PathBuf {
+    mount.mount_point.clone()
+}
+
+fn btrfs_snapshot(folder: &Path, mount: &Mount) -> Result<(Kind, PathBuf)> {
+    let subvolume = subvolume_root(folder, mount);
+    // Nested in the subvolume itself, where it is sure to be on the same
+    // filesystem; a snapshot shows nested subvolumes as empty directories.
+    let snapshot = subvolume.join(format!(".cmpf-snapshot-{}", std::process::id()));
+    run(
+        "btrfs",
+        &[
+            "subvolume",
+            "snapshot",
+            "-r",
+            &subvolume.to_string_lossy(),
+            &snapshot.to_string_lossy(),
+        ],
+    )?;
+    let rel = folder.strip_prefix(&subvolume).unwrap_or(Path::new(""));
+    let folder = snapshot.join(rel);
+    Ok((Kind::Btrfs { snapshot }, folder))
+}
+
+fn lvm_snapshot(folder: &Path, mount: &Mount) -> Result<(Kind, PathBuf)> {
+    let lv = run(
+        "lvs",
+        &["--noheadings", "-o", "vg_name,lv_name", &mount.source],
+    )
+    .with_context(|| {
+        format!(
+            "--fs-snapshot: {} is on {} ({}), which is neither btrfs nor an LVM volume",
+            folder.display(),
+            mount.source,
+            mount.fs_type
+        )
+    })?;
+    let mut names = lv.split_whitespace();
+    let (Some(vg), Some(origin)) = (names.next(), names.next()) else {
+        anyhow::bail!("lvs printed no volume for {}", mount.source);
+    };
+    let name = format!("cmpf-snapshot-{}", std::process::id());
+    run(
+        "lvcreate",
+        &[
+            "--snapshot",
+            "--extents",
+            "10%ORIGIN",
+            "--name",
+            &name,
+            &format!("{}/{}", vg, origin),
+        ],
+    )?;
+    let volume = format!("{}/{}", vg, name);
+
+    let mount_point = std::env::temp_dir().join(&name);
+    // XFS refuses to mount a second filesystem with the same UUID.
+    let options = if mount.fs_type == "xfs" {
+        "ro,nouuid"
+    } else {
+        "ro"
+    };
+    let mounted = fs::create_dir(&mount_point)
+        .context("Failed to create the snapshot mount point")
+        .and_then(|_| {
+            run(
+                "mount",
+                &[
+                    "-o",
+                    options,
+                    &format!("/dev/{}", volume),
+                    &mount_point.to_string_lossy(),
+                ],
+            )
+        });
+    if let Err(e) = mounted {
+        let _ = fs::remove_dir(&mount_point);
+        let _ = run("lvremove", &["--yes", &volume]);
+        return Err(e);
+    }
+    let rel = folder
+        .strip_prefix(&mount.mount_point)
+        .unwrap_or(Path::new(""));
+    let folder = mount_point.join(rel);
+    Ok((
+        Kind::Lvm {
+            volume,
+            mount_point,
+        },
+        folder,
+    ))
+}
@@ -0,0 +1,58 @@
+//! Folder comparison engine behind the `cmpf` command.
+//!
+//! [`compare::compare_folders`] compares two folders and returns the
+//! per-file [`models::ComparisonResult`] values without printing anything;
+//! [`compare::run_compare`] is what the command line runs, reports included.
+//!
+//! Besides [`compare`], [`models`] and [`report`], only the configuration
+//! types are part of the API; the modules hidden from the documentation
+//! serve the `cmpf` binary and may change at any time.
+
+#[doc(hidden)]
+pub mod activity;
+pub(crate) mod archive;
+pub(crate) mod cache;
+pub(crate) mod cleanup;
+pub mod compare;
+pub mod config;
+pub(crate) mod drift;
+pub(crate) mod filetype;
+pub(crate) mod fssnapshot;
+pub mod models;
+#[doc(hidden)]
+pub mod prime;
+pub(crate) mod probe;
+#[doc(hidden)]
+pub mod profile;
+#[doc(hidden)]
+pub mod rclone;
+#[doc(hidden)]
+pub mod recheck;
+pub(crate) mod replicas;
+pub mod report;
+#[doc(hidden)]
+pub mod resolve;
+pub(crate) mod resources;
+#[doc(hidden)]
+pub mod s3;
+#[doc(hidden)]
+pub mod sandbox;
+#[doc(hidden)]
+pub mod snapshot;
+#[doc(hidden)]
+pub mod ssh;
+pub(crate) mod stream;
+#[doc(hidden)]
+pub mod sync;
+pub(crate) mod tabular;
+#[doc(hidden)]
+pub mod utils;
+pub(crate) mod vss;
+pub(crate) mod ziplisting;
+
+pub use prime::PrimeConfig;
+pub use snapshot::{SnapshotConfig, VerifyConfig};
+pub use sync::SyncConfig;
+
+#[cfg(test)]
+mod tests;
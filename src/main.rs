@@ -2,26 +2,37 @@
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-mod compare;
-mod models;
-mod report;
-mod snapshot;
-mod sync;
-mod utils;
-
 #[cfg(test)]
-mod tests;
+mod cli_tests;
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
-use colored::control;
+use anyhow::{Context, Result};
+use clap::builder::BoolishValueParser;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use colored::{Colorize, control};
 use std::io::IsTerminal;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::compare::{CompareConfig, ExitStatus, run_compare};
-use crate::models::{HashAlgo, Mode, OutputFormat, SymlinkMode};
-use crate::snapshot::{SnapshotConfig, VerifyConfig, create_snapshot, verify_snapshot};
-use crate::sync::{SyncConfig, run_sync};
+use cmpf::compare::{CompareConfig, ExitStatus, is_snapshot_file, run_compare};
+use cmpf::config::FileConfig;
+use cmpf::models::{ArchiveMode, HashAlgo, Mode, OutputFormat, Status, StdinFormat, SymlinkMode};
+use cmpf::prime::{PrimeConfig, run_prime};
+use cmpf::profile::{Preset, Profile};
+use cmpf::rclone::is_remote;
+use cmpf::recheck::run_recheck;
+use cmpf::report::{REPORT_SCHEMA, ResultsPage, print_status_line};
+use cmpf::resolve::run_resolve;
+use cmpf::s3::is_s3;
+use cmpf::sandbox::Sandbox;
+use cmpf::snapshot::{
+    RootMap, SnapshotConfig, VerifyConfig, baseline_path, create_snapshot, verify_snapshot,
+};
+use cmpf::ssh::is_ssh;
+use cmpf::sync::{SyncConfig, run_sync};
+use cmpf::utils::{
+    parse_owner, parse_root_mapping, parse_size, parse_status, read_exclude_file, set_max_memory,
+    set_walk_threads,
+};
 
 #[derive(Parser)]
 #[command(
@@ -42,62 +53,543 @@ struct Cli {
     #[arg(help_heading = "Legacy Mode")]
     folder2: Option<PathBuf>,
 
-    #[arg(short, long, value_enum, default_value_t = Mode::Batch, global = true)]
-    /// Processing mode: Realtime (sequential), Batch (parallel report), or Metadata (skip hashing)
+    /// Compare FOLDER against its stored baseline, recording the baseline on the first run
+    #[arg(long, value_name = "FOLDER", conflicts_with_all = ["folder1", "folder2"])]
+    baseline: Option<PathBuf>,
+    /// Directory holding --baseline snapshots (default: $XDG_DATA_HOME/cmpf/baselines)
+    #[arg(long, value_name = "DIR", requires = "baseline")]
+    baseline_dir: Option<PathBuf>,
+    /// Print the JSON Schema of `--output-format json` reports and exit
+    #[arg(long, conflicts_with_all = ["folder1", "folder2", "baseline"])]
+    print_schema: bool,
+
+    #[arg(short, long, value_enum, default_value_t = Mode::Batch, global = true, env = "CMPF_MODE")]
+    /// Processing mode: Realtime (sequential), Batch (parallel report), Metadata (skip hashing), or Bytes (read both files up to the first difference instead of hashing)
     mode: Mode,
-    #[arg(short, long, value_enum, default_value_t = HashAlgo::Blake3, global = true)]
+    #[arg(
+        short,
+        long,
+        value_enum,
+        default_value_t = HashAlgo::Blake3,
+        global = true,
+        env = "CMPF_ALGO"
+    )]
     /// Hashing algorithm to use for file comparison
     algo: HashAlgo,
-    #[arg(short, long, global = true)]
+    #[arg(short, long, global = true, env = "CMPF_OUTPUT_FOLDER")]
     /// (Batch mode only) Folder to save the report file
     output_folder: Option<PathBuf>,
-    #[arg(short = 'f', long, value_enum, default_value_t = OutputFormat::Txt, global = true)]
+    #[arg(
+        short = 'f',
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Txt,
+        global = true,
+        env = "CMPF_OUTPUT_FORMAT"
+    )]
     /// (Batch mode only) Format for the output report
     output_format: OutputFormat,
-    #[arg(long, global = true)]
+    /// Print only tab-separated STATUS and PATH lines, in a format kept stable for scripts (same as --output-format porcelain)
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_PORCELAIN",
+        value_parser = BoolishValueParser::new(),
+        conflicts_with = "output_format"
+    )]
+    porcelain: bool,
+    /// List only results with these statuses in the JSON report (e.g. DIFF,MISSING); the summary still counts all
+    #[arg(
+        long,
+        value_name = "STATUS",
+        value_delimiter = ',',
+        value_parser = parse_status,
+        global = true,
+        env = "CMPF_RESULTS_STATUS"
+    )]
+    results_status: Vec<Status>,
+    /// Skip this many (filtered) results at the start of the JSON report's results
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        global = true,
+        env = "CMPF_RESULTS_OFFSET"
+    )]
+    results_offset: usize,
+    /// List at most this many (filtered) results in the JSON report
+    #[arg(long, value_name = "N", global = true, env = "CMPF_RESULTS_LIMIT")]
+    results_limit: Option<usize>,
+    #[arg(long, global = true, env = "CMPF_DEPTH")]
     /// Maximum recursion depth (default: infinite)
     depth: Option<usize>,
-    #[arg(long, conflicts_with = "depth", global = true)]
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_NO_RECURSIVE",
+        value_parser = BoolishValueParser::new()
+    )]
     /// Disable recursive comparison (equivalent to --depth 1)
     no_recursive: bool,
-    #[arg(long, value_enum, default_value_t = SymlinkMode::Ignore, global = true)]
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = SymlinkMode::Ignore,
+        global = true,
+        env = "CMPF_SYMLINKS"
+    )]
     /// Handling strategy for symbolic links
     symlinks: SymlinkMode,
-    #[arg(short, long, default_value_t = false, global = true)]
+    /// With --symlinks compare, treat links whose targets resolve to the same place as matching
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_NORMALIZE_SYMLINKS",
+        value_parser = BoolishValueParser::new()
+    )]
+    normalize_symlinks: bool,
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        global = true,
+        env = "CMPF_VERBOSE",
+        value_parser = BoolishValueParser::new()
+    )]
     /// Show hash values for matched and different files
     verbose: bool,
-    #[arg(short = 'H', long, default_value_t = false, global = true)]
+    #[arg(
+        short = 'H',
+        long,
+        default_value_t = false,
+        global = true,
+        env = "CMPF_HIDDEN",
+        value_parser = BoolishValueParser::new()
+    )]
     /// Include hidden files and folders in the comparison
     hidden: bool,
-    #[arg(short = 't', long = "type", action = clap::ArgAction::Append, global = true)]
+    #[arg(
+        short = 't',
+        long = "type",
+        action = clap::ArgAction::Append,
+        global = true,
+        env = "CMPF_TYPE"
+    )]
     /// File extensions to include (e.g., "txt", "jpg"). Can be used multiple times.
     types: Option<Vec<String>>,
-    #[arg(short = 'i', long, action = clap::ArgAction::Append, global = true)]
+    #[arg(short = 'i', long, action = clap::ArgAction::Append, global = true, env = "CMPF_IGNORE")]
     /// A gitignore-style pattern to ignore. Can be used multiple times.
     ignore: Option<Vec<String>>,
-    #[arg(short = 'j', long, value_name = "COUNT", global = true)]
+    #[arg(
+        long,
+        value_name = "FILE",
+        action = clap::ArgAction::Append,
+        global = true,
+        env = "CMPF_EXCLUDE_FROM"
+    )]
+    /// Read ignore patterns from an rsync-style exclude file. Can be used multiple times.
+    exclude_from: Option<Vec<PathBuf>>,
+    #[arg(
+        long,
+        value_name = "USER",
+        value_parser = parse_owner,
+        global = true,
+        env = "CMPF_OWNER_FILTER"
+    )]
+    /// Only include files owned by USER (a user name or numeric uid)
+    owner_filter: Option<u32>,
+    #[arg(
+        short = 'x',
+        long,
+        global = true,
+        env = "CMPF_ONE_FILE_SYSTEM",
+        value_parser = BoolishValueParser::new()
+    )]
+    /// Do not cross into other mounted filesystems while walking (like `du -x`)
+    one_file_system: bool,
+    #[arg(
+        short = 'j',
+        long,
+        value_name = "COUNT",
+        global = true,
+        env = "CMPF_THREADS"
+    )]
     /// Number of threads to use for parallel processing (default: number of CPU cores)
     threads: Option<usize>,
-    #[arg(short = 'n', long, default_value_t = false, global = true)]
+    #[arg(
+        short = 'n',
+        long,
+        default_value_t = false,
+        global = true,
+        env = "CMPF_NO_SORT",
+        value_parser = BoolishValueParser::new()
+    )]
     /// Disable alphabetical sorting of the output (improves performance)
     no_sort: bool,
+    /// (Realtime mode) Print EXTRA entries in path order among the others instead of at the end
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "no_sort",
+        env = "CMPF_INTERLEAVE",
+        value_parser = BoolishValueParser::new()
+    )]
+    interleave: bool,
     /// Command to use for external diff (e.g., "code --diff", "vimdiff")
-    #[arg(long, value_name = "COMMAND", global = true)]
+    #[arg(long, value_name = "COMMAND", global = true, env = "CMPF_DIFF_CMD")]
     diff_cmd: Option<String>,
+    /// For differing files larger than --block-size, report which chunks differ
+    #[arg(long, global = true, env = "CMPF_BLOCK_DIFF", value_parser = BoolishValueParser::new())]
+    block_diff: bool,
+    /// Chunk size in bytes used by --block-diff
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = 4 * 1024 * 1024,
+        global = true,
+        env = "CMPF_BLOCK_SIZE"
+    )]
+    block_size: u64,
+    /// Lines of context around each change in the unified diffs --verbose adds for differing text files
+    #[arg(
+        long,
+        value_name = "LINES",
+        default_value_t = 3,
+        global = true,
+        env = "CMPF_DIFF_CONTEXT"
+    )]
+    diff_context: usize,
+    /// Largest file (e.g. 1M) that --verbose computes a unified diff for; 0 turns inline diffs off
+    #[arg(
+        long,
+        value_name = "SIZE",
+        default_value = "1M",
+        value_parser = parse_size,
+        global = true,
+        env = "CMPF_DIFF_MAX_SIZE"
+    )]
+    diff_max_size: u64,
+    /// (Metadata mode only) Compare existence and size only, ignoring modification times
+    #[arg(long, global = true, env = "CMPF_SIZE_ONLY", value_parser = BoolishValueParser::new())]
+    size_only: bool,
+    /// Write a .sha256 sidecar for each saved report and embed a results digest in JSON run_info
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_REPORT_CHECKSUM",
+        value_parser = BoolishValueParser::new()
+    )]
+    report_checksum: bool,
+    /// (Batch mode only) Compare per-directory file counts and sizes before hashing
+    #[arg(long, global = true, env = "CMPF_PRECHECK", value_parser = BoolishValueParser::new())]
+    precheck: bool,
+    /// Stop after --precheck if the trees diverge by more than this many percent
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        requires = "precheck",
+        global = true,
+        env = "CMPF_PRECHECK_THRESHOLD"
+    )]
+    precheck_threshold: Option<f64>,
+    /// (Batch mode only) Reuse MATCH/DIFF verdicts stored in FILE for pairs whose size and mtime are unchanged
+    #[arg(long, value_name = "FILE", global = true, env = "CMPF_VERDICT_CACHE")]
+    verdict_cache: Option<PathBuf>,
+    /// (Batch mode only) Report differences unchanged in the verdict cache for more than DAYS days as ALLOWED_DIFF
+    #[arg(
+        long,
+        value_name = "DAYS",
+        requires = "verdict_cache",
+        global = true,
+        env = "CMPF_IGNORE_KNOWN_DIFFS"
+    )]
+    ignore_known_diffs: Option<u64>,
+    /// Reuse file digests stored in FILE while size, mtime and inode are unchanged; batch runs and `prime` add to it
+    #[arg(
+        long,
+        visible_alias = "cache",
+        value_name = "FILE",
+        global = true,
+        env = "CMPF_HASH_CACHE"
+    )]
+    hash_cache: Option<PathBuf>,
+    /// Only check that files matching PATTERN exist on both sides, not their content. Can be used multiple times.
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        action = clap::ArgAction::Append,
+        global = true,
+        env = "CMPF_PRESENCE_ONLY"
+    )]
+    presence_only: Option<Vec<String>>,
+    /// Stream files through a reader thread that keeps up to BUFFERS 8 MiB buffers ahead of hashing
+    #[arg(long, value_name = "BUFFERS", global = true, env = "CMPF_READ_AHEAD")]
+    read_ahead: Option<usize>,
+    /// Keep hashing buffers under SIZE (e.g. 512M) by streaming files and limiting how many are read at once; compare then runs as with --stream
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        global = true,
+        env = "CMPF_MAX_MEMORY"
+    )]
+    max_memory: Option<u64>,
+    /// Number of threads listing directories (default: chosen by the walker, at most 12); hashing still uses --threads
+    #[arg(long, value_name = "COUNT", global = true, env = "CMPF_WALK_THREADS")]
+    walk_threads: Option<usize>,
+    /// Hash only a random PERCENT of the files present on both sides; the rest are compared by size and mtime
+    #[arg(long, value_name = "PERCENT", global = true, env = "CMPF_SAMPLE")]
+    sample: Option<f64>,
+    /// Seed for random file selections such as --sample; every run records the seed it used in JSON run_info
+    #[arg(long, global = true, env = "CMPF_SEED")]
+    seed: Option<u64>,
+    /// (Batch mode only) Hash MISSING and EXTRA files and report where their content exists on the other side
+    #[arg(long, global = true, env = "CMPF_RELOCATED", value_parser = BoolishValueParser::new())]
+    relocated: bool,
+    /// (Batch mode only) Report a MISSING and an EXTRA file with identical content as one RENAMED entry
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_DETECT_RENAMES",
+        value_parser = BoolishValueParser::new()
+    )]
+    detect_renames: bool,
+    /// Detect a constant mtime offset between the folders (e.g. DST or time zone on SMB shares) and ignore it
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_AUTO_MTIME_SKEW",
+        value_parser = BoolishValueParser::new()
+    )]
+    auto_mtime_skew: bool,
+    /// Probe each folder's case sensitivity, mtime granularity and symlink support, and tolerate the differences
+    #[arg(long, global = true, env = "CMPF_PROBE_FS", value_parser = BoolishValueParser::new())]
+    probe_fs: bool,
+    /// Compare while walking, keeping memory flat on huge trees; results are printed unordered
+    #[arg(long, global = true, env = "CMPF_STREAM", value_parser = BoolishValueParser::new())]
+    stream: bool,
+    /// (Batch mode only) Record the MIME type detected from each file's content in JSON results
+    #[arg(long, global = true, env = "CMPF_DETECT_TYPE", value_parser = BoolishValueParser::new())]
+    detect_type: bool,
+    /// Report only what changed since a previous JSON report: newly differing, matching, missing or extra paths
+    #[arg(
+        long,
+        value_name = "REPORT_JSON",
+        global = true,
+        env = "CMPF_BASELINE_REPORT"
+    )]
+    baseline_report: Option<PathBuf>,
+    /// Do not compare the permissions, owner and extended attributes of the two root folders themselves
+    #[arg(long, global = true, env = "CMPF_NO_ROOT_CHECK", value_parser = BoolishValueParser::new())]
+    no_root_check: bool,
+    /// Show how each summary count moved since an earlier JSON or ndjson report, e.g. `Differences: 14 (+9 vs last run)`
+    #[arg(
+        long,
+        value_name = "REPORT",
+        global = true,
+        env = "CMPF_PREVIOUS_REPORT"
+    )]
+    previous_report: Option<PathBuf>,
+    /// Exit with code 3 instead of 2 when paths could not be read for lack of permission
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_PERMISSION_EXIT",
+        value_parser = BoolishValueParser::new()
+    )]
+    permission_exit: bool,
+    /// Show one progress line per worker thread with the file it is hashing
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_THREAD_ACTIVITY",
+        value_parser = BoolishValueParser::new()
+    )]
+    thread_activity: bool,
+    /// Print each path the walks leave out and which rule excluded it (hidden, ignore file, --ignore, --type, ...)
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_EXPLAIN_SKIPS",
+        value_parser = BoolishValueParser::new()
+    )]
+    explain_skips: bool,
+    /// Print one `status=... key=value` line to stderr at the end, whatever the report format
+    #[arg(long, global = true, env = "CMPF_STATUS_LINE", value_parser = BoolishValueParser::new())]
+    status_line: bool,
+    /// Allow no writes but the explicit report/snapshot outputs (enforced with landlock on Linux)
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_ASSERT_READONLY",
+        value_parser = BoolishValueParser::new()
+    )]
+    assert_readonly: bool,
+    /// Read the compared folders from Volume Shadow Copies, so open or locked files can be hashed (Windows, elevated)
+    #[arg(long, global = true, env = "CMPF_VSS", value_parser = BoolishValueParser::new())]
+    vss: bool,
+    /// Read folder1 from a temporary btrfs or LVM snapshot, removed when the run ends (Linux, root)
+    #[arg(long, global = true, env = "CMPF_FS_SNAPSHOT", value_parser = BoolishValueParser::new())]
+    fs_snapshot: bool,
+    /// Confine the run to reading the compared paths and writing its outputs (Linux landlock; elsewhere reads only, checked in userspace)
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_SANDBOX",
+        value_parser = BoolishValueParser::new()
+    )]
+    sandbox: bool,
+    /// Triage mode: hash only the first and last N bytes (e.g. 1M) of larger files; such matches are reported as MATCH(QUICK)
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = parse_size,
+        global = true,
+        env = "CMPF_QUICK"
+    )]
+    quick: Option<u64>,
+    /// Compare .zip/.jar/.war/.ear files by member names, CRCs and sizes, ignoring member timestamps
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_ZIP_MEMBERS",
+        value_parser = BoolishValueParser::new()
+    )]
+    zip_members: bool,
+    /// Hash s3:// objects by downloading them, even where --algo md5 could use their ETags
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_S3_DOWNLOAD",
+        value_parser = BoolishValueParser::new()
+    )]
+    s3_download: bool,
+    /// Count s3:// objects of equal size and ETag as matching without downloading them
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_TRUST_ETAGS",
+        value_parser = BoolishValueParser::new()
+    )]
+    trust_etags: bool,
+    /// Fail instead of switching to the algorithm a compared snapshot records when it differs from --algo
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_FORCE_ALGO",
+        value_parser = BoolishValueParser::new()
+    )]
+    force_algo: bool,
+    /// Compare .csv/.tsv files field by field, treating numbers within this relative difference (e.g. 1e-9) as equal
+    #[arg(long, value_name = "EPS", global = true, env = "CMPF_FLOAT_TOLERANCE")]
+    float_tolerance: Option<f64>,
+    /// Report peak memory, CPU time, bytes read from storage and open files in the summary and JSON run_info
+    #[arg(
+        long,
+        global = true,
+        env = "CMPF_RESOURCE_USAGE",
+        value_parser = BoolishValueParser::new()
+    )]
+    resource_usage: bool,
+    /// (Batch mode only) Group differences by file extension and size in the report
+    #[arg(long, global = true, env = "CMPF_BREAKDOWN", value_parser = BoolishValueParser::new())]
+    breakdown: bool,
+    /// (Batch mode only) Write diff.txt, missing.txt and extra.txt path lists into DIR
+    #[arg(long, value_name = "DIR", global = true, env = "CMPF_EMIT_LIST_DIR")]
+    emit_list_dir: Option<PathBuf>,
+    /// (Batch mode only) Also write a snapshot of folder1 to FILE, reusing the comparison's digests
+    #[arg(long, value_name = "FILE", global = true, env = "CMPF_EMIT_MANIFEST")]
+    emit_manifest: Option<PathBuf>,
+    /// Format of a folder given as `-`, read from stdin
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = StdinFormat::Tar,
+        global = true,
+        env = "CMPF_STDIN_FORMAT"
+    )]
+    stdin_format: StdinFormat,
+    /// Drop this many leading path components from members of a stdin archive
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        global = true,
+        env = "CMPF_STRIP_COMPONENTS"
+    )]
+    strip_components: usize,
+    /// How archives in the folders are compared: as files, or opened so a .zip or .tar(.gz) matches the folder it was made from
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ArchiveMode::File,
+        global = true,
+        env = "CMPF_ARCHIVES"
+    )]
+    archives: ArchiveMode,
+    /// Levels of nested archives opened by --archives recurse (1 = only archives in the folders)
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        global = true,
+        env = "CMPF_ARCHIVE_DEPTH"
+    )]
+    archive_depth: usize,
+    /// Read status rules and severities from a TOML file
+    #[arg(long, value_name = "FILE", global = true, env = "CMPF_CONFIG")]
+    config: Option<PathBuf>,
+    /// Preset combination of options for a common task; options given explicitly still win
+    #[arg(long, value_enum, global = true, env = "CMPF_PROFILE")]
+    profile: Option<Profile>,
+    #[arg(skip)]
+    file_config: FileConfig,
+}
+
+impl Cli {
+    /// `--output-format`, or porcelain under `--porcelain`.
+    fn output_format(&self) -> OutputFormat {
+        if self.porcelain {
+            OutputFormat::Porcelain
+        } else {
+            self.output_format
+        }
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Standard comparison between two folders
-    Compare { folder1: PathBuf, folder2: PathBuf },
-    /// Create a snapshot of a folder's state
+    /// Standard comparison between two folders (either may be a snapshot file)
+    Compare {
+        folder1: PathBuf,
+        folder2: PathBuf,
+        /// Further replicas: report per path which of them agree and which version most hold
+        #[arg(value_name = "FOLDER")]
+        replicas: Vec<PathBuf>,
+    },
+    /// Create, verify or diff snapshots; `snapshot FOLDER` is short for `snapshot create FOLDER`
+    #[command(args_conflicts_with_subcommands = true)]
     Snapshot {
-        folder: PathBuf,
-        #[arg(short, long)]
-        output: Option<PathBuf>,
+        #[command(subcommand)]
+        action: Option<SnapshotCommands>,
+        #[command(flatten)]
+        create: Option<SnapshotArgs>,
     },
-    /// Verify a folder against a previously created snapshot
-    Verify { folder: PathBuf, snapshot: PathBuf },
+    /// Verify a folder against a previously created snapshot (same as `snapshot verify`)
+    Verify(VerifyArgs),
+    /// Re-verify only the DIFF/MISSING/EXTRA entries of a previous JSON report
+    Recheck {
+        /// JSON report written by `compare --output-format json`
+        report: PathBuf,
+        /// Override the folders recorded in the report
+        #[arg(requires = "folder2")]
+        folder1: Option<PathBuf>,
+        folder2: Option<PathBuf>,
+    },
+    /// Walk the DIFF results one by one and choose which side's file to keep
+    Resolve { folder1: PathBuf, folder2: PathBuf },
+    /// Hash a folder into --hash-cache ahead of time, without comparing it
+    Prime { folder: PathBuf },
     /// Sync changes from source to destination
     Sync {
         /// Source folder
@@ -117,9 +609,69 @@ enum Commands {
         /// Do not delete files, only copy
         #[arg(long, conflicts_with = "delete_extraneous")]
         no_delete: bool,
+        /// Treat SOURCE as a snapshot file and restore DESTINATION to its recorded state
+        #[arg(long)]
+        from_snapshot: bool,
+        /// Recreate files hard-linked together in the source as hard links in the destination
+        #[arg(long)]
+        hard_links: bool,
+        /// Repair permissions, ownership and mtime of files whose content already matches
+        #[arg(long)]
+        fix_metadata: bool,
+        /// Print a compact rsync-style change string (e.g. `>f.st......`) for each file
+        #[arg(long)]
+        itemize: bool,
+        /// Command run after each file is created; `{src}` and `{dst}` are replaced with its paths
+        #[arg(long, value_name = "CMD")]
+        on_create: Option<String>,
+        /// Command run after each file is updated; `{src}` and `{dst}` are replaced with its paths
+        #[arg(long, value_name = "CMD")]
+        on_update: Option<String>,
+        /// Command run after each file is deleted; `{src}` and `{dst}` are replaced with its paths
+        #[arg(long, value_name = "CMD")]
+        on_delete: Option<String>,
     },
 }
 
+#[derive(Subcommand)]
+enum SnapshotCommands {
+    /// Create a snapshot of a folder's state
+    Create(SnapshotArgs),
+    /// Verify a folder against a previously created snapshot
+    Verify(VerifyArgs),
+    /// Compare two snapshot files without reading either folder
+    Diff {
+        snapshot1: PathBuf,
+        snapshot2: PathBuf,
+    },
+}
+
+#[derive(Args)]
+struct SnapshotArgs {
+    folder: PathBuf,
+    /// Path to save the snapshot file (default: stdout)
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Read and hash every file twice and flag files whose reads differ (failing disk or RAM)
+    #[arg(long)]
+    double_read: bool,
+    /// With --double-read, evict each file from the page cache before the second read (Linux)
+    #[arg(long, requires = "double_read")]
+    drop_cache: bool,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    folder: PathBuf,
+    snapshot: PathBuf,
+    /// Translate absolute paths recorded under OLD to NEW (snapshot taken on another mount point). Can be used multiple times.
+    #[arg(long, value_name = "OLD=NEW", value_parser = parse_root_mapping)]
+    map_root: Vec<(PathBuf, PathBuf)>,
+    /// Only list the files added, removed or modified since the snapshot, grouped by directory with counts and sizes.
+    #[arg(long)]
+    changes_only: bool,
+}
+
 fn main() {
     #[cfg(windows)]
     control::set_virtual_terminal(true).ok();
@@ -128,60 +680,151 @@ fn main() {
         control::set_override(false);
     }
 
-    // Fix #5: configure the Rayon thread pool once here, before any subcommand runs.
-    // Individual subcommand functions no longer call build_global() themselves;
-    // they do so with a silent `let _ = ...` as a fallback safety net only.
-    let cli_args: Vec<String> = std::env::args().collect();
-    // We parse threads manually here just for the pool init; Clap will parse it
-    // again properly in run(). This avoids restructuring the entire CLI.
-    if let Some(j_pos) = cli_args.iter().position(|a| a == "-j" || a == "--threads")
-        && let Some(count_str) = cli_args.get(j_pos + 1)
-        && let Ok(n) = count_str.parse::<usize>()
-    {
-        let _ = rayon::ThreadPoolBuilder::new()
-            .num_threads(n)
-            .build_global();
-    }
-
-    match run() {
+    let matches = Cli::command().get_matches();
+    let status_line = matches.get_one::<bool>("status_line") == Some(&true);
+    match run(matches) {
         Ok(status) => match status {
             ExitStatus::Success => std::process::exit(0),
             ExitStatus::Diff => std::process::exit(1),
             ExitStatus::Error => std::process::exit(2),
+            ExitStatus::PermissionDenied => std::process::exit(3),
         },
         Err(e) => {
             eprintln!("Error: {:#}", e);
+            // Wrappers relying on --status-line get one for failed runs too.
+            if status_line {
+                print_status_line(&ExitStatus::Error, &[]);
+            }
             std::process::exit(2);
         }
     }
 }
 
-fn run() -> Result<ExitStatus> {
-    let cli = Cli::parse();
+fn run(matches: ArgMatches) -> Result<ExitStatus> {
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    apply_env(&mut cli, &matches)?;
+    if cli.print_schema {
+        print!("{}", REPORT_SCHEMA);
+        return Ok(ExitStatus::Success);
+    }
+
+    for file in cli.exclude_from.iter().flatten() {
+        cli.ignore
+            .get_or_insert_with(Vec::new)
+            .extend(read_exclude_file(file)?);
+    }
+
+    if let Some(path) = &cli.config {
+        cli.file_config = FileConfig::load(path)?;
+    }
+    if let Some(profile) = cli.profile {
+        let preset = profile.preset(&cli.file_config.profiles);
+        apply_preset(&mut cli, preset, &matches);
+    }
 
-    match cli.command {
-        Some(Commands::Compare { folder1, folder2 }) => run_compare(CompareConfig {
+    if cli.assert_readonly {
+        let rules = readonly_rules(&cli)?;
+        // Only Linux can enforce them; elsewhere the refusals are all there is.
+        if cfg!(target_os = "linux") {
+            rules.apply()?;
+        }
+    }
+    if cli.sandbox {
+        sandbox_paths(&cli)?.apply()?;
+    }
+    // Fix #5: configure the Rayon thread pool once here, before any subcommand
+    // runs and after --sandbox, whose rules only bind threads started later.
+    // Subcommand functions still call build_global() with a silent
+    // `let _ = ...` as a fallback safety net only.
+    if let Some(n) = cli.threads {
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(n)
+            .build_global();
+    }
+    if let Some(bytes) = cli.max_memory {
+        set_max_memory(bytes);
+    }
+    if let Some(threads) = cli.walk_threads {
+        set_walk_threads(threads);
+    }
+    if cli.thread_activity {
+        cmpf::activity::enable();
+    }
+
+    if cli.algo.is_legacy() {
+        eprintln!(
+            "{} --algo {} is broken as a security hash and only meant for matching existing \
+             manifests; it does not detect deliberate tampering",
+            "Warning:".yellow(),
+            format!("{:?}", cli.algo).to_lowercase()
+        );
+    }
+
+    if let Some(folder) = cli.baseline.take() {
+        if cli.command.is_some() {
+            anyhow::bail!("--baseline is a shorthand of its own and takes no subcommand");
+        }
+        return run_baseline(cli, folder);
+    }
+    dispatch(cli)
+}
+
+/// Run the parsed subcommand, or the legacy two-folder compare without one.
+fn dispatch(mut cli: Cli) -> Result<ExitStatus> {
+    match cli.command.take() {
+        Some(Commands::Compare {
             folder1,
             folder2,
-            mode: cli.mode,
-            algo: cli.algo,
-            output_folder: cli.output_folder,
-            output_format: cli.output_format,
-            depth: cli.depth,
-            no_recursive: cli.no_recursive,
-            symlinks: cli.symlinks,
-            verbose: cli.verbose,
-            hidden: cli.hidden,
-            types: cli.types,
-            ignore: cli.ignore,
-            threads: cli.threads,
-            no_sort: cli.no_sort,
-            diff_cmd: cli.diff_cmd,
+            replicas,
+        }) => run_compare(CompareConfig {
+            replicas,
+            ..compare_config(cli, folder1, folder2)
         }),
-        Some(Commands::Snapshot { folder, output }) => {
-            create_snapshot(SnapshotConfig {
+        Some(Commands::Snapshot { action, create }) => match (action, create) {
+            (Some(SnapshotCommands::Create(args)), _) | (None, Some(args)) => {
+                create_snapshot(snapshot_config(cli, args))
+            }
+            (Some(SnapshotCommands::Verify(args)), _) => verify_snapshot(verify_config(cli, args)),
+            (
+                Some(SnapshotCommands::Diff {
+                    snapshot1,
+                    snapshot2,
+                }),
+                _,
+            ) => {
+                for path in [&snapshot1, &snapshot2] {
+                    if !is_snapshot_file(path) {
+                        anyhow::bail!("snapshot diff: {} is not a snapshot file", path.display());
+                    }
+                }
+                run_compare(compare_config(cli, snapshot1, snapshot2))
+            }
+            (None, None) => unreachable!("clap requires FOLDER without a subcommand"),
+        },
+        Some(Commands::Verify(args)) => verify_snapshot(verify_config(cli, args)),
+        Some(Commands::Recheck {
+            report,
+            folder1,
+            folder2,
+        }) => run_recheck(
+            &report,
+            compare_config(
+                cli,
+                folder1.unwrap_or_default(),
+                folder2.unwrap_or_default(),
+            ),
+        ),
+        Some(Commands::Resolve { folder1, folder2 }) => run_resolve(
+            compare_config(cli, folder1, folder2),
+            std::io::stdin().lock(),
+        ),
+        Some(Commands::Prime { folder }) => {
+            let Some(hash_cache) = cli.hash_cache else {
+                anyhow::bail!("prime needs --hash-cache FILE to fill");
+            };
+            run_prime(PrimeConfig {
                 folder,
-                output,
+                hash_cache,
                 algo: cli.algo,
                 depth: cli.depth,
                 no_recursive: cli.no_recursive,
@@ -190,28 +833,39 @@ fn run() -> Result<ExitStatus> {
                 ignore: cli.ignore,
                 symlinks: cli.symlinks,
                 threads: cli.threads,
-            })?;
-            Ok(ExitStatus::Success)
+                read_ahead: cli.read_ahead,
+                owner_filter: cli.owner_filter,
+                one_file_system: cli.one_file_system,
+                explain_skips: cli.explain_skips,
+            })
         }
-        Some(Commands::Verify { folder, snapshot }) => verify_snapshot(VerifyConfig {
-            folder,
-            snapshot_path: snapshot,
-            threads: cli.threads,
-            output_format: cli.output_format,
-            verbose: cli.verbose,
-        }),
         Some(Commands::Sync {
             source,
             destination,
             dry_run,
             delete_extraneous,
             no_delete,
+            from_snapshot,
+            hard_links,
+            fix_metadata,
+            itemize,
+            on_create,
+            on_update,
+            on_delete,
         }) => run_sync(SyncConfig {
             source,
             destination,
             dry_run,
             delete_extraneous,
             no_delete,
+            from_snapshot,
+            hard_links,
+            fix_metadata,
+            itemize,
+            on_create,
+            on_update,
+            on_delete,
+            status_line: cli.status_line,
             algo: cli.algo,
             depth: cli.depth,
             no_recursive: cli.no_recursive,
@@ -220,30 +874,16 @@ fn run() -> Result<ExitStatus> {
             types: cli.types,
             ignore: cli.ignore,
             threads: cli.threads,
+            read_ahead: cli.read_ahead,
+            owner_filter: cli.owner_filter,
+            one_file_system: cli.one_file_system,
+            explain_skips: cli.explain_skips,
         }),
         None => {
             // Default to Compare with legacy args
-            if let (Some(f1), Some(f2)) = (cli.folder1, cli.folder2) {
-                run_compare(CompareConfig {
-                    folder1: f1,
-                    folder2: f2,
-                    mode: cli.mode,
-                    algo: cli.algo,
-                    output_folder: cli.output_folder,
-                    output_format: cli.output_format,
-                    depth: cli.depth,
-                    no_recursive: cli.no_recursive,
-                    symlinks: cli.symlinks,
-                    verbose: cli.verbose,
-                    hidden: cli.hidden,
-                    types: cli.types,
-                    ignore: cli.ignore,
-                    threads: cli.threads,
-                    no_sort: cli.no_sort,
-                    diff_cmd: cli.diff_cmd,
-                })
+            if let (Some(f1), Some(f2)) = (cli.folder1.take(), cli.folder2.take()) {
+                run_compare(compare_config(cli, f1, f2))
             } else {
-                use clap::CommandFactory;
                 let mut cmd = Cli::command();
                 cmd.print_help()?;
                 Ok(ExitStatus::Error)
@@ -251,3 +891,415 @@ fn run() -> Result<ExitStatus> {
         }
     }
 }
+
+/// `--baseline`: snapshot FOLDER the first time, compare against that snapshot afterwards.
+fn run_baseline(cli: Cli, folder: PathBuf) -> Result<ExitStatus> {
+    let folder = std::fs::canonicalize(&folder)
+        .with_context(|| format!("Cannot access {}", folder.display()))?;
+    let path = baseline_path(&folder, cli.baseline_dir.as_deref())?;
+    if path.exists() {
+        return run_compare(compare_config(cli, path, folder));
+    }
+    if cli.assert_readonly {
+        anyhow::bail!(
+            "--assert-readonly: no baseline is recorded for {} yet, and recording one writes {}",
+            folder.display(),
+            path.display()
+        );
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let status = create_snapshot(SnapshotConfig {
+        folder: folder.clone(),
+        output: Some(path.clone()),
+        algo: cli.algo,
+        depth: cli.depth,
+        no_recursive: cli.no_recursive,
+        hidden: cli.hidden,
+        types: cli.types,
+        ignore: cli.ignore,
+        symlinks: cli.symlinks,
+        threads: cli.threads,
+        read_ahead: cli.read_ahead,
+        owner_filter: cli.owner_filter,
+        one_file_system: cli.one_file_system,
+        explain_skips: cli.explain_skips,
+        ..Default::default()
+    })?;
+    eprintln!(
+        "Baseline recorded for {}; later runs compare against it",
+        folder.display()
+    );
+    Ok(status)
+}
+
+/// Finish the `CMPF_*` variable layer clap has filled in. A list option set
+/// through its variable arrives as one value: split it on commas, or like
+/// `PATH` for the files of `CMPF_EXCLUDE_FROM`. `--depth` and
+/// `--no-recursive` conflict only when both come from the same layer;
+/// otherwise the command line wins.
+fn apply_env(cli: &mut Cli, matches: &ArgMatches) -> Result<()> {
+    let from_env = |id: &str| matches.value_source(id) == Some(ValueSource::EnvVariable);
+    if cli.no_recursive && cli.depth.is_some() {
+        match (from_env("depth"), from_env("no_recursive")) {
+            (true, false) => cli.depth = None,
+            (false, true) => cli.no_recursive = false,
+            _ => anyhow::bail!("--no-recursive cannot be used with --depth"),
+        }
+    }
+    for (id, list) in [
+        ("types", &mut cli.types),
+        ("ignore", &mut cli.ignore),
+        ("presence_only", &mut cli.presence_only),
+    ] {
+        if from_env(id)
+            && let Some(values) = list
+        {
+            *values = values
+                .iter()
+                .flat_map(|v| v.split(','))
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(String::from)
+                .collect();
+        }
+    }
+    if from_env("exclude_from")
+        && let Some(files) = &mut cli.exclude_from
+    {
+        *files = files.iter().flat_map(std::env::split_paths).collect();
+    }
+    Ok(())
+}
+
+/// Fill in the options a `--profile` sets, except those given on the command
+/// line or through `CMPF_*` variables. Ignore patterns are added to any given
+/// explicitly.
+fn apply_preset(cli: &mut Cli, preset: Preset, matches: &ArgMatches) {
+    let given = |id: &str| {
+        matches!(
+            matches.value_source(id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
+    };
+    if let Some(mode) = preset.mode.filter(|_| !given("mode")) {
+        cli.mode = mode;
+    }
+    if let Some(algo) = preset.algo.filter(|_| !given("algo")) {
+        cli.algo = algo;
+    }
+    if let Some(symlinks) = preset.symlinks.filter(|_| !given("symlinks")) {
+        cli.symlinks = symlinks;
+    }
+    if let Some(types) = preset.types.filter(|_| !given("types")) {
+        cli.types = Some(types);
+    }
+    if let Some(ignore) = preset.ignore {
+        cli.ignore.get_or_insert_with(Vec::new).extend(ignore);
+    }
+    for (id, flag, value) in [
+        ("hidden", &mut cli.hidden, preset.hidden),
+        ("relocated", &mut cli.relocated, preset.relocated),
+        (
+            "auto_mtime_skew",
+            &mut cli.auto_mtime_skew,
+            preset.auto_mtime_skew,
+        ),
+        ("probe_fs", &mut cli.probe_fs, preset.probe_fs),
+        ("size_only", &mut cli.size_only, preset.size_only),
+    ] {
+        if let Some(value) = value.filter(|_| !given(id)) {
+            *flag = value;
+        }
+    }
+}
+
+/// Under `--assert-readonly`, the outputs the run may write: the explicitly
+/// requested reports and snapshot files. On Linux, landlock enforces this for
+/// the process and every program it starts, while the whole filesystem stays
+/// readable. Other platforms have nothing to enforce it with, so there
+/// `--diff-cmd` is refused as well. Everywhere, the subcommand and options
+/// that write elsewhere are refused up front rather than stopped halfway.
+fn readonly_rules(cli: &Cli) -> Result<Sandbox> {
+    if matches!(cli.command, Some(Commands::Sync { .. })) {
+        anyhow::bail!("--assert-readonly: the sync subcommand modifies files and is refused");
+    }
+    if matches!(cli.command, Some(Commands::Resolve { .. })) {
+        anyhow::bail!("--assert-readonly: the resolve subcommand modifies files and is refused");
+    }
+    if !cfg!(target_os = "linux") && cli.diff_cmd.is_some() {
+        anyhow::bail!("--assert-readonly: --diff-cmd launches external programs and is refused");
+    }
+    if cli.verdict_cache.is_some() {
+        anyhow::bail!("--assert-readonly: --verdict-cache writes a cache file and is refused");
+    }
+    if cli.hash_cache.is_some() {
+        anyhow::bail!("--assert-readonly: --hash-cache writes a cache file and is refused");
+    }
+    if cli.fs_snapshot {
+        anyhow::bail!(
+            "--assert-readonly: --fs-snapshot creates and mounts filesystem snapshots and is refused"
+        );
+    }
+    if cli.vss {
+        anyhow::bail!("--assert-readonly: --vss creates Volume Shadow Copies and is refused");
+    }
+    let mut rules = Sandbox::readonly();
+    if let Some(Commands::Snapshot { action, create }) = &cli.command
+        && let (Some(SnapshotCommands::Create(args)), _) | (None, Some(args)) = (action, create)
+        && let Some(output) = &args.output
+    {
+        rules.write_file(output)?;
+    }
+    for dir in [&cli.output_folder, &cli.emit_list_dir]
+        .into_iter()
+        .flatten()
+    {
+        rules.write_dir(dir)?;
+    }
+    if let Some(manifest) = &cli.emit_manifest {
+        rules.write_file(manifest)?;
+    }
+    Ok(rules)
+}
+
+/// Under `--sandbox`, folders only reachable over the network (rclone
+/// remotes, SSH, S3) are refused up front: their helper programs would be
+/// stopped by the sandbox halfway through the run.
+fn refuse_network(folder: &Path) -> Result<()> {
+    if is_remote(folder) || is_ssh(folder) || is_s3(folder) {
+        anyhow::bail!(
+            "--sandbox: {} is read over the network and is refused",
+            folder.display()
+        );
+    }
+    Ok(())
+}
+
+/// Under `--sandbox`, the paths the parsed command reads and the directories
+/// it writes its outputs to. Commands that modify the compared folders or
+/// run other programs are refused.
+fn sandbox_paths(cli: &Cli) -> Result<Sandbox> {
+    let mut sandbox = Sandbox::default();
+    match &cli.command {
+        Some(Commands::Sync { .. }) => {
+            anyhow::bail!("--sandbox: the sync subcommand modifies files and is refused")
+        }
+        Some(Commands::Resolve { .. }) => {
+            anyhow::bail!("--sandbox: the resolve subcommand modifies files and is refused")
+        }
+        Some(Commands::Compare {
+            folder1,
+            folder2,
+            replicas,
+        }) => {
+            for folder in [folder1, folder2].into_iter().chain(replicas) {
+                refuse_network(folder)?;
+                sandbox.read(folder);
+            }
+        }
+        Some(Commands::Snapshot { action, create }) => match (action, create) {
+            (Some(SnapshotCommands::Create(args)), _) | (None, Some(args)) => {
+                sandbox.read(&args.folder);
+                if let Some(output) = &args.output {
+                    sandbox.write_file(output)?;
+                }
+            }
+            (Some(SnapshotCommands::Verify(args)), _) => {
+                sandbox.read(&args.folder);
+                sandbox.read(&args.snapshot);
+            }
+            (
+                Some(SnapshotCommands::Diff {
+                    snapshot1,
+                    snapshot2,
+                }),
+                _,
+            ) => {
+                sandbox.read(snapshot1);
+                sandbox.read(snapshot2);
+            }
+            (None, None) => {}
+        },
+        Some(Commands::Verify(args)) => {
+            sandbox.read(&args.folder);
+            sandbox.read(&args.snapshot);
+        }
+        Some(Commands::Recheck {
+            report,
+            folder1,
+            folder2,
+        }) => {
+            let (Some(folder1), Some(folder2)) = (folder1, folder2) else {
+                anyhow::bail!("--sandbox: give recheck its folders explicitly");
+            };
+            sandbox.read(report);
+            sandbox.read(folder1);
+            sandbox.read(folder2);
+        }
+        Some(Commands::Prime { folder }) => sandbox.read(folder),
+        None => {
+            if cli.baseline.is_some() {
+                anyhow::bail!("--sandbox: --baseline writes its baseline store and is refused");
+            }
+            for folder in [&cli.folder1, &cli.folder2].into_iter().flatten() {
+                refuse_network(folder)?;
+                sandbox.read(folder);
+            }
+        }
+    }
+    if cli.diff_cmd.is_some() {
+        anyhow::bail!("--sandbox: --diff-cmd launches external programs and is refused");
+    }
+    if cli.fs_snapshot {
+        anyhow::bail!(
+            "--sandbox: --fs-snapshot creates and mounts filesystem snapshots and is refused"
+        );
+    }
+    if cli.vss {
+        anyhow::bail!("--sandbox: --vss creates Volume Shadow Copies and is refused");
+    }
+    for report in [&cli.baseline_report, &cli.previous_report]
+        .into_iter()
+        .flatten()
+    {
+        sandbox.read(report);
+    }
+    for dir in [&cli.output_folder, &cli.emit_list_dir]
+        .into_iter()
+        .flatten()
+    {
+        sandbox.write_dir(dir)?;
+    }
+    for file in [&cli.verdict_cache, &cli.hash_cache, &cli.emit_manifest]
+        .into_iter()
+        .flatten()
+    {
+        sandbox.write_file(file)?;
+    }
+    Ok(sandbox)
+}
+
+/// Shared by `snapshot create` and the bare `snapshot FOLDER` form.
+fn snapshot_config(cli: Cli, args: SnapshotArgs) -> SnapshotConfig {
+    SnapshotConfig {
+        folder: args.folder,
+        output: args.output,
+        algo: cli.algo,
+        depth: cli.depth,
+        no_recursive: cli.no_recursive,
+        hidden: cli.hidden,
+        types: cli.types,
+        ignore: cli.ignore,
+        symlinks: cli.symlinks,
+        threads: cli.threads,
+        read_ahead: cli.read_ahead,
+        owner_filter: cli.owner_filter,
+        one_file_system: cli.one_file_system,
+        explain_skips: cli.explain_skips,
+        double_read: args.double_read,
+        drop_cache: args.drop_cache,
+    }
+}
+
+/// Shared by `verify` and `snapshot verify`.
+fn verify_config(cli: Cli, args: VerifyArgs) -> VerifyConfig {
+    VerifyConfig {
+        folder: args.folder,
+        snapshot_path: args.snapshot,
+        threads: cli.threads,
+        read_ahead: cli.read_ahead,
+        output_format: cli.output_format(),
+        verbose: cli.verbose,
+        assert_readonly: cli.assert_readonly,
+        status_line: cli.status_line,
+        severities: cli.file_config.severity,
+        map_root: RootMap::new(args.map_root),
+        permission_exit: cli.permission_exit,
+        changes_only: args.changes_only,
+        no_root_check: cli.no_root_check,
+    }
+}
+
+/// Shared by the explicit `compare` subcommand and the legacy two-folder form.
+fn compare_config(cli: Cli, folder1: PathBuf, folder2: PathBuf) -> CompareConfig {
+    CompareConfig {
+        folder1,
+        folder2,
+        replicas: Vec::new(),
+        mode: cli.mode,
+        algo: cli.algo,
+        output_format: cli.output_format(),
+        output_folder: cli.output_folder,
+        depth: cli.depth,
+        no_recursive: cli.no_recursive,
+        symlinks: cli.symlinks,
+        verbose: cli.verbose,
+        hidden: cli.hidden,
+        types: cli.types,
+        ignore: cli.ignore,
+        threads: cli.threads,
+        read_ahead: cli.read_ahead,
+        no_sort: cli.no_sort,
+        interleave: cli.interleave,
+        diff_cmd: cli.diff_cmd,
+        block_diff: cli.block_diff,
+        block_size: cli.block_size,
+        diff_context: cli.diff_context,
+        diff_max_size: cli.diff_max_size,
+        size_only: cli.size_only,
+        report_checksum: cli.report_checksum,
+        precheck: cli.precheck,
+        precheck_threshold: cli.precheck_threshold,
+        verdict_cache: cli.verdict_cache,
+        hash_cache: cli.hash_cache,
+        assert_readonly: cli.assert_readonly,
+        vss: cli.vss,
+        fs_snapshot: cli.fs_snapshot,
+        presence_only: cli.presence_only,
+        sample: cli.sample,
+        seed: cli.seed,
+        status_line: cli.status_line,
+        relocated: cli.relocated,
+        detect_renames: cli.detect_renames,
+        auto_mtime_skew: cli.auto_mtime_skew,
+        status_rules: cli.file_config.rules,
+        subtrees: cli.file_config.subtrees,
+        compare_permissions: false,
+        severities: cli.file_config.severity,
+        emit_list_dir: cli.emit_list_dir,
+        emit_manifest: cli.emit_manifest,
+        breakdown: cli.breakdown,
+        resource_usage: cli.resource_usage,
+        quick: cli.quick,
+        zip_members: cli.zip_members,
+        s3_download: cli.s3_download,
+        trust_etags: cli.trust_etags,
+        force_algo: cli.force_algo,
+        float_tolerance: cli.float_tolerance,
+        results_page: ResultsPage {
+            statuses: cli.results_status,
+            offset: cli.results_offset,
+            limit: cli.results_limit,
+        },
+        stdin_format: cli.stdin_format,
+        strip_components: cli.strip_components,
+        archives: cli.archives,
+        archive_depth: cli.archive_depth,
+        probe_fs: cli.probe_fs,
+        stream: cli.stream,
+        max_memory: cli.max_memory,
+        permission_exit: cli.permission_exit,
+        normalize_symlinks: cli.normalize_symlinks,
+        baseline_report: cli.baseline_report,
+        no_root_check: cli.no_root_check,
+        previous_report: cli.previous_report,
+        detect_type: cli.detect_type,
+        ignore_known_diffs: cli.ignore_known_diffs,
+        owner_filter: cli.owner_filter,
+        one_file_system: cli.one_file_system,
+        explain_skips: cli.explain_skips,
+    }
+}
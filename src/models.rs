@@ -1,14 +1,40 @@
 use clap::ValueEnum;
 use colored::*;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum HashAlgo {
     Sha256,
+    #[default]
     Blake3,
     Both,
+    /// XXH3 (128-bit): much faster, but not cryptographic; for trusted local disks.
+    Xxh3,
+    /// SHA-512, for environments that mandate it.
+    Sha512,
+    /// BLAKE2b with a 512-bit digest, for environments that mandate it.
+    Blake2b,
+    /// Broken as a security hash; only for matching existing manifests.
+    Md5,
+    /// Broken as a security hash; only for matching existing manifests.
+    Sha1,
+}
+
+impl HashAlgo {
+    /// MD5 and SHA-1 are offered for interop with existing manifests only.
+    pub fn is_legacy(self) -> bool {
+        matches!(self, HashAlgo::Md5 | HashAlgo::Sha1)
+    }
+
+    /// Whether digests recorded with `self` hold those `algo` compares.
+    pub fn covers(self, algo: HashAlgo) -> bool {
+        self == algo
+            || (self == HashAlgo::Both && matches!(algo, HashAlgo::Sha256 | HashAlgo::Blake3))
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
@@ -16,14 +42,43 @@ pub enum OutputFormat {
     #[default]
     Txt,
     Json,
+    /// GitLab Code Quality JSON, one issue per difference
+    CodeQuality,
+    /// Standalone HTML page with a collapsible directory tree
+    Html,
+    /// Comma-separated values, one row per file
+    Csv,
+    /// Tab-separated values, one row per file
+    Tsv,
+    /// One JSON object per line, printed as soon as each result is known
+    Ndjson,
+    /// Tab-separated `STATUS<TAB>PATH` lines for scripts, stable across versions
+    Porcelain,
+}
+
+impl OutputFormat {
+    /// Formats printed one result per line, as soon as it is known in
+    /// realtime mode and under `--stream`.
+    pub fn is_line_based(self) -> bool {
+        matches!(self, Self::Ndjson | Self::Porcelain)
+    }
+
+    /// Formats read as a whole by a program or browser, which progress notes
+    /// must not be mixed into.
+    pub fn is_document(self) -> bool {
+        !matches!(self, Self::Txt)
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Mode {
     Realtime,
+    #[default]
     Batch,
     Metadata,
+    /// Read both files side by side and stop at the first differing byte.
+    Bytes,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default, Serialize, Deserialize)]
@@ -35,6 +90,24 @@ pub enum SymlinkMode {
     Compare,
 }
 
+/// How archives found in a compared folder are treated (`--archives`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub enum ArchiveMode {
+    /// Compare archives as the files they are
+    #[default]
+    File,
+    /// Open zip and tar archives and compare their members as a directory
+    Recurse,
+}
+
+/// Format of a side read from stdin (given as `-`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub enum StdinFormat {
+    /// A tar archive, e.g. from `tar cf - DIR`
+    #[default]
+    Tar,
+}
+
 // Fix #8: Status is now a typed enum instead of a plain String,
 // eliminating silent fall-through bugs on typos/missing arms.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -42,8 +115,13 @@ pub enum SymlinkMode {
 pub enum Status {
     Match,
     Diff,
+    /// Content differs, but a `--config` rule declares the difference expected
+    AllowedDiff,
     Missing,
     Extra,
+    /// With `--detect-renames`: a MISSING file whose content is an EXTRA file
+    /// under another path
+    Renamed,
     Error,
     /// Used by sync subcommand
     Create,
@@ -51,6 +129,19 @@ pub enum Status {
     Update,
     /// Used by sync subcommand
     Delete,
+    /// Used by sync subcommand: content matches, only permissions/ownership/mtime differ
+    Metadata,
+    /// Walker yielded a path outside the root (e.g. a followed symlink escaping it)
+    OutOfTree,
+    /// With `--symlinks compare`: a symbolic link on one side and a real
+    /// directory on the other
+    TypeMismatch,
+    /// With `--quick`: same size, first and last bytes; the middle was not read
+    #[serde(rename = "MATCH(QUICK)")]
+    QuickMatch,
+    /// A file's size or modification time changed while it was being read,
+    /// so neither MATCH nor DIFF can be trusted
+    Unstable,
 }
 
 impl std::fmt::Display for Status {
@@ -58,34 +149,240 @@ impl std::fmt::Display for Status {
         match self {
             Status::Match => write!(f, "MATCH"),
             Status::Diff => write!(f, "DIFF"),
+            Status::AllowedDiff => write!(f, "ALLOWED_DIFF"),
             Status::Missing => write!(f, "MISSING"),
             Status::Extra => write!(f, "EXTRA"),
+            Status::Renamed => write!(f, "RENAMED"),
             Status::Error => write!(f, "ERROR"),
             Status::Create => write!(f, "CREATE"),
             Status::Update => write!(f, "UPDATE"),
             Status::Delete => write!(f, "DELETE"),
+            Status::Metadata => write!(f, "METADATA"),
+            Status::OutOfTree => write!(f, "OUT_OF_TREE"),
+            Status::TypeMismatch => write!(f, "TYPE_MISMATCH"),
+            Status::QuickMatch => write!(f, "MATCH(QUICK)"),
+            Status::Unstable => write!(f, "UNSTABLE"),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How much a class of results matters: only `Error` makes the exit code 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Severity {
+    pub fn color(self) -> Color {
+        match self {
+            Severity::Error => Color::Red,
+            Severity::Warning => Color::Yellow,
+            Severity::Info => Color::Cyan,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct HashResult {
     pub sha256: Option<String>,
     pub blake3: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xxh3: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blake2b: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+}
+
+impl HashResult {
+    /// Whether two digests agree on the digest(s) selected by `algo`.
+    pub fn matches(&self, other: &HashResult, algo: HashAlgo) -> bool {
+        match algo {
+            HashAlgo::Sha256 => self.sha256 == other.sha256,
+            HashAlgo::Blake3 => self.blake3 == other.blake3,
+            HashAlgo::Both => self.sha256 == other.sha256 && self.blake3 == other.blake3,
+            HashAlgo::Xxh3 => self.xxh3 == other.xxh3,
+            HashAlgo::Sha512 => self.sha512 == other.sha512,
+            HashAlgo::Blake2b => self.blake2b == other.blake2b,
+            HashAlgo::Md5 => self.md5 == other.md5,
+            HashAlgo::Sha1 => self.sha1 == other.sha1,
+        }
+    }
+}
+
+/// Reproducible choice of which files get hashed under `--sample`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SampleParams {
+    pub percent: f64,
+    pub seed: u64,
 }
 
-#[derive(Debug, Clone)]
+impl SampleParams {
+    /// Whether `rel_path` falls in the sample. The decision depends only on the
+    /// seed and the path, so the same seed picks the same files in any order.
+    pub fn selects(&self, rel_path: &std::path::Path) -> bool {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.seed.to_le_bytes());
+        hasher.update(rel_path.as_os_str().as_encoded_bytes());
+        let mut head = [0u8; 8];
+        head.copy_from_slice(&hasher.finalize().as_bytes()[..8]);
+        (u64::from_le_bytes(head) as f64) < self.percent / 100.0 * u64::MAX as f64
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct FileEntry {
     pub path: PathBuf,
     pub size: u64,
     pub modified: Option<std::time::SystemTime>,
     pub symlink_target: Option<String>,
+    /// `(device, inode)` when the file has more than one hard link (Unix only).
+    pub link_id: Option<(u64, u64)>,
+    /// Inode number, Unix only.
+    pub inode: Option<u64>,
+    /// Permission bits (`mode & 0o7777`), Unix only.
+    pub permissions: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// Digest recorded in a snapshot; set when the entry does not exist on disk.
+    pub hashes: Option<HashResult>,
+    /// ETag an object store listed for the entry, without its quotes.
+    pub etag: Option<String>,
+}
+
+/// Metadata of a compared root directory itself, which the file walk never reports.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RootAttrs {
+    /// Permission bits (`mode & 0o7777`), Unix only.
+    pub permissions: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// Extended attribute names mapped to the BLAKE3 digest of their value
+    /// (Linux and macOS).
+    #[serde(default)]
+    pub xattrs: BTreeMap<String, String>,
+}
+
+impl RootAttrs {
+    /// Human-readable list of what differs; fields unknown on either side are skipped.
+    pub fn differences(&self, other: &RootAttrs) -> Vec<String> {
+        let mut out = Vec::new();
+        if let (Some(a), Some(b)) = (self.permissions, other.permissions)
+            && a != b
+        {
+            out.push(format!("permissions {:04o} vs {:04o}", a, b));
+        }
+        if let (Some(a), Some(b)) = (self.uid, other.uid)
+            && a != b
+        {
+            out.push(format!("uid {} vs {}", a, b));
+        }
+        if let (Some(a), Some(b)) = (self.gid, other.gid)
+            && a != b
+        {
+            out.push(format!("gid {} vs {}", a, b));
+        }
+        let names: BTreeSet<&String> = self.xattrs.keys().chain(other.xattrs.keys()).collect();
+        for name in names {
+            match (self.xattrs.get(name), other.xattrs.get(name)) {
+                (Some(_), None) => out.push(format!("xattr {} only in folder1", name)),
+                (None, Some(_)) => out.push(format!("xattr {} only in folder2", name)),
+                (a, b) if a != b => out.push(format!("xattr {} differs", name)),
+                _ => {}
+            }
+        }
+        out
+    }
+}
+
+/// Outcome of comparing the two roots' own metadata.
+#[derive(Debug, Clone, Serialize)]
+pub struct RootCheck {
+    pub status: Status,
+    pub folder1: RootAttrs,
+    pub folder2: RootAttrs,
+    pub differences: Vec<String>,
+}
+
+impl RootCheck {
+    pub fn new(folder1: RootAttrs, folder2: RootAttrs) -> Self {
+        let differences = folder1.differences(&folder2);
+        Self {
+            status: if differences.is_empty() {
+                Status::Match
+            } else {
+                Status::Diff
+            },
+            folder1,
+            folder2,
+            differences,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct ErrorEntry {
     pub path: PathBuf,
     pub error: String,
+    pub kind: ErrorKind,
+}
+
+impl ErrorEntry {
+    pub fn new(path: PathBuf, error: String) -> Self {
+        Self {
+            path,
+            error,
+            kind: ErrorKind::Io,
+        }
+    }
+
+    pub fn permission_denied(&self) -> bool {
+        self.kind == ErrorKind::PermissionDenied
+    }
+}
+
+/// Broad class of an `ErrorEntry`, so permission problems can be told apart
+/// from disks and networks failing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    PermissionDenied,
+    Io,
+}
+
+impl ErrorKind {
+    pub fn of(err: &io::Error) -> Self {
+        if err.kind() == io::ErrorKind::PermissionDenied {
+            Self::PermissionDenied
+        } else {
+            Self::Io
+        }
+    }
+}
+
+/// An inclusive-exclusive byte range `[start, end)` within a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Per-chunk comparison of two differing files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockDiff {
+    pub block_size: u64,
+    pub total_blocks: u64,
+    pub changed_blocks: u64,
+    pub changed_bytes: u64,
+    /// Contiguous runs of differing chunks, merged.
+    pub ranges: Vec<ByteRange>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -100,14 +397,135 @@ pub struct ComparisonResult {
     pub modified2: Option<String>,
     pub symlink1: Option<String>,
     pub symlink2: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_diff: Option<BlockDiff>,
+    /// Offset of the first differing byte (`--mode bytes`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_diff: Option<u64>,
+    /// Why a side was read with plain reads instead of a memory mapping,
+    /// prefixed with the side.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_fallback: Option<String>,
+    /// Unified diff of a differing text file (`--verbose`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unified_diff: Option<String>,
+    /// With `--relocated`: for a MISSING entry, the folder2 paths holding the same
+    /// content; for an EXTRA entry, the folder1 paths.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relocated: Option<Vec<PathBuf>>,
+    /// For a RENAMED entry, its path in folder2; `file` is the folder1 path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_to: Option<PathBuf>,
+    /// Severity assigned by a `[severity]` table in `--config`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    /// Why an ERROR result could not be compared, prefixed with the side that failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<ErrorKind>,
+    /// MIME types detected from the content of each side (`--detect-type`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type1: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type2: Option<String>,
+}
+
+/// A path that was DIFF/MISSING/EXTRA in an earlier report, looked at again.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecheckEntry {
+    pub file: PathBuf,
+    pub previous: Status,
+    /// Status now; `None` when the path no longer exists in either folder.
+    pub current: Option<Status>,
+}
+
+/// A path whose status changed since the report given to `--baseline-report`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DriftEntry {
+    pub file: PathBuf,
+    /// Status in the baseline report; `None` when the report did not list the path.
+    pub previous: Option<Status>,
+    /// Status now; `None` when neither folder has the path any more.
+    pub current: Option<Status>,
+}
+
+/// Agreement of the replicas compared by `compare A B C...` on one path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReplicaStatus {
+    /// Every replica holds the same version.
+    Agree,
+    Diverge,
+    /// At least one replica could not be read.
+    Error,
+}
+
+impl std::fmt::Display for ReplicaStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplicaStatus::Agree => write!(f, "AGREE"),
+            ReplicaStatus::Diverge => write!(f, "DIVERGE"),
+            ReplicaStatus::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+/// One path compared across three or more replicas.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicaEntry {
+    pub file: PathBuf,
+    pub status: ReplicaStatus,
+    /// The version each replica holds, in command-line order: `A` for the
+    /// most common one, then `B` and so on; `-` where the path is absent and
+    /// `!` where it could not be read.
+    pub versions: Vec<String>,
+    /// The version held by more than half of the replicas, `-` when most of
+    /// them lack the path.
+    pub consensus: Option<String>,
+    /// Replicas (numbered from 1) holding the consensus.
+    pub agreeing: Vec<usize>,
+    /// Replicas holding anything else, all of them without a consensus.
+    pub diverging: Vec<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+impl DriftEntry {
+    /// The path now needs attention: it differs, is one-sided, or failed.
+    pub fn worsened(&self) -> bool {
+        !matches!(
+            self.current,
+            None | Some(Status::Match | Status::AllowedDiff)
+        )
+    }
+
+    /// The path needed attention in the baseline and now matches; a path the
+    /// baseline did not list does not count.
+    pub fn improved(&self) -> bool {
+        let matching =
+            |s: Status| matches!(s, Status::Match | Status::QuickMatch | Status::AllowedDiff);
+        self.previous.is_some_and(|s| !matching(s)) && self.current.is_some_and(matching)
+    }
+}
+
+impl RecheckEntry {
+    pub fn resolved(&self) -> bool {
+        matches!(
+            self.current,
+            None | Some(Status::Match | Status::AllowedDiff)
+        )
+    }
 }
 
 // Fix #12: Constructor helpers to eliminate boilerplate None-filled struct construction.
 impl ComparisonResult {
-    pub fn missing(path: PathBuf) -> Self {
+    /// A result with only the path and status filled in. Other constructors and
+    /// struct-update literals build on this so new fields only need a default here.
+    pub fn new(path: PathBuf, status: Status) -> Self {
         Self {
             file: path,
-            status: Status::Missing,
+            status,
             hash1: None,
             hash2: None,
             size1: None,
@@ -116,46 +534,116 @@ impl ComparisonResult {
             modified2: None,
             symlink1: None,
             symlink2: None,
+            block_diff: None,
+            first_diff: None,
+            read_fallback: None,
+            unified_diff: None,
+            relocated: None,
+            renamed_to: None,
+            severity: None,
+            error: None,
+            error_kind: None,
+            mime_type1: None,
+            mime_type2: None,
         }
     }
 
-    pub fn extra(path: PathBuf) -> Self {
+    /// An ERROR result for a file that could not be hashed on `side`.
+    pub fn failed(path: PathBuf, side: &str, err: &io::Error) -> Self {
         Self {
-            file: path,
-            status: Status::Extra,
-            hash1: None,
-            hash2: None,
-            size1: None,
-            size2: None,
-            modified1: None,
-            modified2: None,
-            symlink1: None,
-            symlink2: None,
+            error: Some(format!("{}: {}", side, err)),
+            error_kind: Some(ErrorKind::of(err)),
+            ..Self::new(path, Status::Error)
         }
     }
 
+    pub fn permission_denied(&self) -> bool {
+        self.error_kind == Some(ErrorKind::PermissionDenied)
+    }
+
+    pub fn missing(path: PathBuf) -> Self {
+        Self::new(path, Status::Missing)
+    }
+
+    pub fn extra(path: PathBuf) -> Self {
+        Self::new(path, Status::Extra)
+    }
+
     pub fn format_text(&self, verbose: bool, algo: HashAlgo) -> anyhow::Result<String> {
         let mut output = String::new();
         let (status_colored, file_color) = match self.status {
             Status::Match => ("MATCH".green(), Color::Green),
             Status::Diff => ("DIFF".red(), Color::Red),
+            Status::AllowedDiff => ("ALLOWED_DIFF".yellow(), Color::Yellow),
             Status::Missing => ("MISSING".blue(), Color::Blue),
             Status::Extra => ("EXTRA".blue(), Color::Blue),
+            Status::Renamed => ("RENAMED".cyan(), Color::Cyan),
             Status::Error => ("ERROR".red().on_white(), Color::Red),
             Status::Create => ("CREATE".green(), Color::Green),
             Status::Update => ("UPDATE".yellow(), Color::Yellow),
             Status::Delete => ("DELETE".red(), Color::Red),
+            Status::Metadata => ("METADATA".yellow(), Color::Yellow),
+            Status::OutOfTree => ("OUT_OF_TREE".magenta(), Color::Magenta),
+            Status::TypeMismatch => ("TYPE_MISMATCH".red(), Color::Red),
+            Status::QuickMatch => ("MATCH(QUICK)".green(), Color::Green),
+            Status::Unstable => ("UNSTABLE".magenta(), Color::Magenta),
+        };
+        let (status_colored, file_color) = match self.severity {
+            Some(severity) => (
+                self.status.to_string().color(severity.color()),
+                severity.color(),
+            ),
+            None => (status_colored, file_color),
         };
 
         let file_name = self.file.to_str().unwrap_or("Invalid Name");
+        let file_name = match &self.renamed_to {
+            Some(to) => format!("{} -> {}", file_name, to.display()),
+            None => file_name.to_string(),
+        };
         output.push_str(&format!(
             "[{}]  {}\n",
             status_colored,
             file_name.color(file_color)
         ));
 
+        if let Some(error) = &self.error {
+            output.push_str(&format!("    {}: {}\n", "error".dimmed(), error.red()));
+        }
+
+        if let Some(offset) = self.first_diff {
+            output.push_str(&format!(
+                "    {}: byte {}\n",
+                "first difference".dimmed(),
+                offset.to_string().yellow()
+            ));
+        }
+
+        if let Some(bd) = &self.block_diff {
+            output.push_str(&format!(
+                "    {}: {} of {} blocks changed ({} bytes)\n",
+                "blocks".dimmed(),
+                bd.changed_blocks.to_string().yellow(),
+                bd.total_blocks,
+                bd.changed_bytes
+            ));
+            let ranges: Vec<String> = bd
+                .ranges
+                .iter()
+                .map(|r| format!("{}..{}", r.start, r.end))
+                .collect();
+            output.push_str(&format!(
+                "    {}: {}\n",
+                "ranges".dimmed(),
+                ranges.join(", ").cyan()
+            ));
+        }
+
         if verbose {
-            if self.status == Status::Diff {
+            if let Some(note) = &self.read_fallback {
+                output.push_str(&format!("    {}: {}\n", "note".dimmed(), note.yellow()));
+            }
+            if matches!(self.status, Status::Diff | Status::AllowedDiff) {
                 if let (Some(h1), Some(h2)) = (&self.hash1, &self.hash2) {
                     output.push_str(&format!(
                         "    {}: {}\n",
@@ -207,6 +695,14 @@ impl ComparisonResult {
                         ));
                     }
                 }
+            } else if self.status == Status::TypeMismatch {
+                for (side, link) in [("folder1", &self.symlink1), ("folder2", &self.symlink2)] {
+                    let kind = match link {
+                        Some(target) => format!("-> {}", target),
+                        None => "directory".to_string(),
+                    };
+                    output.push_str(&format!("    {}: {}\n", side.dimmed(), kind.cyan()));
+                }
             } else if self.status == Status::Match
                 && let Some(h1) = &self.hash1
             {
@@ -216,6 +712,20 @@ impl ComparisonResult {
                     self.format_hashres(h1, algo)?
                 ));
             }
+            if let Some(diff) = &self.unified_diff {
+                for line in diff.lines() {
+                    let line = if line.starts_with("@@") {
+                        line.cyan()
+                    } else if line.starts_with('+') {
+                        line.green()
+                    } else if line.starts_with('-') {
+                        line.red()
+                    } else {
+                        line.normal()
+                    };
+                    output.push_str(&format!("    {}\n", line));
+                }
+            }
         }
         Ok(output)
     }
@@ -246,6 +756,36 @@ impl ComparisonResult {
                     .context("BLAKE3 hash not computed")?
                     .color(Color::Cyan)
             )),
+            HashAlgo::Xxh3 => Ok(h
+                .xxh3
+                .as_ref()
+                .context("XXH3 hash not computed")?
+                .color(Color::Cyan)
+                .to_string()),
+            HashAlgo::Sha512 => Ok(h
+                .sha512
+                .as_ref()
+                .context("SHA-512 hash not computed")?
+                .color(Color::Cyan)
+                .to_string()),
+            HashAlgo::Blake2b => Ok(h
+                .blake2b
+                .as_ref()
+                .context("BLAKE2b hash not computed")?
+                .color(Color::Cyan)
+                .to_string()),
+            HashAlgo::Md5 => Ok(h
+                .md5
+                .as_ref()
+                .context("MD5 hash not computed")?
+                .color(Color::Cyan)
+                .to_string()),
+            HashAlgo::Sha1 => Ok(h
+                .sha1
+                .as_ref()
+                .context("SHA-1 hash not computed")?
+                .color(Color::Cyan)
+                .to_string()),
         }
     }
 }
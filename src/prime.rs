@@ -0,0 +1,151 @@
+use anyhow::Result;
+use colored::*;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::activity;
+use crate::cache::HashCache;
+use crate::compare::ExitStatus;
+use crate::models::{FileEntry, HashAlgo, SymlinkMode};
+use crate::report::{print_error_entries, print_skips};
+use crate::utils::{collect_files, compute_hashes, explain_skips};
+
+pub struct PrimeConfig {
+    pub folder: PathBuf,
+    /// Cache file to fill, as given to `--hash-cache`.
+    pub hash_cache: PathBuf,
+    pub algo: HashAlgo,
+    pub depth: Option<usize>,
+    pub no_recursive: bool,
+    pub hidden: bool,
+    pub types: Option<Vec<String>>,
+    pub ignore: Option<Vec<String>>,
+    pub symlinks: SymlinkMode,
+    pub threads: Option<usize>,
+    /// Buffers the reader fills ahead of the hasher (`--read-ahead`); when set,
+    /// every file past the small-file path is streamed.
+    pub read_ahead: Option<usize>,
+    pub owner_filter: Option<u32>,
+    pub one_file_system: bool,
+    pub explain_skips: bool,
+}
+
+/// Hash every file under `config.folder` the cache does not hold yet and save
+/// the cache, so later comparisons touching the tree skip reading it.
+/// Entries for files deleted from the folder are dropped on the way.
+pub fn run_prime(config: PrimeConfig) -> Result<ExitStatus> {
+    let start_time = Instant::now();
+    if let Some(num_threads) = config.threads {
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build_global();
+    }
+    if !config.folder.is_dir() {
+        anyhow::bail!(
+            "prime needs a folder, but {} is not one",
+            config.folder.display()
+        );
+    }
+
+    let mut cache = HashCache::load(&config.hash_cache, config.algo)?;
+    if config.explain_skips {
+        print_skips(
+            &explain_skips(
+                &config.folder,
+                config.depth,
+                config.no_recursive,
+                config.hidden,
+                &config.types,
+                &config.ignore,
+                config.symlinks,
+                config.owner_filter,
+                config.one_file_system,
+            )?,
+            "folder",
+        );
+    }
+    let (files, errors) = collect_files(
+        &config.folder,
+        config.depth,
+        config.no_recursive,
+        config.hidden,
+        &config.types,
+        &config.ignore,
+        config.symlinks,
+        config.owner_filter,
+        config.one_file_system,
+    )?;
+    print_error_entries(&errors, "folder");
+
+    // Links compared by target are never hashed, so there is nothing to cache.
+    let files: Vec<&FileEntry> = files
+        .iter()
+        .filter(|f| f.symlink_target.is_none())
+        .collect();
+    let pending: Vec<&FileEntry> = files
+        .iter()
+        .copied()
+        .filter(|f| cache.lookup(f).is_none())
+        .collect();
+
+    let pb = if io::stderr().is_terminal() {
+        let pb = ProgressBar::new(pending.len() as u64);
+        pb.set_style(ProgressStyle::default_bar().template(
+            "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} ({eta})",
+        )?);
+        Some(activity::attach(pb))
+    } else {
+        None
+    };
+    let hashed: Vec<_> = pending
+        .par_iter()
+        .map(|f| {
+            let h = compute_hashes(&f.path, config.algo, config.read_ahead);
+            if let Some(ref p) = pb {
+                p.inc(1);
+            }
+            (*f, h)
+        })
+        .collect();
+    if let Some(ref p) = pb {
+        p.finish_and_clear();
+    }
+
+    let (mut failed, mut bytes) = (0, 0);
+    for (f, h) in hashed {
+        match h {
+            Ok(h) => {
+                bytes += f.size;
+                cache.record(f, h);
+            }
+            Err(e) => {
+                eprintln!(
+                    "[{}] {} ({})",
+                    "ERROR".red().on_white(),
+                    f.path.display(),
+                    e
+                );
+                failed += 1;
+            }
+        }
+    }
+    cache.prune(&config.folder);
+    cache.save(&config.hash_cache)?;
+
+    println!(
+        "Primed {}: {} file(s) hashed ({} bytes), {} already cached, in {:.2?}",
+        config.folder.display(),
+        pending.len() - failed,
+        bytes,
+        files.len() - pending.len(),
+        start_time.elapsed()
+    );
+    Ok(if errors.is_empty() && failed == 0 {
+        ExitStatus::Success
+    } else {
+        ExitStatus::Error
+    })
+}
@@ -0,0 +1,189 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Coarsest first: the granularity reported is the largest one that divides
+/// the timestamp read back.
+const GRANULARITIES: [Duration; 7] = [
+    Duration::from_secs(2),
+    Duration::from_secs(1),
+    Duration::from_millis(10),
+    Duration::from_millis(1),
+    Duration::from_micros(1),
+    Duration::from_nanos(100),
+    Duration::from_nanos(1),
+];
+
+/// What `--probe-fs` found out about the filesystem holding one folder.
+/// `None` means the property could not be determined.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FsCapabilities {
+    pub case_sensitive: Option<bool>,
+    pub mtime_granularity: Option<Duration>,
+    pub symlinks: Option<bool>,
+    /// Found with a scratch file written into the folder rather than inferred
+    /// from existing entries.
+    pub written: bool,
+}
+
+impl FsCapabilities {
+    pub fn describe(&self) -> String {
+        let case = match self.case_sensitive {
+            Some(true) => "case-sensitive",
+            Some(false) => "case-insensitive",
+            None => "case sensitivity unknown",
+        };
+        let mtime = self
+            .mtime_granularity
+            .map_or("unknown".to_string(), format_granularity);
+        let symlinks = match self.symlinks {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "unknown",
+        };
+        format!(
+            "{}, mtime granularity {}, symlinks {}{}",
+            case,
+            mtime,
+            symlinks,
+            if self.written { "" } else { " (inferred)" }
+        )
+    }
+}
+
+pub fn format_granularity(d: Duration) -> String {
+    if d >= Duration::from_secs(1) {
+        format!("{} s", d.as_secs())
+    } else if d >= Duration::from_millis(1) {
+        format!("{} ms", d.as_millis())
+    } else if d >= Duration::from_micros(1) {
+        format!("{} µs", d.as_micros())
+    } else {
+        format!("{} ns", d.as_nanos())
+    }
+}
+
+/// Probe the filesystem of `root`. With `readonly` nothing is written and the
+/// answers are inferred from entries already there, which may leave some unknown.
+pub fn probe(root: &Path, readonly: bool) -> FsCapabilities {
+    if !readonly && let Some(caps) = probe_by_writing(root) {
+        return caps;
+    }
+    infer(root)
+}
+
+/// Create a scratch file, set a timestamp with an odd second and sub-second
+/// digits, and see what survives; then look it up under another case and try
+/// to link to it. Everything created is removed again.
+fn probe_by_writing(root: &Path) -> Option<FsCapabilities> {
+    let name = format!(".cmpf-probe-{}", std::process::id());
+    let path = root.join(&name);
+    let file = File::create_new(&path).ok()?;
+
+    let written = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_001, 123_456_789);
+    let mtime_granularity = file
+        .set_modified(written)
+        .and_then(|_| file.metadata()?.modified())
+        .ok()
+        .and_then(granularity_of);
+    drop(file);
+
+    let case_sensitive = Some(!root.join(name.to_uppercase()).exists());
+
+    let link = root.join(format!("{}-link", name));
+    let symlinks = Some(make_symlink(&path, &link).is_ok());
+    let _ = fs::remove_file(&link);
+    let _ = fs::remove_file(&path);
+
+    Some(FsCapabilities {
+        case_sensitive,
+        mtime_granularity,
+        symlinks,
+        written: true,
+    })
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn make_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn make_symlink(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Fewest timestamps the read-only fallback needs before naming a granularity;
+/// a handful of whole-second mtimes says little about the filesystem.
+const MIN_MTIME_SAMPLES: usize = 16;
+
+/// Read-only fallback using up to 256 entries near the root: the coarsest
+/// granularity dividing all their mtimes, whether a name with letters also
+/// resolves under swapped case, and whether any symlink exists.
+fn infer(root: &Path) -> FsCapabilities {
+    let mut entries: Vec<PathBuf> = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(read) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for e in read.flatten() {
+            if entries.len() >= 256 {
+                break;
+            }
+            if e.file_type().is_ok_and(|t| t.is_dir()) {
+                dirs.push(e.path());
+            }
+            entries.push(e.path());
+        }
+    }
+
+    let mut caps = FsCapabilities::default();
+    let mut samples = 0;
+    for path in &entries {
+        let Ok(meta) = fs::symlink_metadata(path) else {
+            continue;
+        };
+        if meta.file_type().is_symlink() {
+            caps.symlinks = Some(true);
+        }
+        if let Some(g) = meta.modified().ok().and_then(granularity_of) {
+            caps.mtime_granularity = Some(caps.mtime_granularity.map_or(g, |c| c.min(g)));
+            samples += 1;
+        }
+        if caps.case_sensitive.is_none()
+            && let Some(name) = path.file_name().and_then(|n| n.to_str())
+        {
+            let swapped: String = name
+                .chars()
+                .map(|c| {
+                    if c.is_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect();
+            if swapped != name && !entries.contains(&path.with_file_name(&swapped)) {
+                caps.case_sensitive = Some(!path.with_file_name(&swapped).exists());
+            }
+        }
+    }
+    if samples < MIN_MTIME_SAMPLES {
+        caps.mtime_granularity = None;
+    }
+    caps
+}
+
+fn granularity_of(t: SystemTime) -> Option<Duration> {
+    let nanos = t.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_nanos();
+    GRANULARITIES
+        .iter()
+        .copied()
+        .find(|g| nanos % g.as_nanos() == 0)
+}
@@ -0,0 +1,113 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::models::{HashAlgo, Mode, SymlinkMode};
+
+/// Named bundles of options selected with `--profile`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Profile {
+    /// Media libraries: image and video files only, moved files detected, camera/NAS clock offsets ignored
+    Photos,
+    /// Source trees: dotfiles and symlinks included, build output and VCS metadata skipped
+    Code,
+    /// Restored or copied backups: SHA-256 and BLAKE3, everything included, filesystem quirks tolerated
+    BackupVerify,
+    /// Quick check by size and modification time, without reading file contents
+    Fast,
+}
+
+/// Options a profile sets. Unset fields leave the option at its default;
+/// options given on the command line always win over the profile.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    pub mode: Option<Mode>,
+    pub algo: Option<HashAlgo>,
+    pub symlinks: Option<SymlinkMode>,
+    pub hidden: Option<bool>,
+    pub types: Option<Vec<String>>,
+    pub ignore: Option<Vec<String>>,
+    pub relocated: Option<bool>,
+    pub auto_mtime_skew: Option<bool>,
+    pub probe_fs: Option<bool>,
+    pub size_only: Option<bool>,
+}
+
+impl Preset {
+    /// Fields set in `self` replace those of `base`.
+    fn over(self, base: Preset) -> Preset {
+        Preset {
+            mode: self.mode.or(base.mode),
+            algo: self.algo.or(base.algo),
+            symlinks: self.symlinks.or(base.symlinks),
+            hidden: self.hidden.or(base.hidden),
+            types: self.types.or(base.types),
+            ignore: self.ignore.or(base.ignore),
+            relocated: self.relocated.or(base.relocated),
+            auto_mtime_skew: self.auto_mtime_skew.or(base.auto_mtime_skew),
+            probe_fs: self.probe_fs.or(base.probe_fs),
+            size_only: self.size_only.or(base.size_only),
+        }
+    }
+}
+
+fn strings(items: &[&str]) -> Option<Vec<String>> {
+    Some(items.iter().map(|s| s.to_string()).collect())
+}
+
+impl Profile {
+    pub fn builtin(self) -> Preset {
+        match self {
+            Profile::Photos => Preset {
+                mode: Some(Mode::Batch),
+                types: strings(&[
+                    "jpg", "jpeg", "png", "gif", "heic", "heif", "webp", "tif", "tiff", "dng",
+                    "cr2", "cr3", "nef", "arw", "raf", "orf", "rw2", "mp4", "mov", "m4v",
+                ]),
+                relocated: Some(true),
+                auto_mtime_skew: Some(true),
+                ..Default::default()
+            },
+            Profile::Code => Preset {
+                mode: Some(Mode::Batch),
+                symlinks: Some(SymlinkMode::Compare),
+                hidden: Some(true),
+                ignore: strings(&[
+                    "**/.git/**",
+                    "**/.hg/**",
+                    "**/.svn/**",
+                    "**/target/**",
+                    "**/node_modules/**",
+                    "**/__pycache__/**",
+                    "**/*.pyc",
+                    "**/*.o",
+                ]),
+                ..Default::default()
+            },
+            Profile::BackupVerify => Preset {
+                mode: Some(Mode::Batch),
+                algo: Some(HashAlgo::Both),
+                symlinks: Some(SymlinkMode::Compare),
+                hidden: Some(true),
+                probe_fs: Some(true),
+                ..Default::default()
+            },
+            Profile::Fast => Preset {
+                mode: Some(Mode::Metadata),
+                probe_fs: Some(true),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// The built-in preset with the `[profile.<name>]` table of `--config`
+    /// laid over it.
+    pub fn preset(self, overrides: &HashMap<Profile, Preset>) -> Preset {
+        match overrides.get(&self) {
+            Some(custom) => custom.clone().over(self.builtin()),
+            None => self.builtin(),
+        }
+    }
+}
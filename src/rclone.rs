@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use crate::archive::ListingFilter;
+use crate::compare::CompareConfig;
+use crate::models::{ErrorEntry, FileEntry, HashAlgo, HashResult, Mode};
+
+/// One object of `rclone lsjson` output.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListedObject {
+    path: String,
+    /// -1 when the backend cannot tell, e.g. for Google Docs.
+    size: i64,
+    mod_time: Option<String>,
+    #[serde(default)]
+    is_dir: bool,
+    #[serde(default)]
+    hashes: HashMap<String, String>,
+}
+
+/// Whether `path` names an rclone remote (`remote:path`) rather than something
+/// on the local disk. Existing local paths take precedence, single-letter
+/// names are left to Windows drives and `scheme://` URLs to their readers.
+pub fn is_remote(path: &Path) -> bool {
+    let Some((name, rest)) = path.to_str().and_then(|s| s.split_once(':')) else {
+        return false;
+    };
+    name.len() > 1
+        && !rest.starts_with("//")
+        && !name.starts_with(['-', ' '])
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ' '))
+        && !path.exists()
+}
+
+/// rclone's names for the hash types `algo` needs.
+fn hash_types(algo: HashAlgo) -> Result<&'static [&'static str]> {
+    Ok(match algo {
+        HashAlgo::Sha256 => &["sha256"],
+        HashAlgo::Blake3 => &["blake3"],
+        HashAlgo::Both => &["sha256", "blake3"],
+        // XXH3-128, the digest `--algo xxh3` prints.
+        HashAlgo::Xxh3 => &["xxh128"],
+        HashAlgo::Sha512 => &["sha512"],
+        HashAlgo::Md5 => &["md5"],
+        HashAlgo::Sha1 => &["sha1"],
+        HashAlgo::Blake2b => {
+            anyhow::bail!("rclone has no blake2b hash; compare remotes with another --algo")
+        }
+    })
+}
+
+/// The digests `algo` needs, or `None` when the listing lacks one of them.
+fn hash_result(hashes: &HashMap<String, String>, algo: HashAlgo) -> Option<HashResult> {
+    let get = |name: &str| {
+        hashes
+            .get(name)
+            .filter(|h| !h.is_empty())
+            .map(|h| h.to_lowercase())
+    };
+    let mut h = HashResult::default();
+    match algo {
+        HashAlgo::Sha256 => h.sha256 = Some(get("sha256")?),
+        HashAlgo::Blake3 => h.blake3 = Some(get("blake3")?),
+        HashAlgo::Both => {
+            h.sha256 = Some(get("sha256")?);
+            h.blake3 = Some(get("blake3")?);
+        }
+        HashAlgo::Xxh3 => h.xxh3 = Some(get("xxh128")?),
+        HashAlgo::Sha512 => h.sha512 = Some(get("sha512")?),
+        HashAlgo::Md5 => h.md5 = Some(get("md5")?),
+        HashAlgo::Sha1 => h.sha1 = Some(get("sha1")?),
+        HashAlgo::Blake2b => return None,
+    }
+    Some(h)
+}
+
+/// List an rclone remote as one side of a comparison, with the digests the
+/// backend reports instead of reading any content. Only `rclone lsjson` is
+/// run, so the remote is never written to.
+pub fn read_remote(
+    remote: &Path,
+    config: &CompareConfig,
+) -> Result<(Vec<FileEntry>, Vec<ErrorEntry>)> {
+    if config.owner_filter.is_some() {
+        anyhow::bail!(
+            "--owner-filter cannot be applied to rclone remote {}",
+            remote.display()
+        );
+    }
+    let mut cmd = Command::new("rclone");
+    cmd.args(["lsjson", "--recursive", "--files-only"]);
+    if config.mode != Mode::Metadata {
+        cmd.arg("--hash");
+        for name in hash_types(config.algo)? {
+            cmd.args(["--hash-type", name]);
+        }
+    }
+    if let Some(depth) = if config.no_recursive {
+        Some(1)
+    } else {
+        config.depth
+    } {
+        cmd.args(["--max-depth", &depth.to_string()]);
+    }
+    let output = cmd
+        .arg(remote)
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to run rclone; is it installed and on PATH?")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "rclone lsjson {} failed ({}): {}",
+            remote.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    read_listing(&output.stdout[..], remote, config)
+}
+
+/// Parse `rclone lsjson --recursive` output into entries under `root` that
+/// carry their digests, like snapshot entries. The scan options are applied
+/// to the listed paths as they are to a tar stream. Objects of unknown size,
+/// and objects without the digest `--algo` needs (the backend stores other
+/// hash types), are reported as errors rather than compared.
+pub fn read_listing(
+    reader: impl Read,
+    root: &Path,
+    config: &CompareConfig,
+) -> Result<(Vec<FileEntry>, Vec<ErrorEntry>)> {
+    let filter = ListingFilter::new(config)?;
+    let objects: Vec<ListedObject> =
+        serde_json::from_reader(reader).context("Failed to parse rclone lsjson output")?;
+
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    for object in objects {
+        let rel_path = PathBuf::from(&object.path);
+        if object.is_dir || filter.excludes(&rel_path) {
+            continue;
+        }
+        let Ok(size) = u64::try_from(object.size) else {
+            errors.push(ErrorEntry::new(
+                rel_path,
+                "size unknown on the remote".to_string(),
+            ));
+            continue;
+        };
+        let hashes = if config.mode == Mode::Metadata {
+            None
+        } else if let Some(h) = hash_result(&object.hashes, config.algo) {
+            Some(h)
+        } else {
+            errors.push(ErrorEntry::new(
+                rel_path,
+                format!(
+                    "remote provides no {} hash",
+                    format!("{:?}", config.algo).to_lowercase()
+                ),
+            ));
+            continue;
+        };
+        files.push(FileEntry {
+            path: root.join(&rel_path),
+            size,
+            modified: object
+                .mod_time
+                .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                .map(SystemTime::from),
+            hashes,
+            ..FileEntry::default()
+        });
+    }
+    Ok((files, errors))
+}
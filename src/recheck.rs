@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::compare::{CompareConfig, ExitStatus, Rules, compare_files_core, is_snapshot_file};
+use crate::models::{ComparisonResult, HashAlgo, Mode, OutputFormat, RecheckEntry, Status};
+use crate::rclone::is_remote;
+use crate::report::{
+    RecheckSummary, ReportConfig, generate_recheck_json, generate_recheck_text, print_status_line,
+    write_report,
+};
+use crate::s3::is_s3;
+use crate::ssh::is_ssh;
+use crate::utils::stat_entry;
+
+/// The parts of a JSON compare report that `recheck` and `--baseline-report` need.
+#[derive(Deserialize)]
+pub(crate) struct SavedReport {
+    pub run_info: SavedRunInfo,
+    pub results: Vec<ComparisonResult>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SavedRunInfo {
+    pub mode: Option<Mode>,
+    pub algo: Option<HashAlgo>,
+    pub folder1: Option<PathBuf>,
+    pub folder2: Option<PathBuf>,
+}
+
+pub(crate) fn load_report(report_path: &Path) -> Result<SavedReport> {
+    let content = fs::read_to_string(report_path)
+        .with_context(|| format!("Failed to read report {}", report_path.display()))?;
+    serde_json::from_str(&content).with_context(|| {
+        format!(
+            "{} is not a JSON compare report (re-run compare with --output-format json)",
+            report_path.display()
+        )
+    })
+}
+
+/// Statuses worth looking at again.
+fn needs_recheck(status: Status) -> bool {
+    matches!(status, Status::Diff | Status::Missing | Status::Extra)
+}
+
+/// Re-verify only the DIFF/MISSING/EXTRA entries of a previous JSON report.
+///
+/// `config.folder1`/`folder2` override the roots recorded in the report when
+/// non-empty. Mode and algorithm are taken from the report so that verdicts
+/// are comparable with the original run.
+pub fn run_recheck(report_path: &Path, mut config: CompareConfig) -> Result<ExitStatus> {
+    let start_time = Instant::now();
+
+    let report = load_report(report_path)?;
+
+    let info = report.run_info;
+    for (root, saved, n) in [
+        (&mut config.folder1, info.folder1, 1),
+        (&mut config.folder2, info.folder2, 2),
+    ] {
+        if root.as_os_str().is_empty() {
+            *root = saved.with_context(|| {
+                format!(
+                    "Report does not record folder{}; pass FOLDER1 FOLDER2 explicitly",
+                    n
+                )
+            })?;
+        }
+        if root.as_path() == Path::new("-") {
+            anyhow::bail!(
+                "recheck needs live folders, but folder{} was read from stdin",
+                n
+            );
+        }
+        if is_snapshot_file(root) {
+            anyhow::bail!(
+                "recheck needs live folders, but {} is a snapshot file",
+                root.display()
+            );
+        }
+        if is_remote(root) {
+            anyhow::bail!(
+                "recheck needs live folders, but {} is an rclone remote",
+                root.display()
+            );
+        }
+        if is_ssh(root) {
+            anyhow::bail!(
+                "recheck needs live folders, but {} is read over SSH",
+                root.display()
+            );
+        }
+        if is_s3(root) {
+            anyhow::bail!(
+                "recheck needs live folders, but {} is an S3 bucket",
+                root.display()
+            );
+        }
+    }
+    if let Some(mode) = info.mode {
+        // Realtime only changes how results are printed; recheck always batches.
+        config.mode = if mode == Mode::Realtime {
+            Mode::Batch
+        } else {
+            mode
+        };
+    }
+    if let Some(algo) = info.algo {
+        config.algo = algo;
+    }
+
+    let rules = Rules::new(&config)?;
+    let mut entries: Vec<RecheckEntry> = report
+        .results
+        .into_par_iter()
+        .filter(|r| needs_recheck(r.status))
+        .map(|r| RecheckEntry {
+            current: recheck_one(&r.file, &config, &rules),
+            file: r.file,
+            previous: r.status,
+        })
+        .collect();
+    entries.sort_by(|a, b| a.file.cmp(&b.file));
+
+    let summary = RecheckSummary {
+        rechecked: entries.len(),
+        resolved: entries.iter().filter(|e| e.resolved()).count(),
+        remaining: entries
+            .iter()
+            .filter(|e| e.current.is_some_and(needs_recheck))
+            .count(),
+        errors: entries
+            .iter()
+            .filter(|e| e.current == Some(Status::Error))
+            .count(),
+        elapsed: start_time.elapsed(),
+    };
+
+    let report_conf = ReportConfig {
+        mode: config.mode,
+        algo: config.algo,
+        threads: config.threads,
+        verbose: config.verbose,
+        folders: Some((config.folder1.clone(), config.folder2.clone())),
+        ..Default::default()
+    };
+
+    match config.output_format {
+        OutputFormat::Txt => write_report(
+            generate_recheck_text(&entries, &summary, &report_conf),
+            &config.output_folder,
+            "recheck_report.txt",
+            config.report_checksum,
+        )?,
+        OutputFormat::Json => write_report(
+            generate_recheck_json(&entries, &summary, report_path, &report_conf)?,
+            &config.output_folder,
+            "recheck_report.json",
+            config.report_checksum,
+        )?,
+        OutputFormat::CodeQuality
+        | OutputFormat::Html
+        | OutputFormat::Csv
+        | OutputFormat::Tsv
+        | OutputFormat::Ndjson
+        | OutputFormat::Porcelain => {
+            anyhow::bail!("recheck writes txt or json reports only")
+        }
+    }
+
+    let status = if summary.errors > 0 {
+        ExitStatus::Error
+    } else if summary.remaining > 0 {
+        ExitStatus::Diff
+    } else {
+        ExitStatus::Success
+    };
+    if config.status_line {
+        print_status_line(&status, &summary.status_fields());
+    }
+    Ok(status)
+}
+
+/// Current status of one relative path, or `None` if neither folder has it.
+fn recheck_one(rel_path: &Path, config: &CompareConfig, rules: &Rules) -> Option<Status> {
+    let side1 = stat_entry(&config.folder1.join(rel_path), config.symlinks);
+    let side2 = stat_entry(&config.folder2.join(rel_path), config.symlinks);
+    match (side1, side2) {
+        (Ok(Some(e1)), Ok(Some(e2))) => Some(
+            compare_files_core(rel_path.to_path_buf(), &e1, &e2, config, rules)
+                .map_or(Status::Error, |r| r.status),
+        ),
+        (Ok(Some(_)), Ok(None)) => Some(Status::Missing),
+        (Ok(None), Ok(Some(_))) => Some(Status::Extra),
+        (Ok(None), Ok(None)) => None,
+        _ => Some(Status::Error),
+    }
+}
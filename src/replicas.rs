@@ -0,0 +1,286 @@
+use anyhow::Result;
+use rayon::prelude::*;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+use crate::compare::{CompareConfig, ExitStatus, scan_side};
+use crate::models::{
+    FileEntry, HashAlgo, HashResult, Mode, OutputFormat, ReplicaEntry, ReplicaStatus,
+};
+use crate::report::{
+    ReplicaSummary, ReportConfig, generate_replica_json, generate_replica_text,
+    print_error_entries, print_status_line, write_report,
+};
+use crate::s3::{is_s3, object_hashes};
+use crate::snapshot::LoadedSnapshots;
+use crate::utils::{compute_hashes, key_by_relative_path};
+
+/// What one replica holds at a path, as far as telling versions apart goes.
+#[derive(Debug)]
+enum Holding {
+    Absent,
+    Link(String),
+    File {
+        size: u64,
+        modified: Option<SystemTime>,
+        hashes: Option<HashResult>,
+    },
+    Unreadable(String),
+}
+
+impl Holding {
+    fn same(&self, other: &Holding, mode: Mode, algo: HashAlgo) -> bool {
+        match (self, other) {
+            (Holding::Absent, Holding::Absent) => true,
+            (Holding::Link(a), Holding::Link(b)) => a == b,
+            (
+                Holding::File {
+                    size: s1,
+                    modified: m1,
+                    hashes: h1,
+                },
+                Holding::File {
+                    size: s2,
+                    modified: m2,
+                    hashes: h2,
+                },
+            ) => {
+                s1 == s2
+                    && match (mode, h1, h2) {
+                        (Mode::Metadata, _, _) => m1 == m2,
+                        (_, Some(h1), Some(h2)) => h1.matches(h2, algo),
+                        // Only files of a size no other replica has go unhashed.
+                        _ => false,
+                    }
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Version labels: `A` for the version most replicas hold, then `B`, and so on.
+fn version_label(index: usize) -> String {
+    if index < 26 {
+        char::from(b'A' + index as u8).to_string()
+    } else {
+        format!("V{}", index + 1)
+    }
+}
+
+/// Group what each replica holds at `file` into versions and find the one
+/// held by more than half of the replicas.
+fn classify(file: PathBuf, holdings: &[Holding], mode: Mode, algo: HashAlgo) -> ReplicaEntry {
+    // Versions as (first replica holding it, replicas holding it).
+    let mut versions: Vec<(usize, Vec<usize>)> = Vec::new();
+    for (i, h) in holdings.iter().enumerate() {
+        if matches!(h, Holding::Unreadable(_)) {
+            continue;
+        }
+        match versions
+            .iter_mut()
+            .find(|(first, _)| holdings[*first].same(h, mode, algo))
+        {
+            Some((_, members)) => members.push(i),
+            None => versions.push((i, vec![i])),
+        }
+    }
+    // Most common first; ties keep command-line order.
+    versions.sort_by_key(|(first, members)| (std::cmp::Reverse(members.len()), *first));
+
+    let mut labels = vec!["!".to_string(); holdings.len()];
+    let mut next = 0;
+    for (first, members) in &versions {
+        let label = if matches!(holdings[*first], Holding::Absent) {
+            "-".to_string()
+        } else {
+            next += 1;
+            version_label(next - 1)
+        };
+        for &i in members {
+            labels[i] = label.clone();
+        }
+    }
+
+    let majority = versions
+        .first()
+        .filter(|(_, members)| members.len() * 2 > holdings.len());
+    let consensus = majority.map(|(first, _)| labels[*first].clone());
+    let agreeing: Vec<usize> = majority.map_or_else(Vec::new, |(_, members)| {
+        members.iter().map(|i| i + 1).collect()
+    });
+    let diverging = (1..=holdings.len())
+        .filter(|n| !agreeing.contains(n))
+        .collect();
+    let errors: Vec<String> = holdings
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| match h {
+            Holding::Unreadable(e) => Some(format!("replica {}: {}", i + 1, e)),
+            _ => None,
+        })
+        .collect();
+
+    let status = if !errors.is_empty() {
+        ReplicaStatus::Error
+    } else if versions.len() == 1 {
+        ReplicaStatus::Agree
+    } else {
+        ReplicaStatus::Diverge
+    };
+    ReplicaEntry {
+        file,
+        status,
+        versions: labels,
+        consensus,
+        agreeing,
+        diverging,
+        errors,
+    }
+}
+
+/// What each replica holds at `file`, hashing a file only when another
+/// replica has one of the same size.
+fn holdings(
+    file: &Path,
+    replicas: &[HashMap<PathBuf, FileEntry>],
+    mode: Mode,
+    algo: HashAlgo,
+    read_ahead: Option<usize>,
+) -> Vec<Holding> {
+    let entries: Vec<Option<&FileEntry>> = replicas.iter().map(|r| r.get(file)).collect();
+    let same_size = |size: u64| {
+        entries
+            .iter()
+            .flatten()
+            .filter(|e| e.symlink_target.is_none() && e.size == size)
+            .count()
+            > 1
+    };
+    entries
+        .iter()
+        .map(|entry| match entry {
+            None => Holding::Absent,
+            Some(e) => {
+                if let Some(target) = &e.symlink_target {
+                    return Holding::Link(target.clone());
+                }
+                let hashes = if mode == Mode::Metadata || !same_size(e.size) {
+                    None
+                } else if let Some(h) = &e.hashes {
+                    Some(h.clone())
+                } else {
+                    let hashes = if is_s3(&e.path) {
+                        object_hashes(&e.path, algo)
+                    } else {
+                        compute_hashes(&e.path, algo, read_ahead)
+                    };
+                    match hashes {
+                        Ok(h) => Some(h),
+                        Err(err) => return Holding::Unreadable(err.to_string()),
+                    }
+                };
+                Holding::File {
+                    size: e.size,
+                    modified: e.modified,
+                    hashes,
+                }
+            }
+        })
+        .collect()
+}
+
+/// `compare A B C...`: compare three or more replicas and report, per path,
+/// which of them agree and which version most of them hold.
+pub(crate) fn run_replicas(
+    config: &CompareConfig,
+    snapshots: &LoadedSnapshots,
+    start_time: Instant,
+) -> Result<ExitStatus> {
+    if !matches!(config.output_format, OutputFormat::Txt | OutputFormat::Json) {
+        anyhow::bail!("comparing more than two folders writes txt or json reports only");
+    }
+    if config.stream_flag().is_some()
+        || config.baseline_report.is_some()
+        || config.emit_manifest.is_some()
+        || !config.results_page.is_all()
+    {
+        anyhow::bail!(
+            "--stream, --max-memory, --baseline-report, --emit-manifest and the --results-* options compare two folders only"
+        );
+    }
+
+    let replicas: Vec<PathBuf> = [&config.folder1, &config.folder2]
+        .into_iter()
+        .chain(&config.replicas)
+        .cloned()
+        .collect();
+    if replicas.iter().filter(|r| *r == Path::new("-")).count() > 1 {
+        anyhow::bail!("Only one side can be read from stdin");
+    }
+
+    let mut maps = Vec::new();
+    let mut walk_errors = 0;
+    for (i, replica) in replicas.iter().enumerate() {
+        let side = scan_side(replica, config, snapshots)?;
+        print_error_entries(&side.errors, &format!("replica {}", i + 1));
+        walk_errors += side.errors.len();
+        maps.push(key_by_relative_path(side.files, &side.root).0);
+    }
+
+    let paths: BTreeSet<&PathBuf> = maps.iter().flat_map(|m| m.keys()).collect();
+    let entries: Vec<ReplicaEntry> = paths
+        .into_par_iter()
+        .map(|file| {
+            let holdings = holdings(file, &maps, config.mode, config.algo, config.read_ahead);
+            classify(file.clone(), &holdings, config.mode, config.algo)
+        })
+        .collect();
+
+    let count = |status| entries.iter().filter(|e| e.status == status).count();
+    let summary = ReplicaSummary {
+        replicas: replicas.len(),
+        paths: entries.len(),
+        agree: count(ReplicaStatus::Agree),
+        diverge: count(ReplicaStatus::Diverge),
+        no_consensus: entries.iter().filter(|e| e.consensus.is_none()).count(),
+        errors: walk_errors + count(ReplicaStatus::Error),
+        elapsed: start_time.elapsed(),
+    };
+
+    let report_conf = ReportConfig {
+        mode: config.mode,
+        algo: config.algo,
+        threads: config.threads,
+        verbose: config.verbose,
+        assert_readonly: config.assert_readonly,
+        ..Default::default()
+    };
+    if config.output_format == OutputFormat::Json {
+        write_report(
+            generate_replica_json(&replicas, &entries, &summary, &report_conf)?,
+            &config.output_folder,
+            "replica_report.json",
+            config.report_checksum,
+        )?;
+    } else {
+        write_report(
+            generate_replica_text(&replicas, &entries, &summary, &report_conf),
+            &config.output_folder,
+            "replica_report.txt",
+            config.report_checksum,
+        )?;
+    }
+
+    let status = if summary.errors > 0 {
+        ExitStatus::Error
+    } else if summary.diverge > 0 {
+        ExitStatus::Diff
+    } else {
+        ExitStatus::Success
+    };
+    if config.status_line {
+        print_status_line(&status, &summary.status_fields());
+    }
+    Ok(status)
+}
@@ -1,18 +1,34 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use crate::models::{ComparisonResult, ErrorEntry, HashAlgo, Mode, Status};
+use crate::compare::ExitStatus;
+use crate::config::Severities;
+use crate::models::{
+    ComparisonResult, DriftEntry, ErrorEntry, HashAlgo, HashResult, Mode, RecheckEntry,
+    ReplicaEntry, ReplicaStatus, RootCheck, SampleParams, Severity, Status,
+};
+use crate::probe::format_granularity;
+use crate::resources::ResourceUsage;
+use crate::utils::sha256_hex;
 
 // Fix #8: print_realtime_missing now takes a Status enum instead of &str
-pub fn print_realtime_missing(status: Status, file: &Path, _verbose: bool) -> Result<()> {
-    let (status_colored, file_color) = match status {
-        Status::Missing => ("MISSING".blue(), Color::Blue),
-        Status::Extra => ("EXTRA".blue(), Color::Blue),
-        other => (other.to_string().normal(), Color::White),
+pub fn print_realtime_missing(
+    status: Status,
+    file: &Path,
+    severity: Option<Severity>,
+) -> Result<()> {
+    let (status_colored, file_color) = match (status, severity) {
+        (_, Some(severity)) => (status.to_string().color(severity.color()), severity.color()),
+        (Status::Missing, None) => ("MISSING".blue(), Color::Blue),
+        (Status::Extra, None) => ("EXTRA".blue(), Color::Blue),
+        (Status::OutOfTree, None) => ("OUT_OF_TREE".magenta(), Color::Magenta),
+        (other, None) => (other.to_string().normal(), Color::White),
     };
     println!(
         "[{}]  {}",
@@ -32,37 +48,963 @@ pub fn print_error_entry(e: &ErrorEntry, source: &str) {
     );
 }
 
+/// `--explain-skips`: one line per path the walk of `source` left out.
+pub fn print_skips(skips: &[(PathBuf, String)], source: &str) {
+    for (path, reason) in skips {
+        eprintln!(
+            "[{}] {} ({}: {})",
+            "SKIP".yellow(),
+            path.display(),
+            source,
+            reason
+        );
+    }
+}
+
+/// Print walk errors like `print_error_entry`, collapsing floods that share a
+/// message and a directory (see `ErrorFloods`).
+pub fn print_error_entries(errors: &[ErrorEntry], source: &str) {
+    let floods = ErrorFloods::of(errors.iter().map(|e| (e.error.as_str(), e.path.as_path())));
+    let mut shown = HashSet::new();
+    for e in errors {
+        match floods.line(&e.error, &e.path, &mut shown) {
+            FloodLine::First(line) => {
+                eprintln!("[{}] {} ({})", "ERROR".red().on_white(), line, source)
+            }
+            FloodLine::Repeat => {}
+            FloodLine::Single => print_error_entry(e, source),
+        }
+    }
+}
+
+/// Errors sharing one message at least this many times are shown as one line.
+const ERROR_FLOOD_MIN: usize = 10;
+
+/// Errors sharing a message and a directory at least `ERROR_FLOOD_MIN` times,
+/// e.g. every file of an unreadable folder, counted per (message, directory).
+/// Text output shows each as `<message> in <dir> (N entries)`; JSON keeps
+/// every entry.
+struct ErrorFloods<'a>(HashMap<(&'a str, &'a Path), usize>);
+
+/// How one error is shown in text output.
+enum FloodLine {
+    /// Not part of a flood: the error is shown on its own.
+    Single,
+    /// The first error of a flood, shown as the flood's summary line.
+    First(String),
+    /// A later error of a flood already summarised.
+    Repeat,
+}
+
+impl<'a> ErrorFloods<'a> {
+    fn of(errors: impl Iterator<Item = (&'a str, &'a Path)>) -> Self {
+        let mut groups: HashMap<(&str, &Path), usize> = HashMap::new();
+        for (message, path) in errors {
+            *groups.entry((message, parent_dir(path))).or_default() += 1;
+        }
+        groups.retain(|_, n| *n >= ERROR_FLOOD_MIN);
+        Self(groups)
+    }
+
+    fn line(
+        &self,
+        message: &'a str,
+        path: &'a Path,
+        shown: &mut HashSet<(&'a str, &'a Path)>,
+    ) -> FloodLine {
+        let key = (message, parent_dir(path));
+        let Some(n) = self.0.get(&key) else {
+            return FloodLine::Single;
+        };
+        if !shown.insert(key) {
+            return FloodLine::Repeat;
+        }
+        let dir = if key.1.as_os_str().is_empty() {
+            ".".to_string()
+        } else {
+            key.1.display().to_string()
+        };
+        FloodLine::First(format!(
+            "{} in {} ({} entries)",
+            message,
+            dir,
+            group_thousands(*n)
+        ))
+    }
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    path.parent().unwrap_or(Path::new(""))
+}
+
+/// `1243` as `1,243`.
+fn group_thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// The part of the results a JSON report lists (`--results-status`,
+/// `--results-offset`, `--results-limit`), so a dashboard can page through an
+/// enormous run. The summary always covers every result.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ResultsPage {
+    /// Only results with one of these statuses; empty keeps them all.
+    pub statuses: Vec<Status>,
+    /// Filtered results skipped before the page starts.
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl ResultsPage {
+    /// Whether every result is listed.
+    pub fn is_all(&self) -> bool {
+        self.statuses.is_empty() && self.offset == 0 && self.limit.is_none()
+    }
+
+    /// The results on the page, and how many passed the status filter in all.
+    pub fn select<'a>(
+        &self,
+        results: &'a [ComparisonResult],
+    ) -> (Vec<&'a ComparisonResult>, usize) {
+        let mut matching = 0;
+        let mut page = Vec::new();
+        for r in results {
+            if !self.statuses.is_empty() && !self.statuses.contains(&r.status) {
+                continue;
+            }
+            if matching >= self.offset && self.limit.is_none_or(|l| page.len() < l) {
+                page.push(r);
+            }
+            matching += 1;
+        }
+        (page, matching)
+    }
+}
+
+#[derive(Default)]
 pub struct ReportConfig {
     pub mode: Mode,
     pub algo: HashAlgo,
     pub threads: Option<usize>,
     pub verbose: bool,
+    /// Embed a digest of the results in JSON `run_info` and write `.sha256` sidecars.
+    pub report_checksum: bool,
+    /// Record in `run_info` that the run was made under `--assert-readonly`.
+    pub assert_readonly: bool,
+    /// Seed of the run's random choices (`--seed`), recorded in `run_info`.
+    pub seed: Option<u64>,
+    /// `--sample` percentage and the seed that chose the sample.
+    pub sample: Option<SampleParams>,
+    /// Offset compensated by `--auto-mtime-skew`, in seconds.
+    pub mtime_skew: Option<i64>,
+    /// Mtime difference tolerated after `--probe-fs`.
+    pub mtime_tolerance: Option<Duration>,
+    /// Compared roots, recorded so `recheck` can revisit them.
+    pub folders: Option<(PathBuf, PathBuf)>,
+    /// Slice of the results listed in JSON reports.
+    pub results_page: ResultsPage,
 }
 
+#[derive(Default)]
 pub struct SummaryData {
     pub total: usize,
     pub matches: usize,
+    /// Pairs only compared on their size and ends (`--quick`), not in `matches`.
+    pub quick_matches: usize,
     pub diffs: usize,
+    /// Files that changed while they were read (UNSTABLE results).
+    pub unstable: usize,
+    /// DIFF results downgraded by a `diff-ok` rule; they do not fail the run.
+    pub allowed_diffs: usize,
+    /// Results whose class is configured as a warning: listed, but not failing.
+    pub warnings: usize,
     pub missing: usize,
     pub extra: usize,
+    /// MISSING/EXTRA pairs merged into one RENAMED result (`--detect-renames`).
+    pub renamed: usize,
+    /// Walk errors: paths that could not be listed at all.
     pub errors: usize,
+    /// Files listed on both sides of which one could not be hashed (ERROR results).
+    pub hash_errors: usize,
+    /// Walk and hash errors caused by missing permissions.
+    pub permission_denied: usize,
+    pub out_of_tree: usize,
+    /// Bytes that would have to be copied to make folder2 match folder1
+    /// (folder1 sizes of MISSING and DIFF files).
+    pub transfer_bytes: u64,
+    /// MISSING/EXTRA entries whose content was found under another path (`--relocated`).
+    pub relocated: usize,
+    /// Metadata of the two roots, when known for both.
+    pub root: Option<RootCheck>,
+    /// DIFF/MISSING/EXTRA grouped by extension and size (`--breakdown`).
+    pub breakdown: Option<Breakdown>,
     pub elapsed: Duration,
+    /// Memory, CPU, IO and descriptors used by the run (`--resource-usage`).
+    pub resource_usage: Option<ResourceUsage>,
+    /// Estimate of what the unhashed part of a `--sample` run missed.
+    pub sample_confidence: Option<SampleConfidence>,
+    /// Counts of an earlier run (`--previous-report`), shown as deltas.
+    pub previous: Option<PreviousSummary>,
 }
 
-pub fn generate_summary_text(data: &SummaryData, config: &ReportConfig) -> Vec<String> {
+/// The `summary` counts of an earlier JSON or ndjson report, under the keys
+/// reports write them with; keys an older report lacks count as zero.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreviousSummary {
+    pub total_files_checked: usize,
+    pub matches: usize,
+    pub quick_matches: usize,
+    pub differences: usize,
+    pub allowed_differences: usize,
+    pub unstable: usize,
+    pub warnings: usize,
+    pub missing_in_folder2: usize,
+    pub extra_in_folder2: usize,
+    pub renamed: usize,
+    pub errors: usize,
+    pub hash_errors: usize,
+    pub permission_denied: usize,
+    pub out_of_tree: usize,
+    pub bytes_to_transfer: u64,
+    pub relocated_content: usize,
+}
+
+/// Read the summary of a `--output-format json` report, or the summary line
+/// ending an `ndjson` one.
+pub fn load_previous_summary(path: &Path) -> Result<PreviousSummary> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read report {}", path.display()))?;
+    let summary = serde_json::from_str::<serde_json::Value>(&content)
+        .ok()
+        .and_then(|report| report.get("summary").cloned())
+        .or_else(|| {
+            content.lines().rev().find_map(|line| {
+                serde_json::from_str::<serde_json::Value>(line)
+                    .ok()?
+                    .get("summary")
+                    .cloned()
+            })
+        })
+        .with_context(|| {
+            format!(
+                "{} is not a JSON or ndjson compare report with a summary",
+                path.display()
+            )
+        })?;
+    serde_json::from_value(summary)
+        .with_context(|| format!("Failed to parse the summary of {}", path.display()))
+}
+
+/// How far a count moved since the previous run, as ` (+9 vs last run)`;
+/// empty when there is no previous run.
+fn vs_last_run(now: u64, previous: Option<u64>) -> String {
+    previous
+        .map(|prev| format!(" ({:+} vs last run)", i128::from(now) - i128::from(prev)))
+        .unwrap_or_default()
+}
+
+fn with_delta(now: u64, previous: Option<u64>) -> String {
+    format!("{}{}", now, vs_last_run(now, previous))
+}
+
+/// Upper bounds (exclusive) and labels of the `--breakdown` size buckets.
+const SIZE_BUCKETS: [(u64, &str); 5] = [
+    (4 << 10, "< 4 KiB"),
+    (1 << 20, "4 KiB - 1 MiB"),
+    (64 << 20, "1 MiB - 64 MiB"),
+    (1 << 30, "64 MiB - 1 GiB"),
+    (u64::MAX, ">= 1 GiB"),
+];
+
+/// Differing entries of one file extension.
+#[derive(Debug, Default, Serialize)]
+pub struct ExtensionGroup {
+    /// Lowercased extension without the dot; empty for files without one.
+    pub extension: String,
+    pub diff: usize,
+    pub missing: usize,
+    pub extra: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SizeBucket {
+    pub range: &'static str,
+    pub count: usize,
+    pub bytes: u64,
+}
+
+/// How far the unhashed matches of a `--sample` run can be trusted. Only pairs
+/// whose size and mtime agree take part: those are the ones a metadata check
+/// accepts, and the hashed ones among them show how often that is wrong.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct SampleConfidence {
+    /// Hashed pairs whose size and mtime agreed.
+    pub sampled: usize,
+    /// Of those, pairs whose content differed all the same.
+    pub sampled_diffs: usize,
+    /// Pairs accepted on size and mtime alone.
+    pub unverified: usize,
+}
+
+impl SampleConfidence {
+    /// Confidence of the bounds below.
+    pub const LEVEL: f64 = 0.95;
+    /// One-sided normal quantile for `LEVEL`.
+    const Z: f64 = 1.644_853_6;
+
+    pub fn record(&mut self, sampled: bool, differs: bool) {
+        if sampled {
+            self.sampled += 1;
+            self.sampled_diffs += usize::from(differs);
+        } else {
+            self.unverified += 1;
+        }
+    }
+
+    /// Upper bound on the share of unverified pairs whose content differs: the
+    /// Wilson score bound of the rate seen in the sample. With no differences
+    /// found it comes to about 2.7 / `sampled`; with nothing sampled it is 1.
+    pub fn max_diff_rate(&self) -> f64 {
+        if self.sampled == 0 {
+            return 1.0;
+        }
+        let n = self.sampled as f64;
+        let p = self.sampled_diffs as f64 / n;
+        let z2 = Self::Z * Self::Z;
+        let centre = p + z2 / (2.0 * n);
+        let spread = Self::Z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+        ((centre + spread) / (1.0 + z2 / n)).min(1.0)
+    }
+
+    /// Upper bound on the number of unverified pairs whose content differs.
+    pub fn max_undetected(&self) -> usize {
+        (self.max_diff_rate() * self.unverified as f64).ceil() as usize
+    }
+}
+
+/// Where the differences are: "all .tmp and .log files" versus "the .db files
+/// changed". Sizes are taken from folder1, or folder2 for EXTRA entries.
+#[derive(Debug, Default, Serialize)]
+pub struct Breakdown {
+    /// Largest total size first.
+    pub by_extension: Vec<ExtensionGroup>,
+    /// Only buckets holding at least one entry, smallest sizes first.
+    pub by_size: Vec<SizeBucket>,
+}
+
+impl Breakdown {
+    pub fn of(results: &[ComparisonResult]) -> Self {
+        let mut groups: BTreeMap<String, ExtensionGroup> = BTreeMap::new();
+        let mut buckets: Vec<SizeBucket> = SIZE_BUCKETS
+            .iter()
+            .map(|(_, range)| SizeBucket {
+                range,
+                count: 0,
+                bytes: 0,
+            })
+            .collect();
+
+        for r in results {
+            let size = match r.status {
+                Status::Diff | Status::Missing => r.size1.or(r.size2),
+                Status::Extra => r.size2,
+                _ => continue,
+            }
+            .unwrap_or(0);
+            let extension = r
+                .file
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let group = groups.entry(extension.clone()).or_insert(ExtensionGroup {
+                extension,
+                ..Default::default()
+            });
+            match r.status {
+                Status::Diff => group.diff += 1,
+                Status::Missing => group.missing += 1,
+                _ => group.extra += 1,
+            }
+            group.bytes += size;
+
+            let i = SIZE_BUCKETS
+                .iter()
+                .position(|(limit, _)| size < *limit)
+                .unwrap_or(SIZE_BUCKETS.len() - 1);
+            buckets[i].count += 1;
+            buckets[i].bytes += size;
+        }
+
+        let mut by_extension: Vec<ExtensionGroup> = groups.into_values().collect();
+        by_extension.sort_by_key(|g| std::cmp::Reverse(g.bytes));
+        buckets.retain(|b| b.count > 0);
+        Self {
+            by_extension,
+            by_size: buckets,
+        }
+    }
+
+    fn format_text(&self) -> String {
+        let mut output = format!("\n{}\n", "Differences by extension:".bold());
+        for g in &self.by_extension {
+            let name = if g.extension.is_empty() {
+                "(none)".to_string()
+            } else {
+                format!(".{}", g.extension)
+            };
+            output.push_str(&format!(
+                "  {:<12} {:>6} files  {:>14} bytes  (diff {}, missing {}, extra {})\n",
+                name.cyan(),
+                g.diff + g.missing + g.extra,
+                g.bytes,
+                g.diff,
+                g.missing,
+                g.extra
+            ));
+        }
+        output.push_str(&format!("\n{}\n", "Differences by size:".bold()));
+        for b in &self.by_size {
+            output.push_str(&format!(
+                "  {:<16} {:>6} files  {:>14} bytes\n",
+                b.range, b.count, b.bytes
+            ));
+        }
+        output
+    }
+}
+
+impl SummaryData {
+    pub fn root_differs(&self) -> bool {
+        self.root.as_ref().is_some_and(|r| r.status == Status::Diff)
+    }
+
+    /// Outcome of a finished comparison: errors first, then whatever the
+    /// severities make fail. With `permission_exit`, runs that hit permission
+    /// problems get their own status, so a caller can retry with more privileges.
+    pub fn exit_status(&self, severities: &Severities, permission_exit: bool) -> ExitStatus {
+        if permission_exit && self.permission_denied > 0 {
+            ExitStatus::PermissionDenied
+        } else if self.errors > 0 || self.hash_errors > 0 || self.unstable > 0 {
+            ExitStatus::Error
+        } else if severities.fails(self) {
+            ExitStatus::Diff
+        } else {
+            ExitStatus::Success
+        }
+    }
+
+    /// Counts reported by `--status-line`.
+    pub fn status_fields(&self) -> Vec<(&'static str, u128)> {
+        vec![
+            ("total", self.total as u128),
+            ("matches", self.matches as u128),
+            ("quick_matches", self.quick_matches as u128),
+            ("diffs", self.diffs as u128),
+            ("allowed_diffs", self.allowed_diffs as u128),
+            ("unstable", self.unstable as u128),
+            ("warnings", self.warnings as u128),
+            ("missing", self.missing as u128),
+            ("extra", self.extra as u128),
+            ("renamed", self.renamed as u128),
+            ("errors", self.errors as u128),
+            ("hash_errors", self.hash_errors as u128),
+            ("permission_denied", self.permission_denied as u128),
+            ("out_of_tree", self.out_of_tree as u128),
+            ("bytes_to_transfer", self.transfer_bytes as u128),
+            ("elapsed_ms", self.elapsed.as_millis()),
+        ]
+    }
+}
+
+/// Print the single `status=<status> key=value ...` line of `--status-line` to
+/// stderr, independent of where and in which format the report went.
+pub fn print_status_line(status: &ExitStatus, fields: &[(&str, u128)]) {
+    eprintln!("{}", status_line(status, fields));
+}
+
+pub(crate) fn status_line(status: &ExitStatus, fields: &[(&str, u128)]) -> String {
+    let status = match status {
+        ExitStatus::Success => "success",
+        ExitStatus::Diff => "diff",
+        ExitStatus::Error => "error",
+        ExitStatus::PermissionDenied => "permission_denied",
+    };
+    let mut line = format!("status={}", status);
+    for (key, value) in fields {
+        line.push_str(&format!(" {}={}", key, value));
+    }
+    line
+}
+
+/// File count and byte totals of one directory (files directly inside it) on each side.
+#[derive(Debug, Default)]
+pub struct DirTotals {
+    pub dir: PathBuf,
+    pub files1: usize,
+    pub files2: usize,
+    pub bytes1: u64,
+    pub bytes2: u64,
+    /// Immediate children: files plus subdirectories.
+    pub children1: usize,
+    pub children2: usize,
+    /// Both directories exist on disk and their mtimes disagree.
+    pub mtime_differs: bool,
+}
+
+impl DirTotals {
+    /// Whether the directory is listed: its contents or its own mtime disagree.
+    pub fn differs(&self) -> bool {
+        self.files1 != self.files2
+            || self.bytes1 != self.bytes2
+            || self.children1 != self.children2
+            || self.mtime_differs
+    }
+}
+
+/// Per-directory totals gathered by `--precheck`, sorted by directory.
+#[derive(Debug, Default)]
+pub struct PrecheckData {
+    pub dirs: Vec<DirTotals>,
+}
+
+impl PrecheckData {
+    /// Percentage of files, or of bytes, that do not line up directory by
+    /// directory, whichever is larger.
+    pub fn divergence(&self) -> f64 {
+        let ratio = |off: u64, total: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                off as f64 * 100.0 / total as f64
+            }
+        };
+        let (mut files_off, mut files_total, mut bytes_off, mut bytes_total) = (0, 0, 0, 0);
+        for d in &self.dirs {
+            files_off += d.files1.abs_diff(d.files2) as u64;
+            files_total += d.files1.max(d.files2) as u64;
+            bytes_off += d.bytes1.abs_diff(d.bytes2);
+            bytes_total += d.bytes1.max(d.bytes2);
+        }
+        ratio(files_off, files_total).max(ratio(bytes_off, bytes_total))
+    }
+}
+
+pub fn generate_precheck_text(data: &PrecheckData) -> Vec<String> {
+    let differing: Vec<&DirTotals> = data.dirs.iter().filter(|d| d.differs()).collect();
+    let mtimes = data.dirs.iter().filter(|d| d.mtime_differs).count();
+    let mut output = vec![format!(
+        "{} {} of {} directories differ, {} with differing mtimes (divergence {:.1}%)",
+        "Precheck:".bold(),
+        differing.len().to_string().yellow(),
+        data.dirs.len(),
+        mtimes,
+        data.divergence()
+    )];
+    for d in differing {
+        let dir = if d.dir.as_os_str().is_empty() {
+            ".".to_string()
+        } else {
+            d.dir.display().to_string()
+        };
+        output.push(format!(
+            "[{}]  {}  entries: {} vs {}, files: {} vs {}, bytes: {} vs {}{}",
+            "DIR".yellow(),
+            dir.yellow(),
+            d.children1,
+            d.children2,
+            d.files1,
+            d.files2,
+            d.bytes1,
+            d.bytes2,
+            if d.mtime_differs {
+                ", mtime differs"
+            } else {
+                ""
+            }
+        ));
+    }
+    output
+}
+
+/// `precheck.json`, saved in place of the report when `--precheck-threshold`
+/// stops the run: the divergence, the threshold it exceeded and the
+/// directories that disagree.
+pub fn generate_precheck_json(data: &PrecheckData, threshold: f64) -> Result<String> {
+    let differing: Vec<serde_json::Value> = data
+        .dirs
+        .iter()
+        .filter(|d| d.differs())
+        .map(|d| {
+            serde_json::json!({
+                "dir": d.dir,
+                "entries1": d.children1,
+                "entries2": d.children2,
+                "files1": d.files1,
+                "files2": d.files2,
+                "bytes1": d.bytes1,
+                "bytes2": d.bytes2,
+                "mtime_differs": d.mtime_differs,
+            })
+        })
+        .collect();
+    let output = serde_json::json!({
+        "run_info": {
+            "tool_version": env!("CARGO_PKG_VERSION"),
+            "generated_at": chrono::Local::now().to_rfc3339(),
+        },
+        "divergence_percent": data.divergence(),
+        "threshold_percent": threshold,
+        "directories": data.dirs.len(),
+        "differing": differing,
+    });
+    Ok(serde_json::to_string_pretty(&output)?)
+}
+
+/// Mode / Algorithm / Threads rows shared by every summary box.
+fn run_rows(config: &ReportConfig) -> Vec<(&'static str, String, Color)> {
     let mode_str = format!("{:?}", config.mode);
-    let algo_str = if config.mode == Mode::Metadata {
-        "Metadata".to_string()
-    } else {
-        format!("{:?}", config.algo)
+    let algo_str = match config.mode {
+        Mode::Metadata => "Metadata".to_string(),
+        Mode::Bytes => "Byte by byte".to_string(),
+        _ => format!("{:?}", config.algo),
     };
     let threads_str = if let Some(t) = config.threads {
         t.to_string()
     } else {
         format!("Default ({})", rayon::current_num_threads())
     };
-    let elapsed_str = format!("{:.2?}", data.elapsed);
+    let mut rows = vec![
+        ("Mode", mode_str, Color::Magenta),
+        ("Algorithm", algo_str, Color::Magenta),
+        ("Threads", threads_str, Color::Magenta),
+    ];
+    if let Some(s) = config.sample {
+        rows.push((
+            "Sample",
+            format!("{}% (seed {})", s.percent, s.seed),
+            Color::Magenta,
+        ));
+    }
+    if let Some(skew) = config.mtime_skew {
+        rows.push(("Mtime skew", format!("{:+}s", skew), Color::Magenta));
+    }
+    if let Some(tolerance) = config.mtime_tolerance {
+        rows.push((
+            "Mtime tolerance",
+            format_granularity(tolerance),
+            Color::Magenta,
+        ));
+    }
+    rows
+}
+
+pub fn generate_summary_text(data: &SummaryData, config: &ReportConfig) -> Vec<String> {
+    render_summary_box("Summary", &summary_rows(data, config))
+}
 
+/// Rows of the comparison summary, shared by the text and HTML reports.
+fn summary_rows(data: &SummaryData, config: &ReportConfig) -> Vec<(&'static str, String, Color)> {
+    let prev = data.previous.as_ref();
+    // A count with its change since `--previous-report`; rows hidden at zero
+    // still show when the previous run had some.
+    let count = |now: usize, field: fn(&PreviousSummary) -> usize| {
+        with_delta(now as u64, prev.map(|p| field(p) as u64))
+    };
+    let shown = |now: usize, field: fn(&PreviousSummary) -> usize| {
+        now > 0 || prev.is_some_and(|p| field(p) > 0)
+    };
+    let mut rows = run_rows(config);
+    rows.push((
+        "Total files checked",
+        count(data.total, |p| p.total_files_checked),
+        Color::Blue,
+    ));
+    rows.push((
+        "Missing in Folder2",
+        count(data.missing, |p| p.missing_in_folder2),
+        Color::Blue,
+    ));
+    rows.push((
+        "Extra in Folder2",
+        count(data.extra, |p| p.extra_in_folder2),
+        Color::Blue,
+    ));
+    if shown(data.renamed, |p| p.renamed) {
+        rows.push(("Renamed", count(data.renamed, |p| p.renamed), Color::Cyan));
+    }
+    rows.push(("Matches", count(data.matches, |p| p.matches), Color::Green));
+    if shown(data.quick_matches, |p| p.quick_matches) {
+        rows.push((
+            "Quick matches",
+            count(data.quick_matches, |p| p.quick_matches),
+            Color::Yellow,
+        ));
+    }
+    rows.push((
+        "Differences",
+        count(data.diffs, |p| p.differences),
+        Color::Red,
+    ));
+    if shown(data.unstable, |p| p.unstable) {
+        rows.push((
+            "Changed while read",
+            count(data.unstable, |p| p.unstable),
+            Color::Magenta,
+        ));
+    }
+    if shown(data.allowed_diffs, |p| p.allowed_differences) {
+        rows.push((
+            "Allowed differences",
+            count(data.allowed_diffs, |p| p.allowed_differences),
+            Color::Yellow,
+        ));
+    }
+    if shown(data.warnings, |p| p.warnings) {
+        rows.push((
+            "Warnings",
+            count(data.warnings, |p| p.warnings),
+            Color::Yellow,
+        ));
+    }
+    if let Some(c) = &data.sample_confidence {
+        rows.push((
+            "Unverified matches",
+            c.unverified.to_string(),
+            Color::Yellow,
+        ));
+        rows.push((
+            "Max undetected (95%)",
+            format!("{} ({:.2}%)", c.max_undetected(), c.max_diff_rate() * 100.0),
+            Color::Yellow,
+        ));
+    }
+    if data.transfer_bytes > 0 || prev.is_some_and(|p| p.bytes_to_transfer > 0) {
+        rows.push((
+            "Bytes to transfer",
+            with_delta(data.transfer_bytes, prev.map(|p| p.bytes_to_transfer)),
+            Color::Yellow,
+        ));
+    }
+    if let Some(root) = &data.root {
+        let (text, color) = if root.status == Status::Diff {
+            ("Differs", Color::Red)
+        } else {
+            ("Matches", Color::Green)
+        };
+        rows.push(("Root metadata", text.to_string(), color));
+    }
+    if shown(data.relocated, |p| p.relocated_content) {
+        rows.push((
+            "Relocated content",
+            count(data.relocated, |p| p.relocated_content),
+            Color::Yellow,
+        ));
+    }
+    if shown(data.errors, |p| p.errors) {
+        rows.push(("Errors", count(data.errors, |p| p.errors), Color::Red));
+    }
+    if shown(data.hash_errors, |p| p.hash_errors) {
+        rows.push((
+            "Hash errors",
+            count(data.hash_errors, |p| p.hash_errors),
+            Color::Red,
+        ));
+    }
+    if shown(data.permission_denied, |p| p.permission_denied) {
+        rows.push((
+            "Permission denied",
+            format!(
+                "{} paths{}",
+                data.permission_denied,
+                vs_last_run(
+                    data.permission_denied as u64,
+                    prev.map(|p| p.permission_denied as u64)
+                )
+            ),
+            Color::Red,
+        ));
+    }
+    if shown(data.out_of_tree, |p| p.out_of_tree) {
+        rows.push((
+            "Outside of root",
+            count(data.out_of_tree, |p| p.out_of_tree),
+            Color::Magenta,
+        ));
+    }
+    rows.push(("Time taken", format!("{:.2?}", data.elapsed), Color::Yellow));
+    if let Some(usage) = &data.resource_usage {
+        if let Some(rss) = usage.peak_rss_text() {
+            rows.push(("Peak memory", rss, Color::Yellow));
+        }
+        rows.push((
+            "User CPU time",
+            format!("{:.2}s", usage.user_cpu_secs),
+            Color::Yellow,
+        ));
+        rows.push((
+            "System CPU time",
+            format!("{:.2}s", usage.system_cpu_secs),
+            Color::Yellow,
+        ));
+        if let Some(bytes) = usage.read_bytes {
+            rows.push((
+                "Read from storage",
+                format!("{} bytes", bytes),
+                Color::Yellow,
+            ));
+        }
+        if let Some(open) = usage.open_files {
+            let text = match usage.open_files_limit {
+                Some(limit) => format!("{} of {}", open, limit),
+                None => open.to_string(),
+            };
+            rows.push(("Open files", text, Color::Yellow));
+        }
+    }
+    rows
+}
+
+/// Outcome counts of a sync run.
+#[derive(Debug, Default, Serialize)]
+pub struct SyncSummary {
+    pub created: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub metadata_fixed: usize,
+    pub unchanged: usize,
+    pub failed: usize,
+    /// Files synced whose extended attributes or resource fork could not be copied (macOS).
+    pub attrs_failed: usize,
+    pub bytes_copied: u64,
+    /// Walk errors and other problems not tied to a single action.
+    pub errors: usize,
+    /// What a dry run would transfer, and how long that should take.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<TransferEstimate>,
+    #[serde(serialize_with = "serialize_elapsed")]
+    pub elapsed: Duration,
+}
+
+/// Planned work of a `sync --dry-run`.
+#[derive(Debug, Default, Serialize)]
+pub struct TransferEstimate {
+    pub files_to_copy: usize,
+    pub bytes_to_copy: u64,
+    pub files_to_delete: usize,
+    pub bytes_to_delete: u64,
+    /// Source read rate measured by a short probe, in bytes per second.
+    pub throughput: Option<f64>,
+}
+
+impl TransferEstimate {
+    pub fn duration(&self) -> Option<Duration> {
+        self.throughput
+            .filter(|t| *t > 0.0)
+            .map(|t| Duration::from_secs_f64(self.bytes_to_copy as f64 / t))
+    }
+}
+
+impl SyncSummary {
+    /// Counts reported by `--status-line`.
+    pub fn status_fields(&self) -> Vec<(&'static str, u128)> {
+        vec![
+            ("created", self.created as u128),
+            ("updated", self.updated as u128),
+            ("deleted", self.deleted as u128),
+            ("metadata_fixed", self.metadata_fixed as u128),
+            ("unchanged", self.unchanged as u128),
+            ("failed", self.failed as u128),
+            ("errors", self.errors as u128),
+            ("bytes_copied", self.bytes_copied as u128),
+            ("elapsed_ms", self.elapsed.as_millis()),
+        ]
+    }
+}
+
+fn serialize_elapsed<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format!("{:.2?}", d))
+}
+
+pub fn generate_sync_summary_text(data: &SyncSummary, config: &ReportConfig) -> Vec<String> {
+    render_summary_box("Sync Summary", &sync_summary_rows(data, config))
+}
+
+/// Label, value and colour of each row of the sync summary box.
+pub(crate) fn sync_summary_rows(
+    data: &SyncSummary,
+    config: &ReportConfig,
+) -> Vec<(&'static str, String, Color)> {
+    let mut rows = run_rows(config);
+    rows.push(("Created", data.created.to_string(), Color::Green));
+    rows.push(("Updated", data.updated.to_string(), Color::Yellow));
+    rows.push(("Deleted", data.deleted.to_string(), Color::Red));
+    if data.metadata_fixed > 0 {
+        rows.push((
+            "Metadata fixed",
+            data.metadata_fixed.to_string(),
+            Color::Yellow,
+        ));
+    }
+    rows.push(("Unchanged", data.unchanged.to_string(), Color::Blue));
+    rows.push(("Failed", data.failed.to_string(), Color::Red));
+    if data.attrs_failed > 0 {
+        rows.push((
+            "Attributes not copied",
+            data.attrs_failed.to_string(),
+            Color::Red,
+        ));
+    }
+    rows.push(("Bytes copied", data.bytes_copied.to_string(), Color::Blue));
+    if let Some(est) = &data.estimate {
+        rows.push((
+            "Files to copy",
+            est.files_to_copy.to_string(),
+            Color::Yellow,
+        ));
+        rows.push((
+            "Bytes to copy",
+            est.bytes_to_copy.to_string(),
+            Color::Yellow,
+        ));
+        rows.push((
+            "Files to delete",
+            est.files_to_delete.to_string(),
+            Color::Red,
+        ));
+        rows.push((
+            "Bytes to delete",
+            est.bytes_to_delete.to_string(),
+            Color::Red,
+        ));
+        if let Some(t) = est.throughput {
+            rows.push((
+                "Probed read speed",
+                format!("{:.1} MiB/s", t / (1024.0 * 1024.0)),
+                Color::Magenta,
+            ));
+        }
+        if let Some(d) = est.duration() {
+            rows.push(("Estimated duration", format!("{:.1?}", d), Color::Magenta));
+        }
+    }
+    if data.errors > 0 {
+        rows.push(("Errors", data.errors.to_string(), Color::Red));
+    }
+    rows.push(("Time taken", format!("{:.2?}", data.elapsed), Color::Yellow));
+    rows
+}
+
+/// Draw the boxed `label : value` table used by all summaries.
+fn render_summary_box(title: &str, rows: &[(&str, String, Color)]) -> Vec<String> {
     let content_width = 47;
     let mut output = Vec::new();
 
@@ -73,7 +1015,6 @@ pub fn generate_summary_text(data: &SummaryData, config: &ReportConfig) -> Vec<S
         "╗".bright_blue()
     ));
 
-    let title = "Summary";
     let padding_total = content_width.saturating_sub(title.len());
     let padding_start = padding_total / 2;
     let padding_end = padding_total - padding_start;
@@ -93,93 +1034,22 @@ pub fn generate_summary_text(data: &SummaryData, config: &ReportConfig) -> Vec<S
         "╣".bright_blue()
     ));
 
-    let add_line = |vec: &mut Vec<String>,
-                    label: &str,
-                    value: &str,
-                    label_color: Color,
-                    value_color: Color| {
+    for (label, value, value_color) in rows {
         let colored_line = format!(
             "  {} : {}",
-            format!("{:<22}", label).bold().color(label_color),
-            value.bold().color(value_color)
+            format!("{:<22}", label).bold().color(Color::Cyan),
+            value.bold().color(*value_color)
         );
         let uncolored_len = 2 + 22 + 3 + value.len();
         let padding = " ".repeat(content_width.saturating_sub(uncolored_len));
-        vec.push(format!(
+        output.push(format!(
             "{}{}{}{}",
             "║".bright_blue(),
             colored_line,
             padding,
             "║".bright_blue()
         ));
-    };
-
-    add_line(&mut output, "Mode", &mode_str, Color::Cyan, Color::Magenta);
-    add_line(
-        &mut output,
-        "Algorithm",
-        &algo_str,
-        Color::Cyan,
-        Color::Magenta,
-    );
-    add_line(
-        &mut output,
-        "Threads",
-        &threads_str,
-        Color::Cyan,
-        Color::Magenta,
-    );
-    add_line(
-        &mut output,
-        "Total files checked",
-        &data.total.to_string(),
-        Color::Cyan,
-        Color::Blue,
-    );
-    add_line(
-        &mut output,
-        "Missing in Folder2",
-        &data.missing.to_string(),
-        Color::Cyan,
-        Color::Blue,
-    );
-    add_line(
-        &mut output,
-        "Extra in Folder2",
-        &data.extra.to_string(),
-        Color::Cyan,
-        Color::Blue,
-    );
-    add_line(
-        &mut output,
-        "Matches",
-        &data.matches.to_string(),
-        Color::Cyan,
-        Color::Green,
-    );
-    add_line(
-        &mut output,
-        "Differences",
-        &data.diffs.to_string(),
-        Color::Cyan,
-        Color::Red,
-    );
-    if data.errors > 0 {
-        add_line(
-            &mut output,
-            "Errors",
-            &data.errors.to_string(),
-            Color::Cyan,
-            Color::Red,
-        );
     }
-    add_line(
-        &mut output,
-        "Time taken",
-        &elapsed_str,
-        Color::Cyan,
-        Color::Yellow,
-    );
 
     output.push(format!(
         "{}{}{}",
@@ -191,6 +1061,475 @@ pub fn generate_summary_text(data: &SummaryData, config: &ReportConfig) -> Vec<S
     output
 }
 
+/// Outcome counts of a `recheck` run.
+#[derive(Debug, Default, Serialize)]
+pub struct RecheckSummary {
+    pub rechecked: usize,
+    pub resolved: usize,
+    pub remaining: usize,
+    pub errors: usize,
+    #[serde(serialize_with = "serialize_elapsed")]
+    pub elapsed: Duration,
+}
+
+impl RecheckSummary {
+    /// Counts reported by `--status-line`.
+    pub fn status_fields(&self) -> Vec<(&'static str, u128)> {
+        vec![
+            ("rechecked", self.rechecked as u128),
+            ("resolved", self.resolved as u128),
+            ("remaining", self.remaining as u128),
+            ("errors", self.errors as u128),
+            ("elapsed_ms", self.elapsed.as_millis()),
+        ]
+    }
+}
+
+/// Counts of a `--baseline-report` run.
+#[derive(Debug, Default, Serialize)]
+pub struct DriftSummary {
+    /// Paths listed in the baseline report.
+    pub baseline: usize,
+    /// Paths compared in this run.
+    pub current: usize,
+    pub changed: usize,
+    pub newly_differing: usize,
+    pub newly_matching: usize,
+    pub newly_missing: usize,
+    pub newly_extra: usize,
+    pub errors: usize,
+    #[serde(serialize_with = "serialize_elapsed")]
+    pub elapsed: Duration,
+}
+
+impl DriftSummary {
+    /// Counts reported by `--status-line`.
+    pub fn status_fields(&self) -> Vec<(&'static str, u128)> {
+        vec![
+            ("changed", self.changed as u128),
+            ("newly_differing", self.newly_differing as u128),
+            ("newly_matching", self.newly_matching as u128),
+            ("newly_missing", self.newly_missing as u128),
+            ("newly_extra", self.newly_extra as u128),
+            ("errors", self.errors as u128),
+            ("elapsed_ms", self.elapsed.as_millis()),
+        ]
+    }
+}
+
+/// Counts of a comparison of three or more replicas.
+#[derive(Debug, Default, Serialize)]
+pub struct ReplicaSummary {
+    pub replicas: usize,
+    pub paths: usize,
+    pub agree: usize,
+    pub diverge: usize,
+    /// Paths no version holds a majority of the replicas for.
+    pub no_consensus: usize,
+    /// Walk errors and paths some replica could not read.
+    pub errors: usize,
+    #[serde(serialize_with = "serialize_elapsed")]
+    pub elapsed: Duration,
+}
+
+impl ReplicaSummary {
+    /// Counts reported by `--status-line`.
+    pub fn status_fields(&self) -> Vec<(&'static str, u128)> {
+        vec![
+            ("replicas", self.replicas as u128),
+            ("total", self.paths as u128),
+            ("agree", self.agree as u128),
+            ("diverge", self.diverge as u128),
+            ("no_consensus", self.no_consensus as u128),
+            ("errors", self.errors as u128),
+            ("elapsed_ms", self.elapsed.as_millis()),
+        ]
+    }
+}
+
+/// Outcome counts of a `resolve` session.
+#[derive(Debug, Default)]
+pub struct ResolveSummary {
+    /// DIFF results offered for resolution.
+    pub differing: usize,
+    pub copied: usize,
+    /// Left as they were, including everything after a quit.
+    pub skipped: usize,
+    pub failed: usize,
+    pub bytes_copied: u64,
+    pub errors: usize,
+    pub elapsed: Duration,
+}
+
+impl ResolveSummary {
+    /// Counts reported by `--status-line`.
+    pub fn status_fields(&self) -> Vec<(&'static str, u128)> {
+        vec![
+            ("differing", self.differing as u128),
+            ("copied", self.copied as u128),
+            ("skipped", self.skipped as u128),
+            ("failed", self.failed as u128),
+            ("errors", self.errors as u128),
+            ("bytes_copied", self.bytes_copied as u128),
+            ("elapsed_ms", self.elapsed.as_millis()),
+        ]
+    }
+}
+
+pub fn generate_resolve_summary_text(
+    summary: &ResolveSummary,
+    config: &ReportConfig,
+) -> Vec<String> {
+    let mut rows = run_rows(config);
+    rows.push((
+        "Differing files",
+        summary.differing.to_string(),
+        Color::Blue,
+    ));
+    rows.push(("Copied", summary.copied.to_string(), Color::Green));
+    rows.push(("Skipped", summary.skipped.to_string(), Color::Yellow));
+    if summary.failed > 0 {
+        rows.push(("Failed", summary.failed.to_string(), Color::Red));
+    }
+    if summary.bytes_copied > 0 {
+        rows.push((
+            "Bytes copied",
+            summary.bytes_copied.to_string(),
+            Color::Yellow,
+        ));
+    }
+    if summary.errors > 0 {
+        rows.push(("Errors", summary.errors.to_string(), Color::Red));
+    }
+    rows.push((
+        "Time taken",
+        format!("{:.2?}", summary.elapsed),
+        Color::Yellow,
+    ));
+    render_summary_box("Resolve Summary", &rows)
+}
+
+fn status_label(status: Option<Status>) -> String {
+    status.map_or_else(|| "GONE".to_string(), |s| s.to_string())
+}
+
+pub fn generate_recheck_text(
+    entries: &[RecheckEntry],
+    summary: &RecheckSummary,
+    config: &ReportConfig,
+) -> String {
+    let mut output = String::new();
+    for e in entries {
+        let transition = format!("{} -> {}", e.previous, status_label(e.current));
+        let transition = match e.current {
+            None | Some(Status::Match) => transition.green(),
+            Some(Status::Error) => transition.red().on_white(),
+            Some(_) => transition.red(),
+        };
+        output.push_str(&format!("[{}]  {}\n", transition, e.file.display()));
+    }
+    output.push('\n');
+
+    let mut rows = run_rows(config);
+    rows.push(("Rechecked", summary.rechecked.to_string(), Color::Blue));
+    rows.push(("Resolved", summary.resolved.to_string(), Color::Green));
+    rows.push(("Still differing", summary.remaining.to_string(), Color::Red));
+    if summary.errors > 0 {
+        rows.push(("Errors", summary.errors.to_string(), Color::Red));
+    }
+    rows.push((
+        "Time taken",
+        format!("{:.2?}", summary.elapsed),
+        Color::Yellow,
+    ));
+    output.push_str(&render_summary_box("Recheck Summary", &rows).join("\n"));
+    output
+}
+
+pub fn generate_recheck_json(
+    entries: &[RecheckEntry],
+    summary: &RecheckSummary,
+    source_report: &Path,
+    config: &ReportConfig,
+) -> Result<String> {
+    let output = serde_json::json!({
+        "run_info": {
+            "tool_version": env!("CARGO_PKG_VERSION"),
+            "generated_at": chrono::Local::now().to_rfc3339(),
+            "source_report": source_report,
+            "mode": config.mode,
+            "algo": config.algo,
+            "folder1": config.folders.as_ref().map(|f| &f.0),
+            "folder2": config.folders.as_ref().map(|f| &f.1),
+        },
+        "summary": summary,
+        "results": entries,
+    });
+    Ok(serde_json::to_string_pretty(&output)?)
+}
+
+pub fn generate_drift_text(
+    entries: &[DriftEntry],
+    summary: &DriftSummary,
+    config: &ReportConfig,
+) -> String {
+    let mut output = String::new();
+    for e in entries {
+        let previous = e
+            .previous
+            .map_or_else(|| "NEW".to_string(), |s| s.to_string());
+        let transition = format!("{} -> {}", previous, status_label(e.current));
+        let transition = match e.current {
+            _ if !e.worsened() => transition.green(),
+            Some(Status::Error) => transition.red().on_white(),
+            _ => transition.red(),
+        };
+        output.push_str(&format!("[{}]  {}\n", transition, e.file.display()));
+    }
+    output.push('\n');
+
+    let mut rows = run_rows(config);
+    rows.push(("Baseline paths", summary.baseline.to_string(), Color::Blue));
+    rows.push(("Current paths", summary.current.to_string(), Color::Blue));
+    rows.push(("Changed", summary.changed.to_string(), Color::Yellow));
+    rows.push((
+        "Newly differing",
+        summary.newly_differing.to_string(),
+        Color::Red,
+    ));
+    rows.push((
+        "Newly matching",
+        summary.newly_matching.to_string(),
+        Color::Green,
+    ));
+    rows.push((
+        "Newly missing",
+        summary.newly_missing.to_string(),
+        Color::Red,
+    ));
+    rows.push(("Newly extra", summary.newly_extra.to_string(), Color::Red));
+    if summary.errors > 0 {
+        rows.push(("Errors", summary.errors.to_string(), Color::Red));
+    }
+    rows.push((
+        "Time taken",
+        format!("{:.2?}", summary.elapsed),
+        Color::Yellow,
+    ));
+    output.push_str(&render_summary_box("Drift Summary", &rows).join("\n"));
+    output
+}
+
+fn files_and_bytes(count: usize, bytes: String) -> String {
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{} file{}, {} bytes", group_thousands(count), plural, bytes)
+}
+
+/// What changed since a snapshot (`verify --changes-only`): added, removed and
+/// modified files grouped by directory, each with its size, followed by the
+/// totals. Matching files are left out.
+pub fn generate_change_log(results: &[ComparisonResult], errors: &[ErrorEntry]) -> String {
+    let mut by_dir: BTreeMap<&Path, Vec<&ComparisonResult>> = BTreeMap::new();
+    for r in results {
+        if matches!(r.status, Status::Extra | Status::Missing | Status::Diff) {
+            let dir = r.file.parent().unwrap_or(Path::new(""));
+            by_dir.entry(dir).or_default().push(r);
+        }
+    }
+
+    let size = |s: Option<u64>| s.map_or_else(|| "?".to_string(), |s| group_thousands(s as usize));
+    let (mut added, mut removed, mut modified) = ((0, 0), (0, 0), (0, 0, 0));
+    let mut output = String::new();
+    for (dir, changes) in &by_dir {
+        let count = |status| changes.iter().filter(|r| r.status == status).count();
+        let counts: Vec<String> = [
+            (count(Status::Extra), "added"),
+            (count(Status::Missing), "removed"),
+            (count(Status::Diff), "modified"),
+        ]
+        .iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, what)| format!("{} {}", n, what))
+        .collect();
+        let name = if dir.as_os_str().is_empty() {
+            ".".to_string()
+        } else {
+            format!("{}/", dir.display())
+        };
+        output.push_str(&format!("{} ({})\n", name.bold(), counts.join(", ")));
+
+        for r in changes {
+            let file = r
+                .file
+                .file_name()
+                .unwrap_or(r.file.as_os_str())
+                .to_string_lossy();
+            let line = match r.status {
+                Status::Extra => {
+                    added.0 += 1;
+                    added.1 += r.size2.unwrap_or(0);
+                    format!("  {} {}  ({} bytes)", "+".green(), file, size(r.size2))
+                }
+                Status::Missing => {
+                    removed.0 += 1;
+                    removed.1 += r.size1.unwrap_or(0);
+                    format!("  {} {}  ({} bytes)", "-".red(), file, size(r.size1))
+                }
+                _ => {
+                    modified.0 += 1;
+                    modified.1 += r.size1.unwrap_or(0);
+                    modified.2 += r.size2.unwrap_or(0);
+                    format!(
+                        "  {} {}  ({} -> {} bytes)",
+                        "~".yellow(),
+                        file,
+                        size(r.size1),
+                        size(r.size2)
+                    )
+                }
+            };
+            output.push_str(&line);
+            output.push('\n');
+        }
+        output.push('\n');
+    }
+
+    if by_dir.is_empty() {
+        output.push_str("No changes since the snapshot.\n");
+    } else {
+        output.push_str(&format!(
+            "Added: {}\nRemoved: {}\nModified: {}\n",
+            files_and_bytes(added.0, group_thousands(added.1 as usize)),
+            files_and_bytes(removed.0, group_thousands(removed.1 as usize)),
+            files_and_bytes(
+                modified.0,
+                format!(
+                    "{} -> {}",
+                    group_thousands(modified.1 as usize),
+                    group_thousands(modified.2 as usize)
+                )
+            ),
+        ));
+    }
+    let unreadable = errors.len() + results.iter().filter(|r| r.status == Status::Error).count();
+    if unreadable > 0 {
+        output.push_str(&format!(
+            "{} {} paths could not be read\n",
+            "Warning:".yellow(),
+            unreadable
+        ));
+    }
+    output
+}
+
+pub fn generate_drift_json(
+    entries: &[DriftEntry],
+    summary: &DriftSummary,
+    baseline_report: &Path,
+    config: &ReportConfig,
+) -> Result<String> {
+    let output = serde_json::json!({
+        "run_info": {
+            "tool_version": env!("CARGO_PKG_VERSION"),
+            "generated_at": chrono::Local::now().to_rfc3339(),
+            "baseline_report": baseline_report,
+            "mode": config.mode,
+            "algo": config.algo,
+            "folder1": config.folders.as_ref().map(|f| &f.0),
+            "folder2": config.folders.as_ref().map(|f| &f.1),
+        },
+        "summary": summary,
+        "results": entries,
+    });
+    Ok(serde_json::to_string_pretty(&output)?)
+}
+
+fn replica_numbers(replicas: &[usize]) -> String {
+    let numbers: Vec<String> = replicas.iter().map(usize::to_string).collect();
+    numbers.join(", ")
+}
+
+pub fn generate_replica_text(
+    replicas: &[PathBuf],
+    entries: &[ReplicaEntry],
+    summary: &ReplicaSummary,
+    config: &ReportConfig,
+) -> String {
+    let mut output = String::from("Replicas:\n");
+    for (i, replica) in replicas.iter().enumerate() {
+        output.push_str(&format!("  {}  {}\n", i + 1, replica.display()));
+    }
+    output.push('\n');
+
+    for e in entries {
+        let label = format!("{:<9}", format!("[{}]", e.status));
+        let label = match e.status {
+            ReplicaStatus::Agree => label.green(),
+            ReplicaStatus::Diverge => label.red(),
+            ReplicaStatus::Error => label.red().on_white(),
+        };
+        let mut line = format!("{}  {}  {}", label, e.versions.join(" "), e.file.display());
+        if e.status != ReplicaStatus::Agree {
+            match &e.consensus {
+                Some(version) => line.push_str(&format!(
+                    "  (consensus {}: {}; diverging: {})",
+                    if version == "-" { "absent" } else { version },
+                    replica_numbers(&e.agreeing),
+                    replica_numbers(&e.diverging)
+                )),
+                None => line.push_str(&format!("  ({})", "no consensus".yellow())),
+            }
+        }
+        output.push_str(&line);
+        output.push('\n');
+        for error in &e.errors {
+            output.push_str(&format!("    {}\n", error.red()));
+        }
+    }
+    output.push('\n');
+
+    let mut rows = run_rows(config);
+    rows.push(("Replicas", summary.replicas.to_string(), Color::Blue));
+    rows.push(("Paths", summary.paths.to_string(), Color::Blue));
+    rows.push(("Agree", summary.agree.to_string(), Color::Green));
+    rows.push(("Diverge", summary.diverge.to_string(), Color::Red));
+    rows.push((
+        "No consensus",
+        summary.no_consensus.to_string(),
+        Color::Yellow,
+    ));
+    if summary.errors > 0 {
+        rows.push(("Errors", summary.errors.to_string(), Color::Red));
+    }
+    rows.push((
+        "Time taken",
+        format!("{:.2?}", summary.elapsed),
+        Color::Yellow,
+    ));
+    output.push_str(&render_summary_box("Replica Summary", &rows).join("\n"));
+    output
+}
+
+pub fn generate_replica_json(
+    replicas: &[PathBuf],
+    entries: &[ReplicaEntry],
+    summary: &ReplicaSummary,
+    config: &ReportConfig,
+) -> Result<String> {
+    let output = serde_json::json!({
+        "run_info": {
+            "tool_version": env!("CARGO_PKG_VERSION"),
+            "generated_at": chrono::Local::now().to_rfc3339(),
+            "mode": config.mode,
+            "algo": config.algo,
+            "replicas": replicas,
+        },
+        "summary": summary,
+        "results": entries,
+    });
+    Ok(serde_json::to_string_pretty(&output)?)
+}
+
 pub fn generate_text_report(
     results: &[ComparisonResult],
     errors1: &[ErrorEntry],
@@ -200,25 +1539,114 @@ pub fn generate_text_report(
 ) -> Result<String> {
     let mut output = String::new();
 
-    for e in errors1 {
-        output.push_str(&format!(
-            "[{}] {} (folder1: {})\n",
-            "ERROR".red().on_white(),
-            e.path.display(),
-            e.error
-        ));
+    for (errors, source) in [(errors1, "folder1"), (errors2, "folder2")] {
+        let floods = ErrorFloods::of(errors.iter().map(|e| (e.error.as_str(), e.path.as_path())));
+        let mut shown = HashSet::new();
+        for e in errors {
+            match floods.line(&e.error, &e.path, &mut shown) {
+                FloodLine::First(line) => output.push_str(&format!(
+                    "[{}] {} ({})\n",
+                    "ERROR".red().on_white(),
+                    line,
+                    source
+                )),
+                FloodLine::Repeat => {}
+                FloodLine::Single => output.push_str(&format!(
+                    "[{}] {} ({}: {})\n",
+                    "ERROR".red().on_white(),
+                    e.path.display(),
+                    source,
+                    e.error
+                )),
+            }
+        }
+    }
+
+    // Unreadable files are collapsed the same way, in place of their first entry.
+    let floods = ErrorFloods::of(results.iter().filter_map(|r| {
+        r.error
+            .as_deref()
+            .filter(|_| r.status == Status::Error)
+            .map(|e| (e, r.file.as_path()))
+    }));
+    let mut shown = HashSet::new();
+    for result in results {
+        let flood = match result.error.as_deref() {
+            Some(e) if result.status == Status::Error => floods.line(e, &result.file, &mut shown),
+            _ => FloodLine::Single,
+        };
+        match flood {
+            FloodLine::First(line) => {
+                output.push_str(&format!("[{}] {}\n", "ERROR".red().on_white(), line))
+            }
+            FloodLine::Repeat => {}
+            FloodLine::Single => output.push_str(&result.format_text(config.verbose, config.algo)?),
+        }
     }
-    for e in errors2 {
+
+    if let Some(root) = summary_data
+        .root
+        .as_ref()
+        .filter(|r| r.status == Status::Diff)
+    {
         output.push_str(&format!(
-            "[{}] {} (folder2: {})\n",
-            "ERROR".red().on_white(),
-            e.path.display(),
-            e.error
+            "[{}]  {}\n",
+            "DIFF".red(),
+            "<root>".color(Color::Red)
         ));
+        for d in &root.differences {
+            output.push_str(&format!("    {}\n", d.yellow()));
+        }
     }
 
-    for result in results {
-        output.push_str(&result.format_text(config.verbose, config.algo)?);
+    let warnings: Vec<&ComparisonResult> = results
+        .iter()
+        .filter(|r| r.severity == Some(Severity::Warning))
+        .collect();
+    if !warnings.is_empty() {
+        output.push_str(&format!("\n{}\n", "Warnings:".bold()));
+        for r in warnings {
+            output.push_str(&format!(
+                "[{}]  {}\n",
+                r.status.to_string().yellow(),
+                r.file.display()
+            ));
+        }
+    }
+
+    let relocated: Vec<&ComparisonResult> =
+        results.iter().filter(|r| r.relocated.is_some()).collect();
+    if !relocated.is_empty() {
+        output.push_str(&format!("\n{}\n", "Relocated content:".bold()));
+        for r in relocated {
+            let (arrow, side) = if r.status == Status::Missing {
+                ("->", "folder2")
+            } else {
+                ("<-", "folder1")
+            };
+            let places: Vec<String> = r
+                .relocated
+                .iter()
+                .flatten()
+                .map(|p| p.display().to_string())
+                .collect();
+            output.push_str(&format!(
+                "[{}]  {} {} {} ({})\n",
+                "RELOCATED".yellow(),
+                r.file.display(),
+                arrow,
+                places.join(", ").cyan(),
+                side
+            ));
+        }
+    }
+
+    if let Some(breakdown) = summary_data
+        .breakdown
+        .as_ref()
+        .filter(|b| !b.by_extension.is_empty())
+    {
+        output.push_str(&breakdown.format_text());
     }
 
     output.push('\n');
@@ -229,33 +1657,575 @@ pub fn generate_text_report(
     Ok(output)
 }
 
+/// Version of the JSON report layout, recorded as `schema_version`. Bump it
+/// only when a field is renamed, removed or changes type, and update
+/// `report_schema.json` with every change to the report.
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// JSON Schema of the report `generate_json_report` produces (`--print-schema`).
+pub const REPORT_SCHEMA: &str = include_str!("report_schema.json");
+
 pub fn generate_json_report(
     results: &[ComparisonResult],
     errors1: &[ErrorEntry],
     errors2: &[ErrorEntry],
     summary_data: &SummaryData,
+    config: &ReportConfig,
 ) -> Result<String> {
-    let summary = serde_json::json!({
-        "total_files_checked": summary_data.total,
-        "matches": summary_data.matches,
-        "differences": summary_data.diffs,
-        "missing_in_folder2": summary_data.missing,
-        "extra_in_folder2": summary_data.extra,
-        "errors": summary_data.errors,
-        "time_taken": format!("{:.2?}", summary_data.elapsed),
+    let (results, matching) = config.results_page.select(results);
+    let results_page = (!config.results_page.is_all()).then(|| {
+        serde_json::json!({
+            "statuses": config.results_page.statuses,
+            "offset": config.results_page.offset,
+            "limit": config.results_page.limit,
+            "matching": matching,
+        })
+    });
+
+    // The report cannot contain a digest of itself, so run_info carries the
+    // SHA-256 of the compact `results` serialization; the sidecar covers the file.
+    let results_sha256 = if config.report_checksum {
+        Some(sha256_hex(serde_json::to_string(&results)?.as_bytes()))
+    } else {
+        None
+    };
+
+    let run_info = serde_json::json!({
+        "tool_version": env!("CARGO_PKG_VERSION"),
+        "generated_at": chrono::Local::now().to_rfc3339(),
+        "mode": config.mode,
+        "algo": config.algo,
+        "threads": config.threads.unwrap_or_else(rayon::current_num_threads),
+        "results_sha256": results_sha256,
+        "assert_readonly": config.assert_readonly,
+        "seed": config.seed,
+        "sample": config.sample,
+        "mtime_skew_secs": config.mtime_skew,
+        "mtime_tolerance_ns": config.mtime_tolerance.map(|d| d.as_nanos() as u64),
+        "folder1": config.folders.as_ref().map(|f| &f.0),
+        "folder2": config.folders.as_ref().map(|f| &f.1),
+        "resource_usage": summary_data.resource_usage,
+        "results_page": results_page,
     });
 
     let output = serde_json::json!({
-        "summary": summary,
+        "schema_version": REPORT_SCHEMA_VERSION,
+        "run_info": run_info,
+        "summary": summary_json(summary_data),
         "folder1_errors": errors1,
         "folder2_errors": errors2,
+        "root": summary_data.root,
+        "breakdown": summary_data.breakdown,
         "results": results,
     });
 
     Ok(serde_json::to_string_pretty(&output)?)
 }
 
-pub fn write_report(output: String, output_folder: &Option<PathBuf>, filename: &str) -> Result<()> {
+/// The `summary` object of JSON reports and the last ndjson line.
+fn summary_json(summary_data: &SummaryData) -> serde_json::Value {
+    serde_json::json!({
+        "total_files_checked": summary_data.total,
+        "matches": summary_data.matches,
+        "quick_matches": summary_data.quick_matches,
+        "differences": summary_data.diffs,
+        "allowed_differences": summary_data.allowed_diffs,
+        "unstable": summary_data.unstable,
+        "warnings": summary_data.warnings,
+        "missing_in_folder2": summary_data.missing,
+        "extra_in_folder2": summary_data.extra,
+        "renamed": summary_data.renamed,
+        "errors": summary_data.errors,
+        "hash_errors": summary_data.hash_errors,
+        "permission_denied": summary_data.permission_denied,
+        "out_of_tree": summary_data.out_of_tree,
+        "bytes_to_transfer": summary_data.transfer_bytes,
+        "relocated_content": summary_data.relocated,
+        "time_taken": format!("{:.2?}", summary_data.elapsed),
+        "sample_confidence": summary_data.sample_confidence.map(|c| serde_json::json!({
+            "sampled": c.sampled,
+            "sampled_differences": c.sampled_diffs,
+            "unverified": c.unverified,
+            "confidence": SampleConfidence::LEVEL,
+            "max_difference_rate": c.max_diff_rate(),
+            "max_undetected": c.max_undetected(),
+        })),
+        "previous_run": summary_data.previous,
+    })
+}
+
+/// A walk error as a result row, the way csv and ndjson reports list it: an
+/// `ERROR` status with the side in front of the message.
+pub fn error_result(e: &ErrorEntry, source: &str) -> ComparisonResult {
+    ComparisonResult {
+        error: Some(format!("{}: {}", source, e.error)),
+        ..ComparisonResult::new(e.path.clone(), Status::Error)
+    }
+}
+
+/// One line of `--output-format ndjson`: the result's JSON report object.
+pub fn ndjson_line(result: &ComparisonResult) -> Result<String> {
+    Ok(serde_json::to_string(result)? + "\n")
+}
+
+/// The last line of `--output-format ndjson`, `{"summary": {...}}` with the
+/// same keys as the JSON report's summary.
+pub fn ndjson_summary_line(summary_data: &SummaryData) -> Result<String> {
+    let summary = serde_json::json!({ "summary": summary_json(summary_data) });
+    Ok(serde_json::to_string(&summary)? + "\n")
+}
+
+/// One line of `--porcelain` output: the status and path separated by a tab,
+/// plus the folder2 path of a RENAMED entry. Backslashes, tabs and line
+/// breaks in paths are written as `\\`, `\t`, `\n` and `\r`, so every
+/// result is exactly one line. This format does not change between versions.
+pub fn porcelain_line(result: &ComparisonResult) -> String {
+    let escape = |path: &Path| {
+        let path = path.to_string_lossy();
+        let mut out = String::with_capacity(path.len());
+        for c in path.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '\t' => out.push_str("\\t"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                c => out.push(c),
+            }
+        }
+        out
+    };
+    let mut line = format!("{}\t{}", result.status, escape(&result.file));
+    if let Some(to) = &result.renamed_to {
+        line.push('\t');
+        line.push_str(&escape(to));
+    }
+    line.push('\n');
+    line
+}
+
+/// A whole `--porcelain` report: one line per result, then one `ERROR` line
+/// per walk error. There is no summary; the exit code gives the verdict.
+pub fn generate_porcelain_report(
+    results: &[ComparisonResult],
+    errors1: &[ErrorEntry],
+    errors2: &[ErrorEntry],
+) -> String {
+    let mut out: String = results.iter().map(porcelain_line).collect();
+    for (errors, source) in [(errors1, "folder1"), (errors2, "folder2")] {
+        for e in errors {
+            out.push_str(&porcelain_line(&error_result(e, source)));
+        }
+    }
+    out
+}
+
+/// A whole ndjson report, for modes that only know their results at the end:
+/// every result, then walk errors, then the summary line.
+pub fn generate_ndjson_report(
+    results: &[ComparisonResult],
+    errors1: &[ErrorEntry],
+    errors2: &[ErrorEntry],
+    summary_data: &SummaryData,
+) -> Result<String> {
+    let mut out = String::new();
+    for r in results {
+        out.push_str(&ndjson_line(r)?);
+    }
+    for (errors, source) in [(errors1, "folder1"), (errors2, "folder2")] {
+        for e in errors {
+            out.push_str(&ndjson_line(&error_result(e, source))?);
+        }
+    }
+    out.push_str(&ndjson_summary_line(summary_data)?);
+    Ok(out)
+}
+
+const HTML_STYLE: &str = "\
+body { font-family: system-ui, sans-serif; margin: 2em; color: #1f2328; }
+h1 { font-size: 1.4em; }
+table.summary { border-collapse: collapse; margin-bottom: 1.5em; }
+table.summary td { padding: 0.2em 1em; border-bottom: 1px solid #d0d7de; }
+ul.tree { list-style: none; padding-left: 1.2em; margin: 0; }
+details > summary { cursor: pointer; font-weight: 600; }
+.counts { font-weight: normal; color: #57606a; margin-left: 0.5em; }
+.badge { display: inline-block; min-width: 7em; font-family: monospace; font-weight: 600; }
+.detail { color: #57606a; margin-left: 0.5em; }
+.match { color: #1a7f37; }
+.diff, .error { color: #cf222e; }
+.allowed-diff { color: #9a6700; }
+.missing, .extra { color: #0969da; }
+.renamed { color: #1b7c83; }
+.out-of-tree { color: #8250df; }
+";
+
+/// Escape text for use in HTML element content and attribute values.
+fn html_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn css_color(color: Color) -> &'static str {
+    match color {
+        Color::Green => "#1a7f37",
+        Color::Red => "#cf222e",
+        Color::Yellow => "#9a6700",
+        Color::Blue => "#0969da",
+        Color::Cyan => "#1b7c83",
+        Color::Magenta => "#8250df",
+        _ => "inherit",
+    }
+}
+
+/// Results of one directory and its subdirectories, for the HTML tree.
+#[derive(Default)]
+struct HtmlDir<'a> {
+    dirs: BTreeMap<String, HtmlDir<'a>>,
+    files: Vec<&'a ComparisonResult>,
+}
+
+impl<'a> HtmlDir<'a> {
+    fn of(results: &'a [ComparisonResult]) -> Self {
+        let mut root = Self::default();
+        for r in results {
+            let mut dir = &mut root;
+            if let Some(parent) = r.file.parent() {
+                for part in parent.components() {
+                    let name = part.as_os_str().to_string_lossy().into_owned();
+                    dir = dir.dirs.entry(name).or_default();
+                }
+            }
+            dir.files.push(r);
+        }
+        root
+    }
+
+    /// Results below this directory, and how many of them are not MATCH.
+    fn counts(&self) -> (usize, usize) {
+        self.dirs.values().map(Self::counts).fold(
+            (
+                self.files.len(),
+                self.files
+                    .iter()
+                    .filter(|r| !matches!(r.status, Status::Match | Status::QuickMatch))
+                    .count(),
+            ),
+            |(total, off), (t, o)| (total + t, off + o),
+        )
+    }
+
+    fn render(&self, out: &mut String) {
+        out.push_str("<ul class=\"tree\">\n");
+        for (name, dir) in &self.dirs {
+            let (total, off) = dir.counts();
+            // Only directories holding something to look at start expanded.
+            out.push_str(&format!(
+                "<li><details{}><summary>{}/<span class=\"counts\">{} file(s), {} not matching</span></summary>\n",
+                if off > 0 { " open" } else { "" },
+                html_escape(name),
+                total,
+                off
+            ));
+            dir.render(out);
+            out.push_str("</details></li>\n");
+        }
+        for r in &self.files {
+            let class = r.status.to_string().to_lowercase().replace('_', "-");
+            let name = r
+                .file
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| r.file.display().to_string());
+            let mut detail = String::new();
+            if let Some(to) = &r.renamed_to {
+                detail.push_str(&format!(
+                    "&rarr; {}",
+                    html_escape(&to.display().to_string())
+                ));
+            }
+            if let Some(error) = &r.error {
+                detail.push_str(&html_escape(error));
+            }
+            out.push_str(&format!(
+                "<li class=\"{}\"><span class=\"badge\">{}</span>{}<span class=\"detail\">{}</span></li>\n",
+                class,
+                r.status,
+                html_escape(&name),
+                detail
+            ));
+        }
+        out.push_str("</ul>\n");
+    }
+}
+
+/// A standalone HTML page: summary table, walk errors and every result in a
+/// collapsible directory tree. No scripts or external resources are used, so
+/// the file can be attached to a ticket and opened anywhere.
+pub fn generate_html_report(
+    results: &[ComparisonResult],
+    errors1: &[ErrorEntry],
+    errors2: &[ErrorEntry],
+    summary_data: &SummaryData,
+    config: &ReportConfig,
+) -> String {
+    let title = match &config.folders {
+        Some((f1, f2)) => format!("cmpf: {} vs {}", f1.display(), f2.display()),
+        None => "cmpf comparison".to_string(),
+    };
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<h1>{}</h1>\n<p>Generated {} by cmpf {}</p>\n",
+        html_escape(&title),
+        HTML_STYLE,
+        html_escape(&title),
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    out.push_str("<h2>Summary</h2>\n<table class=\"summary\">\n");
+    for (label, value, color) in summary_rows(summary_data, config) {
+        out.push_str(&format!(
+            "<tr><td>{}</td><td style=\"color: {}\">{}</td></tr>\n",
+            label,
+            css_color(color),
+            html_escape(&value)
+        ));
+    }
+    out.push_str("</table>\n");
+
+    if let Some(root) = summary_data
+        .root
+        .as_ref()
+        .filter(|r| r.status == Status::Diff)
+    {
+        out.push_str("<h2>Root metadata</h2>\n<ul class=\"diff\">\n");
+        for d in &root.differences {
+            out.push_str(&format!("<li>{}</li>\n", html_escape(d)));
+        }
+        out.push_str("</ul>\n");
+    }
+
+    if !errors1.is_empty() || !errors2.is_empty() {
+        out.push_str("<h2>Errors</h2>\n<ul class=\"error\">\n");
+        for (errors, source) in [(errors1, "folder1"), (errors2, "folder2")] {
+            for e in errors {
+                out.push_str(&format!(
+                    "<li>{} ({}: {})</li>\n",
+                    html_escape(&e.path.display().to_string()),
+                    source,
+                    html_escape(&e.error)
+                ));
+            }
+        }
+        out.push_str("</ul>\n");
+    }
+
+    out.push_str("<h2>Files</h2>\n");
+    HtmlDir::of(results).render(&mut out);
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+const CSV_HEADER: [&str; 23] = [
+    "file",
+    "status",
+    "size1",
+    "size2",
+    "modified1",
+    "modified2",
+    "blake3_1",
+    "blake3_2",
+    "sha256_1",
+    "sha256_2",
+    "renamed_to",
+    "severity",
+    "error",
+    "xxh3_1",
+    "xxh3_2",
+    "md5_1",
+    "md5_2",
+    "sha1_1",
+    "sha1_2",
+    "sha512_1",
+    "sha512_2",
+    "blake2b_1",
+    "blake2b_2",
+];
+
+/// Quote a field when it holds the separator, a quote or a line break,
+/// doubling any quotes inside (RFC 4180).
+fn csv_field(value: &str, separator: char) -> String {
+    if value.contains([separator, '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// One row per result, then one `ERROR` row per walk error with the side
+/// in its `error` column. Digests not computed in the run are left empty.
+/// `separator` is `,` for CSV and a tab for TSV.
+pub fn generate_csv_report(
+    results: &[ComparisonResult],
+    errors1: &[ErrorEntry],
+    errors2: &[ErrorEntry],
+    separator: char,
+) -> String {
+    let mut out = String::new();
+    let mut row = |fields: &[String]| {
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f, separator)).collect();
+        out.push_str(&fields.join(&separator.to_string()));
+        out.push_str("\r\n");
+    };
+    row(&CSV_HEADER.map(str::to_string));
+
+    let text = |v: Option<String>| v.unwrap_or_default();
+    let error_rows: Vec<ComparisonResult> = [(errors1, "folder1"), (errors2, "folder2")]
+        .into_iter()
+        .flat_map(|(errors, source)| errors.iter().map(move |e| error_result(e, source)))
+        .collect();
+    for r in results.iter().chain(&error_rows) {
+        let digest = |h: &Option<HashResult>, pick: fn(&HashResult) -> &Option<String>| {
+            h.as_ref().and_then(|h| pick(h).clone()).unwrap_or_default()
+        };
+        row(&[
+            r.file.display().to_string(),
+            r.status.to_string(),
+            text(r.size1.map(|s| s.to_string())),
+            text(r.size2.map(|s| s.to_string())),
+            text(r.modified1.clone()),
+            text(r.modified2.clone()),
+            digest(&r.hash1, |h| &h.blake3),
+            digest(&r.hash2, |h| &h.blake3),
+            digest(&r.hash1, |h| &h.sha256),
+            digest(&r.hash2, |h| &h.sha256),
+            text(r.renamed_to.as_ref().map(|p| p.display().to_string())),
+            text(r.severity.map(|s| format!("{:?}", s).to_lowercase())),
+            text(r.error.clone()),
+            digest(&r.hash1, |h| &h.xxh3),
+            digest(&r.hash2, |h| &h.xxh3),
+            digest(&r.hash1, |h| &h.md5),
+            digest(&r.hash2, |h| &h.md5),
+            digest(&r.hash1, |h| &h.sha1),
+            digest(&r.hash2, |h| &h.sha1),
+            digest(&r.hash1, |h| &h.sha512),
+            digest(&r.hash2, |h| &h.sha512),
+            digest(&r.hash1, |h| &h.blake2b),
+            digest(&r.hash2, |h| &h.blake2b),
+        ]);
+    }
+    out
+}
+
+/// GitLab Code Quality level of a result's severity.
+fn code_quality_severity(severity: Option<Severity>) -> &'static str {
+    match severity {
+        Some(Severity::Error) => "major",
+        Some(Severity::Warning) => "minor",
+        Some(Severity::Info) | None => "info",
+    }
+}
+
+fn code_quality_issue(
+    check: &str,
+    description: String,
+    severity: &str,
+    path: &Path,
+) -> serde_json::Value {
+    serde_json::json!({
+        "type": "issue",
+        "check_name": format!("cmpf-{}", check),
+        "description": description,
+        "categories": ["Bug Risk"],
+        "severity": severity,
+        // Stable across runs so GitLab can tell new issues from resolved ones.
+        "fingerprint": sha256_hex(format!("{}:{}", check, path.display()).as_bytes()),
+        "location": { "path": path, "lines": { "begin": 1 } },
+    })
+}
+
+/// Report in GitLab's Code Quality format: one issue per non-matching result
+/// and per walk error. Paths are given under `roots` as passed on the command
+/// line (folder1 for MISSING, folder2 otherwise) so they resolve inside the
+/// CI checkout. Results without a configured severity use the defaults.
+pub fn generate_code_quality_report(
+    results: &[ComparisonResult],
+    errors1: &[ErrorEntry],
+    errors2: &[ErrorEntry],
+    roots: (&Path, &Path),
+) -> Result<String> {
+    let defaults = Severities::default();
+    let mut issues = Vec::new();
+    for r in results {
+        let severity = match r.status {
+            Status::Match | Status::QuickMatch => continue,
+            Status::Error | Status::Unstable => "critical",
+            status => code_quality_severity(r.severity.or_else(|| defaults.of(status))),
+        };
+        let root = if r.status == Status::Missing {
+            roots.0
+        } else {
+            roots.1
+        };
+        let path = r.renamed_to.as_ref().unwrap_or(&r.file);
+        let check = r.status.to_string().to_lowercase().replace('_', "-");
+        let (file, dir1, dir2) = (r.file.display(), roots.0.display(), roots.1.display());
+        let description = match r.status {
+            Status::Diff => format!("{} differs between {} and {}", file, dir1, dir2),
+            Status::AllowedDiff => format!("{} differs as allowed by the config file", file),
+            Status::Missing => format!("{} is in {} but missing from {}", file, dir1, dir2),
+            Status::Extra => format!("{} is in {} but not in {}", file, dir2, dir1),
+            Status::Renamed => format!(
+                "{} in {} was renamed to {} in {}",
+                file,
+                dir1,
+                path.display(),
+                dir2
+            ),
+            Status::OutOfTree => format!("{} resolves outside the compared root", file),
+            Status::Unstable => format!("{} changed while it was being compared", file),
+            Status::TypeMismatch => format!(
+                "{} is a symbolic link on one side and a directory on the other",
+                file
+            ),
+            _ => format!(
+                "{} could not be compared: {}",
+                file,
+                r.error.as_deref().unwrap_or("read error")
+            ),
+        };
+        issues.push(code_quality_issue(
+            &check,
+            description,
+            severity,
+            &root.join(path),
+        ));
+    }
+    for e in errors1.iter().chain(errors2) {
+        issues.push(code_quality_issue(
+            "error",
+            e.error.clone(),
+            "critical",
+            &e.path,
+        ));
+    }
+    Ok(serde_json::to_string_pretty(&issues)?)
+}
+
+pub fn write_report(
+    output: String,
+    output_folder: &Option<PathBuf>,
+    filename: &str,
+    checksum: bool,
+) -> Result<()> {
     if let Some(output_folder) = output_folder {
         fs::create_dir_all(output_folder)?;
         let report_path = output_folder.join(filename);
@@ -264,10 +2234,51 @@ pub fn write_report(output: String, output_folder: &Option<PathBuf>, filename: &
         if io::stdout().is_terminal() {
             println!("Report saved to {}", report_path.display());
         }
+        if checksum {
+            // `sha256sum -c` compatible: "<hex>  <name>"
+            let sidecar = output_folder.join(format!("{}.sha256", filename));
+            fs::write(
+                &sidecar,
+                format!("{}  {}\n", sha256_hex(output.as_bytes()), filename),
+            )?;
+            if io::stdout().is_terminal() {
+                println!("Checksum saved to {}", sidecar.display());
+            }
+        }
     } else {
+        if checksum {
+            eprintln!(
+                "{} --report-checksum needs --output-folder; no sidecar written",
+                "Warning:".yellow()
+            );
+        }
         for line in output.lines() {
             println!("{}", line);
         }
     }
     Ok(())
 }
+
+/// Write `diff.txt`, `missing.txt` and `extra.txt` into `dir`: one relative
+/// path per line, usable with `rsync --files-from` or `tar -T`. All three are
+/// written, empty when there is nothing in that class.
+pub fn write_status_lists(results: &[ComparisonResult], dir: &Path) -> Result<()> {
+    use anyhow::Context;
+
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create list directory {}", dir.display()))?;
+    for (status, filename) in [
+        (Status::Diff, "diff.txt"),
+        (Status::Missing, "missing.txt"),
+        (Status::Extra, "extra.txt"),
+    ] {
+        let mut list = Vec::new();
+        for r in results.iter().filter(|r| r.status == status) {
+            list.extend_from_slice(r.file.as_os_str().as_encoded_bytes());
+            list.push(b'\n');
+        }
+        let path = dir.join(filename);
+        fs::write(&path, list).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
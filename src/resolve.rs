@@ -0,0 +1,251 @@
+use anyhow::Result;
+use colored::*;
+use rayon::prelude::*;
+use similar::{ChangeTag, TextDiff};
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::compare::{CompareConfig, ExitStatus, Rules, compare_files_core, is_snapshot_file};
+use crate::models::{ComparisonResult, FileEntry, Status};
+use crate::report::{
+    ResolveSummary, generate_resolve_summary_text, print_error_entries, print_status_line,
+};
+use crate::sync::copy_into_place;
+use crate::utils::{collect_files, key_by_relative_path};
+
+/// Files larger than this are not read for a line diff.
+const TEXT_DIFF_MAX_BYTES: u64 = 256 * 1024;
+/// Time the line diff may take before settling for a coarser one.
+const TEXT_DIFF_TIMEOUT: Duration = Duration::from_millis(200);
+/// Changed lines shown before the rest is elided.
+const TEXT_DIFF_MAX_LINES: usize = 40;
+
+/// What the user chose for one differing file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    /// Copy the folder1 file over the folder2 one.
+    Left,
+    /// Copy the folder2 file over the folder1 one.
+    Right,
+    Skip,
+    Quit,
+}
+
+impl Choice {
+    fn parse(answer: &str) -> Option<Self> {
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "l" | "left" => Some(Self::Left),
+            "r" | "right" => Some(Self::Right),
+            "s" | "skip" | "" => Some(Self::Skip),
+            "q" | "quit" => Some(Self::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Compare both folders and walk through the DIFF results one by one, asking on
+/// `input` whether to copy the left file over the right one, the right over the
+/// left, or leave the pair alone.
+///
+/// Files only present on one side are not offered; `sync` handles those.
+pub fn run_resolve(config: CompareConfig, mut input: impl BufRead) -> Result<ExitStatus> {
+    let start_time = Instant::now();
+    for folder in [&config.folder1, &config.folder2] {
+        if folder.as_path() == Path::new("-") || is_snapshot_file(folder) {
+            anyhow::bail!(
+                "resolve needs two live folders, but {} is not one",
+                folder.display()
+            );
+        }
+    }
+
+    let collect = |root: &Path| {
+        collect_files(
+            root,
+            config.depth,
+            config.no_recursive,
+            config.hidden,
+            &config.types,
+            &config.ignore,
+            config.symlinks,
+            config.owner_filter,
+            config.one_file_system,
+        )
+    };
+    let (res1, res2) = rayon::join(|| collect(&config.folder1), || collect(&config.folder2));
+    let (files1, errors1) = res1?;
+    let (files2, errors2) = res2?;
+    print_error_entries(&errors1, "folder1");
+    print_error_entries(&errors2, "folder2");
+
+    let (map1, _) = key_by_relative_path(files1, &config.folder1);
+    let (map2, _) = key_by_relative_path(files2, &config.folder2);
+    let rules = Rules::new(&config)?;
+    let mut diffs: Vec<ComparisonResult> = map1
+        .par_iter()
+        .filter_map(|(rel, e1)| Some((rel, e1, map2.get(rel)?)))
+        .map(|(rel, e1, e2)| compare_files_core(rel.clone(), e1, e2, &config, &rules))
+        .filter(|r| !matches!(r, Ok(r) if r.status != Status::Diff))
+        .collect::<Result<_>>()?;
+    diffs.sort_by(|a, b| a.file.cmp(&b.file));
+
+    let mut summary = ResolveSummary {
+        differing: diffs.len(),
+        errors: errors1.len() + errors2.len(),
+        ..Default::default()
+    };
+    let mut quit = false;
+    for (n, result) in diffs.iter().enumerate() {
+        let (e1, e2) = (&map1[&result.file], &map2[&result.file]);
+        if quit {
+            summary.skipped += 1;
+            continue;
+        }
+        println!("\n({}/{}) {}", n + 1, diffs.len(), "-".repeat(40).dimmed());
+        print!("{}", result.format_text(false, config.algo)?);
+        for (side, entry, modified, hash) in [
+            ("folder1", e1, &result.modified1, &result.hash1),
+            ("folder2", e2, &result.modified2, &result.hash2),
+        ] {
+            let digest = hash
+                .as_ref()
+                .and_then(|h| {
+                    h.blake3
+                        .as_deref()
+                        .or(h.sha256.as_deref())
+                        .or(h.sha512.as_deref())
+                        .or(h.blake2b.as_deref())
+                        .or(h.xxh3.as_deref())
+                        .or(h.sha1.as_deref())
+                        .or(h.md5.as_deref())
+                })
+                .map(|d| format!(", {}", d.cyan()))
+                .unwrap_or_default();
+            println!(
+                "    {}: {} bytes, modified {}{}",
+                side.dimmed(),
+                entry.size,
+                modified.as_deref().unwrap_or("?"),
+                digest
+            );
+        }
+        if e1.symlink_target.is_some() || e2.symlink_target.is_some() {
+            println!("    symlinks are not resolved here; skipped");
+            summary.skipped += 1;
+            continue;
+        }
+        print_text_diff(e1, e2);
+
+        let (source, dest) = match prompt(&mut input)? {
+            Choice::Left => (&e1.path, &e2.path),
+            Choice::Right => (&e2.path, &e1.path),
+            Choice::Skip => {
+                summary.skipped += 1;
+                continue;
+            }
+            Choice::Quit => {
+                quit = true;
+                summary.skipped += 1;
+                continue;
+            }
+        };
+        match copy_into_place(source, dest) {
+            Ok(bytes) => {
+                println!("{} {}", "COPIED".green().bold(), dest.display());
+                summary.copied += 1;
+                summary.bytes_copied += bytes;
+            }
+            Err(e) => {
+                println!("{} {} ({:#})", "FAILED".red().bold(), dest.display(), e);
+                summary.failed += 1;
+            }
+        }
+    }
+    summary.elapsed = start_time.elapsed();
+
+    println!();
+    for line in generate_resolve_summary_text(&summary, &config.report_config(&rules)) {
+        println!("{}", line);
+    }
+
+    let status = if summary.errors > 0 || summary.failed > 0 {
+        ExitStatus::Error
+    } else if summary.skipped > 0 {
+        ExitStatus::Diff
+    } else {
+        ExitStatus::Success
+    };
+    if config.status_line {
+        print_status_line(&status, &summary.status_fields());
+    }
+    Ok(status)
+}
+
+/// Ask until a valid answer arrives; end of input quits.
+fn prompt(input: &mut impl BufRead) -> Result<Choice> {
+    loop {
+        print!(
+            "{} copy left -> right, {} copy right -> left, {} skip, {} quit: ",
+            "[l]".bold(),
+            "[r]".bold(),
+            "[s]".bold(),
+            "[q]".bold()
+        );
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            println!();
+            return Ok(Choice::Quit);
+        }
+        if let Some(choice) = Choice::parse(&answer) {
+            return Ok(choice);
+        }
+        println!("Unknown answer {:?}", answer.trim());
+    }
+}
+
+/// Print the changed lines of two small UTF-8 files; binary or large files
+/// are left to the hashes shown above.
+fn print_text_diff(e1: &FileEntry, e2: &FileEntry) {
+    if e1.size.max(e2.size) > TEXT_DIFF_MAX_BYTES {
+        return;
+    }
+    let (Some(text1), Some(text2)) = (read_text(&e1.path), read_text(&e2.path)) else {
+        return;
+    };
+    let changes = line_diff(&text1, &text2);
+    for change in changes.iter().take(TEXT_DIFF_MAX_LINES) {
+        println!("    {}", change);
+    }
+    if changes.len() > TEXT_DIFF_MAX_LINES {
+        println!(
+            "    ... {} more changed lines",
+            changes.len() - TEXT_DIFF_MAX_LINES
+        );
+    }
+}
+
+fn read_text(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().filter(|t| !t.contains('\0'))
+}
+
+/// Lines removed from `text1` (`-N:`) and added in `text2` (`+N:`).
+pub(crate) fn line_diff(text1: &str, text2: &str) -> Vec<ColoredString> {
+    let diff = TextDiff::configure()
+        .timeout(TEXT_DIFF_TIMEOUT)
+        .diff_lines(text1, text2);
+    diff.iter_all_changes()
+        .filter_map(|change| {
+            let line = change.value().trim_end_matches(['\n', '\r']);
+            match change.tag() {
+                ChangeTag::Delete => Some(format!("-{}: {}", change.old_index()? + 1, line).red()),
+                ChangeTag::Insert => {
+                    Some(format!("+{}: {}", change.new_index()? + 1, line).green())
+                }
+                ChangeTag::Equal => None,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,86 @@
+use serde::Serialize;
+
+/// What the process used during a run (`--resource-usage`), to help choose
+/// `--threads` and similar settings. Fields the platform cannot report are
+/// `None`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ResourceUsage {
+    /// Largest resident set size reached so far.
+    pub peak_rss_bytes: Option<u64>,
+    pub user_cpu_secs: f64,
+    pub system_cpu_secs: f64,
+    /// Bytes fetched from storage (Linux); reads served from the page cache
+    /// are not counted, memory-mapped reads are.
+    pub read_bytes: Option<u64>,
+    /// File descriptors open when measured (Linux).
+    pub open_files: Option<usize>,
+    /// Soft limit on open file descriptors.
+    pub open_files_limit: Option<u64>,
+}
+
+impl ResourceUsage {
+    /// Usage of the whole process up to now; `None` off Unix.
+    pub fn measure() -> Option<Self> {
+        #[cfg(unix)]
+        {
+            Some(unix::measure())
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+
+    /// `12.3 MiB`, for the summary.
+    pub fn peak_rss_text(&self) -> Option<String> {
+        self.peak_rss_bytes
+            .map(|b| format!("{:.1} MiB", b as f64 / (1u64 << 20) as f64))
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::ResourceUsage;
+    use std::fs;
+
+    fn timeval_secs(tv: libc::timeval) -> f64 {
+        tv.tv_sec as f64 + tv.tv_usec as f64 / 1e6
+    }
+
+    /// `read_bytes` from `/proc/self/io`, which some containers hide.
+    fn storage_reads() -> Option<u64> {
+        let io = fs::read_to_string("/proc/self/io").ok()?;
+        io.lines()
+            .find_map(|line| line.strip_prefix("read_bytes:"))
+            .and_then(|n| n.trim().parse().ok())
+    }
+
+    fn open_files() -> Option<usize> {
+        // Listing the directory opens one more descriptor, which is not counted.
+        let fds = fs::read_dir("/proc/self/fd").ok()?;
+        Some(fds.count().saturating_sub(1))
+    }
+
+    pub(super) fn measure() -> ResourceUsage {
+        // SAFETY: both calls only write into the zeroed structs passed to them.
+        let (usage, limit) = unsafe {
+            let mut usage: libc::rusage = std::mem::zeroed();
+            let mut limit: libc::rlimit = std::mem::zeroed();
+            let usage = (libc::getrusage(libc::RUSAGE_SELF, &mut usage) == 0).then_some(usage);
+            let limit = (libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) == 0).then_some(limit);
+            (usage, limit)
+        };
+        // ru_maxrss is in kilobytes, except on macOS where it is in bytes.
+        let rss_unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+        ResourceUsage {
+            peak_rss_bytes: usage.map(|u| u.ru_maxrss as u64 * rss_unit),
+            user_cpu_secs: usage.map_or(0.0, |u| timeval_secs(u.ru_utime)),
+            system_cpu_secs: usage.map_or(0.0, |u| timeval_secs(u.ru_stime)),
+            read_bytes: storage_reads(),
+            open_files: open_files(),
+            open_files_limit: limit
+                .map(|l| l.rlim_cur)
+                .filter(|&l| l != libc::RLIM_INFINITY),
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use serde::Deserialize;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use crate::archive::ListingFilter;
+use crate::compare::CompareConfig;
+use crate::models::{FileEntry, HashAlgo, HashResult, Mode};
+use crate::utils::hash_reader;
+
+/// A bucket and key prefix given as `s3://bucket/prefix`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct S3Location {
+    pub bucket: String,
+    /// Empty, or ending with `/`.
+    pub prefix: String,
+}
+
+pub fn s3_location(path: &Path) -> Option<S3Location> {
+    let rest = path.to_str()?.strip_prefix("s3://")?;
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    if bucket.is_empty() {
+        return None;
+    }
+    let prefix = prefix.trim_end_matches('/');
+    Some(S3Location {
+        bucket: bucket.to_string(),
+        prefix: if prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", prefix)
+        },
+    })
+}
+
+pub fn is_s3(path: &Path) -> bool {
+    s3_location(path).is_some()
+}
+
+/// `aws s3api list-objects-v2` output; the CLI follows the pagination itself.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ObjectListing {
+    #[serde(default)]
+    contents: Vec<ListedObject>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListedObject {
+    key: String,
+    size: u64,
+    last_modified: Option<String>,
+    e_tag: Option<String>,
+}
+
+fn aws(args: &[&str]) -> Command {
+    let mut cmd = Command::new("aws");
+    cmd.args(args).stdin(Stdio::null());
+    cmd
+}
+
+/// The MD5 digest an ETag stands for: only that of an object uploaded in one
+/// part, and not encrypted with SSE-KMS or SSE-C, which S3 cannot tell us.
+fn etag_md5(etag: &str) -> Option<String> {
+    let etag = etag.trim_matches('"');
+    (etag.len() == 32 && etag.bytes().all(|b| b.is_ascii_hexdigit())).then(|| etag.to_lowercase())
+}
+
+/// Parse a listing into entries under `root`, keyed by their path below the
+/// prefix. `folder/` marker objects are skipped and the scan options applied.
+/// With `--algo md5` and no `--s3-download`, single-part ETags become the
+/// entries' digests; every other entry is downloaded by [`object_hashes`] if
+/// its content is compared.
+pub(crate) fn parse_listing(
+    reader: impl Read,
+    location: &S3Location,
+    root: &Path,
+    config: &CompareConfig,
+) -> Result<Vec<(String, FileEntry)>> {
+    let filter = ListingFilter::new(config)?;
+    let listing: ObjectListing =
+        serde_json::from_reader(reader).context("Failed to parse aws s3api output")?;
+    Ok(listing
+        .contents
+        .into_iter()
+        .filter_map(|object| {
+            let rel = object.key.strip_prefix(&location.prefix)?;
+            if rel.is_empty() || rel.ends_with('/') {
+                return None;
+            }
+            let rel_path = PathBuf::from(rel);
+            if filter.excludes(&rel_path) {
+                return None;
+            }
+            let hashes = object
+                .e_tag
+                .as_deref()
+                .and_then(etag_md5)
+                .filter(|_| {
+                    config.mode != Mode::Metadata
+                        && config.algo == HashAlgo::Md5
+                        && !config.s3_download
+                })
+                .map(|md5| HashResult {
+                    md5: Some(md5),
+                    ..HashResult::default()
+                });
+            let entry = FileEntry {
+                path: root.join(&rel_path),
+                size: object.size,
+                modified: object
+                    .last_modified
+                    .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                    .map(SystemTime::from),
+                hashes,
+                etag: object.e_tag.map(|etag| etag.trim_matches('"').to_string()),
+                ..FileEntry::default()
+            };
+            Some((object.key, entry))
+        })
+        .collect())
+}
+
+/// Stream an object through `aws s3 cp` into the hasher.
+fn download_hashes(bucket: &str, key: &str, algo: HashAlgo) -> Result<HashResult> {
+    let url = format!("s3://{}/{}", bucket, key);
+    let mut child = aws(&["s3", "cp", "--quiet", &url, "-"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run aws; is the AWS CLI installed and on PATH?")?;
+    let hashes = hash_reader(child.stdout.take().expect("stdout is piped"), algo);
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "download failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(hashes?)
+}
+
+/// Digests of the object an entry of [`read_s3`] stands for, downloaded and
+/// hashed as it streams by; nothing is written to disk.
+pub(crate) fn object_hashes(path: &Path, algo: HashAlgo) -> io::Result<HashResult> {
+    let location = s3_location(path).expect("object_hashes called on an s3:// path");
+    let key = location.prefix.trim_end_matches('/');
+    download_hashes(&location.bucket, key, algo).map_err(|e| io::Error::other(format!("{:#}", e)))
+}
+
+/// List an S3 bucket prefix as one side of a comparison, with the AWS CLI.
+/// Objects are compared by size and ETag (`--algo md5`), or by the digests of
+/// [`object_hashes`]; only objects whose content is compared are downloaded.
+pub fn read_s3(path: &Path, config: &CompareConfig) -> Result<Vec<FileEntry>> {
+    let location = s3_location(path).expect("read_s3 called on an s3:// path");
+    if config.owner_filter.is_some() {
+        anyhow::bail!("--owner-filter cannot be applied to {}", path.display());
+    }
+    let output = aws(&[
+        "s3api",
+        "list-objects-v2",
+        "--bucket",
+        &location.bucket,
+        "--prefix",
+        &location.prefix,
+        "--output",
+        "json",
+    ])
+    .output()
+    .context("Failed to run aws; is the AWS CLI installed and on PATH?")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "aws s3api list-objects-v2 {} failed ({}): {}",
+            path.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // An empty prefix lists as no output at all.
+    if output.stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Vec::new());
+    }
+    Ok(parse_listing(&output.stdout[..], &location, path, config)?
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .collect())
+}
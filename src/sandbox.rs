@@ -0,0 +1,501 @@
+//! `--sandbox`: confine the process to the paths a run needs before it opens
+//! any of them.
+//!
+//! On Linux this uses landlock, which needs no privileges and binds root as
+//! well: once applied, the calling thread and every thread it starts later
+//! can read only beneath the listed roots and write only into the listed
+//! output directories. Threads that already exist are not confined, so the
+//! sandbox is applied before the Rayon pool is built.
+//!
+//! Elsewhere there is no landlock, so the restriction is kept in userspace:
+//! the walker and every reader of compared files resolve their paths one
+//! component at a time from a descriptor of the read root, as `openat` with
+//! `RESOLVE_BENEATH` would, and a symbolic link is only followed while it
+//! stays beneath a read root. Writes are not restricted there.
+//!
+//! `--assert-readonly` uses the same rules with the whole filesystem readable,
+//! so only writes outside the outputs are denied.
+
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The paths a sandboxed run may use.
+#[derive(Debug)]
+pub struct Sandbox {
+    /// Option named in errors.
+    flag: &'static str,
+    read: Vec<PathBuf>,
+    write: Vec<PathBuf>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Sandbox {
+            flag: "--sandbox",
+            read: Vec::new(),
+            write: Vec::new(),
+        }
+    }
+}
+
+impl Sandbox {
+    /// Rules of `--assert-readonly`: every path stays readable and programs
+    /// can still be run, but only the outputs added with `write_dir` and
+    /// `write_file` can be written.
+    pub fn readonly() -> Self {
+        Sandbox {
+            flag: "--assert-readonly",
+            read: vec![PathBuf::from("/")],
+            write: Vec::new(),
+        }
+    }
+
+    /// Allow reading the file or directory tree at `path`.
+    pub fn read(&mut self, path: &Path) {
+        if path != Path::new("-") {
+            self.read.push(path.to_path_buf());
+        }
+    }
+
+    /// Allow creating and writing files in directory `dir`, which is created
+    /// now since rules can only be attached to existing paths.
+    pub fn write_dir(&mut self, dir: &Path) -> Result<()> {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        fs::create_dir_all(dir)
+            .with_context(|| format!("{}: cannot create {}", self.flag, dir.display()))?;
+        self.write.push(dir.to_path_buf());
+        Ok(())
+    }
+
+    /// Allow creating and writing the file at `path`, which grants its
+    /// directory.
+    pub fn write_file(&mut self, path: &Path) -> Result<()> {
+        self.write_dir(path.parent().unwrap_or(Path::new(".")))
+    }
+
+    /// Restrict the calling thread and the threads it starts from now on.
+    /// On Linux this fails rather than running unconfined when the kernel
+    /// cannot enforce it; elsewhere only reads are restricted, in userspace.
+    pub fn apply(&self) -> Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            landlock::restrict(self.flag, &self.read, &self.write)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let beneath = Beneath::new(self.flag, &self.read)?;
+            CONFINED
+                .set(beneath)
+                .map_err(|_| anyhow::anyhow!("{}: applied twice", self.flag))
+        }
+    }
+}
+
+/// Read roots in force where landlock is not available.
+static CONFINED: OnceLock<Beneath> = OnceLock::new();
+
+/// Open `path` for reading, beneath a read root when the sandbox is kept in
+/// userspace.
+pub(crate) fn open(path: &Path) -> io::Result<File> {
+    match CONFINED.get() {
+        Some(beneath) => beneath.open(path),
+        None => File::open(path),
+    }
+}
+
+/// Whether `path` may be opened under a userspace sandbox; always `Ok` when
+/// there is none. Unlike [`open`], never blocks on a FIFO.
+pub(crate) fn check(path: &Path) -> io::Result<()> {
+    match CONFINED.get() {
+        Some(beneath) => beneath.check(path),
+        None => Ok(()),
+    }
+}
+
+/// Whether the walker has to check the links it follows.
+pub(crate) fn confined() -> bool {
+    CONFINED.get().is_some()
+}
+
+/// Whether `err` is a userspace sandbox refusing a path, rather than an
+/// error of the path itself.
+pub(crate) fn refused(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<Refused>())
+}
+
+#[derive(Debug)]
+struct Refused {
+    flag: &'static str,
+    path: PathBuf,
+}
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} leads outside the folders being read",
+            self.flag,
+            self.path.display()
+        )
+    }
+}
+
+impl std::error::Error for Refused {}
+
+/// Symbolic links followed in one lookup before giving up, as the kernel does.
+const MAX_LINKS: usize = 40;
+
+/// Paths resolved beneath a set of read roots, without the kernel's help.
+#[derive(Debug)]
+pub(crate) struct Beneath {
+    flag: &'static str,
+    roots: Vec<Root>,
+}
+
+#[derive(Debug)]
+struct Root {
+    /// As given, made absolute without resolving links.
+    path: PathBuf,
+    /// With links resolved, for absolute link targets.
+    canonical: PathBuf,
+    /// Held open from the start, so every lookup begins at the directory
+    /// that was checked; `None` for a file root.
+    #[cfg(unix)]
+    dir: Option<File>,
+}
+
+/// Where a lookup continues after entering a root.
+#[cfg(unix)]
+enum Entered {
+    Dir(File),
+    /// A file root, with nothing left to resolve.
+    File(File),
+}
+
+impl Beneath {
+    /// Open every root in `read` now; each has to exist.
+    #[cfg_attr(target_os = "linux", allow(dead_code))]
+    pub(crate) fn new(flag: &'static str, read: &[PathBuf]) -> Result<Self> {
+        let roots = read
+            .iter()
+            .map(|path| {
+                let canonical = fs::canonicalize(path)
+                    .with_context(|| format!("{}: cannot open {}", flag, path.display()))?;
+                Ok(Root {
+                    path: std::path::absolute(path)?,
+                    #[cfg(unix)]
+                    dir: canonical
+                        .is_dir()
+                        .then(|| File::open(&canonical))
+                        .transpose()
+                        .with_context(|| format!("{}: cannot open {}", flag, path.display()))?,
+                    canonical,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Beneath { flag, roots })
+    }
+
+    /// Open `path` for reading if it, and every link on the way, stays
+    /// beneath a root.
+    pub(crate) fn open(&self, path: &Path) -> io::Result<File> {
+        self.resolve(path, false)
+    }
+
+    /// Like [`Beneath::open`], but without blocking on a FIFO or keeping the
+    /// file open.
+    pub(crate) fn check(&self, path: &Path) -> io::Result<()> {
+        self.resolve(path, true).map(drop)
+    }
+
+    fn refuse(&self, path: &Path) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            Refused {
+                flag: self.flag,
+                path: path.to_path_buf(),
+            },
+        )
+    }
+
+    /// The root `path` lies in, by its given or its resolved path, and the
+    /// rest of `path` beneath it. The innermost root wins.
+    fn root_of<'a>(&self, path: &'a Path) -> Option<(usize, &'a Path)> {
+        self.roots
+            .iter()
+            .enumerate()
+            .flat_map(|(i, root)| {
+                [&root.path, &root.canonical]
+                    .into_iter()
+                    .filter_map(move |prefix| path.strip_prefix(prefix).ok().map(|rest| (i, rest)))
+            })
+            .min_by_key(|(_, rest)| rest.components().count())
+    }
+
+    fn resolve(&self, path: &Path, nonblocking: bool) -> io::Result<File> {
+        let absolute = std::path::absolute(path)?;
+        let (root, rest) = self.root_of(&absolute).ok_or_else(|| self.refuse(path))?;
+        self.resolve_beneath(path, root, rest, nonblocking)
+    }
+
+    #[cfg(unix)]
+    fn enter(
+        &self,
+        path: &Path,
+        root: usize,
+        rest: &Path,
+        pending: &mut Vec<std::ffi::OsString>,
+    ) -> io::Result<Entered> {
+        let root = &self.roots[root];
+        match &root.dir {
+            Some(dir) => {
+                pending.extend(
+                    rest.components()
+                        .rev()
+                        .map(|c| c.as_os_str().to_os_string()),
+                );
+                Ok(Entered::Dir(dir.try_clone()?))
+            }
+            None if rest.as_os_str().is_empty() && pending.is_empty() => {
+                File::open(&root.canonical).map(Entered::File)
+            }
+            None => Err(self.refuse(path)),
+        }
+    }
+
+    /// Walk `rest` from the root's descriptor with `openat`, never letting
+    /// the kernel follow a link: links are read and their targets resolved
+    /// the same way, and `..` cannot climb above the root.
+    #[cfg(unix)]
+    fn resolve_beneath(
+        &self,
+        path: &Path,
+        root: usize,
+        rest: &Path,
+        nonblocking: bool,
+    ) -> io::Result<File> {
+        // Components still to resolve, the next one last.
+        let mut pending = Vec::new();
+        // The directories entered so far, the root first.
+        let mut dirs = match self.enter(path, root, rest, &mut pending)? {
+            Entered::Dir(dir) => vec![dir],
+            Entered::File(file) => return Ok(file),
+        };
+        let mut links = 0;
+        while let Some(name) = pending.pop() {
+            if name == "." {
+                continue;
+            }
+            if name == ".." {
+                if dirs.len() == 1 {
+                    return Err(self.refuse(path));
+                }
+                dirs.pop();
+                continue;
+            }
+            let parent = dirs.last().expect("the root is never left");
+            let last = pending.is_empty();
+            let err = match unix::openat(parent, &name, !last, last && nonblocking) {
+                Ok(file) if last => return Ok(file),
+                Ok(dir) => {
+                    dirs.push(dir);
+                    continue;
+                }
+                Err(err) => err,
+            };
+            // A link fails with ELOOP (EMLINK on FreeBSD) under O_NOFOLLOW;
+            // anything that is not a link keeps its own error.
+            let Ok(target) = unix::readlinkat(parent, &name) else {
+                return Err(err);
+            };
+            links += 1;
+            if links > MAX_LINKS {
+                return Err(io::Error::from_raw_os_error(libc::ELOOP));
+            }
+            if target.is_absolute() {
+                let (root, rest) = self.root_of(&target).ok_or_else(|| self.refuse(path))?;
+                match self.enter(path, root, rest, &mut pending)? {
+                    Entered::Dir(dir) => dirs = vec![dir],
+                    Entered::File(file) => return Ok(file),
+                }
+            } else {
+                pending.extend(
+                    target
+                        .components()
+                        .rev()
+                        .map(|c| c.as_os_str().to_os_string()),
+                );
+            }
+        }
+        Ok(dirs.pop().expect("the root is never left"))
+    }
+
+    /// Without `openat`, resolve every link first and require the result to
+    /// lie beneath a root; a link swapped in between is not caught.
+    #[cfg(not(unix))]
+    fn resolve_beneath(
+        &self,
+        path: &Path,
+        root: usize,
+        rest: &Path,
+        _nonblocking: bool,
+    ) -> io::Result<File> {
+        let root = &self.roots[root];
+        let resolved = if rest.as_os_str().is_empty() {
+            root.canonical.clone()
+        } else {
+            fs::canonicalize(root.canonical.join(rest))?
+        };
+        if !self
+            .roots
+            .iter()
+            .any(|root| resolved.starts_with(&root.canonical))
+        {
+            return Err(self.refuse(path));
+        }
+        File::open(&resolved)
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::{CString, OsStr, OsString};
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::PathBuf;
+
+    /// Open `name` in `dir` without following a link.
+    pub(super) fn openat(
+        dir: &File,
+        name: &OsStr,
+        directory: bool,
+        nonblocking: bool,
+    ) -> io::Result<File> {
+        let name = CString::new(name.as_bytes())?;
+        let mut flags = libc::O_RDONLY | libc::O_CLOEXEC | libc::O_NOFOLLOW;
+        if directory {
+            flags |= libc::O_DIRECTORY;
+        }
+        if nonblocking {
+            flags |= libc::O_NONBLOCK;
+        }
+        // SAFETY: the descriptor and the name outlive the call.
+        let fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `fd` was just opened and nothing else owns it.
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// Target of the link `name` in `dir`.
+    pub(super) fn readlinkat(dir: &File, name: &OsStr) -> io::Result<PathBuf> {
+        let name = CString::new(name.as_bytes())?;
+        let mut buf = vec![0u8; 256];
+        loop {
+            // SAFETY: `buf` has `buf.len()` writable bytes.
+            let n = unsafe {
+                libc::readlinkat(
+                    dir.as_raw_fd(),
+                    name.as_ptr(),
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                )
+            };
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // A full buffer may have cut the target short.
+            if (n as usize) < buf.len() {
+                buf.truncate(n as usize);
+                return Ok(PathBuf::from(OsString::from_vec(buf)));
+            }
+            buf.resize(buf.len() * 2, 0);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod landlock {
+    use anyhow::{Context, Result};
+    use landlock::{
+        ABI, Access, AccessFs, BitFlags, PathBeneath, PathFd, Ruleset, RulesetAttr, RulesetCreated,
+        RulesetCreatedAttr, RulesetStatus,
+    };
+    use std::path::{Path, PathBuf};
+
+    /// Every filesystem right up to this ABI is handled, so anything not
+    /// granted by a rule is denied; older kernels enforce what they know.
+    const ABI_VERSION: ABI = ABI::V5;
+
+    fn add_rule(
+        flag: &str,
+        ruleset: RulesetCreated,
+        path: &Path,
+        access: BitFlags<AccessFs>,
+    ) -> Result<RulesetCreated> {
+        let fd = PathFd::new(path)
+            .with_context(|| format!("{}: cannot open {}", flag, path.display()))?;
+        // Rules on a file may only carry rights that apply to files.
+        let access = if path.is_dir() {
+            access
+        } else {
+            access & AccessFs::from_file(ABI_VERSION)
+        };
+        ruleset
+            .add_rule(PathBeneath::new(fd, access))
+            .with_context(|| format!("{}: cannot allow {}", flag, path.display()))
+    }
+
+    pub(super) fn restrict(flag: &str, read: &[PathBuf], write: &[PathBuf]) -> Result<()> {
+        let read_access = AccessFs::from_read(ABI_VERSION);
+        let write_access = read_access
+            | AccessFs::WriteFile
+            | AccessFs::MakeReg
+            | AccessFs::MakeDir
+            | AccessFs::Truncate;
+        let mut ruleset = Ruleset::default()
+            .handle_access(AccessFs::from_all(ABI_VERSION))?
+            .create()
+            .with_context(|| format!("{}: cannot create the landlock ruleset", flag))?;
+        for path in read {
+            ruleset = add_rule(flag, ruleset, path, read_access)?;
+        }
+        for dir in write {
+            ruleset = add_rule(flag, ruleset, dir, write_access)?;
+        }
+        // Reports show local times; keep the zone file readable once it is
+        // needed on a worker thread.
+        if let Ok(zone) = std::fs::canonicalize("/etc/localtime")
+            && let Ok(fd) = PathFd::new(&zone)
+        {
+            ruleset = ruleset.add_rule(PathBeneath::new(fd, AccessFs::ReadFile))?;
+        }
+        // Programs started with a null stdin or output open it for writing.
+        if let Ok(fd) = PathFd::new("/dev/null") {
+            ruleset = ruleset.add_rule(PathBeneath::new(
+                fd,
+                AccessFs::ReadFile | AccessFs::WriteFile,
+            ))?;
+        }
+
+        // Also sets no_new_privs, which landlock requires of unprivileged callers.
+        let status = ruleset
+            .restrict_self()
+            .with_context(|| format!("{}: cannot restrict the process", flag))?;
+        if status.ruleset == RulesetStatus::NotEnforced {
+            anyhow::bail!("{}: landlock is not available in this kernel", flag);
+        }
+        Ok(())
+    }
+}
@@ -6,15 +6,25 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, IsTerminal, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Instant;
 
+use crate::activity;
 use crate::compare::ExitStatus;
+use crate::config::Severities;
 use crate::models::{
-    ComparisonResult, FileEntry, HashAlgo, HashResult, Mode, OutputFormat, Status, SymlinkMode,
+    ComparisonResult, ErrorEntry, FileEntry, HashAlgo, HashResult, Mode, OutputFormat, RootAttrs,
+    RootCheck, Severity, Status, SymlinkMode,
+};
+use crate::report::{
+    ReportConfig, SummaryData, generate_change_log, generate_code_quality_report,
+    generate_csv_report, generate_html_report, generate_json_report, generate_ndjson_report,
+    generate_porcelain_report, generate_text_report, print_skips, print_status_line,
+};
+use crate::utils::{
+    collect_files, compute_hashes, drop_cached_pages, explain_skips, read_root_attributes,
 };
-use crate::report::{ReportConfig, SummaryData, generate_json_report, generate_text_report};
-use crate::utils::{collect_files, compute_hashes};
 
 // Fix #6: store the scan parameters alongside the snapshot data so that
 // verify_snapshot can reproduce the exact same walk instead of hardcoding
@@ -27,6 +37,12 @@ pub struct SnapshotScanParams {
     pub types: Option<Vec<String>>,
     pub ignore: Option<Vec<String>>,
     pub symlinks: SymlinkMode,
+    /// Uid given to `--owner-filter`, if any.
+    #[serde(default)]
+    pub owner_filter: Option<u32>,
+    /// Whether `--one-file-system` kept the walk off other mounts.
+    #[serde(default)]
+    pub one_file_system: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -39,6 +55,9 @@ pub struct Snapshot {
     /// An absent field (old snapshot files) falls back to safe defaults.
     #[serde(default)]
     pub scan_params: Option<SnapshotScanParams>,
+    /// Permissions, owner and xattrs of the snapshotted folder itself.
+    #[serde(default)]
+    pub root: Option<RootAttrs>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -48,8 +67,148 @@ pub struct SnapshotEntry {
     pub modified: Option<std::time::SystemTime>,
     pub hashes: HashResult,
     pub symlink_target: Option<String>,
+    /// `--double-read` got different digests from two reads; `hashes` holds the first.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unstable_read: bool,
+}
+
+/// Read and parse a snapshot file written by `create_snapshot`.
+pub fn load_snapshot(path: &Path) -> Result<Snapshot> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open snapshot {}", path.display()))?;
+    serde_json::from_reader(io::BufReader::new(file))
+        .with_context(|| format!("Failed to parse snapshot {}", path.display()))
+}
+
+/// Snapshot files read for one run, by the path they were named by, so each
+/// is parsed once.
+pub(crate) type LoadedSnapshots = HashMap<PathBuf, Arc<Snapshot>>;
+
+impl Snapshot {
+    /// The recorded files as entries rooted at the snapshot's original folder,
+    /// carrying their recorded digests instead of being read from disk.
+    pub fn entries(&self) -> Vec<FileEntry> {
+        let root = PathBuf::from(&self.root_path);
+        self.files
+            .iter()
+            .map(|e| FileEntry {
+                path: root.join(&e.rel_path),
+                size: e.size,
+                modified: e.modified,
+                symlink_target: e.symlink_target.clone(),
+                hashes: Some(e.hashes.clone()),
+                ..FileEntry::default()
+            })
+            .collect()
+    }
+
+    /// Walk `folder` with the same scan parameters the snapshot was taken with.
+    // Fix #6: reproduce the exact scan parameters used when the snapshot was created.
+    // For old snapshots without scan_params, fall back to sensible defaults.
+    pub fn collect(&self, folder: &Path) -> Result<(Vec<FileEntry>, Vec<ErrorEntry>)> {
+        let sp = self.scan_params.as_ref();
+        collect_files(
+            folder,
+            sp.and_then(|p| p.depth),
+            sp.map(|p| p.no_recursive).unwrap_or(false),
+            sp.map(|p| p.hidden).unwrap_or(false),
+            &sp.and_then(|p| p.types.clone()),
+            &sp.and_then(|p| p.ignore.clone()),
+            sp.map(|p| p.symlinks).unwrap_or(SymlinkMode::Ignore),
+            sp.and_then(|p| p.owner_filter),
+            sp.is_some_and(|p| p.one_file_system),
+        )
+    }
+}
+
+/// Write the files of a compared folder, keyed by their path relative to
+/// `folder`, as a snapshot of it (`--emit-manifest`). Digests in `known` were
+/// computed by the comparison and are reused; only the other files are read.
+/// Returns how many files had to be hashed.
+pub fn write_manifest(
+    path: &Path,
+    folder: &Path,
+    files: &HashMap<PathBuf, FileEntry>,
+    known: &HashMap<&Path, &HashResult>,
+    algo: HashAlgo,
+    read_ahead: Option<usize>,
+    scan_params: SnapshotScanParams,
+) -> Result<usize> {
+    let mut entries: Vec<SnapshotEntry> = files
+        .par_iter()
+        .map(|(rel, f)| {
+            let hashes = match known.get(rel.as_path()) {
+                Some(h) => (*h).clone(),
+                None => compute_hashes(&f.path, algo, read_ahead).with_context(|| {
+                    format!("--emit-manifest: cannot hash {}", f.path.display())
+                })?,
+            };
+            Ok(SnapshotEntry {
+                rel_path: rel.clone(),
+                size: f.size,
+                modified: f.modified,
+                hashes,
+                symlink_target: f.symlink_target.clone(),
+                unstable_read: false,
+            })
+        })
+        .collect::<Result<_>>()?;
+    entries.sort_by(|a, b| a.rel_path.cmp(&b.rel_path));
+    let hashed = files
+        .keys()
+        .filter(|rel| !known.contains_key(rel.as_path()))
+        .count();
+
+    let snapshot = Snapshot {
+        created_at: chrono::Local::now().to_rfc3339(),
+        root_path: folder.to_string_lossy().to_string(),
+        files: entries,
+        algo,
+        scan_params: Some(scan_params),
+        root: root_attributes_or_report(folder),
+    };
+    let json = serde_json::to_string_pretty(&snapshot)?;
+    std::fs::write(path, json)
+        .with_context(|| format!("--emit-manifest: cannot write {}", path.display()))?;
+    Ok(hashed)
+}
+
+/// The root attributes a new snapshot records; one that cannot read them is
+/// still written, without them, after saying so.
+fn root_attributes_or_report(folder: &Path) -> Option<RootAttrs> {
+    let mut errors = Vec::new();
+    let attrs = read_root_attributes(folder, &mut errors);
+    for e in &errors {
+        eprintln!("[{}] {}: {}", "ERROR".red(), e.path.display(), e.error);
+    }
+    attrs
+}
+
+/// Where `--baseline` keeps the snapshot of `folder` (an absolute path).
+///
+/// The file name combines the folder name with a digest of its full path, so
+/// every folder gets its own baseline. Without `dir` the per-user data
+/// directory is used: `$XDG_DATA_HOME`, `~/.local/share` or `%LOCALAPPDATA%`.
+pub fn baseline_path(folder: &Path, dir: Option<&Path>) -> Result<PathBuf> {
+    let dir = match dir {
+        Some(d) => d.to_path_buf(),
+        None => std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))
+            .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+            .context("No data directory found for --baseline; pass --baseline-dir")?
+            .join("cmpf")
+            .join("baselines"),
+    };
+    let digest = blake3::hash(folder.as_os_str().as_encoded_bytes()).to_hex();
+    let name = folder
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "root".to_string());
+    Ok(dir.join(format!("{}-{}.json", name, &digest[..16])))
 }
 
+#[derive(Default)]
 pub struct SnapshotConfig {
     pub folder: PathBuf,
     pub output: Option<PathBuf>,
@@ -61,9 +220,24 @@ pub struct SnapshotConfig {
     pub ignore: Option<Vec<String>>,
     pub symlinks: SymlinkMode,
     pub threads: Option<usize>,
+    /// Buffers the reader fills ahead of the hasher (`--read-ahead`); when set,
+    /// every file past the small-file path is streamed.
+    pub read_ahead: Option<usize>,
+    /// Only snapshot files owned by this uid.
+    pub owner_filter: Option<u32>,
+    /// Do not descend into other mounted filesystems.
+    pub one_file_system: bool,
+    /// Print each path the walk leaves out and the rule that excludes it.
+    pub explain_skips: bool,
+    /// Hash every file twice and flag those whose reads disagree.
+    pub double_read: bool,
+    /// Evict each file from the page cache before its second read.
+    pub drop_cache: bool,
 }
 
-pub fn create_snapshot(config: SnapshotConfig) -> Result<()> {
+/// Snapshot `config.folder`. With `double_read`, files whose two reads hash
+/// differently are still recorded but flagged, and the result is `Error`.
+pub fn create_snapshot(config: SnapshotConfig) -> Result<ExitStatus> {
     // Fix #5: silently ignore if global pool is already initialised
     if let Some(num_threads) = config.threads {
         let _ = rayon::ThreadPoolBuilder::new()
@@ -75,6 +249,23 @@ pub fn create_snapshot(config: SnapshotConfig) -> Result<()> {
         println!("{}", "Creating Snapshot...".bright_cyan());
     }
 
+    if config.explain_skips {
+        print_skips(
+            &explain_skips(
+                &config.folder,
+                config.depth,
+                config.no_recursive,
+                config.hidden,
+                &config.types,
+                &config.ignore,
+                config.symlinks,
+                config.owner_filter,
+                config.one_file_system,
+            )?,
+            "folder",
+        );
+    }
+
     let (files, errors) = collect_files(
         &config.folder,
         config.depth,
@@ -83,18 +274,26 @@ pub fn create_snapshot(config: SnapshotConfig) -> Result<()> {
         &config.types,
         &config.ignore,
         config.symlinks,
+        config.owner_filter,
+        config.one_file_system,
     )?;
 
     for e in &errors {
         eprintln!("[{}] {}", "ERROR".red(), e.error);
     }
+    if config.drop_cache && !cfg!(target_os = "linux") {
+        eprintln!(
+            "{} --drop-cache is only supported on Linux; second reads may come from the cache",
+            "Note:".yellow()
+        );
+    }
 
     let pb = if io::stderr().is_terminal() {
         let pb = ProgressBar::new(files.len() as u64);
         pb.set_style(ProgressStyle::default_bar().template(
             "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} ({eta})",
         )?);
-        Some(pb)
+        Some(activity::attach(pb))
     } else {
         None
     };
@@ -107,7 +306,21 @@ pub fn create_snapshot(config: SnapshotConfig) -> Result<()> {
             }
             // Fix #10: surface hash errors instead of silently storing None hashes.
             // We propagate the error so the snapshot is not saved with corrupt data.
-            let h = compute_hashes(&f.path, config.algo)?;
+            let h = compute_hashes(&f.path, config.algo, config.read_ahead)?;
+            let unstable_read = config.double_read && {
+                if config.drop_cache {
+                    // Best effort: without it the second read may only test RAM.
+                    let _ = drop_cached_pages(&f.path);
+                }
+                !compute_hashes(&f.path, config.algo, config.read_ahead)?.matches(&h, config.algo)
+            };
+            if unstable_read {
+                eprintln!(
+                    "[{}] {} (two reads hashed differently)",
+                    "UNSTABLE".red().on_white(),
+                    f.path.display()
+                );
+            }
             let rel = f
                 .path
                 .strip_prefix(&config.folder)
@@ -119,6 +332,7 @@ pub fn create_snapshot(config: SnapshotConfig) -> Result<()> {
                 modified: f.modified,
                 hashes: h,
                 symlink_target: f.symlink_target.clone(),
+                unstable_read,
             })
         })
         .collect::<Result<Vec<_>>>()?;
@@ -135,14 +349,19 @@ pub fn create_snapshot(config: SnapshotConfig) -> Result<()> {
         types: config.types.clone(),
         ignore: config.ignore.clone(),
         symlinks: config.symlinks,
+        owner_filter: config.owner_filter,
+        one_file_system: config.one_file_system,
     };
 
+    let unstable = entries.iter().filter(|e| e.unstable_read).count();
+
     let snapshot = Snapshot {
         created_at: chrono::Local::now().to_rfc3339(),
         root_path: config.folder.to_string_lossy().to_string(),
         files: entries,
         algo: config.algo,
         scan_params: Some(scan_params),
+        root: root_attributes_or_report(&config.folder),
     };
 
     let json = serde_json::to_string_pretty(&snapshot)?;
@@ -155,15 +374,93 @@ pub fn create_snapshot(config: SnapshotConfig) -> Result<()> {
         println!("{}", json);
     }
 
-    Ok(())
+    if unstable > 0 {
+        eprintln!(
+            "{} {} file(s) read differently twice; check the disk and memory before trusting this snapshot",
+            "Warning:".red().bold(),
+            unstable
+        );
+        return Ok(ExitStatus::Error);
+    }
+    Ok(ExitStatus::Success)
+}
+
+/// `--map-root OLD=NEW` translations for absolute paths recorded in a snapshot
+/// taken while the volume was mounted elsewhere. The longest matching OLD wins,
+/// so nested volumes can be remapped independently of their parent.
+#[derive(Debug, Default)]
+pub struct RootMap(Vec<(PathBuf, PathBuf)>);
+
+impl RootMap {
+    pub fn new(mut mappings: Vec<(PathBuf, PathBuf)>) -> Self {
+        mappings.sort_by_key(|(old, _)| std::cmp::Reverse(old.components().count()));
+        Self(mappings)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// `path` moved to its new location, or `None` if no mapping covers it.
+    pub fn translate(&self, path: &Path) -> Option<PathBuf> {
+        self.0.iter().find_map(|(old, new)| {
+            path.strip_prefix(old).ok().map(|rest| {
+                if rest.as_os_str().is_empty() {
+                    new.clone()
+                } else {
+                    new.join(rest)
+                }
+            })
+        })
+    }
+
+    fn translate_str(&self, path: &mut String) {
+        if let Some(moved) = self.translate(Path::new(path.as_str())) {
+            *path = moved.to_string_lossy().into_owned();
+        }
+    }
+
+    /// Rewrite the absolute paths a snapshot records: absolute `--ignore`
+    /// patterns and absolute symlink targets. Relative ones are left alone.
+    fn apply(&self, snapshot: &mut Snapshot) {
+        if let Some(patterns) = snapshot
+            .scan_params
+            .as_mut()
+            .and_then(|p| p.ignore.as_mut())
+        {
+            patterns.iter_mut().for_each(|p| self.translate_str(p));
+        }
+        for entry in &mut snapshot.files {
+            if let Some(target) = entry.symlink_target.as_mut() {
+                self.translate_str(target);
+            }
+        }
+    }
 }
 
+#[derive(Default)]
 pub struct VerifyConfig {
     pub folder: PathBuf,
     pub snapshot_path: PathBuf,
     pub threads: Option<usize>,
+    /// Buffers the reader fills ahead of the hasher (`--read-ahead`); when set,
+    /// every file past the small-file path is streamed.
+    pub read_ahead: Option<usize>,
     pub output_format: OutputFormat,
     pub verbose: bool,
+    pub assert_readonly: bool,
+    pub status_line: bool,
+    /// Per-class severities from `--config`; the defaults when absent.
+    pub severities: Option<Severities>,
+    /// `--map-root` translations for a snapshot taken on another mount point.
+    pub map_root: RootMap,
+    /// Exit with a dedicated code when paths could not be read for lack of permission.
+    pub permission_exit: bool,
+    /// `--changes-only`: print what was added, removed or modified instead of
+    /// the full report.
+    pub changes_only: bool,
+    /// `--no-root-check`: leave the root folder's own metadata unverified.
+    pub no_root_check: bool,
 }
 
 pub fn verify_snapshot(config: VerifyConfig) -> Result<ExitStatus> {
@@ -174,34 +471,31 @@ pub fn verify_snapshot(config: VerifyConfig) -> Result<ExitStatus> {
             .build_global();
     }
 
+    if config.changes_only && config.output_format != OutputFormat::Txt {
+        anyhow::bail!(
+            "--changes-only prints a text change log; it cannot be combined with another output format"
+        );
+    }
+
     let start_time = Instant::now();
-    let snapshot_file = File::open(&config.snapshot_path)?;
-    let snapshot: Snapshot = serde_json::from_reader(snapshot_file)?;
+    let mut snapshot = load_snapshot(&config.snapshot_path)?;
 
     println!(
         "Verifying against snapshot created at: {}",
         snapshot.created_at.cyan()
     );
 
-    // Fix #6: reproduce the exact scan parameters used when the snapshot was created.
-    // For old snapshots without scan_params, fall back to sensible defaults.
-    let sp = snapshot.scan_params.as_ref();
-    let depth = sp.and_then(|p| p.depth);
-    let no_recursive = sp.map(|p| p.no_recursive).unwrap_or(false);
-    let hidden = sp.map(|p| p.hidden).unwrap_or(false);
-    let types = sp.and_then(|p| p.types.clone());
-    let ignore = sp.and_then(|p| p.ignore.clone());
-    let symlink_mode = sp.map(|p| p.symlinks).unwrap_or(SymlinkMode::Ignore);
-
-    let (current_files, current_errors) = collect_files(
-        &config.folder,
-        depth,
-        no_recursive,
-        hidden,
-        &types,
-        &ignore,
-        symlink_mode,
-    )?;
+    if !config.map_root.is_empty() {
+        check_mapped_root(&config, &snapshot.root_path);
+        config.map_root.apply(&mut snapshot);
+    }
+
+    let (current_files, mut current_errors) = snapshot.collect(&config.folder)?;
+    let root = match snapshot.root.take().filter(|_| !config.no_root_check) {
+        Some(recorded) => read_root_attributes(&config.folder, &mut current_errors)
+            .map(|current| RootCheck::new(recorded, current)),
+        None => None,
+    };
 
     let current_map: HashMap<PathBuf, FileEntry> = current_files
         .into_iter()
@@ -228,7 +522,7 @@ pub fn verify_snapshot(config: VerifyConfig) -> Result<ExitStatus> {
             ProgressStyle::default_bar()
                 .template("{spinner:.green} Verifying {bar:40.cyan/blue} {pos}/{len}")?,
         );
-        Some(pb)
+        Some(activity::attach(pb))
     } else {
         None
     };
@@ -243,49 +537,49 @@ pub fn verify_snapshot(config: VerifyConfig) -> Result<ExitStatus> {
             }
             let snap_entry = snapshot_map.get(rel_path).unwrap();
 
-            if let Some(curr_entry) = current_map.get(rel_path) {
-                // Fix #10: propagate hashing errors instead of silently treating
-                // them as DIFF (the old unwrap_or behaviour).
-                let h = compute_hashes(&curr_entry.path, snapshot.algo)
-                    .context("Failed to hash file during verification")?;
-
-                let status = match snapshot.algo {
-                    HashAlgo::Sha256 => {
-                        if h.sha256 == snap_entry.hashes.sha256 {
-                            Status::Match
-                        } else {
-                            Status::Diff
-                        }
-                    }
-                    HashAlgo::Blake3 => {
-                        if h.blake3 == snap_entry.hashes.blake3 {
-                            Status::Match
-                        } else {
-                            Status::Diff
-                        }
-                    }
-                    HashAlgo::Both => {
-                        if h.sha256 == snap_entry.hashes.sha256
-                            && h.blake3 == snap_entry.hashes.blake3
-                        {
-                            Status::Match
-                        } else {
-                            Status::Diff
-                        }
+            if let Some(curr_entry) = current_map.get(rel_path)
+                && (snap_entry.symlink_target.is_some() || curr_entry.symlink_target.is_some())
+            {
+                // Links are compared by target, so a remapped absolute target
+                // matches even though the old location no longer exists.
+                let status = if snap_entry.symlink_target == curr_entry.symlink_target {
+                    Status::Match
+                } else {
+                    Status::Diff
+                };
+                Ok(ComparisonResult {
+                    symlink1: snap_entry.symlink_target.clone(),
+                    symlink2: curr_entry.symlink_target.clone(),
+                    ..ComparisonResult::new(rel_path.clone(), status)
+                })
+            } else if let Some(curr_entry) = current_map.get(rel_path) {
+                // Fix #10: hashing errors are reported as ERROR, never silently
+                // treated as DIFF (the old unwrap_or behaviour).
+                let h = match compute_hashes(&curr_entry.path, snapshot.algo, config.read_ahead) {
+                    Ok(h) => h,
+                    Err(e) => {
+                        return Ok(ComparisonResult {
+                            size1: Some(snap_entry.size),
+                            size2: Some(curr_entry.size),
+                            ..ComparisonResult::failed(rel_path.clone(), "folder", &e)
+                        });
                     }
                 };
 
+                let status = if h.matches(&snap_entry.hashes, snapshot.algo) {
+                    Status::Match
+                } else {
+                    Status::Diff
+                };
+
                 Ok(ComparisonResult {
-                    file: rel_path.clone(),
-                    status,
                     hash1: Some(snap_entry.hashes.clone()),
                     hash2: Some(h),
                     size1: Some(snap_entry.size),
                     size2: Some(curr_entry.size),
-                    modified1: None,
-                    modified2: None,
                     symlink1: snap_entry.symlink_target.clone(),
                     symlink2: curr_entry.symlink_target.clone(),
+                    ..ComparisonResult::new(rel_path.clone(), status)
                 })
             } else {
                 // Fix #12: use constructor helper
@@ -322,17 +616,32 @@ pub fn verify_snapshot(config: VerifyConfig) -> Result<ExitStatus> {
     results.extend(extras);
 
     results.sort_by(|a, b| a.file.cmp(&b.file));
+    let severities = config.severities.unwrap_or_default();
+    if let Some(configured) = config.severities {
+        for r in &mut results {
+            r.severity = configured.of(r.status);
+        }
+    }
 
     let mut matches = 0;
     let mut diffs = 0;
     let mut missing = 0;
     let mut extra = 0;
+    let mut hash_errors = 0;
+    let mut permission_denied = current_errors
+        .iter()
+        .filter(|e| e.permission_denied())
+        .count();
     for r in &results {
         match r.status {
             Status::Match => matches += 1,
             Status::Diff => diffs += 1,
             Status::Missing => missing += 1,
             Status::Extra => extra += 1,
+            Status::Error => {
+                hash_errors += 1;
+                permission_denied += usize::from(r.permission_denied());
+            }
             _ => (),
         }
     }
@@ -342,32 +651,82 @@ pub fn verify_snapshot(config: VerifyConfig) -> Result<ExitStatus> {
         algo: snapshot.algo,
         threads: config.threads,
         verbose: config.verbose,
+        assert_readonly: config.assert_readonly,
+        ..Default::default()
     };
 
-    let summary_data = SummaryData {
+    let mut summary_data = SummaryData {
         total: results.len(),
         matches,
         diffs,
         missing,
         extra,
         errors: current_errors.len(),
+        hash_errors,
+        permission_denied,
+        root,
         elapsed: start_time.elapsed(),
+        ..Default::default()
     };
 
+    summary_data.warnings = severities.count(&summary_data, Severity::Warning);
+
     let report = match config.output_format {
+        OutputFormat::Txt if config.changes_only => generate_change_log(&results, &current_errors),
         OutputFormat::Txt => {
             generate_text_report(&results, &[], &current_errors, &summary_data, &report_conf)?
         }
-        OutputFormat::Json => generate_json_report(&results, &[], &current_errors, &summary_data)?,
+        OutputFormat::Json => {
+            generate_json_report(&results, &[], &current_errors, &summary_data, &report_conf)?
+        }
+        OutputFormat::CodeQuality => generate_code_quality_report(
+            &results,
+            &[],
+            &current_errors,
+            (&config.folder, &config.folder),
+        )?,
+        OutputFormat::Html => {
+            generate_html_report(&results, &[], &current_errors, &summary_data, &report_conf)
+        }
+        OutputFormat::Csv => generate_csv_report(&results, &[], &current_errors, ','),
+        OutputFormat::Tsv => generate_csv_report(&results, &[], &current_errors, '\t'),
+        OutputFormat::Ndjson => {
+            generate_ndjson_report(&results, &[], &current_errors, &summary_data)?
+        }
+        OutputFormat::Porcelain => generate_porcelain_report(&results, &[], &current_errors),
     };
 
-    println!("{}", report);
+    print!("{}", report);
+    if !report.ends_with('\n') {
+        println!();
+    }
 
-    if !current_errors.is_empty() {
-        Ok(ExitStatus::Error)
-    } else if diffs > 0 || missing > 0 || extra > 0 {
-        Ok(ExitStatus::Diff)
-    } else {
-        Ok(ExitStatus::Success)
+    let status = summary_data.exit_status(&severities, config.permission_exit);
+    if config.status_line {
+        print_status_line(&status, &summary_data.status_fields());
+    }
+    Ok(status)
+}
+
+/// Tell where the snapshot root lands under `--map-root`, and warn when that is
+/// not the folder being verified, which usually means a mistyped mapping.
+fn check_mapped_root(config: &VerifyConfig, root_path: &str) {
+    let Some(mapped) = config.map_root.translate(Path::new(root_path)) else {
+        eprintln!(
+            "{} no --map-root mapping covers the snapshot root {}",
+            "Warning:".yellow(),
+            root_path
+        );
+        return;
+    };
+    println!("Snapshot root {} mapped to {}", root_path, mapped.display());
+    let canonical = |p: &Path| std::fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+    if canonical(&mapped) != canonical(&config.folder) {
+        eprintln!(
+            "{} the mapped root {} is not the verified folder {}",
+            "Warning:".yellow(),
+            mapped.display(),
+            config.folder.display()
+        );
     }
 }
@@ -0,0 +1,304 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, SystemTime};
+
+use crate::archive::ListingFilter;
+use crate::compare::CompareConfig;
+use crate::models::{ErrorEntry, FileEntry, HashAlgo, HashResult, Mode, SymlinkMode};
+
+/// A folder on another machine, given as `user@host:/path`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SshFolder {
+    /// `user@host`, or anything else `ssh` accepts as a destination.
+    pub host: String,
+    pub path: String,
+}
+
+/// `path` as a folder read over SSH. The `@` tells it apart from an rclone
+/// remote (`remote:path`); existing local paths take precedence.
+pub fn ssh_folder(path: &Path) -> Option<SshFolder> {
+    let (host, dir) = path.to_str()?.split_once(':')?;
+    let (user, name) = host.split_once('@')?;
+    let valid = !user.is_empty()
+        && !name.is_empty()
+        && !dir.is_empty()
+        && !host.contains(['/', '\\', ' '])
+        && !host.starts_with('-')
+        && !path.exists();
+    valid.then(|| SshFolder {
+        host: host.to_string(),
+        path: dir.to_string(),
+    })
+}
+
+pub fn is_ssh(path: &Path) -> bool {
+    ssh_folder(path).is_some()
+}
+
+/// `s` quoted for a POSIX shell.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Run `script` with the remote user's shell, feeding it `input`.
+fn run(host: &str, script: &str, input: &[u8]) -> Result<Output> {
+    let mut child = Command::new("ssh")
+        .args(["-o", "BatchMode=yes", "--", host, script])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run ssh; is it installed and on PATH?")?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // Written from another thread, so a long output cannot stall the input.
+    std::thread::scope(|s| {
+        s.spawn(move || stdin.write_all(input));
+        child.wait_with_output()
+    })
+    .with_context(|| format!("Failed to run ssh {}", host))
+}
+
+/// The tools that print the digests `algo` needs, and where each goes.
+fn hash_tools(algo: HashAlgo) -> Result<&'static [(&'static str, HashAlgo)]> {
+    Ok(match algo {
+        HashAlgo::Sha256 => &[("sha256sum", HashAlgo::Sha256)],
+        HashAlgo::Blake3 => &[("b3sum", HashAlgo::Blake3)],
+        HashAlgo::Both => &[("sha256sum", HashAlgo::Sha256), ("b3sum", HashAlgo::Blake3)],
+        HashAlgo::Sha512 => &[("sha512sum", HashAlgo::Sha512)],
+        HashAlgo::Blake2b => &[("b2sum", HashAlgo::Blake2b)],
+        HashAlgo::Md5 => &[("md5sum", HashAlgo::Md5)],
+        HashAlgo::Sha1 => &[("sha1sum", HashAlgo::Sha1)],
+        HashAlgo::Xxh3 => {
+            anyhow::bail!("folders read over SSH cannot be hashed with xxh3; use another --algo")
+        }
+    })
+}
+
+fn set_digest(h: &mut HashResult, algo: HashAlgo, digest: String) {
+    let field = match algo {
+        HashAlgo::Sha256 => &mut h.sha256,
+        HashAlgo::Blake3 => &mut h.blake3,
+        HashAlgo::Sha512 => &mut h.sha512,
+        HashAlgo::Blake2b => &mut h.blake2b,
+        HashAlgo::Md5 => &mut h.md5,
+        HashAlgo::Sha1 => &mut h.sha1,
+        HashAlgo::Xxh3 => &mut h.xxh3,
+        HashAlgo::Both => return,
+    };
+    *field = Some(digest);
+}
+
+/// The `find` command listing the folder: for each member its type (`f` or
+/// `l`), size, mtime, link target and relative path, NUL-separated.
+fn find_command(config: &CompareConfig) -> String {
+    let mut cmd = String::from("LC_ALL=C find");
+    if config.symlinks == SymlinkMode::Follow {
+        cmd.push_str(" -L");
+    }
+    cmd.push_str(" . -mindepth 1");
+    if let Some(depth) = if config.no_recursive {
+        Some(1)
+    } else {
+        config.depth
+    } {
+        cmd.push_str(&format!(" -maxdepth {}", depth));
+    }
+    if config.one_file_system {
+        cmd.push_str(" -xdev");
+    }
+    if let Some(uid) = config.owner_filter {
+        cmd.push_str(&format!(" -uid {}", uid));
+    }
+    if config.symlinks == SymlinkMode::Compare {
+        cmd.push_str(r" \( -type f -o -type l \)");
+    } else {
+        cmd.push_str(" -type f");
+    }
+    cmd.push_str(r" -printf '%y\0%s\0%T@\0%l\0%P\0'");
+    cmd
+}
+
+/// `find -printf %T@` output, seconds with a fraction.
+fn parse_mtime(field: &str) -> Option<SystemTime> {
+    let (secs, frac) = field.split_once('.').unwrap_or((field, ""));
+    let nanos: String = frac.chars().chain(std::iter::repeat('0')).take(9).collect();
+    let offset = Duration::new(
+        secs.trim_start_matches('-').parse().ok()?,
+        nanos.parse().ok()?,
+    );
+    if secs.starts_with('-') {
+        SystemTime::UNIX_EPOCH.checked_sub(offset)
+    } else {
+        SystemTime::UNIX_EPOCH.checked_add(offset)
+    }
+}
+
+/// A member listed by `find`, its name as raw bytes for passing back to the
+/// remote shell.
+struct Listed {
+    name: Vec<u8>,
+    entry: FileEntry,
+}
+
+/// Parse the output of [`find_command`] into entries under `root`.
+fn parse_listing(out: &[u8], root: &Path, filter: &ListingFilter) -> Vec<Listed> {
+    let fields: Vec<&[u8]> = out.split(|&b| b == 0).collect();
+    fields
+        .chunks_exact(5)
+        .filter_map(|f| {
+            let text = |i: usize| String::from_utf8_lossy(f[i]);
+            let rel_path = PathBuf::from(text(4).as_ref());
+            if filter.excludes(&rel_path) {
+                return None;
+            }
+            Some(Listed {
+                name: f[4].to_vec(),
+                entry: FileEntry {
+                    path: root.join(&rel_path),
+                    size: text(1).parse().unwrap_or(0),
+                    modified: parse_mtime(&text(2)),
+                    symlink_target: (f[0] == b"l").then(|| text(3).into_owned()),
+                    ..FileEntry::default()
+                },
+            })
+        })
+        .collect()
+}
+
+/// A line of `sha256sum` and friends: the digest and the file name. Names
+/// holding a backslash or newline are escaped and the line starts with `\`.
+pub fn parse_sum_line(line: &str) -> Option<(String, String)> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (digest, name) = line.split_once(' ')?;
+    let name = name.strip_prefix([' ', '*'])?;
+    if !escaped {
+        return Some((digest.to_lowercase(), name.to_string()));
+    }
+    let mut unescaped = String::new();
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    Some((digest.to_lowercase(), unescaped))
+}
+
+/// Read a folder on another machine as one side of a comparison: `find`
+/// lists it and the `*sum` tools hash it there, so only names and digests
+/// cross the network. The entries carry their digests, like snapshot entries.
+pub fn read_ssh(
+    folder: &Path,
+    config: &CompareConfig,
+) -> Result<(Vec<FileEntry>, Vec<ErrorEntry>)> {
+    let remote = ssh_folder(folder).expect("read_ssh called on an SSH folder");
+    let filter = ListingFilter::new(config)?;
+    let cd = format!("cd -- {} &&", quote(&remote.path));
+
+    let listing = run(
+        &remote.host,
+        &format!("{} {}", cd, find_command(config)),
+        b"",
+    )?;
+    let stderr = String::from_utf8_lossy(&listing.stderr);
+    if !listing.status.success() && listing.stdout.is_empty() {
+        anyhow::bail!(
+            "Listing {} failed ({}): {}",
+            folder.display(),
+            listing.status,
+            stderr.trim()
+        );
+    }
+    let mut errors: Vec<ErrorEntry> = stderr
+        .lines()
+        .filter(|l| l.starts_with("find: "))
+        .map(|l| {
+            let path = l.split('\'').nth(1).unwrap_or(".");
+            ErrorEntry::new(folder.join(path.trim_start_matches("./")), l.to_string())
+        })
+        .collect();
+    let mut listed = parse_listing(&listing.stdout, folder, &filter);
+    if config.mode == Mode::Metadata {
+        return Ok((listed.into_iter().map(|l| l.entry).collect(), errors));
+    }
+
+    // Every regular file is hashed by each tool in one remote run.
+    let mut input = Vec::new();
+    for l in listed.iter().filter(|l| l.entry.symlink_target.is_none()) {
+        input.extend(&l.name);
+        input.push(0);
+    }
+    let tools = hash_tools(config.algo)?;
+    // Digests by file name, with the number of tools that printed one.
+    let mut digests: HashMap<String, (HashResult, usize)> = HashMap::new();
+    for (tool, part) in tools {
+        let output = run(
+            &remote.host,
+            &format!("{} LC_ALL=C xargs -0 -r {} --", cd, tool),
+            &input,
+        )?;
+        // xargs exits 123 when some files could not be read; those are
+        // reported below as missing their digest.
+        if output.status.code() == Some(127) {
+            anyhow::bail!(
+                "{} is not installed on {}; install it there or pick another --algo",
+                tool,
+                remote.host
+            );
+        }
+        if !matches!(output.status.code(), Some(0 | 123)) {
+            anyhow::bail!(
+                "Hashing {} with {} failed ({}): {}",
+                folder.display(),
+                tool,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        for (digest, name) in String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(parse_sum_line)
+        {
+            let (h, found) = digests.entry(name).or_default();
+            set_digest(h, *part, digest);
+            *found += 1;
+        }
+    }
+
+    let files = listed
+        .drain(..)
+        .filter_map(|mut l| {
+            if l.entry.symlink_target.is_some() {
+                return Some(l.entry);
+            }
+            let name = String::from_utf8_lossy(&l.name);
+            match digests.remove(name.as_ref()) {
+                Some((h, found)) if found == tools.len() => {
+                    l.entry.hashes = Some(h);
+                    Some(l.entry)
+                }
+                _ => {
+                    errors.push(ErrorEntry::new(
+                        l.entry.path,
+                        format!("could not be hashed on {}", remote.host),
+                    ));
+                    None
+                }
+            }
+        })
+        .collect();
+    Ok((files, errors))
+}
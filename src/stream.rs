@@ -0,0 +1,353 @@
+use anyhow::Result;
+use rayon::prelude::*;
+use std::cmp::Ordering as PathOrder;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::time::Instant;
+
+use crate::compare::{
+    CompareConfig, ExitStatus, Rules, compare_files_core, is_snapshot_file, print_live_result,
+    print_one_sided, print_walk_errors,
+};
+use crate::models::{ComparisonResult, FileEntry, OutputFormat, Severity, Status, SymlinkMode};
+use crate::rclone::is_remote;
+use crate::report::{SummaryData, generate_summary_text, ndjson_summary_line, print_status_line};
+use crate::resources::ResourceUsage;
+use crate::s3::is_s3;
+use crate::ssh::is_ssh;
+use crate::utils::{Walked, stream_files};
+
+/// Entries each walker may run ahead of the merge, and pairs the merge may run
+/// ahead of the workers. Together these bound the memory of a streamed run.
+const STREAM_BOUND: usize = 4096;
+
+/// A path seen by the merge of the two sorted walks.
+enum Pair {
+    Both(PathBuf, Box<(FileEntry, FileEntry)>),
+    Missing(PathBuf, u64),
+    Extra(PathBuf, u64),
+    OutOfTree(PathBuf),
+    /// With `--symlinks compare`: link targets on each side of a path that is
+    /// a link on one side and a directory on the other.
+    TypeMismatch(PathBuf, Option<String>, Option<String>),
+}
+
+#[derive(Default)]
+struct Tally {
+    total: AtomicUsize,
+    matches: AtomicUsize,
+    quick_matches: AtomicUsize,
+    diffs: AtomicUsize,
+    unstable: AtomicUsize,
+    allowed_diffs: AtomicUsize,
+    missing: AtomicUsize,
+    extra: AtomicUsize,
+    errors: AtomicUsize,
+    hash_errors: AtomicUsize,
+    permission_denied: AtomicUsize,
+    out_of_tree: AtomicUsize,
+    transfer_bytes: AtomicU64,
+}
+
+impl Tally {
+    fn add(counter: &AtomicUsize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn summary(&self, start_time: Instant) -> SummaryData {
+        let get = |c: &AtomicUsize| c.load(Ordering::Relaxed);
+        SummaryData {
+            total: get(&self.total),
+            matches: get(&self.matches),
+            quick_matches: get(&self.quick_matches),
+            diffs: get(&self.diffs),
+            unstable: get(&self.unstable),
+            allowed_diffs: get(&self.allowed_diffs),
+            missing: get(&self.missing),
+            extra: get(&self.extra),
+            errors: get(&self.errors),
+            hash_errors: get(&self.hash_errors),
+            permission_denied: get(&self.permission_denied),
+            out_of_tree: get(&self.out_of_tree),
+            transfer_bytes: self.transfer_bytes.load(Ordering::Relaxed),
+            elapsed: start_time.elapsed(),
+            ..Default::default()
+        }
+    }
+}
+
+/// `--stream`: walk both folders in path order, merge the walks and compare
+/// each pair as soon as it is known. Only counters are kept, so memory stays
+/// flat however many files the trees hold. Results are printed as workers
+/// finish them, so their order is not sorted.
+pub(crate) fn run_stream(
+    config: &CompareConfig,
+    rules: &Rules,
+    start_time: Instant,
+) -> Result<ExitStatus> {
+    check_streamable(config)?;
+
+    let walk = |root: &Path| {
+        stream_files(
+            root,
+            config.depth,
+            config.no_recursive,
+            config.hidden,
+            &config.types,
+            &config.ignore,
+            config.symlinks,
+            config.owner_filter,
+            config.one_file_system,
+            STREAM_BOUND,
+        )
+    };
+    let walk1 = walk(&config.folder1)?;
+    let walk2 = walk(&config.folder2)?;
+    let tally = Tally::default();
+    let (pair_tx, pair_rx) = mpsc::sync_channel(STREAM_BOUND);
+
+    std::thread::scope(|s| {
+        s.spawn(|| merge(walk1, walk2, config, &tally, pair_tx));
+        pair_rx
+            .into_iter()
+            .par_bridge()
+            .try_for_each(|pair| settle(pair, config, rules, &tally))
+    })?;
+
+    let report_conf = config.report_config(rules);
+    let severities = config.severities.unwrap_or_default();
+    let mut summary_data = tally.summary(start_time);
+    summary_data.resource_usage = config.resource_usage.then(ResourceUsage::measure).flatten();
+    summary_data.previous = rules.previous.clone();
+    summary_data.warnings = severities.count(&summary_data, Severity::Warning);
+
+    match config.output_format {
+        OutputFormat::Ndjson => print!("{}", ndjson_summary_line(&summary_data)?),
+        OutputFormat::Porcelain => (),
+        _ => {
+            println!();
+            for line in generate_summary_text(&summary_data, &report_conf) {
+                println!("{}", line);
+            }
+        }
+    }
+
+    let status = summary_data.exit_status(&severities, config.permission_exit);
+    if config.status_line {
+        print_status_line(&status, &summary_data.status_fields());
+    }
+    Ok(status)
+}
+
+/// Streaming keeps nothing to look back at, so options that need the whole
+/// tree (or a saved report) are refused rather than silently ignored.
+fn check_streamable(config: &CompareConfig) -> Result<()> {
+    let flag = config.stream_flag().unwrap_or("--stream");
+    for folder in [&config.folder1, &config.folder2] {
+        if folder == Path::new("-")
+            || is_snapshot_file(folder)
+            || is_remote(folder)
+            || is_ssh(folder)
+            || is_s3(folder)
+        {
+            anyhow::bail!(
+                "{} needs two folders, but {} is not one",
+                flag,
+                folder.display()
+            );
+        }
+    }
+    let conflicts: Vec<&str> = [
+        (
+            !matches!(
+                config.output_format,
+                OutputFormat::Txt | OutputFormat::Ndjson | OutputFormat::Porcelain
+            ),
+            "--output-format",
+        ),
+        (config.output_folder.is_some(), "--output-folder"),
+        (config.precheck, "--precheck"),
+        (config.verdict_cache.is_some(), "--verdict-cache"),
+        (config.hash_cache.is_some(), "--hash-cache"),
+        (config.relocated, "--relocated"),
+        (config.detect_renames, "--detect-renames"),
+        (config.auto_mtime_skew, "--auto-mtime-skew"),
+        (config.breakdown, "--breakdown"),
+        (config.emit_list_dir.is_some(), "--emit-list-dir"),
+        (config.emit_manifest.is_some(), "--emit-manifest"),
+        (config.diff_cmd.is_some(), "--diff-cmd"),
+        (config.baseline_report.is_some(), "--baseline-report"),
+    ]
+    .into_iter()
+    .filter_map(|(set, flag)| set.then_some(flag))
+    .collect();
+    if !conflicts.is_empty() {
+        anyhow::bail!(
+            "{} prints results as it goes and cannot be combined with {}",
+            flag,
+            conflicts.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Next file of a walk with its relative path; walk errors are reported and
+/// counted on the way.
+fn next_file(
+    walk: &Receiver<Walked>,
+    root: &Path,
+    side: &str,
+    config: &CompareConfig,
+    tally: &Tally,
+    tx: &SyncSender<Pair>,
+) -> Option<(PathBuf, FileEntry)> {
+    for item in walk {
+        match item {
+            Walked::Error(e) => {
+                // Only stdout can fail here; the end of the run reports that.
+                let _ = print_walk_errors(std::slice::from_ref(&e), side, config);
+                Tally::add(&tally.errors);
+                if e.permission_denied() {
+                    Tally::add(&tally.permission_denied);
+                }
+            }
+            Walked::File(f) => match f.path.strip_prefix(root) {
+                Ok(rel) => return Some((rel.to_path_buf(), *f)),
+                Err(_) => {
+                    let _ = tx.send(Pair::OutOfTree(f.path));
+                }
+            },
+        }
+    }
+    None
+}
+
+/// Join the two walks on relative path. Both arrive in `Path` order, so the
+/// smaller head is the one the other side lacks.
+fn merge(
+    walk1: Receiver<Walked>,
+    walk2: Receiver<Walked>,
+    config: &CompareConfig,
+    tally: &Tally,
+    tx: SyncSender<Pair>,
+) {
+    let next1 = || next_file(&walk1, &config.folder1, "folder1", config, tally, &tx);
+    let next2 = || next_file(&walk2, &config.folder2, "folder2", config, tally, &tx);
+    // A link sorts right before the paths under it, so a link facing a real
+    // directory is followed by that directory's files on the other side.
+    let link_over = |link: &Path, e: &FileEntry, other: &Path| {
+        config.symlinks == SymlinkMode::Compare
+            && e.symlink_target.is_some()
+            && other.starts_with(link)
+    };
+    let (mut head1, mut head2) = (next1(), next2());
+    loop {
+        let pair = match (head1.take(), head2.take()) {
+            (None, None) => break,
+            (Some((rel, e1)), None) => {
+                head1 = next1();
+                Tally::add(&tally.missing);
+                tally.transfer_bytes.fetch_add(e1.size, Ordering::Relaxed);
+                Pair::Missing(rel, e1.size)
+            }
+            (None, Some((rel, e2))) => {
+                head2 = next2();
+                Pair::Extra(rel, e2.size)
+            }
+            (Some((rel1, e1)), Some((rel2, e2))) => match rel1.cmp(&rel2) {
+                PathOrder::Less if link_over(&rel1, &e1, &rel2) => {
+                    head1 = next1();
+                    head2 = next2();
+                    while head2
+                        .as_ref()
+                        .is_some_and(|(rel, _)| rel.starts_with(&rel1))
+                    {
+                        head2 = next2();
+                    }
+                    Pair::TypeMismatch(rel1, e1.symlink_target, None)
+                }
+                PathOrder::Greater if link_over(&rel2, &e2, &rel1) => {
+                    head1 = next1();
+                    head2 = next2();
+                    while head1
+                        .as_ref()
+                        .is_some_and(|(rel, _)| rel.starts_with(&rel2))
+                    {
+                        head1 = next1();
+                    }
+                    Pair::TypeMismatch(rel2, None, e2.symlink_target)
+                }
+                PathOrder::Less => {
+                    head1 = next1();
+                    head2 = Some((rel2, e2));
+                    Tally::add(&tally.missing);
+                    tally.transfer_bytes.fetch_add(e1.size, Ordering::Relaxed);
+                    Pair::Missing(rel1, e1.size)
+                }
+                PathOrder::Greater => {
+                    head1 = Some((rel1, e1));
+                    head2 = next2();
+                    Pair::Extra(rel2, e2.size)
+                }
+                PathOrder::Equal => {
+                    head1 = next1();
+                    head2 = next2();
+                    Pair::Both(rel1, Box::new((e1, e2)))
+                }
+            },
+        };
+        // Workers stopped on an error; it is reported from there.
+        if tx.send(pair).is_err() {
+            break;
+        }
+    }
+}
+
+fn settle(pair: Pair, config: &CompareConfig, rules: &Rules, tally: &Tally) -> Result<()> {
+    Tally::add(&tally.total);
+    let (status, path, size) = match pair {
+        Pair::Both(rel, entries) => {
+            let (e1, e2) = *entries;
+            let result = compare_files_core(rel, &e1, &e2, config, rules)?;
+            match result.status {
+                Status::Match => Tally::add(&tally.matches),
+                Status::QuickMatch => Tally::add(&tally.quick_matches),
+                Status::Diff => {
+                    Tally::add(&tally.diffs);
+                    tally.transfer_bytes.fetch_add(e1.size, Ordering::Relaxed);
+                }
+                Status::AllowedDiff => Tally::add(&tally.allowed_diffs),
+                Status::Unstable => Tally::add(&tally.unstable),
+                Status::Error => {
+                    Tally::add(&tally.hash_errors);
+                    if result.permission_denied() {
+                        Tally::add(&tally.permission_denied);
+                    }
+                }
+                _ => (),
+            }
+            return print_live_result(&result, config);
+        }
+        Pair::Missing(rel, size) => (Status::Missing, rel, Some(size)),
+        Pair::Extra(rel, size) => {
+            Tally::add(&tally.extra);
+            (Status::Extra, rel, Some(size))
+        }
+        Pair::OutOfTree(path) => {
+            Tally::add(&tally.out_of_tree);
+            (Status::OutOfTree, path, None)
+        }
+        Pair::TypeMismatch(rel, symlink1, symlink2) => {
+            Tally::add(&tally.diffs);
+            let result = ComparisonResult {
+                symlink1,
+                symlink2,
+                severity: config.severity_of(Status::TypeMismatch),
+                ..ComparisonResult::new(rel, Status::TypeMismatch)
+            };
+            return print_live_result(&result, config);
+        }
+    };
+    print_one_sided(status, &path, size, config)
+}
@@ -4,15 +4,26 @@ use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::{self, IsTerminal};
-use std::path::PathBuf;
-use std::time::Instant;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
+use crate::activity;
 use crate::compare::ExitStatus;
-use crate::models::{ComparisonResult, FileEntry, HashAlgo, Mode, Status, SymlinkMode};
-use crate::report::{ReportConfig, SummaryData, generate_summary_text, print_error_entry};
-use crate::utils::{collect_files, compute_hashes};
-
+use crate::models::{
+    ComparisonResult, ErrorEntry, ErrorKind, FileEntry, HashAlgo, Mode, Status, SymlinkMode,
+};
+use crate::report::{
+    ReportConfig, SyncSummary, TransferEstimate, generate_sync_summary_text, print_error_entries,
+    print_realtime_missing, print_skips, print_status_line,
+};
+use crate::snapshot::load_snapshot;
+use crate::utils::{
+    collect_files, compute_hashes, explain_skips, key_by_relative_path, split_command,
+};
+
+#[derive(Default)]
 pub struct SyncConfig {
     pub source: PathBuf,
     pub destination: PathBuf,
@@ -27,10 +38,61 @@ pub struct SyncConfig {
     pub types: Option<Vec<String>>,
     pub ignore: Option<Vec<String>>,
     pub threads: Option<usize>,
+    /// Buffers the reader fills ahead of the hasher (`--read-ahead`); when set,
+    /// every file past the small-file path is streamed.
+    pub read_ahead: Option<usize>,
+    /// Only files owned by this uid are synced or deleted (`--owner-filter`).
+    pub owner_filter: Option<u32>,
+    /// Do not descend into other mounted filesystems (`--one-file-system`).
+    pub one_file_system: bool,
+    /// Print each path the walks leave out and the rule that excludes it.
+    pub explain_skips: bool,
+    /// Treat `source` as a snapshot file and restore `destination` to its recorded state.
+    pub from_snapshot: bool,
+    /// Recreate source hard links as hard links instead of independent copies.
+    pub hard_links: bool,
+    /// Repair permissions/ownership/mtime of content-identical files without copying.
+    pub fix_metadata: bool,
+    /// Print one rsync-style change string per action instead of the labelled lines.
+    pub itemize: bool,
+    /// Commands run after each applied create/update/delete; `{src}` and
+    /// `{dst}` are replaced with the file's source and destination paths.
+    pub on_create: Option<String>,
+    pub on_update: Option<String>,
+    pub on_delete: Option<String>,
+    /// Print a one-line machine-readable outcome to stderr at the end.
+    pub status_line: bool,
+}
+
+impl SyncConfig {
+    /// Reject flag combinations clap would refuse on the command line, so the
+    /// same rules hold when `SyncConfig` is built programmatically.
+    pub fn validate(&self) -> Result<()> {
+        if self.delete_extraneous && self.no_delete {
+            anyhow::bail!("--delete-extraneous and --no-delete cannot be used together");
+        }
+        Ok(())
+    }
+
+    fn hook_for(&self, status: Status) -> Option<&str> {
+        match status {
+            Status::Create => self.on_create.as_deref(),
+            Status::Update => self.on_update.as_deref(),
+            Status::Delete => self.on_delete.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// DELETE actions are only planned when explicitly requested and never
+    /// under `--no-delete`, whatever else is set.
+    fn allows_delete(&self) -> bool {
+        self.delete_extraneous && !self.no_delete
+    }
 }
 
 pub fn run_sync(config: SyncConfig) -> Result<ExitStatus> {
     let start_time = Instant::now();
+    config.validate()?;
 
     // Fix #5: silently ignore if global pool is already initialised
     if let Some(num_threads) = config.threads {
@@ -59,6 +121,34 @@ pub fn run_sync(config: SyncConfig) -> Result<ExitStatus> {
         println!();
     }
 
+    if config.from_snapshot {
+        return run_snapshot_sync(&config, start_time);
+    }
+
+    if config.explain_skips {
+        for (folder, side) in [
+            (&config.source, "source"),
+            (&config.destination, "destination"),
+        ] {
+            if folder.is_dir() {
+                print_skips(
+                    &explain_skips(
+                        folder,
+                        config.depth,
+                        config.no_recursive,
+                        config.hidden,
+                        &config.types,
+                        &config.ignore,
+                        config.symlinks,
+                        config.owner_filter,
+                        config.one_file_system,
+                    )?,
+                    side,
+                );
+            }
+        }
+    }
+
     // Fix #1: collect both folders in parallel (was sequential in original)
     let (res_source, res_dest) = rayon::join(
         || {
@@ -70,6 +160,8 @@ pub fn run_sync(config: SyncConfig) -> Result<ExitStatus> {
                 &config.types,
                 &config.ignore,
                 config.symlinks,
+                config.owner_filter,
+                config.one_file_system,
             )
         },
         || {
@@ -81,42 +173,31 @@ pub fn run_sync(config: SyncConfig) -> Result<ExitStatus> {
                 &config.types,
                 &config.ignore,
                 config.symlinks,
+                config.owner_filter,
+                config.one_file_system,
             )
         },
     );
     let (source_files, source_errors) = res_source?;
     let (dest_files, dest_errors) = res_dest?;
 
-    for e in &source_errors {
-        print_error_entry(e, "source");
+    print_error_entries(&source_errors, "source");
+    print_error_entries(&dest_errors, "destination");
+
+    let (source_map, source_outside) = key_by_relative_path(source_files, &config.source);
+    let (dest_map, dest_outside) = key_by_relative_path(dest_files, &config.destination);
+
+    // Entries that resolve outside their root cannot be mapped onto the other
+    // side, so they are reported and left untouched rather than aborting the sync.
+    for path in &source_outside {
+        print_realtime_missing(Status::OutOfTree, path, None)?;
     }
-    for e in &dest_errors {
-        print_error_entry(e, "destination");
+    for path in &dest_outside {
+        print_realtime_missing(Status::OutOfTree, path, None)?;
     }
 
-    let total_errors = source_errors.len() + dest_errors.len();
-
-    let source_map: HashMap<PathBuf, FileEntry> = source_files
-        .into_par_iter()
-        .map(|f| {
-            (
-                f.path.strip_prefix(&config.source).unwrap().to_path_buf(),
-                f,
-            )
-        })
-        .collect();
-    let dest_map: HashMap<PathBuf, FileEntry> = dest_files
-        .into_par_iter()
-        .map(|f| {
-            (
-                f.path
-                    .strip_prefix(&config.destination)
-                    .unwrap()
-                    .to_path_buf(),
-                f,
-            )
-        })
-        .collect();
+    let total_errors =
+        source_errors.len() + dest_errors.len() + source_outside.len() + dest_outside.len();
 
     let source_paths: HashSet<&PathBuf> = source_map.keys().collect();
     let dest_paths: HashSet<&PathBuf> = dest_map.keys().collect();
@@ -134,7 +215,7 @@ pub fn run_sync(config: SyncConfig) -> Result<ExitStatus> {
                 .progress_chars("#>- ")
         );
         pb.set_draw_target(indicatif::ProgressDrawTarget::stderr_with_hz(10));
-        Some(pb)
+        Some(activity::attach(pb))
     } else {
         None
     };
@@ -152,55 +233,43 @@ pub fn run_sync(config: SyncConfig) -> Result<ExitStatus> {
             //   if sizes differ we already know it's a DIFF, no hashing needed.
             if source_entry.size != dest_entry.size {
                 return Some(Ok(ComparisonResult {
-                    file: rel_path.clone(),
-                    status: Status::Diff,
-                    hash1: None,
-                    hash2: None,
                     size1: Some(source_entry.size),
                     size2: Some(dest_entry.size),
-                    modified1: None,
-                    modified2: None,
                     symlink1: source_entry.symlink_target.clone(),
                     symlink2: dest_entry.symlink_target.clone(),
+                    ..ComparisonResult::new(rel_path.clone(), Status::Diff)
                 }));
             }
 
             // Fix #7: metadata fast-path now applies for *every* algorithm, not
             //   only HashAlgo::Both. (Original code had `&& config.algo == HashAlgo::Both`
             //   which meant Sha256 and Blake3 modes always hashed even on matching metadata.)
-            if source_entry.modified == dest_entry.modified {
-                return None; // Same size + same mtime → skip hashing
-            }
-
-            let (h_source_res, h_dest_res) = rayon::join(
-                || compute_hashes(&source_entry.path, config.algo),
-                || compute_hashes(&dest_entry.path, config.algo),
-            );
-
-            let result = match (h_source_res, h_dest_res) {
-                (Ok(h_source), Ok(h_dest)) => match config.algo {
-                    HashAlgo::Sha256 => h_source.sha256 != h_dest.sha256,
-                    HashAlgo::Blake3 => h_source.blake3 != h_dest.blake3,
-                    HashAlgo::Both => {
-                        h_source.sha256 != h_dest.sha256 || h_source.blake3 != h_dest.blake3
-                    }
-                },
-                _ => true, // Treat hashing errors as differences
+            // Same size + same mtime → skip hashing
+            let result = source_entry.modified != dest_entry.modified && {
+                let (h_source_res, h_dest_res) = rayon::join(
+                    || compute_hashes(&source_entry.path, config.algo, config.read_ahead),
+                    || compute_hashes(&dest_entry.path, config.algo, config.read_ahead),
+                );
+
+                match (h_source_res, h_dest_res) {
+                    (Ok(h_source), Ok(h_dest)) => !h_source.matches(&h_dest, config.algo),
+                    _ => true, // Treat hashing errors as differences
+                }
             };
 
             if result {
                 Some(Ok(ComparisonResult {
-                    file: rel_path.clone(),
-                    status: Status::Diff,
-                    hash1: None,
-                    hash2: None,
                     size1: Some(source_entry.size),
                     size2: Some(dest_entry.size),
-                    modified1: None,
-                    modified2: None,
                     symlink1: source_entry.symlink_target.clone(),
                     symlink2: dest_entry.symlink_target.clone(),
+                    ..ComparisonResult::new(rel_path.clone(), Status::Diff)
                 }))
+            } else if config.fix_metadata && metadata_differs(source_entry, dest_entry) {
+                Some(Ok(ComparisonResult::new(
+                    rel_path.clone(),
+                    Status::Metadata,
+                )))
             } else {
                 None
             }
@@ -212,9 +281,28 @@ pub fn run_sync(config: SyncConfig) -> Result<ExitStatus> {
     }
 
     let mut actions: Vec<ComparisonResult> = Vec::new();
-    let mut created_count = 0;
-    let mut updated_count = 0;
-    let mut deleted_count = 0;
+    let mut summary = SyncSummary {
+        unchanged: common_paths.len() - sync_actions.len(),
+        errors: total_errors,
+        ..Default::default()
+    };
+    let mut failures: Vec<ErrorEntry> = Vec::new();
+
+    // Source inodes with several links map to the destination path that already
+    // holds their content; later links to the same inode become hard links to it.
+    let mut linked: HashMap<(u64, u64), PathBuf> = HashMap::new();
+    if config.hard_links {
+        let changed: HashSet<&PathBuf> = sync_actions.iter().map(|a| &a.file).collect();
+        for rel_path in &common_paths {
+            if let Some(id) = source_map[rel_path].link_id
+                && !changed.contains(rel_path)
+            {
+                linked
+                    .entry(id)
+                    .or_insert_with(|| config.destination.join(rel_path));
+            }
+        }
+    }
 
     // Files only in source → CREATE in destination
     // Fix #12: use constructor helpers
@@ -225,7 +313,7 @@ pub fn run_sync(config: SyncConfig) -> Result<ExitStatus> {
     }
 
     // Files only in destination → DELETE from destination
-    if config.delete_extraneous && !config.no_delete {
+    if config.allows_delete() {
         for rel_path in dest_paths.difference(&source_paths) {
             let mut r = ComparisonResult::extra((*rel_path).clone());
             r.status = Status::Delete;
@@ -233,14 +321,20 @@ pub fn run_sync(config: SyncConfig) -> Result<ExitStatus> {
         }
     }
 
-    // Common files that differ → UPDATE in destination
+    // Common files that differ → UPDATE in destination (METADATA ones keep their status)
     for mut res in sync_actions {
-        res.status = Status::Update;
+        if res.status == Status::Diff {
+            res.status = Status::Update;
+        }
         actions.push(res);
     }
 
     actions.sort_by(|a, b| a.file.cmp(&b.file));
 
+    if config.dry_run {
+        summary.estimate = Some(estimate_transfer(&actions, &source_map, &dest_map));
+    }
+
     if io::stdout().is_terminal() {
         println!("\nApplying synchronization actions...");
     }
@@ -262,8 +356,17 @@ pub fn run_sync(config: SyncConfig) -> Result<ExitStatus> {
         }
         let source_path = config.source.join(&action.file);
         let dest_path = config.destination.join(&action.file);
+        let code = config.itemize.then(|| {
+            itemize(
+                action.status,
+                source_map.get(&action.file),
+                dest_map.get(&action.file),
+            )
+        });
 
-        if config.dry_run {
+        if let Some(code) = code.as_ref().filter(|_| config.dry_run) {
+            println!("{} {}", code, action.file.display());
+        } else if config.dry_run {
             match action.status {
                 Status::Create => println!(
                     "{} (Dry Run): Will create {}",
@@ -280,30 +383,70 @@ pub fn run_sync(config: SyncConfig) -> Result<ExitStatus> {
                     "DELETE".red().bold(),
                     dest_path.display()
                 ),
+                Status::Metadata => println!(
+                    "{} (Dry Run): Will fix permissions/ownership/mtime of {}",
+                    "METADATA".yellow().bold(),
+                    dest_path.display()
+                ),
                 _ => {}
             }
         } else {
-            match action.status {
+            // A failed action is recorded and the remaining actions still run.
+            let outcome = match action.status {
                 Status::Create | Status::Update => {
-                    let parent = dest_path
-                        .parent()
-                        .context("Failed to get parent directory")?;
-                    fs::create_dir_all(parent)?;
-                    fs::copy(&source_path, &dest_path)?;
-                    if action.status == Status::Create {
-                        created_count += 1;
-                        println!("{} {}", "CREATED".green(), dest_path.display());
-                    } else {
-                        updated_count += 1;
-                        println!("{} {}", "UPDATED".yellow(), dest_path.display());
+                    let link_id = source_map
+                        .get(&action.file)
+                        .and_then(|e| e.link_id)
+                        .filter(|_| config.hard_links);
+                    match link_id.and_then(|id| linked.get(&id)) {
+                        Some(leader) => link_into_place(leader, &dest_path).map(|_| 0),
+                        None => copy_into_place(&source_path, &dest_path).inspect(|_| {
+                            if let Some(id) = link_id {
+                                linked.insert(id, dest_path.clone());
+                            }
+                        }),
                     }
                 }
-                Status::Delete => {
-                    fs::remove_file(&dest_path)?;
-                    deleted_count += 1;
-                    println!("{} {}", "DELETED".red(), dest_path.display());
+                Status::Delete => fs::remove_file(&dest_path).map(|_| 0).map_err(Into::into),
+                Status::Metadata => {
+                    apply_metadata(&source_map[&action.file], &dest_path).map(|_| 0)
                 }
-                _ => {}
+                _ => continue,
+            };
+            match outcome {
+                Ok(bytes) => {
+                    summary.bytes_copied += bytes;
+                    if action.status != Status::Delete
+                        && let Err(e) = copy_attributes(&source_path, &dest_path)
+                    {
+                        summary.attrs_failed += 1;
+                        eprintln!("{} {} ({:#})", "Warning:".yellow(), dest_path.display(), e);
+                    }
+                    let label = match action.status {
+                        Status::Create => {
+                            summary.created += 1;
+                            "CREATED".green()
+                        }
+                        Status::Update => {
+                            summary.updated += 1;
+                            "UPDATED".yellow()
+                        }
+                        Status::Metadata => {
+                            summary.metadata_fixed += 1;
+                            "METADATA".yellow()
+                        }
+                        _ => {
+                            summary.deleted += 1;
+                            "DELETED".red()
+                        }
+                    };
+                    match &code {
+                        Some(code) => println!("{} {}", code, action.file.display()),
+                        None => println!("{} {}", label, dest_path.display()),
+                    }
+                    run_hook(config.hook_for(action.status), &source_path, &dest_path);
+                }
+                Err(e) => record_failure(&mut failures, dest_path, &e),
             }
         }
     }
@@ -312,36 +455,512 @@ pub fn run_sync(config: SyncConfig) -> Result<ExitStatus> {
         p.finish_with_message("Actions applied");
     }
 
-    let elapsed = start_time.elapsed();
-    let total_actions = created_count + updated_count + deleted_count;
+    summary.elapsed = start_time.elapsed();
 
     let report_conf = ReportConfig {
         mode: Mode::Batch,
         algo: config.algo,
         threads: config.threads,
         verbose: false,
+        ..Default::default()
     };
 
-    let summary_data = SummaryData {
-        total: total_actions,
-        matches: 0,
-        diffs: updated_count,
-        missing: created_count,
-        extra: deleted_count,
-        errors: total_errors,
-        elapsed,
+    Ok(finish_sync(&mut summary, &failures, &report_conf, &config))
+}
+
+/// Read at most this much source data to gauge throughput for a dry run.
+const PROBE_BYTES: u64 = 64 * 1024 * 1024;
+/// ...or stop after this long, whichever comes first.
+const PROBE_TIME: Duration = Duration::from_secs(1);
+
+/// Total what the planned actions would copy and delete, and time a short
+/// read of the files to be copied (largest first) to estimate the duration.
+fn estimate_transfer(
+    actions: &[ComparisonResult],
+    source_map: &HashMap<PathBuf, FileEntry>,
+    dest_map: &HashMap<PathBuf, FileEntry>,
+) -> TransferEstimate {
+    let mut est = TransferEstimate::default();
+    let mut to_copy: Vec<&FileEntry> = Vec::new();
+    for action in actions {
+        match action.status {
+            Status::Create | Status::Update => {
+                let entry = &source_map[&action.file];
+                est.files_to_copy += 1;
+                est.bytes_to_copy += entry.size;
+                if entry.symlink_target.is_none() {
+                    to_copy.push(entry);
+                }
+            }
+            Status::Delete => {
+                est.files_to_delete += 1;
+                est.bytes_to_delete += dest_map[&action.file].size;
+            }
+            _ => {}
+        }
+    }
+    to_copy.sort_by_key(|e| std::cmp::Reverse(e.size));
+    est.throughput = probe_read_throughput(&to_copy);
+    est
+}
+
+/// Sequentially read from `files` until `PROBE_BYTES` or `PROBE_TIME` is
+/// reached and return the rate in bytes per second.
+fn probe_read_throughput(files: &[&FileEntry]) -> Option<f64> {
+    let start = Instant::now();
+    let mut buf = vec![0u8; 1024 * 1024];
+    let mut read = 0u64;
+    'files: for entry in files {
+        let Ok(mut file) = fs::File::open(&entry.path) else {
+            continue;
+        };
+        loop {
+            match file.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => read += n as u64,
+            }
+            if read >= PROBE_BYTES || start.elapsed() >= PROBE_TIME {
+                break 'files;
+            }
+        }
+    }
+    let secs = start.elapsed().as_secs_f64();
+    (read > 0 && secs > 0.0).then(|| read as f64 / secs)
+}
+
+/// Copy `source_path` to `dest_path` through a temporary file in the same
+/// directory, renamed over the target once complete: readers see either the
+/// old file or the new one, never a partial copy, and the rename cannot cross
+/// filesystems. The temporary file is removed if the copy fails.
+///
+/// The temporary name does not include the target's, so names close to the
+/// filesystem's length limit still copy, and the copy is flushed to disk
+/// before the rename so a crash cannot leave an empty file in its place.
+/// Because the rename replaces the target's inode, other hard links to the
+/// old file keep the old content, and the new file is owned by the user
+/// running the sync rather than the old file's owner.
+pub(crate) fn copy_into_place(source_path: &Path, dest_path: &Path) -> Result<u64> {
+    static STAGED: AtomicUsize = AtomicUsize::new(0);
+
+    let parent = dest_path
+        .parent()
+        .context("Failed to get parent directory")?;
+    fs::create_dir_all(parent)?;
+    let staging = parent.join(format!(
+        ".cmpf-{}-{}.tmp",
+        std::process::id(),
+        STAGED.fetch_add(1, Ordering::Relaxed)
+    ));
+    let copied = fs::copy(source_path, &staging)
+        .and_then(|bytes| {
+            // The copy keeps the source's mode, which may be read-only. Unix
+            // flushes through any handle; Windows only through a writable one.
+            #[cfg(unix)]
+            let staged = fs::File::open(&staging)?;
+            #[cfg(not(unix))]
+            let staged = fs::OpenOptions::new().write(true).open(&staging)?;
+            staged.sync_all()?;
+            Ok(bytes)
+        })
+        .with_context(|| format!("Failed to copy {}", source_path.display()))
+        .and_then(|bytes| {
+            fs::rename(&staging, dest_path)
+                .with_context(|| format!("Failed to move a copy into {}", dest_path.display()))?;
+            Ok(bytes)
+        });
+    if copied.is_err() {
+        let _ = fs::remove_file(&staging);
+    }
+    copied
+}
+
+/// rsync-style `YXcstpoguax` change string for a sync action.
+///
+/// `Y` is `>` for a transfer, `.` for an attribute-only fix and `*` for a
+/// message (`*deleting`); `X` is `f` or `L` (symlink). The attribute columns
+/// show `c` (content differs at equal size), `s` (size), `t` (mtime),
+/// `p` (permissions), `o` (owner) and `g` (group); a new file is all `+`.
+pub fn itemize(status: Status, source: Option<&FileEntry>, dest: Option<&FileEntry>) -> String {
+    if status == Status::Delete {
+        return format!("{:<11}", "*deleting");
+    }
+    let kind = match source {
+        Some(s) if s.symlink_target.is_some() => 'L',
+        _ => 'f',
+    };
+    let (Some(source), Some(dest)) = (source, dest) else {
+        return format!(">{}+++++++++", kind);
+    };
+    let flag = |differs: bool, c: char| if differs { c } else { '.' };
+    let update = if status == Status::Metadata { '.' } else { '>' };
+    format!(
+        "{}{}{}{}{}{}{}{}...",
+        update,
+        kind,
+        flag(status == Status::Update && source.size == dest.size, 'c'),
+        flag(source.size != dest.size, 's'),
+        flag(source.modified != dest.modified, 't'),
+        flag(source.permissions != dest.permissions, 'p'),
+        flag(source.uid != dest.uid, 'o'),
+        flag(source.gid != dest.gid, 'g'),
+    )
+}
+
+/// Content is identical; do permissions, ownership or mtime disagree?
+fn metadata_differs(source: &FileEntry, dest: &FileEntry) -> bool {
+    source.modified != dest.modified
+        || source.permissions != dest.permissions
+        || source.uid != dest.uid
+        || source.gid != dest.gid
+}
+
+/// Copy ownership, permission bits and mtime from `source` onto `dest_path`
+/// without touching its content.
+fn apply_metadata(source: &FileEntry, dest_path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if source.uid.is_some() || source.gid.is_some() {
+            std::os::unix::fs::chown(dest_path, source.uid, source.gid)
+                .with_context(|| format!("Failed to chown {}", dest_path.display()))?;
+        }
+        if let Some(mode) = source.permissions {
+            fs::set_permissions(dest_path, fs::Permissions::from_mode(mode))
+                .with_context(|| format!("Failed to chmod {}", dest_path.display()))?;
+        }
+    }
+    if let Some(modified) = source.modified {
+        // On Unix, setting explicit times needs ownership, not write access,
+        // so a read-only handle works even for read-only files. Windows needs
+        // a handle with attribute-write access, which read-only files grant.
+        #[cfg(not(windows))]
+        let dest = fs::File::open(dest_path);
+        #[cfg(windows)]
+        let dest = {
+            use std::os::windows::fs::OpenOptionsExt;
+            const FILE_WRITE_ATTRIBUTES: u32 = 0x100;
+            fs::OpenOptions::new()
+                .access_mode(FILE_WRITE_ATTRIBUTES)
+                .open(dest_path)
+        };
+        dest.and_then(|f| f.set_modified(modified))
+            .with_context(|| format!("Failed to set mtime on {}", dest_path.display()))?;
+    }
+    Ok(())
+}
+
+/// Copy extended attributes (Finder info, tags, quarantine flags, the resource
+/// fork) and ACLs from `source` onto `dest_path`.
+#[cfg(target_os = "macos")]
+fn copy_attributes(source: &Path, dest_path: &Path) -> Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let from = CString::new(source.as_os_str().as_bytes())?;
+    let to = CString::new(dest_path.as_os_str().as_bytes())?;
+    // SAFETY: both arguments are valid NUL-terminated paths and copyfile
+    // accepts a null state.
+    let rc = unsafe {
+        libc::copyfile(
+            from.as_ptr(),
+            to.as_ptr(),
+            std::ptr::null_mut(),
+            libc::COPYFILE_XATTR | libc::COPYFILE_ACL,
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error()).with_context(|| {
+            format!(
+                "Failed to copy extended attributes to {}",
+                dest_path.display()
+            )
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn copy_attributes(_source: &Path, _dest_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+fn link_into_place(leader: &Path, dest_path: &Path) -> Result<()> {
+    let parent = dest_path
+        .parent()
+        .context("Failed to get parent directory")?;
+    fs::create_dir_all(parent)?;
+    if fs::symlink_metadata(dest_path).is_ok() {
+        fs::remove_file(dest_path)?;
+    }
+    fs::hard_link(leader, dest_path)
+        .with_context(|| format!("Failed to hard link to {}", leader.display()))
+}
+
+/// Run an `--on-*` hook for an applied action. Tokens are substituted after
+/// splitting, so paths with spaces stay single arguments. A failing hook is
+/// reported but does not undo or fail the action.
+fn run_hook(cmd: Option<&str>, src: &Path, dst: &Path) {
+    let Some(cmd) = cmd else {
+        return;
+    };
+    let (src, dst) = (src.to_string_lossy(), dst.to_string_lossy());
+    let parts: Vec<String> = split_command(cmd)
+        .iter()
+        .map(|p| p.replace("{src}", &src).replace("{dst}", &dst))
+        .collect();
+    let Some((program, args)) = parts.split_first() else {
+        return;
     };
+    match std::process::Command::new(program).args(args).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!(
+            "{} hook `{}` exited with {} for {}",
+            "Warning:".yellow(),
+            cmd,
+            status,
+            dst
+        ),
+        Err(e) => eprintln!(
+            "{} hook `{}` could not run for {}: {}",
+            "Warning:".yellow(),
+            cmd,
+            dst,
+            e
+        ),
+    }
+}
+
+fn record_failure(failures: &mut Vec<ErrorEntry>, dest_path: PathBuf, e: &anyhow::Error) {
+    println!(
+        "{} {} ({:#})",
+        "FAILED".red().bold(),
+        dest_path.display(),
+        e
+    );
+    let kind = e
+        .chain()
+        .find_map(|c| c.downcast_ref::<io::Error>())
+        .map_or(ErrorKind::Io, ErrorKind::of);
+    failures.push(ErrorEntry {
+        kind,
+        ..ErrorEntry::new(dest_path, format!("{:#}", e))
+    });
+}
+
+/// Print the failed-action list and summary box, and derive the exit status.
+fn finish_sync(
+    summary: &mut SyncSummary,
+    failures: &[ErrorEntry],
+    report_conf: &ReportConfig,
+    config: &SyncConfig,
+) -> ExitStatus {
+    summary.failed = failures.len();
+    if !failures.is_empty() {
+        println!("\n{} ({}):", "Failed actions".red().bold(), failures.len());
+        for f in failures {
+            println!("  [{}] {} ({})", "FAILED".red(), f.path.display(), f.error);
+        }
+        println!();
+    }
 
-    let summary_lines = generate_summary_text(&summary_data, &report_conf);
-    for line in summary_lines {
+    for line in generate_sync_summary_text(summary, report_conf) {
         println!("{}", line);
     }
 
-    if total_errors > 0 {
-        Ok(ExitStatus::Error)
-    } else if created_count > 0 || updated_count > 0 || deleted_count > 0 {
-        Ok(ExitStatus::Diff)
+    let status = if summary.errors > 0 || summary.failed > 0 {
+        ExitStatus::Error
+    } else if summary.created > 0
+        || summary.updated > 0
+        || summary.deleted > 0
+        || summary.metadata_fixed > 0
+    {
+        ExitStatus::Diff
     } else {
-        Ok(ExitStatus::Success)
+        ExitStatus::Success
+    };
+    if config.status_line {
+        print_status_line(&status, &summary.status_fields());
+    }
+    status
+}
+
+/// Bring `destination` into the state recorded by the snapshot at `config.source`.
+///
+/// Snapshots store digests, not file contents, so only what the snapshot fully
+/// describes can be restored: extraneous files are deleted (with
+/// `--delete-extraneous`) and symlinks are recreated from their recorded target.
+/// Regular files that are missing or have drifted are reported as unrestorable.
+fn run_snapshot_sync(config: &SyncConfig, start_time: Instant) -> Result<ExitStatus> {
+    let snapshot = load_snapshot(&config.source)?;
+    if io::stdout().is_terminal() {
+        println!(
+            "Syncing against snapshot created at: {}",
+            snapshot.created_at.cyan()
+        );
+    }
+
+    let (dest_files, dest_errors) = snapshot.collect(&config.destination)?;
+    print_error_entries(&dest_errors, "destination");
+    let (dest_map, dest_outside) = key_by_relative_path(dest_files, &config.destination);
+    for path in &dest_outside {
+        print_realtime_missing(Status::OutOfTree, path, None)?;
     }
+
+    let snapshot_paths: HashSet<&PathBuf> = snapshot.files.iter().map(|e| &e.rel_path).collect();
+
+    // Snapshot entries whose destination copy is absent or drifted.
+    let mut actions: Vec<ComparisonResult> = snapshot
+        .files
+        .par_iter()
+        .filter_map(|entry| {
+            let current = dest_map.get(&entry.rel_path);
+            if let Some(target) = &entry.symlink_target {
+                if current.and_then(|c| c.symlink_target.as_ref()) == Some(target) {
+                    return None;
+                }
+                let status = if current.is_some() {
+                    Status::Update
+                } else {
+                    Status::Create
+                };
+                return Some(ComparisonResult {
+                    symlink1: Some(target.clone()),
+                    ..ComparisonResult::new(entry.rel_path.clone(), status)
+                });
+            }
+            match current {
+                Some(c)
+                    if c.size == entry.size
+                        && compute_hashes(&c.path, snapshot.algo, config.read_ahead)
+                            .is_ok_and(|h| h.matches(&entry.hashes, snapshot.algo)) =>
+                {
+                    None
+                }
+                Some(c) => Some(ComparisonResult {
+                    size1: Some(entry.size),
+                    size2: Some(c.size),
+                    ..ComparisonResult::new(entry.rel_path.clone(), Status::Diff)
+                }),
+                None => Some(ComparisonResult {
+                    size1: Some(entry.size),
+                    ..ComparisonResult::missing(entry.rel_path.clone())
+                }),
+            }
+        })
+        .collect();
+
+    if config.allows_delete() {
+        for rel_path in dest_map.keys().filter(|p| !snapshot_paths.contains(p)) {
+            actions.push(ComparisonResult::new(rel_path.clone(), Status::Delete));
+        }
+    }
+    actions.sort_by(|a, b| a.file.cmp(&b.file));
+
+    let planned_restores = actions
+        .iter()
+        .filter(|a| a.status != Status::Delete)
+        .count();
+    let mut summary = SyncSummary {
+        unchanged: snapshot.files.len() - planned_restores,
+        errors: dest_errors.len() + dest_outside.len(),
+        ..Default::default()
+    };
+    let mut failures: Vec<ErrorEntry> = Vec::new();
+
+    for action in &actions {
+        let dest_path = config.destination.join(&action.file);
+        match action.status {
+            Status::Diff | Status::Missing => {
+                let reason = if action.status == Status::Diff {
+                    "content drifted; snapshot stores no file contents"
+                } else {
+                    "missing; snapshot stores no file contents"
+                };
+                println!(
+                    "{} {} ({})",
+                    "CANNOT RESTORE".red().bold(),
+                    dest_path.display(),
+                    reason
+                );
+                failures.push(ErrorEntry::new(dest_path, reason.to_string()));
+            }
+            Status::Create | Status::Update => {
+                let target = action.symlink1.as_deref().unwrap_or_default();
+                if config.dry_run {
+                    println!(
+                        "{} (Dry Run): Will restore symlink {} -> {}",
+                        action.status.to_string().green().bold(),
+                        dest_path.display(),
+                        target
+                    );
+                    continue;
+                }
+                if let Err(e) = restore_symlink(&dest_path, target) {
+                    record_failure(&mut failures, dest_path, &e);
+                    continue;
+                }
+                if action.status == Status::Create {
+                    summary.created += 1;
+                } else {
+                    summary.updated += 1;
+                }
+                println!(
+                    "{} {} -> {}",
+                    "RESTORED".green(),
+                    dest_path.display(),
+                    target
+                );
+                run_hook(config.hook_for(action.status), &config.source, &dest_path);
+            }
+            Status::Delete => {
+                if config.dry_run {
+                    println!(
+                        "{} (Dry Run): Will delete {}",
+                        "DELETE".red().bold(),
+                        dest_path.display()
+                    );
+                    continue;
+                }
+                if let Err(e) = fs::remove_file(&dest_path) {
+                    record_failure(&mut failures, dest_path, &e.into());
+                    continue;
+                }
+                summary.deleted += 1;
+                println!("{} {}", "DELETED".red(), dest_path.display());
+                run_hook(config.on_delete.as_deref(), &config.source, &dest_path);
+            }
+            _ => {}
+        }
+    }
+    summary.elapsed = start_time.elapsed();
+
+    let report_conf = ReportConfig {
+        mode: Mode::Batch,
+        algo: snapshot.algo,
+        threads: config.threads,
+        ..Default::default()
+    };
+
+    Ok(finish_sync(&mut summary, &failures, &report_conf, config))
+}
+
+#[cfg(unix)]
+fn restore_symlink(dest_path: &Path, target: &str) -> Result<()> {
+    if fs::symlink_metadata(dest_path).is_ok() {
+        fs::remove_file(dest_path)?;
+    }
+    let parent = dest_path
+        .parent()
+        .context("Failed to get parent directory")?;
+    fs::create_dir_all(parent)?;
+    std::os::unix::fs::symlink(target, dest_path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restore_symlink(dest_path: &Path, _target: &str) -> Result<()> {
+    anyhow::bail!(
+        "Restoring symlinks from a snapshot is only supported on Unix: {}",
+        dest_path.display()
+    )
 }
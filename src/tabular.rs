@@ -0,0 +1,67 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// Field separator of the delimited data files compared value by value under
+/// `--float-tolerance`, chosen by extension.
+pub fn delimiter(path: &Path) -> Option<char> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "csv" => Some(','),
+        "tsv" | "tab" => Some('\t'),
+        _ => None,
+    }
+}
+
+/// A field read as a finite number, with surrounding blanks and quotes dropped.
+fn number(field: &str) -> Option<f64> {
+    field
+        .trim()
+        .trim_matches('"')
+        .parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite())
+}
+
+/// Whether `a` and `b` differ by at most `tolerance` times the larger of the two.
+pub fn within(a: f64, b: f64, tolerance: f64) -> bool {
+    a == b || (a - b).abs() <= tolerance * a.abs().max(b.abs())
+}
+
+fn same_row(line1: &str, line2: &str, delimiter: char, tolerance: f64) -> bool {
+    if line1 == line2 {
+        return true;
+    }
+    let fields1: Vec<&str> = line1.split(delimiter).collect();
+    let fields2: Vec<&str> = line2.split(delimiter).collect();
+    fields1.len() == fields2.len()
+        && fields1.iter().zip(&fields2).all(|(f1, f2)| {
+            f1 == f2
+                || matches!((number(f1), number(f2)), (Some(a), Some(b)) if within(a, b, tolerance))
+        })
+}
+
+/// Whether two delimited text files hold the same rows, numeric fields being
+/// equal within the relative `tolerance` and all other fields identical.
+/// Lines are split at every delimiter, so a quoted field holding one splits
+/// the same way on both sides. Files that are not UTF-8 are an error.
+pub fn same_values(
+    path1: &Path,
+    path2: &Path,
+    delimiter: char,
+    tolerance: f64,
+) -> io::Result<bool> {
+    let mut lines1 = BufReader::new(File::open(path1)?).lines();
+    let mut lines2 = BufReader::new(File::open(path2)?).lines();
+    loop {
+        match (lines1.next().transpose()?, lines2.next().transpose()?) {
+            (None, None) => return Ok(true),
+            (Some(line1), Some(line2)) => {
+                if !same_row(&line1, &line2, delimiter, tolerance) {
+                    return Ok(false);
+                }
+            }
+            _ => return Ok(false),
+        }
+    }
+}
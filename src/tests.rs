@@ -1,13 +1,37 @@
 #[cfg(test)]
 mod cmpf_tests {
-    use crate::compare::ExitStatus;
-    use crate::models::{HashAlgo, OutputFormat, SymlinkMode};
-    use crate::snapshot::{SnapshotConfig, VerifyConfig, create_snapshot, verify_snapshot};
-    use crate::sync::{SyncConfig, run_sync};
-    use crate::utils::{collect_files, compute_hashes};
+    use crate::archive::read_tar;
+    use crate::compare::{CompareConfig, ExitStatus, compare_folders, precheck, run_compare};
+    use crate::config::{Expect, FileConfig, StatusRule};
+    use crate::models::{FileEntry, HashAlgo, Mode, OutputFormat, Status, SymlinkMode};
+    use crate::prime::{PrimeConfig, run_prime};
+    use crate::rclone::{is_remote, read_listing};
+    use crate::recheck::run_recheck;
+    use crate::report::SummaryData;
+    use crate::sandbox::{Beneath, Sandbox, refused};
+    use crate::snapshot::{
+        RootMap, SnapshotConfig, VerifyConfig, create_snapshot, verify_snapshot,
+    };
+    use crate::sync::{SyncConfig, itemize, run_sync};
+    use crate::utils::{
+        collect_files, compute_hashes, compute_hashes_pipelined, diff_blocks, explain_skips,
+        key_by_relative_path, parse_size, read_exclude_file,
+    };
+    use std::collections::HashMap;
     use std::fs::{self, File};
     use std::io::Write;
-    use tempfile::tempdir;
+    use std::path::{Path, PathBuf};
+    use tempfile::{TempDir, tempdir};
+
+    /// A temporary directory holding the empty folders `f1` and `f2`.
+    fn two_trees() -> (TempDir, PathBuf, PathBuf) {
+        let dir = tempdir().unwrap();
+        let f1 = dir.path().join("f1");
+        let f2 = dir.path().join("f2");
+        fs::create_dir_all(&f1).unwrap();
+        fs::create_dir_all(&f2).unwrap();
+        (dir, f1, f2)
+    }
 
     #[test]
     fn test_compute_hashes_empty_file() {
@@ -15,7 +39,7 @@ mod cmpf_tests {
         let file_path = dir.path().join("empty.txt");
         File::create(&file_path).unwrap();
 
-        let res = compute_hashes(&file_path, HashAlgo::Blake3).unwrap();
+        let res = compute_hashes(&file_path, HashAlgo::Blake3, None).unwrap();
         assert!(res.blake3.is_some());
         assert!(res.sha256.is_none());
     }
@@ -27,8 +51,8 @@ mod cmpf_tests {
         let mut file = File::create(&file_path).unwrap();
         writeln!(file, "hello world").unwrap();
 
-        let res_b3 = compute_hashes(&file_path, HashAlgo::Blake3).unwrap();
-        let res_sha = compute_hashes(&file_path, HashAlgo::Sha256).unwrap();
+        let res_b3 = compute_hashes(&file_path, HashAlgo::Blake3, None).unwrap();
+        let res_sha = compute_hashes(&file_path, HashAlgo::Sha256, None).unwrap();
 
         assert!(res_b3.blake3.is_some());
         assert!(res_sha.sha256.is_some());
@@ -51,6 +75,8 @@ mod cmpf_tests {
             &None,
             &None,
             SymlinkMode::Ignore,
+            None,
+            false,
         )
         .unwrap();
 
@@ -58,6 +84,121 @@ mod cmpf_tests {
         assert_eq!(errors.len(), 0);
     }
 
+    #[test]
+    fn test_collect_files_single_walk_thread() {
+        use crate::utils::set_walk_threads;
+
+        let dir = tempdir().unwrap();
+        for d in 0..8 {
+            let sub = dir.path().join(format!("d{}", d));
+            fs::create_dir(&sub).unwrap();
+            for f in 0..4 {
+                File::create(sub.join(format!("f{}", f))).unwrap();
+            }
+        }
+
+        // Any thread count must list the same tree; only the pace changes.
+        set_walk_threads(1);
+        let (files, errors) = collect_files(
+            dir.path(),
+            None,
+            false,
+            false,
+            &None,
+            &None,
+            SymlinkMode::Ignore,
+            None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(files.len(), 32);
+        assert!(errors.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_files_owner_filter() {
+        use crate::utils::parse_owner;
+        use std::os::unix::fs::MetadataExt;
+
+        assert_eq!(parse_owner("1234"), Ok(1234));
+        assert_eq!(parse_owner("root"), Ok(0));
+        assert!(parse_owner("no-such-user-cmpf").is_err());
+
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("mine"), "a").unwrap();
+        fs::write(dir.path().join("theirs"), "b").unwrap();
+        let me = fs::metadata(dir.path().join("mine")).unwrap().uid();
+        let other = if me == 0 { 65534 } else { me };
+        if me == 0 {
+            std::os::unix::fs::chown(dir.path().join("theirs"), Some(other), None).unwrap();
+        }
+
+        let collect = |owner| {
+            let (files, _) = collect_files(
+                dir.path(),
+                None,
+                false,
+                false,
+                &None,
+                &None,
+                SymlinkMode::Ignore,
+                Some(owner),
+                false,
+            )
+            .unwrap();
+            let mut names: Vec<String> = files
+                .iter()
+                .map(|f| f.path.file_name().unwrap().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+        if me == 0 {
+            assert_eq!(collect(me), vec!["mine"]);
+            assert_eq!(collect(other), vec!["theirs"]);
+        } else {
+            assert_eq!(collect(me), vec!["mine", "theirs"]);
+        }
+        assert!(collect(4_000_000).is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_collect_files_one_file_system() {
+        use std::os::unix::fs::MetadataExt;
+
+        // Needs a mount below a walkable directory; /dev/shm usually is one.
+        let (Ok(dev), Ok(shm)) = (fs::metadata("/dev"), fs::metadata("/dev/shm")) else {
+            return;
+        };
+        if dev.dev() == shm.dev() {
+            return;
+        }
+        let Ok(dir) = tempfile::tempdir_in("/dev/shm") else {
+            return;
+        };
+        fs::write(dir.path().join("on-tmpfs"), "x").unwrap();
+
+        let found = |one_file_system| {
+            let (files, _) = collect_files(
+                Path::new("/dev"),
+                Some(3),
+                false,
+                true,
+                &None,
+                &None,
+                SymlinkMode::Ignore,
+                None,
+                one_file_system,
+            )
+            .unwrap();
+            files.iter().any(|f| f.path.ends_with("on-tmpfs"))
+        };
+        assert!(found(false));
+        assert!(!found(true));
+    }
+
     #[test]
     fn test_collect_files_recursive_off() {
         let dir = tempdir().unwrap();
@@ -75,6 +216,8 @@ mod cmpf_tests {
             &None,
             &None,
             SymlinkMode::Ignore,
+            None,
+            false,
         )
         .unwrap();
 
@@ -104,6 +247,7 @@ mod cmpf_tests {
             ignore: None,
             symlinks: SymlinkMode::Ignore,
             threads: None,
+            ..Default::default()
         })
         .unwrap();
 
@@ -116,6 +260,7 @@ mod cmpf_tests {
             threads: None,
             output_format: OutputFormat::Txt,
             verbose: false,
+            ..Default::default()
         })
         .unwrap();
         assert_eq!(status, ExitStatus::Success);
@@ -129,6 +274,7 @@ mod cmpf_tests {
             threads: None,
             output_format: OutputFormat::Txt,
             verbose: false,
+            ..Default::default()
         })
         .unwrap();
         assert_eq!(status, ExitStatus::Diff);
@@ -161,6 +307,7 @@ mod cmpf_tests {
             types: None,
             ignore: None,
             threads: None,
+            ..Default::default()
         })
         .unwrap();
 
@@ -185,6 +332,8 @@ mod cmpf_tests {
             &None,
             &None,
             SymlinkMode::Ignore,
+            None,
+            false,
         )
         .unwrap();
         assert_eq!(files.len(), 1);
@@ -198,6 +347,8 @@ mod cmpf_tests {
             &None,
             &None,
             SymlinkMode::Ignore,
+            None,
+            false,
         )
         .unwrap();
         assert_eq!(files.len(), 2);
@@ -218,6 +369,8 @@ mod cmpf_tests {
             &types,
             &None,
             SymlinkMode::Ignore,
+            None,
+            false,
         )
         .unwrap();
         assert_eq!(files.len(), 1);
@@ -239,6 +392,8 @@ mod cmpf_tests {
             &None,
             &ignore,
             SymlinkMode::Ignore,
+            None,
+            false,
         )
         .unwrap();
         assert_eq!(files.len(), 1);
@@ -269,9 +424,4185 @@ mod cmpf_tests {
             types: None,
             ignore: None,
             threads: None,
+            ..Default::default()
         })
         .unwrap();
 
         assert!(!dst.join("file.txt").exists());
     }
+
+    #[test]
+    fn test_diff_blocks_reports_changed_ranges() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.bin");
+        let b = dir.path().join("b.bin");
+        fs::write(&a, b"aaaabbbbccccdddd").unwrap();
+        fs::write(&b, b"aaaaXbbbccccddddee").unwrap();
+
+        let bd = diff_blocks(&a, &b, 4).unwrap();
+        assert_eq!(bd.total_blocks, 5);
+        assert_eq!(bd.changed_blocks, 2);
+        assert_eq!(bd.changed_bytes, 6);
+        assert_eq!(bd.ranges.len(), 2);
+        assert_eq!((bd.ranges[0].start, bd.ranges[0].end), (4, 8));
+        assert_eq!((bd.ranges[1].start, bd.ranges[1].end), (16, 18));
+    }
+
+    #[test]
+    fn test_key_by_relative_path_separates_out_of_tree() {
+        let entry = |p: &str| FileEntry {
+            path: p.into(),
+            ..Default::default()
+        };
+        let files = vec![entry("/root/a/x.txt"), entry("/elsewhere/y.txt")];
+
+        let (map, outside) = key_by_relative_path(files, std::path::Path::new("/root/a"));
+        assert!(map.contains_key(std::path::Path::new("x.txt")));
+        assert_eq!(outside, vec![std::path::PathBuf::from("/elsewhere/y.txt")]);
+    }
+
+    #[test]
+    fn test_metadata_size_only_ignores_mtime() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::create_dir(&a).unwrap();
+        fs::create_dir(&b).unwrap();
+        fs::write(a.join("f.txt"), "same").unwrap();
+        fs::write(b.join("f.txt"), "same").unwrap();
+        let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        File::options()
+            .write(true)
+            .open(b.join("f.txt"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        let config = |size_only| CompareConfig {
+            folder1: a.clone(),
+            folder2: b.clone(),
+            mode: Mode::Metadata,
+            output_folder: Some(dir.path().join("out")),
+            size_only,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config(false)).unwrap(), ExitStatus::Diff);
+        assert_eq!(run_compare(config(true)).unwrap(), ExitStatus::Success);
+    }
+
+    #[test]
+    fn test_report_checksum_sidecar_and_run_info() {
+        use crate::models::ComparisonResult;
+        use crate::report::{ReportConfig, SummaryData, generate_json_report, write_report};
+        use crate::utils::sha256_hex;
+
+        let dir = tempdir().unwrap();
+        let results = vec![ComparisonResult::missing("a.txt".into())];
+        let config = ReportConfig {
+            report_checksum: true,
+            ..Default::default()
+        };
+        let json =
+            generate_json_report(&results, &[], &[], &SummaryData::default(), &config).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            parsed["run_info"]["results_sha256"],
+            sha256_hex(serde_json::to_string(&results).unwrap().as_bytes())
+        );
+
+        let out = Some(dir.path().to_path_buf());
+        write_report(json.clone(), &out, "report.json", true).unwrap();
+        let sidecar = fs::read_to_string(dir.path().join("report.json.sha256")).unwrap();
+        assert_eq!(
+            sidecar,
+            format!("{}  report.json\n", sha256_hex(json.as_bytes()))
+        );
+    }
+
+    #[test]
+    fn test_sync_from_snapshot_deletes_extras_and_flags_drift() {
+        let dir = tempdir().unwrap();
+        let folder = dir.path().join("cfg");
+        fs::create_dir(&folder).unwrap();
+        fs::write(folder.join("a.conf"), "a").unwrap();
+        fs::write(folder.join("b.conf"), "b").unwrap();
+        let snap = dir.path().join("snap.json");
+        create_snapshot(SnapshotConfig {
+            folder: folder.clone(),
+            output: Some(snap.clone()),
+            algo: HashAlgo::Blake3,
+            depth: None,
+            no_recursive: false,
+            hidden: false,
+            types: None,
+            ignore: None,
+            symlinks: SymlinkMode::Ignore,
+            threads: None,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let sync = |folder: &std::path::Path| {
+            run_sync(SyncConfig {
+                source: snap.clone(),
+                destination: folder.to_path_buf(),
+                delete_extraneous: true,
+                from_snapshot: true,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        fs::write(folder.join("stray.conf"), "x").unwrap();
+        assert_eq!(sync(&folder), ExitStatus::Diff);
+        assert!(!folder.join("stray.conf").exists());
+
+        fs::write(folder.join("b.conf"), "drifted").unwrap();
+        assert_eq!(sync(&folder), ExitStatus::Error);
+        assert!(folder.join("b.conf").exists());
+    }
+
+    #[test]
+    fn test_sync_summary_lists_all_outcomes() {
+        use crate::report::{ReportConfig, SyncSummary, generate_sync_summary_text};
+
+        colored::control::set_override(false);
+        let summary = SyncSummary {
+            created: 2,
+            unchanged: 7,
+            bytes_copied: 1024,
+            ..Default::default()
+        };
+        let text = generate_sync_summary_text(&summary, &ReportConfig::default()).join("\n");
+        for label in ["Created", "Updated", "Deleted", "Unchanged", "Failed"] {
+            assert!(text.contains(label), "missing row {}", label);
+        }
+        assert!(text.contains("Unchanged              : 7"));
+        assert!(text.contains("Bytes copied           : 1024"));
+    }
+
+    #[test]
+    fn test_sync_no_delete_is_enforced() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+        fs::create_dir(&src).unwrap();
+        fs::create_dir(&dst).unwrap();
+        fs::write(dst.join("keep.txt"), "extra").unwrap();
+
+        let both = run_sync(SyncConfig {
+            source: src.clone(),
+            destination: dst.clone(),
+            delete_extraneous: true,
+            no_delete: true,
+            ..Default::default()
+        });
+        assert!(both.is_err());
+        assert!(dst.join("keep.txt").exists());
+
+        run_sync(SyncConfig {
+            source: src,
+            destination: dst.clone(),
+            no_delete: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(dst.join("keep.txt").exists());
+    }
+
+    #[test]
+    fn test_sync_update_replaces_target_atomically() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+        fs::create_dir(&src).unwrap();
+        fs::create_dir(&dst).unwrap();
+        fs::write(src.join("a.txt"), "new content").unwrap();
+        fs::write(dst.join("a.txt"), "old").unwrap();
+        // A second name for the old file: an in-place copy would change it too.
+        let other = dir.path().join("other-name");
+        fs::hard_link(dst.join("a.txt"), &other).unwrap();
+        // The temporary name must not grow with the target's.
+        let long = format!("{}.txt", "n".repeat(246));
+        fs::write(src.join(&long), "long").unwrap();
+
+        let status = run_sync(SyncConfig {
+            source: src,
+            destination: dst.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(status, ExitStatus::Diff);
+        assert_eq!(
+            fs::read_to_string(dst.join("a.txt")).unwrap(),
+            "new content"
+        );
+        assert_eq!(fs::read_to_string(&other).unwrap(), "old");
+        assert_eq!(fs::read_to_string(dst.join(&long)).unwrap(), "long");
+        let mut names: Vec<_> = fs::read_dir(&dst)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a.txt".to_string(), long]);
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_sync_copies_extended_attributes_on_macos() {
+        use std::process::Command;
+
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+        fs::create_dir(&src).unwrap();
+        fs::create_dir(&dst).unwrap();
+        fs::write(src.join("tagged.txt"), "content").unwrap();
+        let status = Command::new("xattr")
+            .args(["-w", "com.example.cmpf", "kept"])
+            .arg(src.join("tagged.txt"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        run_sync(SyncConfig {
+            source: src,
+            destination: dst.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+        let output = Command::new("xattr")
+            .args(["-p", "com.example.cmpf"])
+            .arg(dst.join("tagged.txt"))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "kept");
+    }
+
+    #[test]
+    fn test_sync_continues_past_failed_action() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+        fs::create_dir(&src).unwrap();
+        fs::create_dir(&dst).unwrap();
+        fs::write(src.join("a.txt"), "a").unwrap();
+        fs::write(src.join("b.txt"), "b").unwrap();
+        // A directory in the way makes copying a.txt fail.
+        fs::create_dir(dst.join("a.txt")).unwrap();
+
+        let status = run_sync(SyncConfig {
+            source: src,
+            destination: dst.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(status, ExitStatus::Error);
+        assert_eq!(fs::read_to_string(dst.join("b.txt")).unwrap(), "b");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sync_preserves_hard_links() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+        fs::create_dir(&src).unwrap();
+        fs::create_dir(&dst).unwrap();
+        fs::write(src.join("a.bin"), "shared").unwrap();
+        fs::hard_link(src.join("a.bin"), src.join("b.bin")).unwrap();
+
+        run_sync(SyncConfig {
+            source: src,
+            destination: dst.clone(),
+            hard_links: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let a = fs::metadata(dst.join("a.bin")).unwrap();
+        let b = fs::metadata(dst.join("b.bin")).unwrap();
+        assert_eq!(a.ino(), b.ino());
+        assert_eq!(fs::read_to_string(dst.join("b.bin")).unwrap(), "shared");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sync_fix_metadata_repairs_mode_and_mtime() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+        fs::create_dir(&src).unwrap();
+        fs::create_dir(&dst).unwrap();
+        fs::write(src.join("f.sh"), "#!/bin/sh").unwrap();
+        fs::write(dst.join("f.sh"), "#!/bin/sh").unwrap();
+        fs::set_permissions(src.join("f.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(dst.join("f.sh"), fs::Permissions::from_mode(0o600)).unwrap();
+
+        let status = run_sync(SyncConfig {
+            source: src.clone(),
+            destination: dst.clone(),
+            fix_metadata: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(status, ExitStatus::Diff);
+        let src_meta = fs::metadata(src.join("f.sh")).unwrap();
+        let dst_meta = fs::metadata(dst.join("f.sh")).unwrap();
+        assert_eq!(dst_meta.permissions().mode() & 0o7777, 0o755);
+        assert_eq!(dst_meta.modified().unwrap(), src_meta.modified().unwrap());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_copy_of_read_only_source_as_unprivileged_user() {
+        use crate::sync::copy_into_place;
+        use std::os::unix::fs::PermissionsExt;
+
+        let (dir, src, dst) = two_trees();
+        fs::write(src.join("ro.txt"), "read-only").unwrap();
+        fs::set_permissions(src.join("ro.txt"), fs::Permissions::from_mode(0o444)).unwrap();
+        for path in [dir.path(), &src, &dst] {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o777)).unwrap();
+        }
+
+        // Under root, a filesystem uid of nobody drops the permission
+        // overrides for this thread only, so the copy sees what a user would.
+        let copied = std::thread::spawn(move || {
+            unsafe { libc::setfsuid(65534) };
+            copy_into_place(&src.join("ro.txt"), &dst.join("ro.txt")).map(|_| dst)
+        })
+        .join()
+        .unwrap();
+
+        let dst = copied.unwrap();
+        assert_eq!(fs::read_to_string(dst.join("ro.txt")).unwrap(), "read-only");
+    }
+
+    #[test]
+    fn test_itemize_change_strings() {
+        let src = FileEntry {
+            size: 10,
+            modified: Some(std::time::UNIX_EPOCH),
+            permissions: Some(0o644),
+            ..Default::default()
+        };
+        let same_size = FileEntry {
+            modified: None,
+            ..src.clone()
+        };
+        let resized = FileEntry {
+            size: 12,
+            permissions: Some(0o600),
+            ..src.clone()
+        };
+
+        assert_eq!(itemize(Status::Create, Some(&src), None), ">f+++++++++");
+        assert_eq!(itemize(Status::Delete, None, Some(&src)), "*deleting  ");
+        assert_eq!(
+            itemize(Status::Update, Some(&src), Some(&same_size)),
+            ">fc.t......"
+        );
+        assert_eq!(
+            itemize(Status::Update, Some(&src), Some(&resized)),
+            ">f.s.p....."
+        );
+        assert_eq!(
+            itemize(Status::Metadata, Some(&src), Some(&same_size)),
+            ".f..t......"
+        );
+    }
+
+    #[test]
+    fn test_exclude_from_rsync_rules() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        for sub in ["build", "src/build", "cache"] {
+            fs::create_dir_all(root.join(sub)).unwrap();
+        }
+        for file in [
+            "keep.txt",
+            "debug.log",
+            "build/out.o",
+            "src/build/gen.rs",
+            "src/main.rs",
+            "cache/blob",
+        ] {
+            fs::write(root.join(file), "x").unwrap();
+        }
+        let excludes = dir.path().join("excludes.txt");
+        fs::write(&excludes, "# rsync excludes\n\n*.log\n- /build/\ncache/\n").unwrap();
+
+        let patterns = read_exclude_file(&excludes).unwrap();
+        let (files, _) = collect_files(
+            &root,
+            None,
+            false,
+            false,
+            &None,
+            &Some(patterns),
+            SymlinkMode::Ignore,
+            None,
+            false,
+        )
+        .unwrap();
+        let mut rel: Vec<String> = files
+            .iter()
+            .map(|f| {
+                f.path
+                    .strip_prefix(&root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect();
+        rel.sort();
+        assert_eq!(rel, ["keep.txt", "src/build/gen.rs", "src/main.rs"]);
+
+        fs::write(&excludes, "+ *.rs\n").unwrap();
+        assert!(read_exclude_file(&excludes).is_err());
+    }
+
+    #[test]
+    fn test_precheck_threshold_stops_before_report() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::create_dir_all(f1.join("data")).unwrap();
+        fs::create_dir_all(&f2).unwrap();
+        for i in 0..4 {
+            fs::write(f1.join("data").join(format!("{}.bin", i)), "payload").unwrap();
+        }
+        fs::write(f1.join("readme"), "same").unwrap();
+        fs::write(f2.join("readme"), "same").unwrap();
+
+        let compare = |output_format| {
+            run_compare(CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                output_folder: Some(out.clone()),
+                output_format,
+                precheck: true,
+                precheck_threshold: Some(50.0),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        // The precheck is saved in place of the report it stopped.
+        assert_eq!(compare(OutputFormat::Txt), ExitStatus::Diff);
+        assert!(!out.join("report.txt").exists());
+        let text = fs::read_to_string(out.join("precheck.txt")).unwrap();
+        assert!(text.contains("divergence 87.5%"), "{}", text);
+        assert!(text.contains("[DIR]  data  "), "{}", text);
+
+        assert_eq!(compare(OutputFormat::Json), ExitStatus::Diff);
+        assert!(!out.join("report.json").exists());
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("precheck.json")).unwrap()).unwrap();
+        assert_eq!(json["threshold_percent"], 50.0);
+        let data = json["differing"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|d| d["dir"] == "data")
+            .unwrap();
+        assert_eq!(data["files1"], 4);
+        assert_eq!(data["files2"], 0);
+    }
+
+    #[test]
+    fn test_no_sort_keeps_missing_and_extra_sections_ordered() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        for name in ["m3", "m1", "m2", "common"] {
+            fs::write(f1.join(name), name).unwrap();
+        }
+        for name in ["e2", "e3", "e1", "common"] {
+            fs::write(f2.join(name), name).unwrap();
+        }
+
+        run_compare(CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Json,
+            no_sort: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        let files: Vec<&str> = report["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["file"].as_str().unwrap())
+            .collect();
+        assert_eq!(files, ["common", "m1", "m2", "m3", "e1", "e2", "e3"]);
+    }
+
+    #[test]
+    fn test_summary_counts_bytes_to_transfer() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::write(f1.join("missing"), "12345").unwrap();
+        fs::write(f1.join("changed"), "abcdefgh").unwrap();
+        fs::write(f2.join("changed"), "abc").unwrap();
+        fs::write(f1.join("same"), "same").unwrap();
+        fs::write(f2.join("same"), "same").unwrap();
+        fs::write(f2.join("extra"), "ignored").unwrap();
+
+        run_compare(CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Json,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        assert_eq!(report["summary"]["bytes_to_transfer"], 13);
+    }
+
+    #[test]
+    fn test_detect_type_in_json_results() {
+        use crate::filetype::sniff;
+
+        assert_eq!(sniff(b"\x89PNG\r\n\x1A\n\0\0"), Some("image/png"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(
+            sniff(b"\0\0\0\x18ftypheic\0\0\0\0mif1heic"),
+            Some("image/heif")
+        );
+        // An audio-only ISO box is not a video, and a bare `MZ` or icon
+        // header in front of text is not an executable or an icon.
+        assert_eq!(
+            sniff(b"\0\0\0\x18ftypM4A \0\0\0\0M4A mp42"),
+            Some("audio/m4a")
+        );
+        assert_eq!(sniff(b"MZ is a postcode area"), Some("text/plain"));
+        assert_eq!(sniff(b"\0\0\x01\0\x02\x03"), None);
+        let mut pe = vec![0u8; 0x84];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3C] = 0x80;
+        pe[0x80..].copy_from_slice(b"PE\0\0");
+        assert_eq!(
+            sniff(&pe),
+            Some("application/vnd.microsoft.portable-executable")
+        );
+        assert_eq!(sniff("plain text é".as_bytes()), Some("text/plain"));
+        assert_eq!(sniff(&[0xE2, 0x82]), Some("text/plain"));
+        assert_eq!(sniff(b"\x01\x02\0\x03"), None);
+        assert_eq!(sniff(b""), None);
+
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::write(f1.join("photo"), b"\xFF\xD8\xFF\xE0 one").unwrap();
+        fs::write(f2.join("photo"), b"\xFF\xD8\xFF\xE0 two").unwrap();
+        fs::write(f1.join("notes"), "hello").unwrap();
+
+        run_compare(CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Json,
+            detect_type: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        let results = report["results"].as_array().unwrap();
+        let by_file = |name: &str| results.iter().find(|r| r["file"] == name).unwrap();
+        assert_eq!(by_file("photo")["mime_type1"], "image/jpeg");
+        assert_eq!(by_file("photo")["mime_type2"], "image/jpeg");
+        assert_eq!(by_file("notes")["mime_type1"], "text/plain");
+        assert!(by_file("notes").get("mime_type2").is_none());
+    }
+
+    #[test]
+    fn test_verdict_cache_reuses_untouched_pairs() {
+        let (dir, f1, f2) = two_trees();
+        let cache = dir.path().join("verdicts.json");
+        fs::write(f1.join("a"), "same").unwrap();
+        fs::write(f2.join("a"), "same").unwrap();
+        let config = || CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            output_folder: Some(dir.path().join("out")),
+            verdict_cache: Some(cache.clone()),
+            ..Default::default()
+        };
+
+        assert_eq!(run_compare(config()).unwrap(), ExitStatus::Success);
+        assert!(cache.exists());
+
+        // Same size and mtime: the cached MATCH wins over the (now different) content.
+        let mtime = fs::metadata(f2.join("a")).unwrap().modified().unwrap();
+        fs::write(f2.join("a"), "SAME").unwrap();
+        File::options()
+            .write(true)
+            .open(f2.join("a"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        assert_eq!(run_compare(config()).unwrap(), ExitStatus::Success);
+
+        // Touching the file invalidates the entry.
+        File::options()
+            .write(true)
+            .open(f2.join("a"))
+            .unwrap()
+            .set_modified(mtime + std::time::Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(run_compare(config()).unwrap(), ExitStatus::Diff);
+    }
+
+    #[test]
+    fn test_ignore_known_diffs_by_age() {
+        let (dir, f1, f2) = two_trees();
+        let cache = dir.path().join("verdicts.json");
+        fs::write(f1.join("a"), "old").unwrap();
+        fs::write(f2.join("a"), "new").unwrap();
+        let config = |days| CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            output_folder: Some(dir.path().join("out")),
+            verdict_cache: Some(cache.clone()),
+            ignore_known_diffs: days,
+            ..Default::default()
+        };
+
+        // First sighting: the difference is fresh.
+        assert_eq!(run_compare(config(Some(7))).unwrap(), ExitStatus::Diff);
+
+        // Pretend it was first recorded ten days ago.
+        let mut saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&cache).unwrap()).unwrap();
+        let since = &mut saved["entries"]["a"]["since"]["secs_since_epoch"];
+        *since = (since.as_u64().unwrap() - 10 * 24 * 60 * 60).into();
+        fs::write(&cache, saved.to_string()).unwrap();
+
+        assert_eq!(run_compare(config(Some(30))).unwrap(), ExitStatus::Diff);
+        assert_eq!(run_compare(config(Some(7))).unwrap(), ExitStatus::Success);
+        // The age survives the rewrite of the cache.
+        assert_eq!(run_compare(config(Some(7))).unwrap(), ExitStatus::Success);
+        assert_eq!(run_compare(config(None)).unwrap(), ExitStatus::Diff);
+
+        // Touching the file starts the clock again.
+        fs::write(f2.join("a"), "newer").unwrap();
+        assert_eq!(run_compare(config(Some(7))).unwrap(), ExitStatus::Diff);
+    }
+
+    #[test]
+    fn test_presence_only_skips_content() {
+        let (dir, f1, f2) = two_trees();
+        fs::create_dir_all(f1.join("logs")).unwrap();
+        fs::create_dir_all(f2.join("logs")).unwrap();
+        fs::write(f1.join("logs/app.log"), "short").unwrap();
+        fs::write(f2.join("logs/app.log"), "much longer content").unwrap();
+        fs::write(f1.join("data.txt"), "same").unwrap();
+        fs::write(f2.join("data.txt"), "same").unwrap();
+        let config = |presence_only| CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            output_folder: Some(dir.path().join("out")),
+            presence_only,
+            // A DIFF cached before the rule was added must not override it.
+            verdict_cache: Some(dir.path().join("verdicts.json")),
+            ..Default::default()
+        };
+
+        assert_eq!(run_compare(config(None)).unwrap(), ExitStatus::Diff);
+        assert_eq!(
+            run_compare(config(Some(vec!["**/*.log".to_string()]))).unwrap(),
+            ExitStatus::Success
+        );
+    }
+
+    #[test]
+    fn test_compare_accepts_snapshot_in_place_of_folder() {
+        let dir = tempdir().unwrap();
+        let folder = dir.path().join("folder");
+        let snap = dir.path().join("before.json");
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("a.txt"), "original").unwrap();
+        create_snapshot(SnapshotConfig {
+            folder: folder.clone(),
+            output: Some(snap.clone()),
+            algo: HashAlgo::Blake3,
+            depth: None,
+            no_recursive: false,
+            hidden: false,
+            types: None,
+            ignore: None,
+            symlinks: SymlinkMode::Ignore,
+            threads: None,
+            ..Default::default()
+        })
+        .unwrap();
+        let config = |folder1, folder2, algo| CompareConfig {
+            folder1,
+            folder2,
+            algo,
+            output_folder: Some(dir.path().join("out")),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            run_compare(config(snap.clone(), folder.clone(), HashAlgo::Blake3)).unwrap(),
+            ExitStatus::Success
+        );
+        assert_eq!(
+            run_compare(config(snap.clone(), snap.clone(), HashAlgo::Blake3)).unwrap(),
+            ExitStatus::Success
+        );
+
+        // Same size, different content: only the recorded digest can tell.
+        fs::write(folder.join("a.txt"), "ORIGINAL").unwrap();
+        assert_eq!(
+            run_compare(config(folder.clone(), snap.clone(), HashAlgo::Blake3)).unwrap(),
+            ExitStatus::Diff
+        );
+        // The recorded algorithm wins unless --force-algo insists on another.
+        assert_eq!(
+            run_compare(config(folder.clone(), snap.clone(), HashAlgo::Sha256)).unwrap(),
+            ExitStatus::Diff
+        );
+        assert!(
+            run_compare(CompareConfig {
+                force_algo: true,
+                ..config(folder, snap, HashAlgo::Sha256)
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_pipelined_hashing_matches_mmap() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("big.bin");
+        // Spans several pipeline chunks and ends mid-chunk.
+        let data: Vec<u8> = (0..20 * 1024 * 1024 + 123)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        fs::write(&path, &data).unwrap();
+
+        let expected = compute_hashes(&path, HashAlgo::Both, None).unwrap();
+        let piped = compute_hashes_pipelined(&path, HashAlgo::Both, None).unwrap();
+        assert_eq!(piped.sha256, expected.sha256);
+        assert_eq!(piped.blake3, expected.blake3);
+    }
+
+    #[test]
+    fn test_precheck_counts_immediate_children() {
+        let entry = |size| FileEntry {
+            size,
+            ..Default::default()
+        };
+        let files1 = HashMap::from([
+            (PathBuf::from("a/x"), entry(1)),
+            (PathBuf::from("a/sub/y"), entry(2)),
+        ]);
+        let files2 = HashMap::from([(PathBuf::from("a/x"), entry(1))]);
+
+        let data = precheck(&files1, &files2, None, None);
+        let a = data.dirs.iter().find(|d| d.dir == Path::new("a")).unwrap();
+        assert_eq!((a.children1, a.children2), (2, 1));
+        assert_eq!((a.files1, a.files2), (1, 1));
+        assert!(a.differs());
+        let root = data.dirs.iter().find(|d| d.dir == Path::new("")).unwrap();
+        assert!(!root.differs());
+
+        // A directory whose contents line up is still listed when only its
+        // own mtime differs.
+        let touched = crate::report::DirTotals {
+            dir: PathBuf::from("b"),
+            files1: 1,
+            files2: 1,
+            mtime_differs: true,
+            ..Default::default()
+        };
+        assert!(touched.differs());
+        let text = crate::report::generate_precheck_text(&crate::report::PrecheckData {
+            dirs: vec![touched],
+        });
+        assert_eq!(text.len(), 2, "{:?}", text);
+        assert!(text[1].ends_with("files: 1 vs 1, bytes: 0 vs 0, mtime differs"));
+    }
+
+    #[test]
+    fn test_seed_is_recorded_in_run_info() {
+        let dir = tempdir().unwrap();
+        let f1 = dir.path().join("f1");
+        let f2 = dir.path().join("f2");
+        let out = dir.path().join("out");
+        fs::create_dir_all(&f1).unwrap();
+        fs::create_dir_all(&f2).unwrap();
+        fs::write(f1.join("a"), "a").unwrap();
+        fs::write(f2.join("a"), "a").unwrap();
+        let run_info = |seed| {
+            run_compare(CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                output_folder: Some(out.clone()),
+                output_format: OutputFormat::Json,
+                seed,
+                ..Default::default()
+            })
+            .unwrap();
+            let report: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap())
+                    .unwrap();
+            report["run_info"].clone()
+        };
+        assert_eq!(run_info(Some(7))["seed"], 7);
+        // Without --seed the time-based seed is recorded, so the run can be repeated.
+        assert!(run_info(None)["seed"].is_u64());
+    }
+
+    #[test]
+    fn test_sample_seed_is_reproducible_and_recorded() {
+        let sample = crate::models::SampleParams {
+            percent: 50.0,
+            seed: 42,
+        };
+        let paths: Vec<PathBuf> = (0..200).map(|i| PathBuf::from(format!("f{}", i))).collect();
+        let picked: Vec<bool> = paths.iter().map(|p| sample.selects(p)).collect();
+        let again: Vec<bool> = paths.iter().map(|p| sample.selects(p)).collect();
+        assert_eq!(picked, again);
+        let count = picked.iter().filter(|&&b| b).count();
+        assert!((50..150).contains(&count), "{} of 200 sampled", count);
+
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::write(f1.join("a"), "a").unwrap();
+        fs::write(f2.join("a"), "a").unwrap();
+        run_compare(CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Json,
+            sample: Some(10.0),
+            seed: Some(7),
+            ..Default::default()
+        })
+        .unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        assert_eq!(report["run_info"]["sample"]["seed"], 7);
+        assert_eq!(report["run_info"]["sample"]["percent"], 10.0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sync_runs_on_create_hook() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+        fs::create_dir(&src).unwrap();
+        fs::create_dir(&dst).unwrap();
+        fs::write(src.join("new file.txt"), "hello").unwrap();
+
+        run_sync(SyncConfig {
+            source: src.clone(),
+            destination: dst.clone(),
+            on_create: Some("cp {src} {dst}.hooked".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dst.join("new file.txt.hooked")).unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn test_status_line_reports_the_comparison_outcome() {
+        use crate::report::status_line;
+
+        let (_dir, f1, f2) = two_trees();
+        fs::write(f1.join("same"), "same").unwrap();
+        fs::write(f2.join("same"), "same").unwrap();
+        fs::write(f1.join("changed"), "abcdefgh").unwrap();
+        fs::write(f2.join("changed"), "abc").unwrap();
+        fs::write(f1.join("missing"), "12345").unwrap();
+        fs::write(f2.join("extra"), "x").unwrap();
+        let line = |f1: &PathBuf, f2: &PathBuf| {
+            let comparison = compare_folders(&CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                ..Default::default()
+            })
+            .unwrap();
+            status_line(&comparison.status, &comparison.summary.status_fields())
+        };
+
+        let diff = line(&f1, &f2);
+        let fields: HashMap<&str, &str> = diff
+            .split(' ')
+            .map(|field| field.split_once('=').unwrap())
+            .collect();
+        assert!(
+            diff.starts_with("status=diff total=4 matches=1 "),
+            "{}",
+            diff
+        );
+        for (key, value) in [
+            ("diffs", "1"),
+            ("missing", "1"),
+            ("extra", "1"),
+            ("errors", "0"),
+            ("bytes_to_transfer", "13"),
+        ] {
+            assert_eq!(fields[key], value, "{}", diff);
+        }
+        assert!(fields["elapsed_ms"].parse::<u128>().is_ok());
+
+        assert!(line(&f1, &f1).starts_with("status=success total=3 matches=3 "));
+    }
+
+    #[test]
+    fn test_recheck_only_revisits_reported_paths() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::write(f1.join("same"), "x").unwrap();
+        fs::write(f2.join("same"), "x").unwrap();
+        fs::write(f1.join("changed"), "old").unwrap();
+        fs::write(f2.join("changed"), "new").unwrap();
+        fs::write(f1.join("lost"), "data").unwrap();
+        let config = CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Json,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config).unwrap(), ExitStatus::Diff);
+
+        // Fix one entry; a file that differs now but was not reported stays unseen.
+        fs::write(f2.join("changed"), "old").unwrap();
+        fs::write(f2.join("same"), "y").unwrap();
+        let recheck = || CompareConfig {
+            output_folder: Some(dir.path().join("recheck")),
+            output_format: OutputFormat::Json,
+            ..Default::default()
+        };
+        let report = out.join("report.json");
+        assert_eq!(run_recheck(&report, recheck()).unwrap(), ExitStatus::Diff);
+
+        let delta: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(dir.path().join("recheck/recheck_report.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(delta["summary"]["rechecked"], 2);
+        assert_eq!(delta["summary"]["resolved"], 1);
+        assert_eq!(delta["results"][0]["file"], "changed");
+        assert_eq!(delta["results"][0]["current"], "MATCH");
+        assert_eq!(delta["results"][1]["current"], "MISSING");
+
+        fs::remove_file(f1.join("lost")).unwrap();
+        assert_eq!(
+            run_recheck(&report, recheck()).unwrap(),
+            ExitStatus::Success
+        );
+    }
+
+    #[test]
+    fn test_relocated_content_cross_matches() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::create_dir_all(f2.join("archive")).unwrap();
+        fs::write(f1.join("report.pdf"), "quarterly").unwrap();
+        fs::write(f2.join("archive/report-q3.pdf"), "quarterly").unwrap();
+        fs::write(f1.join("kept"), "same").unwrap();
+        fs::write(f2.join("kept"), "same").unwrap();
+        fs::write(f2.join("kept-copy"), "same").unwrap();
+        fs::write(f2.join("new"), "fresh").unwrap();
+        let config = CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Json,
+            relocated: true,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config).unwrap(), ExitStatus::Diff);
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        let relocated = |file: &str| {
+            report["results"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|r| r["file"] == file)
+                .unwrap()["relocated"]
+                .clone()
+        };
+        assert_eq!(
+            relocated("report.pdf"),
+            serde_json::json!(["archive/report-q3.pdf"])
+        );
+        assert_eq!(
+            relocated("archive/report-q3.pdf"),
+            serde_json::json!(["report.pdf"])
+        );
+        // Content found on the other side even though that path is not MISSING there.
+        assert_eq!(relocated("kept-copy"), serde_json::json!(["kept"]));
+        assert!(relocated("new").is_null());
+        assert_eq!(report["summary"]["relocated_content"], 3);
+    }
+
+    #[test]
+    fn test_detect_renames_pairs_missing_with_extra() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::create_dir_all(f2.join("archive")).unwrap();
+        fs::write(f1.join("a.txt"), "twin").unwrap();
+        fs::write(f1.join("b.txt"), "twin").unwrap();
+        fs::write(f1.join("c.txt"), "twin").unwrap();
+        fs::write(f2.join("archive/a.txt"), "twin").unwrap();
+        fs::write(f2.join("archive/b.txt"), "twin").unwrap();
+        fs::write(f2.join("unrelated"), "other").unwrap();
+        let config = |format| CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            output_folder: Some(out.clone()),
+            output_format: format,
+            detect_renames: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            run_compare(config(OutputFormat::Json)).unwrap(),
+            ExitStatus::Diff
+        );
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        let results: Vec<(&str, &str, &str)> = report["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| {
+                (
+                    r["file"].as_str().unwrap(),
+                    r["status"].as_str().unwrap(),
+                    r["renamed_to"].as_str().unwrap_or(""),
+                )
+            })
+            .collect();
+        // Each EXTRA file takes part in one rename at most.
+        assert_eq!(
+            results,
+            [
+                ("a.txt", "RENAMED", "archive/a.txt"),
+                ("b.txt", "RENAMED", "archive/b.txt"),
+                ("c.txt", "MISSING", ""),
+                ("unrelated", "EXTRA", ""),
+            ]
+        );
+        assert_eq!(report["summary"]["renamed"], 2);
+        assert_eq!(report["summary"]["missing_in_folder2"], 1);
+
+        run_compare(config(OutputFormat::Txt)).unwrap();
+        let text = fs::read_to_string(out.join("report.txt")).unwrap();
+        assert!(text.contains("a.txt -> archive/a.txt"));
+    }
+
+    #[test]
+    fn test_parse_size_units() {
+        assert_eq!(parse_size("1048576"), Ok(1 << 20));
+        assert_eq!(parse_size("512M"), Ok(512 << 20));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_size("64kb"), Ok(64 << 10));
+        assert!(parse_size("12X").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn test_memory_budget_holds_reservations_to_the_limit() {
+        use crate::utils::MemoryBudget;
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        let budget = MemoryBudget::new();
+        budget.set_limit(100);
+        // Bytes the threads hold between reserving and releasing them.
+        let held = AtomicU64::new(0);
+        let peak = AtomicU64::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..20 {
+                        let reservation = budget.reserve(40);
+                        let now = held.fetch_add(40, Ordering::SeqCst) + 40;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        std::thread::sleep(std::time::Duration::from_millis(1));
+                        held.fetch_sub(40, Ordering::SeqCst);
+                        drop(reservation);
+                    }
+                });
+            }
+        });
+        assert!(peak.into_inner() <= 80);
+
+        // More than the whole budget gets through alone rather than never,
+        // and holds everything else back until it is released.
+        let big = budget.reserve(500);
+        std::thread::scope(|s| {
+            let small = s.spawn(|| {
+                drop(budget.reserve(1));
+                std::time::Instant::now()
+            });
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            let released = std::time::Instant::now();
+            drop(big);
+            assert!(small.join().unwrap() >= released);
+        });
+    }
+
+    #[test]
+    fn test_dry_run_estimate_rows() {
+        use crate::report::{ReportConfig, SyncSummary, TransferEstimate, sync_summary_rows};
+
+        let estimate = TransferEstimate {
+            files_to_copy: 3,
+            bytes_to_copy: 4 * 1024 * 1024,
+            files_to_delete: 1,
+            bytes_to_delete: 10,
+            throughput: Some(2.0 * 1024.0 * 1024.0),
+        };
+        assert_eq!(estimate.duration(), Some(std::time::Duration::from_secs(2)));
+        let summary = SyncSummary {
+            estimate: Some(estimate),
+            ..Default::default()
+        };
+        let rows: Vec<(&str, String)> = sync_summary_rows(&summary, &ReportConfig::default())
+            .into_iter()
+            .map(|(label, value, _)| (label, value))
+            .collect();
+        let value = |label: &str| {
+            rows.iter()
+                .find(|(l, _)| *l == label)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(value("Files to copy"), Some("3"));
+        assert_eq!(value("Bytes to copy"), Some("4194304"));
+        assert_eq!(value("Files to delete"), Some("1"));
+        assert_eq!(value("Bytes to delete"), Some("10"));
+        assert_eq!(value("Probed read speed"), Some("2.0 MiB/s"));
+        assert_eq!(value("Estimated duration"), Some("2.0s"));
+        // Without an estimate, a real run shows none of these rows.
+        let rows = sync_summary_rows(&SyncSummary::default(), &ReportConfig::default());
+        assert!(rows.iter().all(|(label, ..)| *label != "Files to copy"));
+    }
+
+    #[test]
+    fn test_activity_path_keeps_file_name() {
+        use crate::activity::shorten;
+
+        assert_eq!(shorten("a/b.txt"), "a/b.txt");
+        let long = format!("{}/report-final.pdf", "deep/".repeat(20));
+        let short = shorten(&long);
+        assert_eq!(short.chars().count(), 48);
+        assert!(short.starts_with('…'));
+        assert!(short.ends_with("/report-final.pdf"));
+    }
+
+    #[test]
+    fn test_auto_mtime_skew_compensates_constant_offset() {
+        let (dir, f1, f2) = two_trees();
+        let base =
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let hour = std::time::Duration::from_secs(3600);
+        for (name, shift) in [("a", hour), ("b", hour), ("c", hour), ("d", hour * 2)] {
+            fs::write(f1.join(name), name).unwrap();
+            fs::write(f2.join(name), name).unwrap();
+            File::options()
+                .write(true)
+                .open(f1.join(name))
+                .unwrap()
+                .set_modified(base)
+                .unwrap();
+            File::options()
+                .write(true)
+                .open(f2.join(name))
+                .unwrap()
+                .set_modified(base + shift)
+                .unwrap();
+        }
+        let files = |p: &Path| {
+            key_by_relative_path(
+                collect_files(
+                    p,
+                    None,
+                    false,
+                    false,
+                    &None,
+                    &None,
+                    SymlinkMode::Ignore,
+                    None,
+                    false,
+                )
+                .unwrap()
+                .0,
+                p,
+            )
+            .0
+        };
+        assert_eq!(
+            crate::compare::detect_mtime_skew(&files(&f1), &files(&f2)),
+            Some(3600)
+        );
+
+        let out = dir.path().join("out");
+        let config = |auto_mtime_skew| CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            mode: Mode::Metadata,
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Json,
+            auto_mtime_skew,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config(true)).unwrap(), ExitStatus::Diff);
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        assert_eq!(report["summary"]["differences"], 1);
+        assert_eq!(report["run_info"]["mtime_skew_secs"], 3600);
+
+        run_compare(config(false)).unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        assert_eq!(report["summary"]["differences"], 4);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_root_permissions_are_compared() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::write(f1.join("a"), "same").unwrap();
+        fs::write(f2.join("a"), "same").unwrap();
+        fs::set_permissions(&f1, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(&f2, fs::Permissions::from_mode(0o700)).unwrap();
+        let config = || CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Json,
+            ..Default::default()
+        };
+
+        assert_eq!(run_compare(config()).unwrap(), ExitStatus::Diff);
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        assert_eq!(report["root"]["status"], "DIFF");
+        assert_eq!(report["root"]["differences"][0], "permissions 0755 vs 0700");
+        assert_eq!(
+            run_compare(CompareConfig {
+                no_root_check: true,
+                ..config()
+            })
+            .unwrap(),
+            ExitStatus::Success
+        );
+
+        fs::set_permissions(&f2, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(run_compare(config()).unwrap(), ExitStatus::Success);
+
+        // Unreadable root metadata is an error, not a skipped check.
+        let mut errors = Vec::new();
+        let gone = dir.path().join("gone");
+        assert!(crate::utils::read_root_attributes(&gone, &mut errors).is_none());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, gone);
+    }
+
+    #[test]
+    fn test_config_rule_allows_diff() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::write(f1.join("schema.generated"), "v1").unwrap();
+        fs::write(f2.join("schema.generated"), "v2").unwrap();
+        fs::write(f1.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(f2.join("main.rs"), "fn main() {}").unwrap();
+        let cfg = dir.path().join("cmpf.toml");
+        fs::write(
+            &cfg,
+            "[[rule]]\npattern = \"*.generated\"\nexpect = \"diff-ok\"\n",
+        )
+        .unwrap();
+        let config = CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Json,
+            status_rules: FileConfig::load(&cfg).unwrap().rules,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config).unwrap(), ExitStatus::Success);
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        assert_eq!(report["summary"]["allowed_differences"], 1);
+        assert_eq!(report["summary"]["differences"], 0);
+
+        // A main.rs change is still a real difference.
+        fs::write(f2.join("main.rs"), "fn main() { todo!() }").unwrap();
+        let config = CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(out),
+            status_rules: FileConfig::load(&cfg).unwrap().rules,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config).unwrap(), ExitStatus::Diff);
+
+        fs::write(&cfg, "[[rule]]\npattern = \"*\"\nexpect = \"whatever\"\n").unwrap();
+        assert!(FileConfig::load(&cfg).is_err());
+    }
+
+    #[test]
+    fn test_extra_as_warning_does_not_fail() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::write(f1.join("kept"), "same").unwrap();
+        fs::write(f2.join("kept"), "same").unwrap();
+        fs::write(f2.join("scratch.tmp"), "new").unwrap();
+        let cfg = dir.path().join("cmpf.toml");
+        fs::write(&cfg, "[severity]\nextra = \"warning\"\n").unwrap();
+        let config = CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Json,
+            severities: FileConfig::load(&cfg).unwrap().severity,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config).unwrap(), ExitStatus::Success);
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        assert_eq!(report["summary"]["warnings"], 1);
+        let extra = report["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|r| r["file"] == "scratch.tmp")
+            .unwrap();
+        assert_eq!(extra["severity"], "warning");
+
+        // Missing files keep their default error severity.
+        fs::write(f1.join("lost"), "gone").unwrap();
+        let config = CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(out),
+            severities: FileConfig::load(&cfg).unwrap().severity,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config).unwrap(), ExitStatus::Diff);
+    }
+
+    #[test]
+    fn test_emit_list_dir_writes_plain_lists() {
+        let (dir, f1, f2) = two_trees();
+        let lists = dir.path().join("lists");
+        fs::create_dir_all(f1.join("sub")).unwrap();
+        fs::create_dir_all(&f2).unwrap();
+        fs::write(f1.join("changed"), "old").unwrap();
+        fs::write(f2.join("changed"), "new").unwrap();
+        fs::write(f1.join("sub/lost one"), "x").unwrap();
+        fs::write(f1.join("sub/lost two"), "y").unwrap();
+        let config = CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(dir.path().join("out")),
+            emit_list_dir: Some(lists.clone()),
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config).unwrap(), ExitStatus::Diff);
+
+        assert_eq!(
+            fs::read_to_string(lists.join("diff.txt")).unwrap(),
+            "changed\n"
+        );
+        assert_eq!(
+            fs::read_to_string(lists.join("missing.txt")).unwrap(),
+            "sub/lost one\nsub/lost two\n"
+        );
+        assert_eq!(fs::read_to_string(lists.join("extra.txt")).unwrap(), "");
+    }
+
+    #[test]
+    fn test_breakdown_groups_by_extension() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::write(f1.join("app.db"), vec![1u8; 8192]).unwrap();
+        fs::write(f2.join("app.db"), vec![2u8; 8192]).unwrap();
+        fs::write(f1.join("a.log"), "one").unwrap();
+        fs::write(f2.join("b.LOG"), "three").unwrap();
+        fs::write(f1.join("same.txt"), "x").unwrap();
+        fs::write(f2.join("same.txt"), "x").unwrap();
+        let config = CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Json,
+            breakdown: true,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config).unwrap(), ExitStatus::Diff);
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        let breakdown = &report["breakdown"];
+        assert_eq!(
+            breakdown["by_extension"],
+            serde_json::json!([
+                {"extension": "db", "diff": 1, "missing": 0, "extra": 0, "bytes": 8192},
+                {"extension": "log", "diff": 0, "missing": 1, "extra": 1, "bytes": 8},
+            ])
+        );
+        assert_eq!(
+            breakdown["by_size"],
+            serde_json::json!([
+                {"range": "< 4 KiB", "count": 2, "bytes": 8},
+                {"range": "4 KiB - 1 MiB", "count": 1, "bytes": 8192},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_read_tar_side() {
+        let dir = tempdir().unwrap();
+        let data = dir.path().join("data");
+        fs::create_dir_all(data.join("sub")).unwrap();
+        fs::write(data.join("a.txt"), "alpha").unwrap();
+        fs::write(data.join("sub/b.txt"), "beta").unwrap();
+        fs::write(data.join(".hidden"), "h").unwrap();
+
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append_dir_all("data", &data).unwrap();
+        let stream = builder.into_inner().unwrap();
+
+        let config = CompareConfig {
+            strip_components: 1,
+            ..Default::default()
+        };
+        let (files, errors) = read_tar(stream.as_slice(), Path::new("-"), &config).unwrap();
+        assert!(errors.is_empty());
+        let (map, outside) = key_by_relative_path(files, Path::new("-"));
+        assert!(outside.is_empty());
+        let mut paths: Vec<_> = map.keys().cloned().collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![PathBuf::from("a.txt"), PathBuf::from("sub/b.txt")]
+        );
+        let a = &map[Path::new("a.txt")];
+        assert_eq!(a.size, 5);
+        assert_eq!(
+            a.hashes.as_ref().unwrap().blake3,
+            compute_hashes(&data.join("a.txt"), HashAlgo::Blake3, None)
+                .unwrap()
+                .blake3
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_verify_with_mapped_root() {
+        let dir = tempdir().unwrap();
+        let old = dir.path().join("old");
+        let new = dir.path().join("new");
+        let snap = dir.path().join("snap.json");
+        fs::create_dir_all(&old).unwrap();
+        fs::write(old.join("data"), "payload").unwrap();
+        std::os::unix::fs::symlink(old.join("data"), old.join("link")).unwrap();
+        create_snapshot(SnapshotConfig {
+            folder: old.clone(),
+            output: Some(snap.clone()),
+            algo: HashAlgo::Blake3,
+            symlinks: SymlinkMode::Compare,
+            ..Default::default()
+        })
+        .unwrap();
+
+        // The volume is remounted elsewhere: same tree, absolute links now point there.
+        fs::rename(&old, &new).unwrap();
+        fs::remove_file(new.join("link")).unwrap();
+        std::os::unix::fs::symlink(new.join("data"), new.join("link")).unwrap();
+
+        let verify = |map_root| {
+            verify_snapshot(VerifyConfig {
+                folder: new.clone(),
+                snapshot_path: snap.clone(),
+                map_root,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        assert_eq!(verify(RootMap::default()), ExitStatus::Diff);
+        assert_eq!(
+            verify(RootMap::new(vec![(old.clone(), new.clone())])),
+            ExitStatus::Success
+        );
+    }
+
+    #[test]
+    fn test_probe_fs_leaves_no_trace() {
+        use crate::probe::probe;
+
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("file"), "x").unwrap();
+        let caps = probe(dir.path(), false);
+        assert!(caps.written);
+        assert!(caps.case_sensitive.is_some());
+        assert!(caps.mtime_granularity.is_some());
+        let names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec![std::ffi::OsString::from("file")]);
+
+        // Read-only: one file is too little to name a granularity.
+        let caps = probe(dir.path(), true);
+        assert!(!caps.written);
+        assert_eq!(caps.mtime_granularity, None);
+    }
+
+    #[test]
+    fn test_mtime_tolerance_from_probe() {
+        use std::time::{Duration, SystemTime};
+
+        let (dir, f1, f2) = two_trees();
+        let t = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 700_000_000);
+        for (folder, t) in [(&f1, t), (&f2, t + Duration::from_millis(900))] {
+            let file = File::create(folder.join("photo.jpg")).unwrap();
+            file.set_modified(t).unwrap();
+        }
+        let config = |probe_fs| CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            mode: Mode::Metadata,
+            output_folder: Some(dir.path().join("out")),
+            probe_fs,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config(false)).unwrap(), ExitStatus::Diff);
+        // Both sides sit on the same filesystem, so nothing is tolerated.
+        assert_eq!(run_compare(config(true)).unwrap(), ExitStatus::Diff);
+    }
+
+    #[test]
+    fn test_stream_merges_sorted_walks() {
+        let (_dir, f1, f2) = two_trees();
+        fs::create_dir_all(f1.join("a/b")).unwrap();
+        fs::create_dir_all(f2.join("a/b")).unwrap();
+        fs::write(f1.join("a/b/same"), "same").unwrap();
+        fs::write(f2.join("a/b/same"), "same").unwrap();
+        fs::write(f1.join("a/changed"), "one").unwrap();
+        fs::write(f2.join("a/changed"), "two").unwrap();
+        // "a.txt" sorts after the directory "a" in a walk and as a path.
+        fs::write(f1.join("a.txt"), "only here").unwrap();
+        fs::write(f2.join("z"), "only there").unwrap();
+
+        let config = |allow_changed: bool| CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            stream: true,
+            status_rules: if allow_changed {
+                vec![StatusRule {
+                    pattern: "a/changed".to_string(),
+                    expect: Expect::DiffOk,
+                }]
+            } else {
+                Vec::new()
+            },
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config(false)).unwrap(), ExitStatus::Diff);
+
+        let json = CompareConfig {
+            output_format: OutputFormat::Json,
+            ..config(false)
+        };
+        assert!(run_compare(json).is_err());
+
+        // --max-memory compares the same way, with the same limits.
+        let budgeted = |output_format| CompareConfig {
+            stream: false,
+            max_memory: Some(64 << 20),
+            output_format,
+            ..config(false)
+        };
+        assert_eq!(
+            run_compare(budgeted(OutputFormat::Txt)).unwrap(),
+            ExitStatus::Diff
+        );
+        let err = run_compare(budgeted(OutputFormat::Json)).unwrap_err();
+        assert!(err.to_string().contains("--max-memory"), "{}", err);
+
+        fs::remove_file(f1.join("a.txt")).unwrap();
+        fs::remove_file(f2.join("z")).unwrap();
+        assert_eq!(run_compare(config(true)).unwrap(), ExitStatus::Success);
+    }
+
+    #[test]
+    fn test_permission_denied_exit_status() {
+        use crate::config::Severities;
+        use crate::models::{ErrorEntry, ErrorKind};
+        use std::io;
+
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let entry = ErrorEntry {
+            kind: ErrorKind::of(&denied),
+            ..ErrorEntry::new(PathBuf::from("secret"), denied.to_string())
+        };
+        assert!(entry.permission_denied());
+        assert_eq!(
+            ErrorKind::of(&io::Error::from(io::ErrorKind::NotFound)),
+            ErrorKind::Io
+        );
+
+        let severities = Severities::default();
+        let data = SummaryData {
+            errors: 2,
+            permission_denied: 1,
+            ..Default::default()
+        };
+        assert_eq!(data.exit_status(&severities, false), ExitStatus::Error);
+        assert_eq!(
+            data.exit_status(&severities, true),
+            ExitStatus::PermissionDenied
+        );
+        assert!(data.status_fields().contains(&("permission_denied", 1)));
+    }
+
+    #[test]
+    fn test_hash_error_keeps_message() {
+        use crate::compare::{Rules, compare_files_core};
+
+        let dir = tempdir().unwrap();
+        let present = dir.path().join("present");
+        fs::write(&present, "data").unwrap();
+        let entry = |path: PathBuf| FileEntry {
+            path,
+            size: 4,
+            ..Default::default()
+        };
+        let config = CompareConfig::default();
+        let result = compare_files_core(
+            PathBuf::from("gone"),
+            &entry(present),
+            &entry(dir.path().join("gone")),
+            &config,
+            &Rules::default(),
+        )
+        .unwrap();
+        assert_eq!(result.status, Status::Error);
+        assert!(result.error.as_deref().unwrap().starts_with("folder2: "));
+        assert!(!result.permission_denied());
+
+        let data = SummaryData {
+            hash_errors: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            data.exit_status(&Default::default(), false),
+            ExitStatus::Error
+        );
+    }
+
+    #[test]
+    fn test_error_flood_collapsed_in_text_report() {
+        use crate::models::ErrorEntry;
+        use crate::report::{ReportConfig, generate_text_report};
+
+        colored::control::set_override(false);
+        let mut errors: Vec<ErrorEntry> = (0..1243)
+            .map(|i| {
+                ErrorEntry::new(
+                    PathBuf::from(format!("/data/locked/sub{}/f{}", i % 3, i)),
+                    "Permission denied (os error 13)".to_string(),
+                )
+            })
+            .collect();
+        errors.push(ErrorEntry::new(
+            PathBuf::from("/data/other"),
+            "Input/output error (os error 5)".to_string(),
+        ));
+        // Ten alike, but spread over two directories: not a flood in either.
+        for i in 0..10 {
+            errors.push(ErrorEntry::new(
+                PathBuf::from(format!("/data/bad{}/f", i % 2)),
+                "Stale file handle (os error 116)".to_string(),
+            ));
+        }
+        let text = generate_text_report(
+            &[],
+            &errors,
+            &[],
+            &SummaryData::default(),
+            &ReportConfig::default(),
+        )
+        .unwrap();
+        for (dir, n) in [("sub0", 415), ("sub1", 414), ("sub2", 414)] {
+            assert!(
+                text.contains(&format!(
+                    "[ERROR] Permission denied (os error 13) in /data/locked/{} ({} entries) (folder1)\n",
+                    dir, n
+                )),
+                "{}",
+                text
+            );
+        }
+        assert!(text.contains("[ERROR] /data/other (folder1: Input/output error (os error 5))"));
+        assert!(text.contains("[ERROR] /data/bad1/f (folder1: Stale file handle (os error 116))"));
+        assert!(!text.contains("/data/locked/sub0/f0"));
+        assert!(!text.contains("[ERROR]  "));
+    }
+
+    #[test]
+    fn test_snapshot_double_read_stable_files() {
+        let dir = tempdir().unwrap();
+        let folder = dir.path().join("data");
+        fs::create_dir(&folder).unwrap();
+        fs::write(folder.join("small"), "content").unwrap();
+        fs::write(folder.join("large"), vec![7u8; 256 * 1024]).unwrap();
+        let snap = dir.path().join("snap.json");
+
+        let status = create_snapshot(SnapshotConfig {
+            folder: folder.clone(),
+            output: Some(snap.clone()),
+            algo: HashAlgo::Both,
+            double_read: true,
+            drop_cache: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(status, ExitStatus::Success);
+
+        let content = fs::read_to_string(&snap).unwrap();
+        assert!(!content.contains("unstable_read"));
+        let snapshot = crate::snapshot::load_snapshot(&snap).unwrap();
+        assert_eq!(snapshot.files.len(), 2);
+        assert!(snapshot.files.iter().all(|e| !e.unstable_read));
+    }
+
+    #[test]
+    fn test_live_counts_on_progress_bar() {
+        use crate::compare::LiveCounts;
+        use indicatif::ProgressBar;
+
+        colored::control::set_override(false);
+        let pb = ProgressBar::hidden();
+        let live = LiveCounts::default();
+        live.record(Status::Match, &pb);
+        assert_eq!(pb.message(), "match 1 diff 0 error 0");
+        live.record(Status::Match, &pb);
+        // Matches alone do not refresh every time.
+        assert_eq!(pb.message(), "match 1 diff 0 error 0");
+        live.record(Status::Diff, &pb);
+        assert_eq!(pb.message(), "match 2 diff 1 error 0");
+        live.record(Status::Error, &pb);
+        assert_eq!(pb.message(), "match 2 diff 1 error 1");
+    }
+
+    #[test]
+    fn test_drift_against_baseline_report() {
+        use crate::drift::drift_entries;
+        use crate::models::ComparisonResult;
+
+        let result = |path: &str, status| ComparisonResult::new(PathBuf::from(path), status);
+        let previous = vec![
+            result("same", Status::Match),
+            result("broke", Status::Match),
+            result("fixed", Status::Diff),
+            result("deleted", Status::Missing),
+        ];
+        let current = vec![
+            result("same", Status::Match),
+            result("broke", Status::Diff),
+            result("fixed", Status::Match),
+            result("added", Status::Extra),
+            result("new", Status::Match),
+        ];
+        let entries = drift_entries(previous, &current);
+        let changes: Vec<(&str, Option<Status>, Option<Status>)> = entries
+            .iter()
+            .map(|e| (e.file.to_str().unwrap(), e.previous, e.current))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("added", None, Some(Status::Extra)),
+                ("broke", Some(Status::Match), Some(Status::Diff)),
+                ("deleted", Some(Status::Missing), None),
+                ("fixed", Some(Status::Diff), Some(Status::Match)),
+                ("new", None, Some(Status::Match)),
+            ]
+        );
+        let worsened: Vec<bool> = entries.iter().map(|e| e.worsened()).collect();
+        assert_eq!(worsened, vec![true, true, false, false, false]);
+        // Only a path that used to differ counts as newly matching.
+        let improved: Vec<bool> = entries.iter().map(|e| e.improved()).collect();
+        assert_eq!(improved, vec![false, false, false, true, false]);
+    }
+
+    #[test]
+    fn test_resolve_line_diff_numbers_changed_lines() {
+        use crate::resolve::line_diff;
+
+        let changes: Vec<String> = line_diff("a\nb\nc\r\n", "a\nc\r\nd")
+            .into_iter()
+            .map(|c| c.input)
+            .collect();
+        assert_eq!(changes, ["-2: b", "+3: d"]);
+        assert!(line_diff("same\n", "same\n").is_empty());
+    }
+
+    #[test]
+    fn test_resolve_copies_chosen_side() {
+        use crate::resolve::run_resolve;
+        use std::io::Cursor;
+
+        let (_dir, f1, f2) = two_trees();
+        for (name, left, right) in [("a", "left a", "right a"), ("b", "left b", "right b")] {
+            fs::write(f1.join(name), left).unwrap();
+            fs::write(f2.join(name), right).unwrap();
+        }
+        fs::write(f1.join("c"), "left c").unwrap();
+        fs::write(f2.join("c"), "right c").unwrap();
+        fs::write(f1.join("same"), "same").unwrap();
+        fs::write(f2.join("same"), "same").unwrap();
+
+        let config = || CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            ..Default::default()
+        };
+        // An unknown answer is asked again; input ends before "c" is decided.
+        let status = run_resolve(config(), Cursor::new("?\nl\nr\n")).unwrap();
+        assert_eq!(status, ExitStatus::Diff);
+        assert_eq!(fs::read_to_string(f2.join("a")).unwrap(), "left a");
+        assert_eq!(fs::read_to_string(f1.join("b")).unwrap(), "right b");
+        assert_eq!(fs::read_to_string(f2.join("c")).unwrap(), "right c");
+
+        let status = run_resolve(config(), Cursor::new("l\n")).unwrap();
+        assert_eq!(status, ExitStatus::Success);
+        assert_eq!(run_compare(config()).unwrap(), ExitStatus::Success);
+    }
+
+    #[test]
+    fn test_profile_presets_and_overrides() {
+        use crate::profile::Profile;
+        use crate::utils::build_globset;
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cmpf.toml");
+        fs::write(
+            &path,
+            "[profile.fast]\nmode = \"batch\"\nsize_only = true\n",
+        )
+        .unwrap();
+        let file_config = FileConfig::load(&path).unwrap();
+
+        let fast = Profile::Fast.preset(&file_config.profiles);
+        assert_eq!(fast.mode, Some(Mode::Batch));
+        assert_eq!(fast.size_only, Some(true));
+        assert_eq!(fast.probe_fs, Some(true));
+        assert_eq!(
+            Profile::Code.preset(&file_config.profiles),
+            Profile::Code.builtin()
+        );
+
+        let code = build_globset(&Profile::Code.builtin().ignore)
+            .unwrap()
+            .unwrap();
+        assert!(code.is_match("crates/app/target/debug/app"));
+        assert!(code.is_match(".git/HEAD"));
+        assert!(!code.is_match("src/targets.rs"));
+
+        fs::write(&path, "[profile.fast]\ncolour = true\n").unwrap();
+        assert!(FileConfig::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_code_quality_report() {
+        let (dir, f1, f2) = two_trees();
+        fs::write(f1.join("same"), "same").unwrap();
+        fs::write(f2.join("same"), "same").unwrap();
+        fs::write(f1.join("changed"), "one").unwrap();
+        fs::write(f2.join("changed"), "two").unwrap();
+        fs::write(f1.join("gone"), "gone").unwrap();
+
+        let out = dir.path().join("out");
+        let config = CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            output_format: OutputFormat::CodeQuality,
+            output_folder: Some(out.clone()),
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config).unwrap(), ExitStatus::Diff);
+
+        let report = fs::read_to_string(out.join("gl-code-quality-report.json")).unwrap();
+        let issues: Vec<serde_json::Value> = serde_json::from_str(&report).unwrap();
+        assert_eq!(issues.len(), 2);
+        let diff = &issues[0];
+        assert_eq!(diff["check_name"], "cmpf-diff");
+        assert_eq!(diff["severity"], "major");
+        assert_eq!(
+            diff["location"]["path"],
+            f2.join("changed").to_str().unwrap()
+        );
+        assert_eq!(
+            issues[1]["location"]["path"],
+            f1.join("gone").to_str().unwrap()
+        );
+        assert_ne!(diff["fingerprint"], issues[1]["fingerprint"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_normalize_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let (dir, f1, f2) = two_trees();
+        fs::create_dir_all(f1.join("data")).unwrap();
+        fs::create_dir_all(f1.join("sub")).unwrap();
+        fs::create_dir_all(f2.join("sub")).unwrap();
+        fs::write(f1.join("data/file"), "data").unwrap();
+        // Same destination, written relative on one side and absolute on the other.
+        symlink("../data/file", f1.join("sub/link")).unwrap();
+        symlink(f1.join("data/file"), f2.join("sub/link")).unwrap();
+        fs::create_dir_all(f2.join("data")).unwrap();
+        fs::write(f2.join("data/file"), "data").unwrap();
+
+        let config = |normalize_symlinks| CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            symlinks: SymlinkMode::Compare,
+            normalize_symlinks,
+            output_folder: Some(dir.path().join("out")),
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config(false)).unwrap(), ExitStatus::Diff);
+        assert_eq!(run_compare(config(true)).unwrap(), ExitStatus::Success);
+
+        // Identical relative targets still match, though they point into different trees.
+        fs::remove_file(f2.join("sub/link")).unwrap();
+        symlink("../data/file", f2.join("sub/link")).unwrap();
+        assert_eq!(run_compare(config(true)).unwrap(), ExitStatus::Success);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_directory_against_real_directory() {
+        use std::os::unix::fs::symlink;
+
+        let (dir, f1, f2) = two_trees();
+        let target = dir.path().join("target");
+        for d in [f1.join("docs"), f2.clone(), target.clone()] {
+            fs::create_dir_all(d).unwrap();
+        }
+        fs::write(f1.join("docs/a"), "a").unwrap();
+        fs::write(f1.join("docs/b"), "b").unwrap();
+        fs::write(target.join("a"), "a").unwrap();
+        fs::write(target.join("b"), "b").unwrap();
+        symlink(&target, f2.join("docs")).unwrap();
+
+        let config = |symlinks| CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            symlinks,
+            output_folder: Some(dir.path().join("out")),
+            ..Default::default()
+        };
+
+        // Followed, the link's contents are compared file by file.
+        let followed = compare_folders(&config(SymlinkMode::Follow)).unwrap();
+        assert!(followed.results.iter().all(|r| r.status == Status::Match));
+        assert_eq!(followed.results.len(), 2);
+
+        // Compared, the path is one TYPE_MISMATCH instead of MISSING files
+        // plus an EXTRA link.
+        let compared = compare_folders(&config(SymlinkMode::Compare)).unwrap();
+        assert_eq!(compared.results.len(), 1);
+        let r = &compared.results[0];
+        assert_eq!(
+            (r.file.as_path(), r.status),
+            (Path::new("docs"), Status::TypeMismatch)
+        );
+        assert_eq!(r.symlink1, None);
+        assert_eq!(r.symlink2.as_deref(), target.to_str());
+        assert_eq!(
+            run_compare(config(SymlinkMode::Compare)).unwrap(),
+            ExitStatus::Diff
+        );
+    }
+
+    #[test]
+    fn test_compare_folders_returns_results_without_report() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::write(f1.join("same"), "x").unwrap();
+        fs::write(f2.join("same"), "x").unwrap();
+        fs::write(f1.join("changed"), "old").unwrap();
+        fs::write(f2.join("changed"), "new").unwrap();
+        fs::write(f1.join("gone"), "y").unwrap();
+        let verdicts = dir.path().join("verdicts.json");
+
+        let comparison = compare_folders(&CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(out.clone()),
+            precheck: true,
+            verdict_cache: Some(verdicts.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+        let statuses: Vec<(String, Status)> = comparison
+            .results
+            .iter()
+            .map(|r| (r.file.display().to_string(), r.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("changed".to_string(), Status::Diff),
+                ("gone".to_string(), Status::Missing),
+                ("same".to_string(), Status::Match),
+            ]
+        );
+        assert_eq!(comparison.summary.total, 3);
+        assert_eq!(comparison.status, ExitStatus::Diff);
+        assert!(!out.exists());
+        // Caches named in the configuration are not reports: they are saved.
+        assert!(verdicts.exists());
+    }
+
+    #[test]
+    fn test_prime_fills_hash_cache_used_by_compare() {
+        let (dir, f1, f2) = two_trees();
+        let cache = dir.path().join("hashes.json");
+        fs::write(f1.join("a"), "one").unwrap();
+        fs::write(f2.join("a"), "one").unwrap();
+
+        let prime = || {
+            run_prime(PrimeConfig {
+                folder: f1.clone(),
+                hash_cache: cache.clone(),
+                algo: HashAlgo::Blake3,
+                depth: None,
+                no_recursive: false,
+                hidden: false,
+                types: None,
+                ignore: None,
+                symlinks: SymlinkMode::Ignore,
+                threads: None,
+                read_ahead: None,
+                owner_filter: None,
+                one_file_system: false,
+                explain_skips: false,
+            })
+            .unwrap()
+        };
+        assert_eq!(prime(), ExitStatus::Success);
+
+        // Same size and mtime: the cached digest stands in for the new content.
+        let mtime = fs::metadata(f1.join("a")).unwrap().modified().unwrap();
+        fs::write(f1.join("a"), "two").unwrap();
+        File::options()
+            .write(true)
+            .open(f1.join("a"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        let compare = |hash_cache: Option<PathBuf>| {
+            compare_folders(&CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                hash_cache,
+                ..Default::default()
+            })
+            .unwrap()
+            .status
+        };
+        assert_eq!(compare(Some(cache.clone())), ExitStatus::Success);
+        assert_eq!(compare(None), ExitStatus::Diff);
+
+        let saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&cache).unwrap()).unwrap();
+        assert_eq!(saved["entries"].as_object().unwrap().len(), 2);
+
+        fs::remove_file(f1.join("a")).unwrap();
+        assert_eq!(prime(), ExitStatus::Success);
+        let saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&cache).unwrap()).unwrap();
+        assert_eq!(saved["entries"].as_object().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_rclone_listing_becomes_hashed_entries() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "hello").unwrap();
+        let sha256 = compute_hashes(&dir.path().join("a.txt"), HashAlgo::Sha256, None)
+            .unwrap()
+            .sha256
+            .unwrap();
+
+        let listing = format!(
+            r#"[
+                {{"Path":"docs","Name":"docs","Size":-1,"ModTime":"2024-05-01T10:00:00Z","IsDir":true}},
+                {{"Path":"docs/a.txt","Name":"a.txt","Size":5,"ModTime":"2024-05-01T10:00:00.5Z","IsDir":false,"Hashes":{{"sha256":"{}"}}}},
+                {{"Path":"docs/.hidden","Name":".hidden","Size":1,"ModTime":"2024-05-01T10:00:00Z","IsDir":false,"Hashes":{{"sha256":"00"}}}},
+                {{"Path":"docs/b.txt","Name":"b.txt","Size":3,"ModTime":"2024-05-01T10:00:00Z","IsDir":false,"Hashes":{{"md5":"00"}}}},
+                {{"Path":"sheet","Name":"sheet","Size":-1,"ModTime":"2024-05-01T10:00:00Z","IsDir":false}}
+            ]"#,
+            sha256.to_uppercase()
+        );
+        let root = Path::new("s3:bucket/data");
+        let config = CompareConfig {
+            algo: HashAlgo::Sha256,
+            ..Default::default()
+        };
+        let (files, errors) = read_listing(listing.as_bytes(), root, &config).unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, root.join("docs/a.txt"));
+        assert_eq!(files[0].size, 5);
+        assert_eq!(
+            files[0].hashes.as_ref().unwrap().sha256.as_ref(),
+            Some(&sha256)
+        );
+        assert!(files[0].modified.is_some());
+        let failed: Vec<&str> = errors.iter().map(|e| e.path.to_str().unwrap()).collect();
+        assert_eq!(failed, ["docs/b.txt", "sheet"]);
+
+        assert!(is_remote(root));
+        assert!(!is_remote(Path::new("C:/data")));
+        assert!(!is_remote(dir.path()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hash_cache_misses_a_replaced_file() {
+        let (dir, f1, f2) = two_trees();
+        let cache = dir.path().join("hashes.json");
+        fs::write(f1.join("a"), "one").unwrap();
+        fs::write(f2.join("a"), "one").unwrap();
+
+        let compare = || {
+            compare_folders(&CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                hash_cache: Some(cache.clone()),
+                ..Default::default()
+            })
+            .unwrap()
+            .status
+        };
+        assert_eq!(compare(), ExitStatus::Success);
+
+        // Swapped in by rename with the old size and mtime, as `rsync -t`
+        // does: only the inode tells the cached digest is stale.
+        let mtime = fs::metadata(f1.join("a")).unwrap().modified().unwrap();
+        let staged = dir.path().join("staged");
+        fs::write(&staged, "two").unwrap();
+        File::options()
+            .write(true)
+            .open(&staged)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        fs::rename(&staged, f1.join("a")).unwrap();
+        assert_eq!(compare(), ExitStatus::Diff);
+    }
+
+    #[test]
+    fn test_html_report_is_standalone_tree() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::create_dir_all(f1.join("docs/old")).unwrap();
+        fs::create_dir_all(f2.join("docs/old")).unwrap();
+        fs::create_dir_all(f1.join("same")).unwrap();
+        fs::create_dir_all(f2.join("same")).unwrap();
+        fs::write(f1.join("docs/old/<a&b>.txt"), "one").unwrap();
+        fs::write(f2.join("docs/old/<a&b>.txt"), "two").unwrap();
+        fs::write(f1.join("same/x"), "x").unwrap();
+        fs::write(f2.join("same/x"), "x").unwrap();
+        let config = CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Html,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config).unwrap(), ExitStatus::Diff);
+
+        let html = fs::read_to_string(out.join("report.html")).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(!html.contains("<script") && !html.contains("<link"));
+        assert!(html.contains("<td>Differences</td>"));
+        // Directories with differences start expanded, matching ones collapsed.
+        assert!(html.contains("<details open><summary>docs/"));
+        assert!(html.contains("<details><summary>same/"));
+        assert!(
+            html.contains(
+                "<li class=\"diff\"><span class=\"badge\">DIFF</span>&lt;a&amp;b&gt;.txt"
+            )
+        );
+    }
+
+    #[test]
+    fn test_csv_report_rows() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::write(f1.join("same"), "x").unwrap();
+        fs::write(f2.join("same"), "x").unwrap();
+        fs::write(f1.join("a, \"b\".txt"), "gone").unwrap();
+        let config = |format| CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            output_folder: Some(out.clone()),
+            output_format: format,
+            algo: HashAlgo::Both,
+            ..Default::default()
+        };
+        assert_eq!(
+            run_compare(config(OutputFormat::Csv)).unwrap(),
+            ExitStatus::Diff
+        );
+
+        let csv = fs::read_to_string(out.join("report.csv")).unwrap();
+        let lines: Vec<&str> = csv.split_terminator("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("file,status,size1,size2,modified1,modified2,blake3_1"));
+        assert!(lines[1].starts_with("\"a, \"\"b\"\".txt\",MISSING,4,,"));
+        let same: Vec<&str> = lines[2].split(',').collect();
+        assert_eq!(same[..4], ["same", "MATCH", "1", "1"]);
+        // Both digests of both sides are filled in under --algo both.
+        assert!(same[6..10].iter().all(|d| d.len() == 64));
+        assert_eq!(same[6], same[7]);
+
+        run_compare(config(OutputFormat::Tsv)).unwrap();
+        let tsv = fs::read_to_string(out.join("report.tsv")).unwrap();
+        assert!(tsv.contains("\"a, \"\"b\"\".txt\"\tMISSING\t4\t"));
+        assert!(tsv.contains("same\tMATCH\t1\t1\t"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sandbox_confines_later_threads() {
+        let dir = tempdir().unwrap();
+        let (root, other, out) = (
+            dir.path().join("root"),
+            dir.path().join("other"),
+            dir.path().join("out"),
+        );
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&other).unwrap();
+        fs::write(root.join("a"), "inside").unwrap();
+        fs::write(other.join("b"), "outside").unwrap();
+
+        // Landlock binds the calling thread and its children, so confine a
+        // thread of our own rather than the test harness.
+        std::thread::spawn(move || {
+            let mut sandbox = Sandbox::default();
+            sandbox.read(&root);
+            sandbox.write_dir(&out).unwrap();
+            if let Err(e) = sandbox.apply() {
+                eprintln!("skipping: {:#}", e);
+                return;
+            }
+            std::thread::spawn(move || {
+                assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "inside");
+                let denied = fs::read_to_string(other.join("b")).unwrap_err();
+                assert_eq!(denied.kind(), std::io::ErrorKind::PermissionDenied);
+                assert!(fs::write(root.join("new"), "x").is_err());
+                fs::write(out.join("report.txt"), "ok").unwrap();
+            })
+            .join()
+            .unwrap();
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_userspace_sandbox_follows_links_only_beneath_the_roots() {
+        use std::io::Read;
+        use std::os::unix::fs::symlink;
+
+        let (dir, root, report_dir) = two_trees();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a"), "a").unwrap();
+        fs::write(root.join("sub/b"), "b").unwrap();
+        let report = report_dir.join("report.json");
+        fs::write(&report, "{}").unwrap();
+        let secret = dir.path().join("secret");
+        fs::write(&secret, "secret").unwrap();
+        symlink("sub/b", root.join("relative")).unwrap();
+        symlink(
+            root.canonicalize().unwrap().join("a"),
+            root.join("absolute"),
+        )
+        .unwrap();
+        symlink("../secret", root.join("escaping")).unwrap();
+        symlink(dir.path(), root.join("parent")).unwrap();
+        symlink("loop", root.join("loop")).unwrap();
+
+        let beneath = Beneath::new("--sandbox", &[root.clone(), report.clone()]).unwrap();
+        let read = |path: PathBuf| {
+            let mut text = String::new();
+            beneath.open(&path)?.read_to_string(&mut text)?;
+            Ok::<_, std::io::Error>(text)
+        };
+        assert_eq!(read(root.join("a")).unwrap(), "a");
+        assert_eq!(read(root.join("relative")).unwrap(), "b");
+        assert_eq!(read(root.join("absolute")).unwrap(), "a");
+        assert_eq!(read(root.join("sub/../sub/b")).unwrap(), "b");
+        assert_eq!(read(report.clone()).unwrap(), "{}");
+        beneath.check(&root.join("sub")).unwrap();
+
+        for path in [
+            root.join("escaping"),
+            root.join("parent/secret"),
+            root.join("sub/../../secret"),
+            secret.clone(),
+            report_dir.join("other.json"),
+        ] {
+            let err = read(path.clone()).unwrap_err();
+            assert!(refused(&err), "{}: {}", path.display(), err);
+            assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        }
+        // Errors of the path itself are kept as they are.
+        let missing = read(root.join("missing")).unwrap_err();
+        assert_eq!(missing.kind(), std::io::ErrorKind::NotFound);
+        assert!(!refused(&read(root.join("loop")).unwrap_err()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_readonly_rules_allow_reads_and_only_output_writes() {
+        let (dir, folder, out) = two_trees();
+        fs::write(folder.join("a"), "evidence").unwrap();
+        let elsewhere = dir.path().join("elsewhere.txt");
+        fs::write(&elsewhere, "unrelated").unwrap();
+
+        std::thread::spawn(move || {
+            let mut rules = Sandbox::readonly();
+            rules.write_dir(&out).unwrap();
+            if let Err(e) = rules.apply() {
+                eprintln!("skipping: {:#}", e);
+                return;
+            }
+            assert_eq!(fs::read_to_string(folder.join("a")).unwrap(), "evidence");
+            assert_eq!(fs::read_to_string(&elsewhere).unwrap(), "unrelated");
+            assert!(fs::write(folder.join("a"), "tampered").is_err());
+            assert!(fs::write(&elsewhere, "tampered").is_err());
+            assert!(fs::remove_file(&elsewhere).is_err());
+            // Programs the run starts are bound as well.
+            let touched = std::process::Command::new("sh")
+                .args(["-c", "echo x > \"$0\""])
+                .arg(folder.join("b"))
+                .status()
+                .unwrap();
+            assert!(!touched.success());
+            fs::write(out.join("report.txt"), "ok").unwrap();
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_ndjson_report_one_object_per_line() {
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::write(f1.join("same"), "x").unwrap();
+        fs::write(f2.join("same"), "x").unwrap();
+        fs::write(f1.join("gone"), "abc").unwrap();
+        fs::write(f2.join("new"), "de").unwrap();
+        let config = CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Ndjson,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config).unwrap(), ExitStatus::Diff);
+
+        let report = fs::read_to_string(out.join("report.ndjson")).unwrap();
+        let lines: Vec<serde_json::Value> = report
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        let status = |file: &str| {
+            let line = lines.iter().find(|l| l["file"] == file).unwrap();
+            (
+                line["status"].clone(),
+                line["size1"].clone(),
+                line["size2"].clone(),
+            )
+        };
+        assert_eq!(
+            status("gone"),
+            ("MISSING".into(), 3.into(), serde_json::Value::Null)
+        );
+        assert_eq!(
+            status("new"),
+            ("EXTRA".into(), serde_json::Value::Null, 2.into())
+        );
+        assert_eq!(status("same").0, "MATCH");
+        let summary = &lines[3]["summary"];
+        assert_eq!(summary["missing_in_folder2"], 1);
+        assert_eq!(summary["extra_in_folder2"], 1);
+        assert_eq!(summary["total_files_checked"], 3);
+    }
+
+    #[test]
+    fn test_explain_skips_names_the_rule() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("keep/deep/deeper")).unwrap();
+        fs::create_dir_all(root.join("vendor")).unwrap();
+        for file in [
+            "a.txt",
+            ".hidden.txt",
+            "b.tmp",
+            "c.jpg",
+            "vendor/v.txt",
+            "keep/k.txt",
+            "keep/deep/d.txt",
+            "keep/deep/deeper/x.txt",
+        ] {
+            fs::write(root.join(file), "x").unwrap();
+        }
+        fs::write(root.join(".ignore"), "vendor/\n").unwrap();
+
+        let skips = explain_skips(
+            root,
+            Some(2),
+            false,
+            false,
+            &Some(vec!["txt".to_string(), "tmp".to_string()]),
+            &Some(vec!["*.log".to_string(), "*.tmp".to_string()]),
+            SymlinkMode::Ignore,
+            None,
+            false,
+        )
+        .unwrap();
+        let reasons: HashMap<String, String> = skips
+            .into_iter()
+            .map(|(p, r)| (p.strip_prefix(root).unwrap().display().to_string(), r))
+            .collect();
+
+        assert_eq!(reasons[".hidden.txt"], "hidden (--hidden includes it)");
+        assert_eq!(reasons["b.tmp"], "--ignore pattern #2 `*.tmp`");
+        assert_eq!(reasons["c.jpg"], "extension not in --type");
+        assert!(reasons["vendor"].starts_with("`vendor/` in "));
+        assert_eq!(reasons["keep/deep"], "contents not walked (--depth 2)");
+        // Compared files and what lies below a skipped directory are not listed.
+        for path in ["a.txt", "keep/k.txt", "vendor/v.txt", "keep/deep/d.txt"] {
+            assert!(!reasons.contains_key(path), "{}", path);
+        }
+    }
+
+    /// Enough of JSON Schema for `report_schema.json`: `$ref`, `oneOf`,
+    /// `const`, `enum`, `type`, `properties`, `required`,
+    /// `additionalProperties` and `items`.
+    fn schema_errors(
+        value: &serde_json::Value,
+        schema: &serde_json::Value,
+        root: &serde_json::Value,
+        at: &str,
+    ) -> Vec<String> {
+        use serde_json::Value;
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/$defs/");
+            return schema_errors(value, &root["$defs"][name], root, at);
+        }
+        if let Some(options) = schema["oneOf"].as_array() {
+            let matching = options
+                .iter()
+                .filter(|o| schema_errors(value, o, root, at).is_empty())
+                .count();
+            if matching == 1 {
+                return Vec::new();
+            }
+            return options
+                .iter()
+                .flat_map(|o| schema_errors(value, o, root, at))
+                .collect();
+        }
+        let mut errors = Vec::new();
+        if !schema["const"].is_null() && &schema["const"] != value {
+            errors.push(format!("{}: {} is not {}", at, value, schema["const"]));
+        }
+        if let Some(values) = schema["enum"].as_array()
+            && !values.contains(value)
+        {
+            errors.push(format!("{}: {} not in enum", at, value));
+        }
+        let kind = match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(n) if n.is_f64() => "number",
+            Value::Number(_) => "integer",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        let types: Vec<&str> = match &schema["type"] {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        let typed = types.is_empty()
+            || types.contains(&kind)
+            || (kind == "integer" && types.contains(&"number"));
+        if !typed {
+            errors.push(format!("{}: {} is not {:?}", at, kind, types));
+        }
+        if let Value::Object(map) = value {
+            for key in schema["required"].as_array().into_iter().flatten() {
+                if !map.contains_key(key.as_str().unwrap()) {
+                    errors.push(format!("{}: missing {}", at, key));
+                }
+            }
+            for (key, field) in map {
+                let path = format!("{}.{}", at, key);
+                match (
+                    schema["properties"].get(key),
+                    &schema["additionalProperties"],
+                ) {
+                    (Some(sub), _) => errors.extend(schema_errors(field, sub, root, &path)),
+                    (None, Value::Bool(false)) => errors.push(format!("{}: not in schema", path)),
+                    (None, Value::Object(_)) => errors.extend(schema_errors(
+                        field,
+                        &schema["additionalProperties"],
+                        root,
+                        &path,
+                    )),
+                    (None, _) => {}
+                }
+            }
+        }
+        if let Value::Array(items) = value {
+            for (i, item) in items.iter().enumerate() {
+                errors.extend(schema_errors(
+                    item,
+                    &schema["items"],
+                    root,
+                    &format!("{}[{}]", at, i),
+                ));
+            }
+        }
+        errors
+    }
+
+    #[test]
+    fn test_json_report_matches_its_schema() {
+        use crate::models::{
+            BlockDiff, ByteRange, ComparisonResult, ErrorEntry, ErrorKind, HashResult, RootAttrs,
+            RootCheck, SampleParams, Severity,
+        };
+        use crate::report::{
+            Breakdown, REPORT_SCHEMA, REPORT_SCHEMA_VERSION, ReportConfig, SummaryData,
+            generate_json_report,
+        };
+
+        let schema: serde_json::Value = serde_json::from_str(REPORT_SCHEMA).unwrap();
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            REPORT_SCHEMA_VERSION
+        );
+
+        // Every optional field filled in, so a renamed or new field shows up.
+        let hash = HashResult {
+            sha256: Some("ab".repeat(32)),
+            blake3: None,
+            xxh3: Some("ef".repeat(16)),
+            ..Default::default()
+        };
+        let mut results = vec![
+            ComparisonResult {
+                hash1: Some(hash.clone()),
+                hash2: Some(hash),
+                size1: Some(10),
+                size2: Some(12),
+                modified1: Some("2024-01-01 00:00:00".into()),
+                modified2: Some("2024-01-02 00:00:00".into()),
+                block_diff: Some(BlockDiff {
+                    block_size: 4,
+                    total_blocks: 3,
+                    changed_blocks: 1,
+                    changed_bytes: 4,
+                    ranges: vec![ByteRange { start: 4, end: 8 }],
+                }),
+                severity: Some(Severity::Warning),
+                mime_type1: Some("text/plain".into()),
+                mime_type2: Some("text/plain".into()),
+                ..ComparisonResult::new("a.txt".into(), Status::Diff)
+            },
+            ComparisonResult {
+                relocated: Some(vec!["moved/b.txt".into()]),
+                ..ComparisonResult::missing("b.txt".into())
+            },
+            ComparisonResult {
+                renamed_to: Some("d.txt".into()),
+                ..ComparisonResult::new("c.txt".into(), Status::Renamed)
+            },
+            ComparisonResult {
+                error: Some("folder2: Permission denied".into()),
+                error_kind: Some(ErrorKind::PermissionDenied),
+                ..ComparisonResult::new("e.txt".into(), Status::Error)
+            },
+        ];
+        results[0].symlink1 = Some("target".into());
+        let mut attrs = RootAttrs {
+            permissions: Some(0o755),
+            uid: Some(1000),
+            gid: Some(1000),
+            ..Default::default()
+        };
+        attrs.xattrs.insert("user.tag".into(), "cd".repeat(32));
+        let summary = SummaryData {
+            total: 4,
+            root: Some(RootCheck::new(attrs, RootAttrs::default())),
+            breakdown: Some(Breakdown::of(&results)),
+            resource_usage: Some(crate::resources::ResourceUsage {
+                peak_rss_bytes: Some(64 << 20),
+                read_bytes: Some(4096),
+                open_files: Some(12),
+                open_files_limit: Some(1024),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let config = ReportConfig {
+            threads: Some(2),
+            report_checksum: true,
+            seed: Some(7),
+            sample: Some(SampleParams {
+                percent: 12.5,
+                seed: 7,
+            }),
+            mtime_skew: Some(-3600),
+            mtime_tolerance: Some(std::time::Duration::from_secs(2)),
+            folders: Some(("/a".into(), "/b".into())),
+            ..Default::default()
+        };
+        let errors = [ErrorEntry::new("locked".into(), "Permission denied".into())];
+        let json = generate_json_report(&results, &errors, &[], &summary, &config).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(report["schema_version"], REPORT_SCHEMA_VERSION);
+        assert_eq!(
+            schema_errors(&report, &schema, &schema, "report"),
+            Vec::<String>::new()
+        );
+    }
+
+    /// A stored (uncompressed) zip archive of `members`, all dated `dos_time`.
+    fn stored_zip(members: &[(&str, &[u8])], dos_time: u32) -> Vec<u8> {
+        fn crc32(data: &[u8]) -> u32 {
+            let mut crc = !0u32;
+            for &b in data {
+                crc ^= u32::from(b);
+                for _ in 0..8 {
+                    crc = if crc & 1 == 1 {
+                        (crc >> 1) ^ 0xEDB8_8320
+                    } else {
+                        crc >> 1
+                    };
+                }
+            }
+            !crc
+        }
+        let (mut out, mut central) = (Vec::new(), Vec::new());
+        for (name, data) in members {
+            let (crc, len) = (crc32(data), data.len() as u32);
+            let offset = out.len() as u32;
+            let fields = |sig: u32, buf: &mut Vec<u8>, central: bool| {
+                buf.extend(sig.to_le_bytes());
+                if central {
+                    buf.extend(20u16.to_le_bytes());
+                }
+                buf.extend([20, 0, 0, 0, 0, 0]);
+                buf.extend(dos_time.to_le_bytes());
+                for v in [crc, len, len] {
+                    buf.extend(v.to_le_bytes());
+                }
+                buf.extend((name.len() as u16).to_le_bytes());
+                buf.extend([0, 0]);
+                if central {
+                    buf.extend([0; 10]);
+                    buf.extend(offset.to_le_bytes());
+                }
+                buf.extend(name.as_bytes());
+            };
+            fields(0x0403_4b50, &mut out, false);
+            out.extend(*data);
+            fields(0x0201_4b50, &mut central, true);
+        }
+        let (cd_offset, cd_len) = (out.len() as u32, central.len() as u32);
+        out.extend(&central);
+        out.extend(0x0605_4b50u32.to_le_bytes());
+        out.extend([0; 4]);
+        for _ in 0..2 {
+            out.extend((members.len() as u16).to_le_bytes());
+        }
+        out.extend(cd_len.to_le_bytes());
+        out.extend(cd_offset.to_le_bytes());
+        out.extend([0, 0]);
+        out
+    }
+
+    #[test]
+    fn test_zip_members_ignore_timestamps() {
+        use crate::ziplisting::zip_members;
+
+        let (_dir, f1, f2) = two_trees();
+        let manifest: (&str, &[u8]) = ("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0\n");
+        let class: (&str, &[u8]) = ("app/Main.class", b"\xCA\xFE\xBA\xBE");
+        // Same members, built at another time and written in another order.
+        fs::write(
+            f1.join("app.jar"),
+            stored_zip(&[manifest, class], 0x5021_0000),
+        )
+        .unwrap();
+        fs::write(
+            f2.join("app.jar"),
+            stored_zip(&[class, manifest], 0x5A85_6C20),
+        )
+        .unwrap();
+        // A member whose content changed.
+        fs::write(f1.join("lib.zip"), stored_zip(&[manifest], 0x5021_0000)).unwrap();
+        fs::write(
+            f2.join("lib.zip"),
+            stored_zip(&[(manifest.0, b"Manifest-Version: 2.0\n")], 0x5021_0000),
+        )
+        .unwrap();
+        // Not a zip archive despite its name: compared as bytes.
+        fs::write(f1.join("fake.zip"), "same").unwrap();
+        fs::write(f2.join("fake.zip"), "same").unwrap();
+
+        let members = zip_members(&f1.join("app.jar")).unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].name, b"META-INF/MANIFEST.MF");
+        assert_eq!(members[0].size, 22);
+
+        let statuses = |zip_members| {
+            compare_folders(&CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                zip_members,
+                ..Default::default()
+            })
+            .unwrap()
+            .results
+            .iter()
+            .map(|r| (r.file.display().to_string(), r.status))
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            statuses(true),
+            [
+                ("app.jar".to_string(), Status::Match),
+                ("fake.zip".to_string(), Status::Match),
+                ("lib.zip".to_string(), Status::Diff),
+            ]
+        );
+        assert_eq!(statuses(false)[0], ("app.jar".to_string(), Status::Diff));
+    }
+
+    #[test]
+    fn test_zip_members_verdicts_are_not_reused_without_it() {
+        let (dir, f1, f2) = two_trees();
+        let cache = dir.path().join("verdicts.json");
+        let manifest: (&str, &[u8]) = ("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0\n");
+        fs::write(f1.join("app.jar"), stored_zip(&[manifest], 0x5021_0000)).unwrap();
+        fs::write(f2.join("app.jar"), stored_zip(&[manifest], 0x5A85_6C20)).unwrap();
+        let compare = |zip_members| {
+            run_compare(CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                output_folder: Some(dir.path().join("out")),
+                zip_members,
+                verdict_cache: Some(cache.clone()),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        assert_eq!(compare(true), ExitStatus::Success);
+        assert_eq!(compare(false), ExitStatus::Diff);
+        assert_eq!(compare(true), ExitStatus::Success);
+    }
+
+    #[test]
+    fn test_quick_compares_only_the_ends() {
+        let (_dir, f1, f2) = two_trees();
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let mut middle = data.clone();
+        middle[5_000] ^= 1;
+        let mut tail = data.clone();
+        tail[9_999] ^= 1;
+        fs::write(f1.join("middle"), &data).unwrap();
+        fs::write(f2.join("middle"), &middle).unwrap();
+        fs::write(f1.join("tail"), &data).unwrap();
+        fs::write(f2.join("tail"), &tail).unwrap();
+        // Not longer than both ends together: hashed whole.
+        fs::write(f1.join("small"), "small").unwrap();
+        fs::write(f2.join("small"), "small").unwrap();
+
+        let comparison = compare_folders(&CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            quick: Some(1024),
+            ..Default::default()
+        })
+        .unwrap();
+        let statuses: Vec<(String, Status)> = comparison
+            .results
+            .iter()
+            .map(|r| (r.file.display().to_string(), r.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("middle".to_string(), Status::QuickMatch),
+                ("small".to_string(), Status::Match),
+                ("tail".to_string(), Status::Diff),
+            ]
+        );
+        assert!(comparison.results[0].hash1.is_none());
+        assert_eq!(comparison.summary.quick_matches, 1);
+        assert_eq!(comparison.summary.matches, 1);
+        assert_eq!(comparison.status, ExitStatus::Diff);
+        assert_eq!(
+            serde_json::to_value(Status::QuickMatch).unwrap(),
+            "MATCH(QUICK)"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resource_usage_in_summary() {
+        let (_dir, f1, f2) = two_trees();
+        fs::write(f1.join("a"), "same").unwrap();
+        fs::write(f2.join("a"), "same").unwrap();
+
+        let compare = |resource_usage| {
+            compare_folders(&CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                resource_usage,
+                ..Default::default()
+            })
+            .unwrap()
+            .summary
+        };
+        assert_eq!(compare(false).resource_usage, None);
+        let summary = compare(true);
+        let usage = summary.resource_usage.as_ref().unwrap();
+        assert!(usage.peak_rss_bytes.unwrap() > 0);
+        assert!(usage.user_cpu_secs >= 0.0);
+        if cfg!(target_os = "linux") {
+            // At least stdin, stdout and stderr.
+            assert!(usage.open_files.unwrap() >= 3);
+        }
+
+        let rows = crate::report::generate_summary_text(&summary, &Default::default()).join("\n");
+        assert!(rows.contains("Peak memory"));
+        assert!(rows.contains("User CPU time"));
+    }
+
+    #[test]
+    fn test_xxh3_digests_agree_across_readers() {
+        let dir = tempdir().unwrap();
+        let small = dir.path().join("small");
+        let large = dir.path().join("large");
+        fs::write(&small, b"hello").unwrap();
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&large, &data).unwrap();
+
+        for path in [&small, &large] {
+            let h = compute_hashes(path, HashAlgo::Xxh3, None).unwrap();
+            let digest = h.xxh3.clone().unwrap();
+            assert_eq!(digest.len(), 32);
+            assert!(h.sha256.is_none() && h.blake3.is_none());
+            let piped = compute_hashes_pipelined(path, HashAlgo::Xxh3, None).unwrap();
+            assert_eq!(piped.xxh3.as_deref(), Some(digest.as_str()));
+            let read =
+                crate::utils::hash_reader(fs::File::open(path).unwrap(), HashAlgo::Xxh3).unwrap();
+            assert!(read.matches(&h, HashAlgo::Xxh3));
+        }
+        assert_eq!(
+            compute_hashes(&small, HashAlgo::Xxh3, None)
+                .unwrap()
+                .xxh3
+                .unwrap(),
+            format!("{:032x}", xxhash_rust::xxh3::xxh3_128(b"hello"))
+        );
+
+        let f1 = dir.path().join("f1");
+        let f2 = dir.path().join("f2");
+        fs::create_dir_all(&f1).unwrap();
+        fs::create_dir_all(&f2).unwrap();
+        fs::write(f1.join("a"), "same").unwrap();
+        fs::write(f2.join("a"), "same").unwrap();
+        let config = |f2: &std::path::Path| CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.to_path_buf(),
+            algo: HashAlgo::Xxh3,
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config(&f2)).unwrap(), ExitStatus::Success);
+        fs::write(f2.join("a"), "diff").unwrap();
+        assert_eq!(run_compare(config(&f2)).unwrap(), ExitStatus::Diff);
+    }
+
+    #[test]
+    fn test_emit_manifest_reuses_comparison_digests() {
+        use crate::snapshot::{SnapshotScanParams, load_snapshot, write_manifest};
+
+        let (dir, f1, f2) = two_trees();
+        fs::write(f1.join("same"), "x").unwrap();
+        fs::write(f2.join("same"), "x").unwrap();
+        fs::write(f1.join("resized"), "abc").unwrap();
+        fs::write(f2.join("resized"), "abcd").unwrap();
+        fs::write(f1.join("gone"), "only here").unwrap();
+        fs::write(f2.join("new"), "only there").unwrap();
+        let manifest = dir.path().join("manifest.json");
+        let config = CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            emit_manifest: Some(manifest.clone()),
+            ..Default::default()
+        };
+        assert_eq!(run_compare(config).unwrap(), ExitStatus::Diff);
+
+        // Every folder1 file is listed, including those never hashed to compare.
+        let snapshot = load_snapshot(&manifest).unwrap();
+        let listed: Vec<_> = snapshot.files.iter().map(|e| e.rel_path.clone()).collect();
+        assert_eq!(
+            listed,
+            [PathBuf::from("gone"), "resized".into(), "same".into()]
+        );
+        let status = verify_snapshot(VerifyConfig {
+            folder: f1.clone(),
+            snapshot_path: manifest.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(status, ExitStatus::Success);
+
+        // Known digests are taken as they are; only the rest is read.
+        let (files, _) = collect_files(
+            &f1,
+            None,
+            false,
+            false,
+            &None,
+            &None,
+            SymlinkMode::Ignore,
+            None,
+            false,
+        )
+        .unwrap();
+        let (files, _) = key_by_relative_path(files, &f1);
+        let marker = crate::models::HashResult {
+            sha256: None,
+            blake3: Some("reused".into()),
+            ..Default::default()
+        };
+        let known = HashMap::from([(Path::new("same"), &marker)]);
+        let params = SnapshotScanParams {
+            depth: None,
+            no_recursive: false,
+            hidden: false,
+            types: None,
+            ignore: None,
+            symlinks: SymlinkMode::Ignore,
+            owner_filter: None,
+            one_file_system: false,
+        };
+        let hashed = write_manifest(
+            &manifest,
+            &f1,
+            &files,
+            &known,
+            HashAlgo::Blake3,
+            None,
+            params,
+        )
+        .unwrap();
+        assert_eq!(hashed, 2);
+        let snapshot = load_snapshot(&manifest).unwrap();
+        let same = snapshot
+            .files
+            .iter()
+            .find(|e| e.rel_path == Path::new("same"));
+        assert_eq!(same.unwrap().hashes.blake3.as_deref(), Some("reused"));
+    }
+
+    #[test]
+    fn test_legacy_digests_match_known_values() {
+        let dir = tempdir().unwrap();
+        let small = dir.path().join("abc");
+        fs::write(&small, "abc").unwrap();
+        let h = compute_hashes(&small, HashAlgo::Md5, None).unwrap();
+        assert_eq!(h.md5.as_deref(), Some("900150983cd24fb0d6963f7d28e17f72"));
+        assert!(h.sha256.is_none() && h.blake3.is_none() && h.sha1.is_none());
+        let h = compute_hashes(&small, HashAlgo::Sha1, None).unwrap();
+        assert_eq!(
+            h.sha1.as_deref(),
+            Some("a9993e364706816aba3e25717850c26c9cd0d89d")
+        );
+
+        // Large files take the mapped and pipelined paths.
+        let large = dir.path().join("large");
+        fs::write(&large, vec![7u8; 300_000]).unwrap();
+        for algo in [HashAlgo::Md5, HashAlgo::Sha1] {
+            let mapped = compute_hashes(&large, algo, None).unwrap();
+            let piped = compute_hashes_pipelined(&large, algo, None).unwrap();
+            assert!(mapped.matches(&piped, algo));
+            assert!(!mapped.matches(&compute_hashes(&small, algo, None).unwrap(), algo));
+            assert!(algo.is_legacy());
+        }
+        assert!(!HashAlgo::Blake3.is_legacy());
+    }
+
+    #[test]
+    fn test_sha512_and_blake2b_snapshots_verify() {
+        let dir = tempdir().unwrap();
+        let folder = dir.path().join("data");
+        fs::create_dir(&folder).unwrap();
+        fs::write(folder.join("abc"), "abc").unwrap();
+
+        for (algo, digest) in [
+            (
+                HashAlgo::Sha512,
+                "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                 2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+            ),
+            (
+                HashAlgo::Blake2b,
+                "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+                 7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923",
+            ),
+        ] {
+            let snap = dir.path().join(format!("{:?}.json", algo));
+            create_snapshot(SnapshotConfig {
+                folder: folder.clone(),
+                output: Some(snap.clone()),
+                algo,
+                ..Default::default()
+            })
+            .unwrap();
+            let recorded = fs::read_to_string(&snap).unwrap();
+            assert!(recorded.contains(digest), "{:?}", algo);
+
+            let verify = || {
+                verify_snapshot(VerifyConfig {
+                    folder: folder.clone(),
+                    snapshot_path: snap.clone(),
+                    ..Default::default()
+                })
+                .unwrap()
+            };
+            assert_eq!(verify(), ExitStatus::Success);
+            fs::write(folder.join("abc"), "abd").unwrap();
+            assert_eq!(verify(), ExitStatus::Diff);
+            fs::write(folder.join("abc"), "abc").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_sample_confidence_estimate() {
+        use crate::report::SampleConfidence;
+
+        let (_dir, f1, f2) = two_trees();
+        let mtime =
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        // Every fifth file changed without its size or mtime changing.
+        for i in 0..40 {
+            let name = format!("f{:02}", i);
+            fs::write(f1.join(&name), "same").unwrap();
+            fs::write(f2.join(&name), if i % 5 == 0 { "diff" } else { "same" }).unwrap();
+            for side in [&f1, &f2] {
+                File::options()
+                    .write(true)
+                    .open(side.join(&name))
+                    .unwrap()
+                    .set_modified(mtime)
+                    .unwrap();
+            }
+        }
+        // A touched file is a DIFF by metadata and says nothing about the rest.
+        fs::write(f1.join("touched"), "x").unwrap();
+        fs::write(f2.join("touched"), "y").unwrap();
+        File::options()
+            .write(true)
+            .open(f2.join("touched"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        let sample = crate::models::SampleParams {
+            percent: 50.0,
+            seed: 3,
+        };
+        let comparison = compare_folders(&CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            sample: Some(sample.percent),
+            seed: Some(sample.seed),
+            ..Default::default()
+        })
+        .unwrap();
+        let confidence = comparison.summary.sample_confidence.unwrap();
+        let picked: Vec<usize> = (0..40)
+            .filter(|i| sample.selects(Path::new(&format!("f{:02}", i))))
+            .collect();
+        assert_eq!(confidence.sampled, picked.len());
+        assert_eq!(
+            confidence.sampled_diffs,
+            picked.iter().filter(|&&i| i % 5 == 0).count()
+        );
+        assert_eq!(confidence.unverified, 40 - picked.len());
+        assert!(confidence.max_diff_rate() > 0.0 && confidence.max_diff_rate() < 1.0);
+
+        // Nothing found in 100 hashed files: the bound is close to 3 / 100.
+        let clean = SampleConfidence {
+            sampled: 100,
+            sampled_diffs: 0,
+            unverified: 900,
+        };
+        assert!((0.025..0.03).contains(&clean.max_diff_rate()));
+        assert_eq!(clean.max_undetected(), 24);
+        assert_eq!(SampleConfidence::default().max_diff_rate(), 1.0);
+
+        let rows = crate::report::generate_summary_text(&comparison.summary, &Default::default())
+            .join("\n");
+        assert!(rows.contains("Unverified matches"), "{}", rows);
+        assert!(rows.contains("Max undetected (95%)"), "{}", rows);
+
+        let without = compare_folders(&CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(without.summary.sample_confidence.is_none());
+    }
+
+    #[test]
+    fn test_float_tolerance_in_data_files() {
+        let (_dir, f1, f2) = two_trees();
+        fs::write(f1.join("ulp.csv"), "x,y\n0.1,\"a\"\n3,1e-300\n").unwrap();
+        fs::write(
+            f2.join("ulp.csv"),
+            "x,y\r\n0.10000000000000002,\"a\"\r\n3.0,1.0000000000000002e-300\r\n",
+        )
+        .unwrap();
+        fs::write(f1.join("far.tsv"), "1.0\t2.0\n").unwrap();
+        fs::write(f2.join("far.tsv"), "1.0\t2.1\n").unwrap();
+        fs::write(f1.join("label.csv"), "1,a\n").unwrap();
+        fs::write(f2.join("label.csv"), "1,b\n").unwrap();
+        fs::write(f1.join("extra_row.csv"), "1\n").unwrap();
+        fs::write(f2.join("extra_row.csv"), "1\n2\n").unwrap();
+        // Other extensions are compared byte for byte.
+        fs::write(f1.join("notes.txt"), "1.0").unwrap();
+        fs::write(f2.join("notes.txt"), "1.00").unwrap();
+
+        let compare = |float_tolerance| {
+            compare_folders(&CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                float_tolerance,
+                ..Default::default()
+            })
+            .unwrap()
+            .results
+            .into_iter()
+            .map(|r| (r.file.display().to_string(), r.status))
+            .collect::<Vec<_>>()
+        };
+        let with = compare(Some(1e-9));
+        assert_eq!(
+            with,
+            [
+                ("extra_row.csv".to_string(), Status::Diff),
+                ("far.tsv".to_string(), Status::Diff),
+                ("label.csv".to_string(), Status::Diff),
+                ("notes.txt".to_string(), Status::Diff),
+                ("ulp.csv".to_string(), Status::Match),
+            ]
+        );
+        assert!(compare(None).iter().all(|(_, s)| *s == Status::Diff));
+        assert!(!crate::tabular::within(100.0, 100.0 + 1e-6, 1e-9));
+        assert!(crate::tabular::within(0.0, 0.0, 0.0));
+
+        let err = compare_folders(&CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            float_tolerance: Some(-1.0),
+            ..Default::default()
+        })
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("--float-tolerance"), "{}", err);
+    }
+
+    #[test]
+    fn test_verdict_cache_is_dropped_when_options_change() {
+        let (dir, f1, f2) = two_trees();
+        let cache = dir.path().join("verdicts.json");
+        fs::write(f1.join("x.csv"), "0.1\n").unwrap();
+        fs::write(f2.join("x.csv"), "0.10000000000000002\n").unwrap();
+        let compare = |float_tolerance, verdict_cache| {
+            run_compare(CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                output_folder: Some(dir.path().join("out")),
+                float_tolerance,
+                verdict_cache,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        assert_eq!(
+            compare(Some(1e-9), Some(cache.clone())),
+            ExitStatus::Success
+        );
+        // The MATCH was reached under the tolerance and must not outlive it.
+        assert_eq!(compare(None, Some(cache.clone())), ExitStatus::Diff);
+        assert_eq!(compare(None, None), ExitStatus::Diff);
+    }
+
+    #[test]
+    fn test_bytes_mode_reports_first_difference() {
+        let (_dir, f1, f2) = two_trees();
+        // Past the first read chunk, so the offset carries across chunks.
+        let data: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+        let mut changed = data.clone();
+        changed[2_500_000] ^= 0xff;
+        fs::write(f1.join("big"), &data).unwrap();
+        fs::write(f2.join("big"), &changed).unwrap();
+        fs::write(f1.join("same"), &data).unwrap();
+        fs::write(f2.join("same"), &data).unwrap();
+        fs::write(f1.join("empty"), "").unwrap();
+        fs::write(f2.join("empty"), "").unwrap();
+
+        let comparison = compare_folders(&CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            mode: Mode::Bytes,
+            ..Default::default()
+        })
+        .unwrap();
+        let results: Vec<(String, Status, Option<u64>)> = comparison
+            .results
+            .iter()
+            .map(|r| (r.file.display().to_string(), r.status, r.first_diff))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("big".to_string(), Status::Diff, Some(2_500_000)),
+                ("empty".to_string(), Status::Match, None),
+                ("same".to_string(), Status::Match, None),
+            ]
+        );
+        assert!(comparison.results.iter().all(|r| r.hash1.is_none()));
+        let text = comparison.results[0]
+            .format_text(false, HashAlgo::Blake3)
+            .unwrap();
+        assert!(text.contains("byte 2500000"), "{}", text);
+
+        // A file that is a prefix of the other differs where the shorter ends.
+        fs::write(f1.join("prefix"), "abc").unwrap();
+        fs::write(f2.join("prefix"), "abcd").unwrap();
+        assert_eq!(
+            crate::utils::first_difference(&f1.join("prefix"), &f2.join("prefix")).unwrap(),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_verbose_embeds_unified_diff_for_text() {
+        let (_dir, f1, f2) = two_trees();
+        let lines1: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        let lines2 = lines1.replace("line 10\n", "line ten\n");
+        fs::write(f1.join("notes.txt"), &lines1).unwrap();
+        fs::write(f2.join("notes.txt"), &lines2).unwrap();
+        fs::write(f1.join("blob.bin"), b"\0\x01\x02").unwrap();
+        fs::write(f2.join("blob.bin"), b"\0\x01\x03").unwrap();
+        fs::write(f1.join("large.txt"), "a\n".repeat(150)).unwrap();
+        fs::write(f2.join("large.txt"), "b\n".repeat(150)).unwrap();
+
+        let compare = |verbose| {
+            compare_folders(&CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                verbose,
+                diff_context: 1,
+                diff_max_size: 200,
+                ..Default::default()
+            })
+            .unwrap()
+            .results
+        };
+        let results = compare(true);
+        let diff_of = |name: &str| {
+            results
+                .iter()
+                .find(|r| r.file == Path::new(name))
+                .unwrap()
+                .unified_diff
+                .clone()
+        };
+        assert_eq!(
+            diff_of("notes.txt").unwrap(),
+            "--- folder1/notes.txt\n+++ folder2/notes.txt\n\
+             @@ -9,3 +9,3 @@\n line 9\n-line 10\n+line ten\n line 11\n"
+        );
+        assert_eq!(diff_of("blob.bin"), None);
+        // Over --diff-max-size.
+        assert_eq!(diff_of("large.txt"), None);
+        assert!(compare(false).iter().all(|r| r.unified_diff.is_none()));
+
+        let notes = results
+            .iter()
+            .find(|r| r.file == Path::new("notes.txt"))
+            .unwrap();
+        let text = notes.format_text(true, HashAlgo::Blake3).unwrap();
+        assert!(text.contains("+line ten"), "{}", text);
+    }
+
+    #[test]
+    fn test_porcelain_report_lines() {
+        use crate::models::ComparisonResult;
+        use crate::report::porcelain_line;
+
+        let renamed = ComparisonResult {
+            renamed_to: Some(PathBuf::from("new\tname")),
+            ..ComparisonResult::new(PathBuf::from("old\\name\n"), Status::Renamed)
+        };
+        assert_eq!(
+            porcelain_line(&renamed),
+            "RENAMED\told\\\\name\\n\tnew\\tname\n"
+        );
+
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        fs::write(f1.join("a"), "same").unwrap();
+        fs::write(f2.join("a"), "same").unwrap();
+        fs::write(f1.join("b"), "one").unwrap();
+        fs::write(f2.join("b"), "two").unwrap();
+        fs::write(f1.join("c"), "gone").unwrap();
+        fs::write(f2.join("d"), "new").unwrap();
+        let status = run_compare(CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Porcelain,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(status, ExitStatus::Diff);
+        assert_eq!(
+            fs::read_to_string(out.join("report.porcelain")).unwrap(),
+            "MATCH\ta\nDIFF\tb\nMISSING\tc\nEXTRA\td\n"
+        );
+    }
+
+    #[test]
+    fn test_read_fallback_note() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("mapped");
+        // Above the size read in one go, so the file is memory-mapped.
+        fs::write(&path, vec![7u8; 64 * 1024]).unwrap();
+        let (hashes, note) =
+            crate::utils::compute_hashes_noting(&path, HashAlgo::Blake3, None).unwrap();
+        assert_eq!(note, None);
+        assert_eq!(
+            hashes.blake3,
+            compute_hashes(&path, HashAlgo::Blake3, None)
+                .unwrap()
+                .blake3
+        );
+
+        let result = crate::models::ComparisonResult {
+            read_fallback: Some(
+                "folder2: memory mapping failed (No such device); read with plain reads".into(),
+            ),
+            ..crate::models::ComparisonResult::new(PathBuf::from("mapped"), Status::Match)
+        };
+        let verbose = result.format_text(true, HashAlgo::Blake3).unwrap();
+        assert!(
+            verbose.contains("folder2: memory mapping failed"),
+            "{}",
+            verbose
+        );
+        let quiet = result.format_text(false, HashAlgo::Blake3).unwrap();
+        assert!(!quiet.contains("memory mapping"), "{}", quiet);
+        let json = serde_json::to_value(&result).unwrap();
+        assert!(json["read_fallback"].is_string());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_batch_diff_review_prompts_per_difference() {
+        let (_dir, f1, f2) = two_trees();
+        for name in ["a", "b", "c"] {
+            fs::write(f1.join(name), "left").unwrap();
+            fs::write(f2.join(name), "right").unwrap();
+        }
+        // `cp` as the diff tool makes each opened pair visible afterwards.
+        let config = CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            diff_cmd: Some("cp".to_string()),
+            ..Default::default()
+        };
+        let comparison = compare_folders(&config).unwrap();
+        let input = std::io::Cursor::new("y\nwhat\nn\nq\n");
+        let opened = crate::compare::review_diffs(&comparison.results, &config, input).unwrap();
+        assert_eq!(opened, 1);
+        assert_eq!(fs::read_to_string(f2.join("a")).unwrap(), "left");
+        assert_eq!(fs::read_to_string(f2.join("b")).unwrap(), "right");
+        assert_eq!(fs::read_to_string(f2.join("c")).unwrap(), "right");
+
+        // Enter opens; end of input stops.
+        let comparison = compare_folders(&config).unwrap();
+        let input = std::io::Cursor::new("\n");
+        let opened = crate::compare::review_diffs(&comparison.results, &config, input).unwrap();
+        assert_eq!(opened, 1);
+        assert_eq!(fs::read_to_string(f2.join("b")).unwrap(), "left");
+        assert_eq!(fs::read_to_string(f2.join("c")).unwrap(), "right");
+    }
+
+    #[test]
+    fn test_json_results_page() {
+        use crate::report::ResultsPage;
+
+        let (dir, f1, f2) = two_trees();
+        let out = dir.path().join("out");
+        for i in 0..5 {
+            fs::write(f1.join(format!("d{}", i)), "left").unwrap();
+            fs::write(f2.join(format!("d{}", i)), "right").unwrap();
+        }
+        fs::write(f1.join("m"), "same").unwrap();
+        fs::write(f2.join("m"), "same").unwrap();
+        fs::write(f1.join("gone"), "x").unwrap();
+
+        let page = ResultsPage {
+            statuses: vec![
+                crate::utils::parse_status("diff").unwrap(),
+                crate::utils::parse_status("Missing").unwrap(),
+            ],
+            offset: 1,
+            limit: Some(3),
+        };
+        run_compare(CompareConfig {
+            folder1: f1.clone(),
+            folder2: f2.clone(),
+            output_folder: Some(out.clone()),
+            output_format: OutputFormat::Json,
+            results_page: page.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+        let files: Vec<&str> = report["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["file"].as_str().unwrap())
+            .collect();
+        // Sorted: d0..d4, gone, m; MATCH is filtered out and d0 skipped.
+        assert_eq!(files, ["d1", "d2", "d3"]);
+        assert_eq!(report["run_info"]["results_page"]["matching"], 6);
+        assert_eq!(report["run_info"]["results_page"]["statuses"][1], "MISSING");
+        assert_eq!(report["summary"]["total_files_checked"], 7);
+        assert!(crate::utils::parse_status("sideways").is_err());
+
+        let err = run_compare(CompareConfig {
+            folder1: f1,
+            folder2: f2,
+            results_page: page,
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("json"), "{}", err);
+    }
+
+    #[test]
+    fn test_change_log_groups_by_directory() {
+        use crate::models::ComparisonResult;
+        use crate::report::generate_change_log;
+
+        let mut results = vec![
+            ComparisonResult::new(PathBuf::from("same"), Status::Match),
+            ComparisonResult::extra(PathBuf::from("docs/new.md")),
+            ComparisonResult::missing(PathBuf::from("docs/old.md")),
+            ComparisonResult::missing(PathBuf::from("gone")),
+            ComparisonResult::new(PathBuf::from("docs/guide.md"), Status::Diff),
+        ];
+        results[1].size2 = Some(1500);
+        results[2].size1 = Some(20);
+        results[3].size1 = Some(7);
+        results[4].size1 = Some(100);
+        results[4].size2 = Some(120);
+        results.sort_by(|a, b| a.file.cmp(&b.file));
+
+        colored::control::set_override(false);
+        let log = generate_change_log(&results, &[]);
+        assert_eq!(
+            log,
+            ". (1 removed)\n  - gone  (7 bytes)\n\n\
+             docs/ (1 added, 1 removed, 1 modified)\n  ~ guide.md  (100 -> 120 bytes)\n  \
+             + new.md  (1,500 bytes)\n  - old.md  (20 bytes)\n\n\
+             Added: 1 file, 1,500 bytes\nRemoved: 2 files, 27 bytes\nModified: 1 file, 100 -> 120 bytes\n"
+        );
+        assert_eq!(
+            generate_change_log(&results[4..], &[]),
+            "No changes since the snapshot.\n"
+        );
+    }
+
+    #[test]
+    fn test_replicas_report_consensus() {
+        let dir = tempdir().unwrap();
+        let replicas: Vec<PathBuf> = ["r1", "r2", "r3"]
+            .iter()
+            .map(|r| dir.path().join(r))
+            .collect();
+        for r in &replicas {
+            fs::create_dir(r).unwrap();
+            fs::write(r.join("same"), "same").unwrap();
+        }
+        fs::write(replicas[0].join("drifted"), "good").unwrap();
+        fs::write(replicas[1].join("drifted"), "good").unwrap();
+        fs::write(replicas[2].join("drifted"), "gone").unwrap();
+        fs::write(replicas[0].join("split"), "1").unwrap();
+        fs::write(replicas[1].join("split"), "22").unwrap();
+        fs::write(replicas[2].join("split"), "333").unwrap();
+        fs::write(replicas[1].join("stray"), "stray").unwrap();
+
+        let out = dir.path().join("out");
+        let status = run_compare(CompareConfig {
+            folder1: replicas[0].clone(),
+            folder2: replicas[1].clone(),
+            replicas: vec![replicas[2].clone()],
+            output_format: OutputFormat::Json,
+            output_folder: Some(out.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(status, ExitStatus::Diff);
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("replica_report.json")).unwrap())
+                .unwrap();
+        let by_file = |file: &str| {
+            report["results"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|r| r["file"] == file)
+                .unwrap()
+                .clone()
+        };
+        // "drifted" has the same size everywhere, so only the digests tell.
+        let drifted = by_file("drifted");
+        assert_eq!(drifted["status"], "DIVERGE");
+        assert_eq!(drifted["versions"], serde_json::json!(["A", "A", "B"]));
+        assert_eq!(drifted["consensus"], "A");
+        assert_eq!(drifted["diverging"], serde_json::json!([3]));
+        let split = by_file("split");
+        assert_eq!(split["consensus"], serde_json::Value::Null);
+        assert_eq!(split["diverging"], serde_json::json!([1, 2, 3]));
+        let stray = by_file("stray");
+        assert_eq!(stray["versions"], serde_json::json!(["-", "A", "-"]));
+        assert_eq!(stray["consensus"], "-");
+        assert_eq!(by_file("same")["status"], "AGREE");
+        assert_eq!(report["summary"]["no_consensus"], 1);
+        assert_eq!(report["summary"]["replicas"], 3);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_vss_needs_windows() {
+        let dir = tempdir().unwrap();
+        let err = run_compare(CompareConfig {
+            folder1: dir.path().to_path_buf(),
+            folder2: dir.path().to_path_buf(),
+            vss: true,
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("needs Windows"), "{}", err);
+    }
+
+    #[test]
+    fn test_mount_of_longest_prefix() {
+        use crate::fssnapshot::{Mount, mount_of};
+
+        let mountinfo = "\
+22 1 253:0 / / rw,relatime shared:1 - ext4 /dev/mapper/vg0-root rw
+35 22 0:31 / /data rw,relatime shared:2 - btrfs /dev/sdb1 rw,subvol=/
+36 35 0:31 /backups /data/my\\040backups rw shared:3 - btrfs /dev/sdb1 rw,subvol=/backups
+";
+        assert_eq!(
+            mount_of(Path::new("/data/my backups/2024"), mountinfo),
+            Some(Mount {
+                mount_point: PathBuf::from("/data/my backups"),
+                fs_type: "btrfs".to_string(),
+                source: "/dev/sdb1".to_string(),
+            })
+        );
+        assert_eq!(
+            mount_of(Path::new("/database"), mountinfo).unwrap().source,
+            "/dev/mapper/vg0-root"
+        );
+    }
+
+    #[test]
+    fn test_file_changed_while_read_is_unstable() {
+        use crate::compare::{Rules, compare_files_core};
+
+        let dir = tempdir().unwrap();
+        let (path1, path2) = (dir.path().join("a"), dir.path().join("b"));
+        fs::write(&path1, "data").unwrap();
+        fs::write(&path2, "data").unwrap();
+        let entry = |path: &Path| FileEntry {
+            path: path.to_path_buf(),
+            size: 4,
+            modified: fs::metadata(path).unwrap().modified().ok(),
+            ..Default::default()
+        };
+        let (entry1, entry2) = (entry(&path1), entry(&path2));
+        let config = CompareConfig::default();
+        let compare = || {
+            compare_files_core(
+                PathBuf::from("a"),
+                &entry1,
+                &entry2,
+                &config,
+                &Rules::default(),
+            )
+            .unwrap()
+        };
+        assert_eq!(compare().status, Status::Match);
+
+        // Written to after the walk saw it: the same size, a later mtime.
+        fs::write(&path2, "DATA").unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(5);
+        fs::File::options()
+            .write(true)
+            .open(&path2)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(compare().status, Status::Unstable);
+
+        let data = SummaryData {
+            unstable: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            data.exit_status(&Default::default(), false),
+            ExitStatus::Error
+        );
+    }
+
+    #[test]
+    fn test_archives_recurse_matches_unpacked_folder() {
+        use crate::models::ArchiveMode;
+
+        let (_dir, f1, f2) = two_trees();
+        fs::create_dir_all(f1.join("docs/sub")).unwrap();
+        fs::create_dir_all(&f2).unwrap();
+        fs::write(f1.join("docs/a.txt"), "alpha").unwrap();
+        fs::write(f1.join("docs/sub/b.txt"), "beta").unwrap();
+
+        // docs.zip holds a.txt and sub.tar, which holds b.txt.
+        let mut builder = tar::Builder::new(Vec::new());
+        builder
+            .append_path_with_name(f1.join("docs/sub/b.txt"), "b.txt")
+            .unwrap();
+        let tar = builder.into_inner().unwrap();
+        fs::write(
+            f2.join("docs.zip"),
+            stored_zip(&[("a.txt", b"alpha"), ("sub.tar", &tar)], 0x5021_0000),
+        )
+        .unwrap();
+
+        let statuses = |archives, archive_depth| {
+            let mut results: Vec<_> = compare_folders(&CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                archives,
+                archive_depth,
+                ..Default::default()
+            })
+            .unwrap()
+            .results
+            .iter()
+            .map(|r| (r.file.display().to_string(), r.status))
+            .collect();
+            results.sort_by(|a, b| a.0.cmp(&b.0));
+            results
+        };
+        assert_eq!(
+            statuses(ArchiveMode::Recurse, 2),
+            [
+                ("docs/a.txt".to_string(), Status::Match),
+                ("docs/sub/b.txt".to_string(), Status::Match),
+            ]
+        );
+        // One level: the nested tar is compared as a file.
+        assert_eq!(
+            statuses(ArchiveMode::Recurse, 1),
+            [
+                ("docs/a.txt".to_string(), Status::Match),
+                ("docs/sub.tar".to_string(), Status::Extra),
+                ("docs/sub/b.txt".to_string(), Status::Missing),
+            ]
+        );
+        assert_eq!(
+            statuses(ArchiveMode::File, 1)[0],
+            ("docs.zip".to_string(), Status::Extra)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_subtree_rules_apply_their_own_options() {
+        use crate::config::FileConfig;
+        use std::os::unix::fs::PermissionsExt;
+
+        let (_dir, f1, f2) = two_trees();
+        for side in [&f1, &f2] {
+            fs::create_dir_all(side.join("photos")).unwrap();
+            fs::create_dir_all(side.join("finance")).unwrap();
+        }
+        // Same size and mtime, different content: only hashing tells them apart.
+        let mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        for (side, content) in [(&f1, "aaaa"), (&f2, "bbbb")] {
+            fs::write(side.join("photos/a.jpg"), content).unwrap();
+            File::options()
+                .write(true)
+                .open(side.join("photos/a.jpg"))
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+        }
+        // Same content, different permissions.
+        for (side, mode) in [(&f1, 0o600), (&f2, 0o644)] {
+            fs::write(side.join("finance/q1.csv"), "1,2").unwrap();
+            fs::set_permissions(
+                side.join("finance/q1.csv"),
+                fs::Permissions::from_mode(mode),
+            )
+            .unwrap();
+        }
+
+        let file_config: FileConfig = toml::from_str(
+            r#"
+            [[subtree]]
+            pattern = "photos/**"
+            mode = "metadata"
+
+            [[subtree]]
+            pattern = "finance/**"
+            algo = "sha256"
+            permissions = true
+            "#,
+        )
+        .unwrap();
+        let statuses = |subtrees| {
+            compare_folders(&CompareConfig {
+                folder1: f1.clone(),
+                folder2: f2.clone(),
+                subtrees,
+                ..Default::default()
+            })
+            .unwrap()
+            .results
+            .iter()
+            .map(|r| (r.file.display().to_string(), r.status))
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            statuses(file_config.subtrees),
+            [
+                ("finance/q1.csv".to_string(), Status::Diff),
+                ("photos/a.jpg".to_string(), Status::Match),
+            ]
+        );
+        assert_eq!(
+            statuses(Vec::new()),
+            [
+                ("finance/q1.csv".to_string(), Status::Match),
+                ("photos/a.jpg".to_string(), Status::Diff),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ssh_folder_and_sum_lines() {
+        use crate::ssh::{SshFolder, parse_sum_line, ssh_folder};
+
+        assert_eq!(
+            ssh_folder(Path::new("backup@nas.local:/srv/data")),
+            Some(SshFolder {
+                host: "backup@nas.local".to_string(),
+                path: "/srv/data".to_string(),
+            })
+        );
+        // rclone remotes and Windows drives have no user part.
+        assert_eq!(ssh_folder(Path::new("gdrive:photos")), None);
+        assert_eq!(ssh_folder(Path::new("C:/data")), None);
+        assert_eq!(ssh_folder(Path::new("./a@b:c/d")), None);
+
+        assert_eq!(
+            parse_sum_line("AB12  dir/file name.txt"),
+            Some(("ab12".to_string(), "dir/file name.txt".to_string()))
+        );
+        assert_eq!(
+            parse_sum_line("ab12 *bin.dat"),
+            Some(("ab12".to_string(), "bin.dat".to_string()))
+        );
+        assert_eq!(
+            parse_sum_line("\\ab12  a\\\\b\\nc"),
+            Some(("ab12".to_string(), "a\\b\nc".to_string()))
+        );
+        assert_eq!(parse_sum_line("garbage"), None);
+    }
+
+    #[test]
+    fn test_s3_listing_uses_single_part_etags() {
+        use crate::s3::{is_s3, parse_listing, s3_location};
+
+        let root = Path::new("s3://backups/data");
+        let location = s3_location(root).unwrap();
+        assert_eq!(location.bucket, "backups");
+        assert_eq!(location.prefix, "data/");
+        assert!(is_s3(root));
+        assert!(!is_remote(root));
+        assert!(!is_s3(Path::new("s3:backups/data")));
+
+        let listing = r#"{"Contents": [
+            {"Key": "data/", "Size": 0, "ETag": "\"d41d8cd98f00b204e9800998ecf8427e\""},
+            {"Key": "data/a.txt", "Size": 5, "LastModified": "2024-05-01T10:00:00.000Z",
+             "ETag": "\"2C1743A391305FBF367DF8E4F069F9F9\""},
+            {"Key": "data/big.iso", "Size": 9000000, "ETag": "\"9b2cf535f27731c974343645a3985328-2\""},
+            {"Key": "data/.cache/x", "Size": 1, "ETag": "\"0cc175b9c0f1b6a831c399e269772661\""}
+        ]}"#;
+        let config = |algo| CompareConfig {
+            algo,
+            ..Default::default()
+        };
+        let entries =
+            parse_listing(listing.as_bytes(), &location, root, &config(HashAlgo::Md5)).unwrap();
+        let keys: Vec<&str> = entries.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["data/a.txt", "data/big.iso"]);
+        let (_, a) = &entries[0];
+        assert_eq!(a.path, root.join("a.txt"));
+        assert!(a.modified.is_some());
+        assert_eq!(
+            a.hashes.as_ref().unwrap().md5.as_deref(),
+            Some("2c1743a391305fbf367df8e4f069f9f9")
+        );
+        // A multipart ETag is no MD5 of the content; the object is downloaded.
+        assert!(entries[1].1.hashes.is_none());
+
+        let entries = parse_listing(
+            listing.as_bytes(),
+            &location,
+            root,
+            &config(HashAlgo::Sha256),
+        )
+        .unwrap();
+        assert!(entries.iter().all(|(_, e)| e.hashes.is_none()));
+    }
+
+    #[test]
+    fn test_previous_report_counts_show_as_deltas() {
+        use crate::report::{
+            PreviousSummary, generate_summary_text, load_previous_summary, ndjson_summary_line,
+        };
+
+        let dir = tempdir().unwrap();
+        let json = dir.path().join("report.json");
+        fs::write(
+            &json,
+            r#"{"summary": {"total_files_checked": 20, "differences": 5, "errors": 2}, "results": []}"#,
+        )
+        .unwrap();
+        let ndjson = dir.path().join("report.ndjson");
+        fs::write(
+            &ndjson,
+            "{\"path\": \"a\", \"status\": \"DIFF\"}\n{\"summary\": {\"differences\": 5, \"errors\": 2, \"total_files_checked\": 20}}\n",
+        )
+        .unwrap();
+        let previous = load_previous_summary(&json).unwrap();
+        assert_eq!(previous, load_previous_summary(&ndjson).unwrap());
+        assert_eq!(
+            previous,
+            PreviousSummary {
+                total_files_checked: 20,
+                differences: 5,
+                errors: 2,
+                ..Default::default()
+            }
+        );
+        let not_a_report = dir.path().join("notes.txt");
+        fs::write(&not_a_report, "hello").unwrap();
+        assert!(load_previous_summary(&not_a_report).is_err());
+
+        let summary = SummaryData {
+            total: 20,
+            diffs: 14,
+            previous: Some(previous),
+            ..Default::default()
+        };
+        let rows = generate_summary_text(&summary, &Default::default()).join("\n");
+        assert!(rows.contains("14 (+9 vs last run)"));
+        assert!(rows.contains("20 (+0 vs last run)"));
+        // Errors are gone since the last run; the row stays to say so.
+        assert!(rows.contains("0 (-2 vs last run)"));
+        assert!(!rows.contains("Hash errors"));
+
+        let line: serde_json::Value =
+            serde_json::from_str(&ndjson_summary_line(&summary).unwrap()).unwrap();
+        assert_eq!(line["summary"]["previous_run"]["differences"], 5);
+    }
+
+    #[test]
+    fn test_negotiate_algo_picks_what_snapshots_record() {
+        use crate::compare::negotiate_algo;
+        use crate::snapshot::load_snapshot;
+        use std::sync::Arc;
+
+        let dir = tempdir().unwrap();
+        let folder = dir.path().join("f");
+        fs::create_dir(&folder).unwrap();
+        fs::write(folder.join("a.txt"), "hello").unwrap();
+        let snapshot = |name: &str, algo| {
+            let output = dir.path().join(name);
+            create_snapshot(SnapshotConfig {
+                folder: folder.clone(),
+                output: Some(output.clone()),
+                algo,
+                ..Default::default()
+            })
+            .unwrap();
+            output
+        };
+        let both = snapshot("both.json", HashAlgo::Both);
+        let sha = snapshot("sha.json", HashAlgo::Sha256);
+        let md5 = snapshot("md5.json", HashAlgo::Md5);
+        let negotiated = |folder1: &PathBuf, folder2: &PathBuf, algo, force_algo| {
+            let mut config = CompareConfig {
+                folder1: folder1.clone(),
+                folder2: folder2.clone(),
+                algo,
+                force_algo,
+                ..Default::default()
+            };
+            let snapshots = [folder1, folder2]
+                .into_iter()
+                .filter(|p| p.extension().is_some())
+                .map(|p| (p.clone(), Arc::new(load_snapshot(p).unwrap())))
+                .collect();
+            negotiate_algo(&mut config, &snapshots).map(|_| config.algo)
+        };
+
+        // A `both` snapshot holds sha256 and blake3 digests.
+        assert_eq!(
+            negotiated(&both, &folder, HashAlgo::Sha256, true).unwrap(),
+            HashAlgo::Sha256
+        );
+        assert_eq!(
+            negotiated(&both, &folder, HashAlgo::Md5, false).unwrap(),
+            HashAlgo::Both
+        );
+        assert!(negotiated(&both, &folder, HashAlgo::Md5, true).is_err());
+        assert_eq!(
+            negotiated(&folder, &sha, HashAlgo::Blake3, false).unwrap(),
+            HashAlgo::Sha256
+        );
+        assert_eq!(
+            negotiated(&both, &sha, HashAlgo::Blake3, false).unwrap(),
+            HashAlgo::Sha256
+        );
+        assert!(negotiated(&md5, &sha, HashAlgo::Md5, false).is_err());
+        // Metadata mode reads no digests and keeps --algo.
+        let snapshots = [&md5, &sha]
+            .into_iter()
+            .map(|p| (p.clone(), Arc::new(load_snapshot(p).unwrap())))
+            .collect();
+        let mut config = CompareConfig {
+            folder1: md5,
+            folder2: sha,
+            mode: Mode::Metadata,
+            ..Default::default()
+        };
+        negotiate_algo(&mut config, &snapshots).unwrap();
+        assert_eq!(config.algo, HashAlgo::Blake3);
+    }
+
+    #[test]
+    fn test_cleanup_runs_its_teardown_once() {
+        use crate::cleanup::Cleanup;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counted = |runs: &Arc<AtomicUsize>| {
+            let runs = Arc::clone(runs);
+            Cleanup::register(move || {
+                runs.fetch_add(1, Ordering::Relaxed);
+            })
+        };
+        let first = counted(&runs);
+        let second = counted(&runs);
+        drop(second);
+        assert_eq!(runs.load(Ordering::Relaxed), 1);
+        drop(first);
+        assert_eq!(runs.load(Ordering::Relaxed), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_s3_objects_are_downloaded_only_when_compared() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A stand-in for the AWS CLI: listings come from `<bucket>.json`,
+        // objects from `objects/<bucket>/<key>`, and each download is logged.
+        let dir = tempdir().unwrap();
+        let bin = dir.path().join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(
+            bin.join("aws"),
+            "#!/bin/sh\n\
+             dir=$(dirname \"$0\")\n\
+             case \"$1\" in\n\
+             s3api) cat \"$dir/$4.json\" ;;\n\
+             s3) echo \"$4\" >> \"$dir/downloads\"; cat \"$dir/objects/${4#s3://}\" ;;\n\
+             esac\n",
+        )
+        .unwrap();
+        fs::set_permissions(bin.join("aws"), fs::Permissions::from_mode(0o755)).unwrap();
+        let objects = [
+            ("left", "same.txt", "same", "e1"),
+            ("right", "same.txt", "same", "e1"),
+            ("left", "changed.txt", "old", "e2"),
+            ("right", "changed.txt", "new", "e3"),
+            ("left", "resized.txt", "short", "e4"),
+            ("right", "resized.txt", "longer", "e5"),
+            ("left", "gone.txt", "gone", "e6"),
+        ];
+        for bucket in ["left", "right"] {
+            let contents: Vec<String> = objects
+                .iter()
+                .filter(|(b, ..)| *b == bucket)
+                .map(|(_, key, body, etag)| {
+                    fs::create_dir_all(bin.join("objects").join(bucket)).unwrap();
+                    fs::write(bin.join("objects").join(bucket).join(key), body).unwrap();
+                    format!(
+                        r#"{{"Key": "{}", "Size": {}, "ETag": "\"{}\""}}"#,
+                        key,
+                        body.len(),
+                        etag
+                    )
+                })
+                .collect();
+            fs::write(
+                bin.join(format!("{}.json", bucket)),
+                format!(r#"{{"Contents": [{}]}}"#, contents.join(",")),
+            )
+            .unwrap();
+        }
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let mut paths = vec![bin.clone()];
+        paths.extend(std::env::split_paths(&path));
+        // SAFETY: only adds a directory in front; other tests' commands still resolve.
+        unsafe { std::env::set_var("PATH", std::env::join_paths(paths).unwrap()) };
+
+        let compare = |trust_etags| {
+            let _ = fs::remove_file(bin.join("downloads"));
+            let comparison = compare_folders(&CompareConfig {
+                folder1: PathBuf::from("s3://left"),
+                folder2: PathBuf::from("s3://right"),
+                algo: HashAlgo::Sha256,
+                trust_etags,
+                ..Default::default()
+            })
+            .unwrap();
+            let statuses: Vec<(String, Status)> = comparison
+                .results
+                .iter()
+                .map(|r| (r.file.display().to_string(), r.status))
+                .collect();
+            assert_eq!(
+                statuses,
+                [
+                    ("changed.txt".to_string(), Status::Diff),
+                    ("gone.txt".to_string(), Status::Missing),
+                    ("resized.txt".to_string(), Status::Diff),
+                    ("same.txt".to_string(), Status::Match),
+                ]
+            );
+            let mut downloads: Vec<String> = fs::read_to_string(bin.join("downloads"))
+                .unwrap()
+                .lines()
+                .map(str::to_string)
+                .collect();
+            downloads.sort();
+            downloads
+        };
+        // Missing objects and objects of another size are never downloaded.
+        assert_eq!(
+            compare(false),
+            [
+                "s3://left/changed.txt",
+                "s3://left/same.txt",
+                "s3://right/changed.txt",
+                "s3://right/same.txt"
+            ]
+        );
+        assert_eq!(
+            compare(true),
+            ["s3://left/changed.txt", "s3://right/changed.txt"]
+        );
+    }
 }
@@ -1,24 +1,68 @@
 use anyhow::Result;
-use globset::{Glob, GlobSetBuilder};
+use blake2::Blake2b512;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use ignore::WalkBuilder;
+use md5::Md5;
 use memmap2::Mmap;
-use sha2::{Digest, Sha256};
-use std::collections::HashSet;
+use rayon::iter::{Either, IntoParallelIterator, ParallelIterator};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Write as FmtWrite;
 use std::fs::{self, File};
-use std::io;
-use std::path::{Path, PathBuf};
-use std::sync::mpsc;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, mpsc};
+use xxhash_rust::xxh3::Xxh3;
 
-use crate::models::{ErrorEntry, FileEntry, HashAlgo, HashResult, SymlinkMode};
+use crate::activity;
+use crate::models::{
+    BlockDiff, ByteRange, ErrorEntry, ErrorKind, FileEntry, HashAlgo, HashResult, RootAttrs,
+    Status, SymlinkMode,
+};
+use crate::s3::is_s3;
+use crate::sandbox;
 
-pub fn compute_hashes(path: &Path, algo: HashAlgo) -> io::Result<HashResult> {
-    let metadata = fs::metadata(path)?;
+/// Digests of the file at `path`. With a `read_ahead` depth (`--read-ahead`),
+/// every file past the small-file path is streamed through that many buffers.
+pub fn compute_hashes(
+    path: &Path,
+    algo: HashAlgo,
+    read_ahead: Option<usize>,
+) -> io::Result<HashResult> {
+    compute_hashes_noting(path, algo, read_ahead).map(|(hashes, _)| hashes)
+}
+
+/// [`compute_hashes`], also telling why a memory-mapped read was given up for
+/// plain reads, if it was: the file could not be mapped, or it changed while
+/// mapped. Only an error of the plain reads is returned.
+pub fn compute_hashes_noting(
+    path: &Path,
+    algo: HashAlgo,
+    read_ahead: Option<usize>,
+) -> io::Result<(HashResult, Option<String>)> {
+    let file = sandbox::open(path)?;
+    let metadata = file.metadata()?;
     let len = metadata.len();
 
     const MMAP_THRESHOLD: u64 = 32 * 1024;
     const RAYON_THRESHOLD: u64 = 128 * 1024 * 1024;
 
+    // SHA-256 cannot be split across cores, so for large files the best we can
+    // do is keep the disk busy while it runs. With --read-ahead every file past
+    // the small-file path is streamed, which suits slow network mounts.
+    // Under --max-memory nothing is mapped: mapped pages count towards RSS and
+    // cannot be bounded, while the pipeline's buffers can.
+    if len >= MMAP_THRESHOLD
+        && (read_ahead.is_some()
+            || max_memory().is_some()
+            || (len > RAYON_THRESHOLD && matches!(algo, HashAlgo::Sha256 | HashAlgo::Both)))
+    {
+        return Ok((compute_hashes_pipelined(path, algo, read_ahead)?, None));
+    }
+
+    let activity = activity::track(path, len);
     let mut sha256_hasher = if matches!(algo, HashAlgo::Sha256 | HashAlgo::Both) {
         Some(Sha256::new())
     } else {
@@ -29,36 +73,82 @@ pub fn compute_hashes(path: &Path, algo: HashAlgo) -> io::Result<HashResult> {
     } else {
         None
     };
+    let mut solo_hasher = SoloHasher::new(algo);
 
     if len == 0 {
-        return Ok(HashResult {
+        let hashes = HashResult {
             // Fix #3: pre-allocate a 64-char buffer instead of one String-per-byte
             sha256: sha256_hasher.map(|h| bytes_to_hex(&h.finalize())),
             blake3: blake3_hasher.map(|h| h.finalize().to_hex().to_string()),
-        });
+            ..SoloHasher::finish(solo_hasher)
+        };
+        return Ok((hashes, None));
     }
 
     if len < MMAP_THRESHOLD {
-        let data = fs::read(path)?;
+        let mut data = Vec::with_capacity(len as usize);
+        (&file).read_to_end(&mut data)?;
         if let Some(h) = sha256_hasher.as_mut() {
             h.update(&data);
         }
         if let Some(bh) = blake3_hasher.as_mut() {
             bh.update(&data);
         }
+        if let Some(xh) = solo_hasher.as_mut() {
+            xh.update(&data);
+        }
     } else {
-        let f = File::open(path)?;
-        let mmap = unsafe { Mmap::map(&f)? };
-
-        if let Some(h) = sha256_hasher.as_mut() {
-            h.update(&mmap);
+        let f = file;
+        // A mapped file truncated by another client raises SIGBUS, which
+        // cannot be recovered from, so network mounts are always read.
+        if on_network_fs(&f) {
+            drop(activity);
+            return Ok((compute_hashes_pipelined(path, algo, read_ahead)?, None));
         }
-        if let Some(bh) = blake3_hasher.as_mut() {
-            if len > RAYON_THRESHOLD {
-                bh.update_rayon(&mmap);
-            } else {
-                bh.update(&mmap);
+        let mmap = match unsafe { Mmap::map(&f) } {
+            Ok(mmap) => mmap,
+            Err(e) => {
+                drop(activity);
+                let note = format!("memory mapping failed ({}); read with plain reads", e);
+                return Ok((
+                    compute_hashes_pipelined(path, algo, read_ahead)?,
+                    Some(note),
+                ));
             }
+        };
+
+        // Hashing in slices only matters when --thread-activity shows progress.
+        let slice = if activity.is_shown() {
+            PIPELINE_CHUNK
+        } else {
+            mmap.len()
+        };
+        for part in mmap.chunks(slice) {
+            if let Some(h) = sha256_hasher.as_mut() {
+                h.update(part);
+            }
+            if let Some(bh) = blake3_hasher.as_mut() {
+                if len > RAYON_THRESHOLD {
+                    bh.update_rayon(part);
+                } else {
+                    bh.update(part);
+                }
+            }
+            if let Some(xh) = solo_hasher.as_mut() {
+                xh.update(part);
+            }
+            activity.advance(part.len() as u64);
+        }
+
+        // Pages of a file rewritten while mapped may mix old and new content.
+        let now = f.metadata()?;
+        if now.len() != len || now.modified().ok() != metadata.modified().ok() {
+            drop(activity);
+            let note = "changed while memory-mapped; read again with plain reads".to_string();
+            return Ok((
+                compute_hashes_pipelined(path, algo, read_ahead)?,
+                Some(note),
+            ));
         }
     }
 
@@ -66,7 +156,500 @@ pub fn compute_hashes(path: &Path, algo: HashAlgo) -> io::Result<HashResult> {
     let sha256 = sha256_hasher.map(|h| bytes_to_hex(&h.finalize()));
     let blake3 = blake3_hasher.map(|h| h.finalize().to_hex().to_string());
 
-    Ok(HashResult { sha256, blake3 })
+    let hashes = HashResult {
+        sha256,
+        blake3,
+        ..SoloHasher::finish(solo_hasher)
+    };
+    Ok((hashes, None))
+}
+
+/// Whether `file` lives on NFS, SMB/CIFS or FUSE (sshfs, rclone mount), where
+/// another client can truncate it under a mapping.
+#[cfg(target_os = "linux")]
+fn on_network_fs(file: &File) -> bool {
+    use std::os::fd::AsRawFd;
+    const NETWORK_MAGICS: [u32; 5] = [
+        0x6969,      // NFS
+        0x517b,      // SMB
+        0xff53_4d42, // CIFS
+        0xfe53_4d42, // SMB2
+        0x6573_5546, // FUSE
+    ];
+    // SAFETY: fstatfs only writes into the zeroed struct passed to it.
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::fstatfs(file.as_raw_fd(), &mut stat) } == 0;
+    ok && NETWORK_MAGICS.contains(&(stat.f_type as u32))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn on_network_fs(_file: &File) -> bool {
+    false
+}
+
+/// The digests only ever computed on their own, never with `--algo both`.
+enum SoloHasher {
+    Xxh3(Box<Xxh3>),
+    Sha512(Sha512),
+    Blake2b(Blake2b512),
+    Md5(Md5),
+    Sha1(Sha1),
+}
+
+impl SoloHasher {
+    fn new(algo: HashAlgo) -> Option<Self> {
+        match algo {
+            HashAlgo::Xxh3 => Some(Self::Xxh3(Box::default())),
+            HashAlgo::Sha512 => Some(Self::Sha512(Sha512::new())),
+            HashAlgo::Blake2b => Some(Self::Blake2b(Blake2b512::new())),
+            HashAlgo::Md5 => Some(Self::Md5(Md5::new())),
+            HashAlgo::Sha1 => Some(Self::Sha1(Sha1::new())),
+            HashAlgo::Sha256 | HashAlgo::Blake3 | HashAlgo::Both => None,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Xxh3(h) => h.update(data),
+            Self::Sha512(h) => h.update(data),
+            Self::Blake2b(h) => h.update(data),
+            Self::Md5(h) => h.update(data),
+            Self::Sha1(h) => h.update(data),
+        }
+    }
+
+    /// The digest of `hasher`, if any, in its field of an otherwise empty result.
+    fn finish(hasher: Option<Self>) -> HashResult {
+        match hasher {
+            // The 128-bit digest as 32 hex digits.
+            Some(Self::Xxh3(h)) => HashResult {
+                xxh3: Some(format!("{:032x}", h.digest128())),
+                ..Default::default()
+            },
+            Some(Self::Sha512(h)) => HashResult {
+                sha512: Some(bytes_to_hex(&h.finalize())),
+                ..Default::default()
+            },
+            Some(Self::Blake2b(h)) => HashResult {
+                blake2b: Some(bytes_to_hex(&h.finalize())),
+                ..Default::default()
+            },
+            Some(Self::Md5(h)) => HashResult {
+                md5: Some(bytes_to_hex(&h.finalize())),
+                ..Default::default()
+            },
+            Some(Self::Sha1(h)) => HashResult {
+                sha1: Some(bytes_to_hex(&h.finalize())),
+                ..Default::default()
+            },
+            None => HashResult::default(),
+        }
+    }
+}
+
+/// Size of each buffer handed from the reader thread to the hasher.
+const PIPELINE_CHUNK: usize = 8 * 1024 * 1024;
+/// Number of filled buffers the reader may run ahead of the hasher, unless
+/// overridden with `--read-ahead`.
+const PIPELINE_DEPTH: usize = 4;
+
+/// Budget of the hashing buffers, set by `--max-memory`.
+static MAX_MEMORY: MemoryBudget = MemoryBudget::new();
+
+/// Cap the memory held by hashing buffers across all threads. Set once from `main`.
+pub fn set_max_memory(bytes: u64) {
+    MAX_MEMORY.set_limit(bytes);
+}
+
+fn max_memory() -> Option<u64> {
+    MAX_MEMORY.limit()
+}
+
+/// A byte limit shared by threads that reserve part of it before allocating.
+pub(crate) struct MemoryBudget {
+    /// 0 when unlimited.
+    limit: AtomicU64,
+    /// Bytes currently reserved, and a wakeup for threads waiting on them.
+    in_use: Mutex<u64>,
+    freed: Condvar,
+}
+
+impl MemoryBudget {
+    pub(crate) const fn new() -> Self {
+        MemoryBudget {
+            limit: AtomicU64::new(0),
+            in_use: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn set_limit(&self, bytes: u64) {
+        self.limit.store(bytes.max(1), Ordering::Relaxed);
+    }
+
+    fn limit(&self) -> Option<u64> {
+        match self.limit.load(Ordering::Relaxed) {
+            0 => None,
+            n => Some(n),
+        }
+    }
+
+    /// Block until `bytes` fit in the budget. A request larger than the whole
+    /// budget is let through once nothing else is held, so it cannot starve.
+    pub(crate) fn reserve(&self, bytes: u64) -> MemoryReservation<'_> {
+        let Some(limit) = self.limit() else {
+            return MemoryReservation {
+                budget: self,
+                bytes: 0,
+            };
+        };
+        let mut used = self.in_use.lock().unwrap_or_else(|e| e.into_inner());
+        while *used > 0 && *used + bytes > limit {
+            used = self.freed.wait(used).unwrap_or_else(|e| e.into_inner());
+        }
+        *used += bytes;
+        MemoryReservation {
+            budget: self,
+            bytes,
+        }
+    }
+}
+
+/// Share of a [`MemoryBudget`], returned when dropped.
+pub(crate) struct MemoryReservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl Drop for MemoryReservation<'_> {
+    fn drop(&mut self) {
+        if self.bytes > 0 {
+            *self.budget.in_use.lock().unwrap_or_else(|e| e.into_inner()) -= self.bytes;
+            self.budget.freed.notify_all();
+        }
+    }
+}
+
+/// Resolve `--owner-filter`: a numeric uid, or a user name looked up in the
+/// system's user database.
+#[cfg(unix)]
+pub fn parse_owner(s: &str) -> std::result::Result<u32, String> {
+    use std::ffi::CString;
+
+    if let Ok(uid) = s.parse::<u32>() {
+        return Ok(uid);
+    }
+    let name = CString::new(s).map_err(|_| format!("invalid user name {:?}", s))?;
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        // SAFETY: `passwd` is plain data the call fills in; its string fields
+        // point into `buf`, which outlives every use of them.
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        let rc = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                &mut pwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut found,
+            )
+        };
+        match rc {
+            libc::ERANGE => buf.resize(buf.len() * 2, 0),
+            0 if !found.is_null() => return Ok(pwd.pw_uid),
+            0 => return Err(format!("no such user {:?}", s)),
+            errno => {
+                return Err(format!(
+                    "cannot look up user {:?}: {}",
+                    s,
+                    io::Error::from_raw_os_error(errno)
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn parse_owner(_s: &str) -> std::result::Result<u32, String> {
+    Err("file owners are only available on Unix".to_string())
+}
+
+/// Parse a result status the way reports print it, ignoring case and taking
+/// `-` for `_`: `diff`, `MISSING`, `type-mismatch`, `match(quick)`.
+pub fn parse_status(s: &str) -> std::result::Result<Status, String> {
+    let name = s.trim().to_ascii_uppercase().replace('-', "_");
+    serde_json::from_value(serde_json::Value::String(name))
+        .map_err(|_| format!("unknown status '{}': use e.g. DIFF, MISSING or EXTRA", s))
+}
+
+/// Parse a byte size such as `512M`, `2GiB` or `1048576` (binary multiples).
+pub fn parse_size(s: &str) -> std::result::Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, unit) = s.split_at(split);
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size '{}': expected e.g. 512M or 2G", s))?;
+    let shift = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
+    {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(format!("unknown size unit in '{}': use K, M, G or T", s)),
+    };
+    value
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("size '{}' is too large", s))
+}
+
+/// Parse an `OLD=NEW` pair of `--map-root`. Both sides must be absolute.
+pub fn parse_root_mapping(s: &str) -> std::result::Result<(PathBuf, PathBuf), String> {
+    let (old, new) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid mapping '{}': expected OLD=NEW", s))?;
+    let (old, new) = (PathBuf::from(old), PathBuf::from(new));
+    if !old.is_absolute() || !new.is_absolute() {
+        return Err(format!(
+            "invalid mapping '{}': both paths must be absolute",
+            s
+        ));
+    }
+    Ok((old, new))
+}
+
+/// Where the symlink at `link` with target `target` points, as an absolute
+/// path: relative targets are taken from the link's directory, and the result
+/// is canonicalized when it exists. Dangling targets are only normalized
+/// lexically (`.` and `..` removed).
+pub fn resolve_link_target(link: &Path, target: &str) -> PathBuf {
+    let joined = link.parent().unwrap_or(Path::new("")).join(target);
+    if let Ok(canonical) = fs::canonicalize(&joined) {
+        return canonical;
+    }
+    let absolute = std::path::absolute(&joined).unwrap_or(joined);
+    let mut normalized = PathBuf::new();
+    for c in absolute.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+/// Hash a file with a reader thread filling buffers ahead of the hasher, so
+/// reads overlap with hashing instead of alternating with it. With
+/// `HashAlgo::Both` the two digests of each chunk are computed in parallel.
+pub(crate) fn compute_hashes_pipelined(
+    path: &Path,
+    algo: HashAlgo,
+    read_ahead: Option<usize>,
+) -> io::Result<HashResult> {
+    let mut file = sandbox::open(path)?;
+    let activity = activity::track(path, file.metadata()?.len());
+    let mut sha256_hasher = matches!(algo, HashAlgo::Sha256 | HashAlgo::Both).then(Sha256::new);
+    let mut blake3_hasher =
+        matches!(algo, HashAlgo::Blake3 | HashAlgo::Both).then(blake3::Hasher::new);
+    let mut solo_hasher = SoloHasher::new(algo);
+
+    let mut depth = read_ahead.map_or(PIPELINE_DEPTH, |depth| depth.max(1));
+    // Buffers in flight: the queue, one being filled and one being hashed.
+    let budgeted = max_memory().inspect(|&budget| {
+        let fit = (budget / PIPELINE_CHUNK as u64).saturating_sub(2) as usize;
+        depth = depth.min(fit.max(1));
+    });
+    let _reservation = MAX_MEMORY.reserve(((depth + 2) * PIPELINE_CHUNK) as u64);
+    let (full_tx, full_rx) = mpsc::sync_channel::<io::Result<Vec<u8>>>(depth);
+    let (empty_tx, empty_rx) = mpsc::channel::<Vec<u8>>();
+
+    std::thread::scope(|s| {
+        s.spawn(move || {
+            loop {
+                // Reuse buffers the hasher is done with instead of allocating.
+                let mut buf = empty_rx.try_recv().unwrap_or_default();
+                buf.resize(PIPELINE_CHUNK, 0);
+                let chunk = read_chunk(&mut file, &mut buf).map(|n| {
+                    buf.truncate(n);
+                    buf
+                });
+                let done = !matches!(&chunk, Ok(b) if !b.is_empty());
+                // A closed channel means the hasher bailed out early.
+                if full_tx.send(chunk).is_err() || done {
+                    break;
+                }
+            }
+        });
+
+        for chunk in full_rx {
+            let chunk = chunk?;
+            if chunk.is_empty() {
+                break;
+            }
+            if let Some(xh) = solo_hasher.as_mut() {
+                xh.update(&chunk);
+            }
+            match (sha256_hasher.as_mut(), blake3_hasher.as_mut()) {
+                // Holding a reservation, this thread must not wait inside Rayon: it could
+                // pick up another file's hash there and block on the budget it holds itself.
+                (Some(h), Some(bh)) if budgeted.is_some() => {
+                    h.update(&chunk);
+                    bh.update(&chunk);
+                }
+                (Some(h), Some(bh)) => {
+                    rayon::join(|| h.update(&chunk), || bh.update_rayon(&chunk));
+                }
+                (Some(h), None) => h.update(&chunk),
+                (None, Some(bh)) if budgeted.is_some() => {
+                    bh.update(&chunk);
+                }
+                (None, Some(bh)) => {
+                    bh.update_rayon(&chunk);
+                }
+                (None, None) => {}
+            }
+            activity.advance(chunk.len() as u64);
+            let _ = empty_tx.send(chunk);
+        }
+        Ok::<(), io::Error>(())
+    })?;
+
+    Ok(HashResult {
+        sha256: sha256_hasher.map(|h| bytes_to_hex(&h.finalize())),
+        blake3: blake3_hasher.map(|h| h.finalize().to_hex().to_string()),
+        ..SoloHasher::finish(solo_hasher)
+    })
+}
+
+/// Digest of everything `reader` yields, for content that only exists as a
+/// stream (a member of a tar archive read from stdin).
+pub fn hash_reader(mut reader: impl Read, algo: HashAlgo) -> io::Result<HashResult> {
+    let mut sha256_hasher = matches!(algo, HashAlgo::Sha256 | HashAlgo::Both).then(Sha256::new);
+    let mut blake3_hasher =
+        matches!(algo, HashAlgo::Blake3 | HashAlgo::Both).then(blake3::Hasher::new);
+    let mut solo_hasher = SoloHasher::new(algo);
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(h) = sha256_hasher.as_mut() {
+            h.update(&buf[..n]);
+        }
+        if let Some(bh) = blake3_hasher.as_mut() {
+            bh.update(&buf[..n]);
+        }
+        if let Some(xh) = solo_hasher.as_mut() {
+            xh.update(&buf[..n]);
+        }
+    }
+    Ok(HashResult {
+        sha256: sha256_hasher.map(|h| bytes_to_hex(&h.finalize())),
+        blake3: blake3_hasher.map(|h| h.finalize().to_hex().to_string()),
+        ..SoloHasher::finish(solo_hasher)
+    })
+}
+
+/// Digest of the first and last `n` bytes of a file of `size` bytes, for
+/// `--quick`. Only meaningful for files longer than `2 * n`; shorter ones
+/// should be hashed whole.
+pub fn quick_hashes(path: &Path, size: u64, n: u64, algo: HashAlgo) -> io::Result<HashResult> {
+    let head = sandbox::open(path)?.take(n);
+    let mut tail = sandbox::open(path)?;
+    tail.seek(SeekFrom::Start(size.saturating_sub(n)))?;
+    hash_reader(head.chain(tail.take(n)), algo)
+}
+
+/// Unified diff of two text files, headed `folder1/<rel_path>` and
+/// `folder2/<rel_path>`. `None` when either file cannot be read or is not
+/// text (not UTF-8, or holding a NUL byte), or when the lines are the same.
+pub fn unified_diff(path1: &Path, path2: &Path, rel_path: &Path, context: usize) -> Option<String> {
+    let text = |path: &Path| {
+        let mut data = Vec::new();
+        sandbox::open(path).ok()?.read_to_end(&mut data).ok()?;
+        String::from_utf8(data).ok().filter(|s| !s.contains('\0'))
+    };
+    let (text1, text2) = (text(path1)?, text(path2)?);
+    let diff = similar::TextDiff::from_lines(&text1, &text2);
+    let unified = diff
+        .unified_diff()
+        .context_radius(context)
+        .header(
+            &format!("folder1/{}", rel_path.display()),
+            &format!("folder2/{}", rel_path.display()),
+        )
+        .to_string();
+    (!diff
+        .ops()
+        .iter()
+        .all(|op| op.tag() == similar::DiffTag::Equal))
+    .then_some(unified)
+}
+
+/// Chunk read from each side at a time by `first_difference`.
+const BYTES_CHUNK: usize = 1024 * 1024;
+
+/// Offset of the first byte at which two files differ, or `None` when they
+/// are identical (`--mode bytes`). Both files are read side by side, each
+/// chunk of the two in parallel, and reading stops at the first mismatch. A
+/// file that is a prefix of the other differs at the shorter one's length.
+pub fn first_difference(path1: &Path, path2: &Path) -> io::Result<Option<u64>> {
+    let (mut file1, mut file2) = (sandbox::open(path1)?, sandbox::open(path2)?);
+    let (mut buf1, mut buf2) = (vec![0u8; BYTES_CHUNK], vec![0u8; BYTES_CHUNK]);
+    let mut offset = 0u64;
+    loop {
+        let (n1, n2) = rayon::join(
+            || read_chunk(&mut file1, &mut buf1),
+            || read_chunk(&mut file2, &mut buf2),
+        );
+        let (n1, n2) = (n1?, n2?);
+        let common = n1.min(n2);
+        if let Some(i) = buf1[..common]
+            .iter()
+            .zip(&buf2[..common])
+            .position(|(a, b)| a != b)
+        {
+            return Ok(Some(offset + i as u64));
+        }
+        if n1 != n2 {
+            return Ok(Some(offset + common as u64));
+        }
+        if n1 == 0 {
+            return Ok(None);
+        }
+        offset += n1 as u64;
+    }
+}
+
+/// Fill `buf` from `file`, returning fewer bytes only at end of file.
+fn read_chunk(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// SHA-256 of an in-memory buffer as lowercase hex.
+pub fn sha256_hex(data: &[u8]) -> String {
+    bytes_to_hex(&Sha256::digest(data))
 }
 
 /// Encode a byte slice to lowercase hex with a single pre-allocated String.
@@ -81,131 +664,851 @@ fn bytes_to_hex(bytes: &[u8]) -> String {
     s
 }
 
-pub fn collect_files(
-    dir: &Path,
-    depth: Option<usize>,
-    no_recursive: bool,
-    hidden: bool,
-    types: &Option<Vec<String>>,
-    ignore_patterns: &Option<Vec<String>>,
+/// BLAKE3 digest of each consecutive `block_size` chunk of a file.
+fn block_hashes(path: &Path, block_size: u64) -> io::Result<Vec<blake3::Hash>> {
+    let mut file = sandbox::open(path)?;
+    let mut hashes = Vec::new();
+    loop {
+        let mut hasher = blake3::Hasher::new();
+        let read = io::copy(&mut (&mut file).take(block_size), &mut hasher)?;
+        if read == 0 {
+            break;
+        }
+        hashes.push(hasher.finalize());
+        if read < block_size {
+            break;
+        }
+    }
+    Ok(hashes)
+}
+
+/// Compare two files chunk by chunk and report which byte ranges differ.
+/// Chunks present on only one side (the files differ in length) count as changed.
+pub fn diff_blocks(path1: &Path, path2: &Path, block_size: u64) -> io::Result<BlockDiff> {
+    let block_size = block_size.max(1);
+    let (len1, len2) = (fs::metadata(path1)?.len(), fs::metadata(path2)?.len());
+    let (h1, h2) = rayon::join(
+        || block_hashes(path1, block_size),
+        || block_hashes(path2, block_size),
+    );
+    let (h1, h2) = (h1?, h2?);
+
+    let longest = len1.max(len2);
+    let total_blocks = h1.len().max(h2.len()) as u64;
+    let mut changed_blocks = 0;
+    let mut changed_bytes = 0;
+    let mut ranges: Vec<ByteRange> = Vec::new();
+
+    for i in 0..total_blocks {
+        let idx = i as usize;
+        if h1.get(idx).is_some() && h1.get(idx) == h2.get(idx) {
+            continue;
+        }
+        let start = i * block_size;
+        let end = (start + block_size).min(longest);
+        changed_blocks += 1;
+        changed_bytes += end - start;
+        match ranges.last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => ranges.push(ByteRange { start, end }),
+        }
+    }
+
+    Ok(BlockDiff {
+        block_size,
+        total_blocks,
+        changed_blocks,
+        changed_bytes,
+        ranges,
+    })
+}
+
+impl FileEntry {
+    /// Whether the content is read from a local file. Snapshot, archive and
+    /// remote entries carry their digests instead, and `s3://` objects are
+    /// downloaded only when their content is compared.
+    pub fn on_disk(&self) -> bool {
+        self.hashes.is_none() && !is_s3(&self.path)
+    }
+
+    /// Fill in permission bits, owner and inode from `meta` (no-op off Unix).
+    fn with_ownership(mut self, meta: &fs::Metadata) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            self.permissions = Some(meta.mode() & 0o7777);
+            self.uid = Some(meta.uid());
+            self.gid = Some(meta.gid());
+            self.inode = Some(meta.ino());
+        }
+        #[cfg(not(unix))]
+        let _ = meta;
+        self
+    }
+}
+
+#[cfg(unix)]
+fn link_id(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (meta.nlink() > 1).then(|| (meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn link_id(_meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Stat a single path the way `collect_files` would see it.
+///
+/// Returns `None` when the path does not exist, is a directory, or is a
+/// symlink that `symlink_mode` leaves out.
+pub fn stat_entry(path: &Path, symlink_mode: SymlinkMode) -> io::Result<Option<FileEntry>> {
+    let lmeta = match fs::symlink_metadata(path) {
+        Ok(m) => m,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let is_symlink = lmeta.file_type().is_symlink();
+    let (meta, symlink_target) = match (is_symlink, symlink_mode) {
+        (false, _) => (lmeta, None),
+        (true, SymlinkMode::Ignore) => return Ok(None),
+        (true, SymlinkMode::Follow) => match fs::metadata(path) {
+            Ok(m) => (m, None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        },
+        (true, SymlinkMode::Compare) => {
+            let target = fs::read_link(path)?.to_string_lossy().to_string();
+            (lmeta, Some(target))
+        }
+    };
+    if meta.is_dir() {
+        return Ok(None);
+    }
+    Ok(Some(
+        FileEntry {
+            path: path.to_path_buf(),
+            size: meta.len(),
+            modified: meta.modified().ok(),
+            symlink_target,
+            link_id: link_id(&meta),
+            ..FileEntry::default()
+        }
+        .with_ownership(&meta),
+    ))
+}
+
+/// [`root_attributes`], with a failure to read them recorded in `errors`
+/// like any other unreadable path instead of turning the root check off.
+pub fn read_root_attributes(path: &Path, errors: &mut Vec<ErrorEntry>) -> Option<RootAttrs> {
+    match root_attributes(path) {
+        Ok(attrs) => Some(attrs),
+        Err(e) => {
+            errors.push(ErrorEntry {
+                kind: ErrorKind::of(&e),
+                ..ErrorEntry::new(
+                    path.to_path_buf(),
+                    format!("cannot read the root folder's metadata: {}", e),
+                )
+            });
+            None
+        }
+    }
+}
+
+/// Permissions, owner and extended attributes of a root directory.
+pub fn root_attributes(path: &Path) -> io::Result<RootAttrs> {
+    let meta = fs::metadata(path)?;
+    let entry = FileEntry::default().with_ownership(&meta);
+    Ok(RootAttrs {
+        permissions: entry.permissions,
+        uid: entry.uid,
+        gid: entry.gid,
+        xattrs: read_xattrs(path)?,
+    })
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn read_xattrs(path: &Path) -> io::Result<BTreeMap<String, String>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: the path is NUL-terminated and the buffer pointer/length pair
+    // comes straight from `xattr_call`.
+    let names = match xattr_call(|buf, len| unsafe { sys_listxattr(&c_path, buf, len) }) {
+        Err(e) if e.raw_os_error() == Some(libc::ENOTSUP) => return Ok(BTreeMap::new()),
+        other => other?,
+    };
+    let mut xattrs = BTreeMap::new();
+    for name in names.split(|b| *b == 0).filter(|n| !n.is_empty()) {
+        let c_name = CString::new(name)?;
+        // SAFETY: as above; the name is NUL-terminated.
+        let value = xattr_call(|buf, len| unsafe { sys_getxattr(&c_path, &c_name, buf, len) })?;
+        xattrs.insert(
+            String::from_utf8_lossy(name).into_owned(),
+            blake3::hash(&value).to_hex().to_string(),
+        );
+    }
+    Ok(xattrs)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn read_xattrs(_path: &Path) -> io::Result<BTreeMap<String, String>> {
+    Ok(BTreeMap::new())
+}
+
+/// Run a size-query-then-fill xattr call, retrying if the value grew in between.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn xattr_call(mut call: impl FnMut(*mut u8, usize) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let size = call(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; size as usize];
+        let n = call(buf.as_mut_ptr(), buf.len());
+        if n >= 0 {
+            buf.truncate(n as usize);
+            return Ok(buf);
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn sys_listxattr(path: &std::ffi::CStr, buf: *mut u8, len: usize) -> isize {
+    unsafe { libc::listxattr(path.as_ptr(), buf.cast(), len) }
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn sys_getxattr(
+    path: &std::ffi::CStr,
+    name: &std::ffi::CStr,
+    buf: *mut u8,
+    len: usize,
+) -> isize {
+    unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf.cast(), len) }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn sys_listxattr(path: &std::ffi::CStr, buf: *mut u8, len: usize) -> isize {
+    unsafe { libc::listxattr(path.as_ptr(), buf.cast(), len, 0) }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn sys_getxattr(
+    path: &std::ffi::CStr,
+    name: &std::ffi::CStr,
+    buf: *mut u8,
+    len: usize,
+) -> isize {
+    unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf.cast(), len, 0, 0) }
+}
+
+/// Ask the kernel to evict `path` from the page cache, so the next read
+/// comes from the disk. Only dirty-free pages are dropped.
+#[cfg(target_os = "linux")]
+pub fn drop_cached_pages(path: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let file = fs::File::open(path)?;
+    // SAFETY: the descriptor stays open for the duration of the call.
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn drop_cached_pages(_path: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Compile a list of glob patterns, if any were given.
+pub fn build_globset(patterns: &Option<Vec<String>>) -> Result<Option<GlobSet>> {
+    let Some(patterns) = patterns else {
+        return Ok(None);
+    };
+    let mut builder = GlobSetBuilder::new();
+    for p in patterns {
+        builder.add(Glob::new(p)?);
+    }
+    Ok(Some(builder.build()?))
+}
+
+/// Read an rsync-style exclude file and translate each rule into glob patterns
+/// for `--ignore`.
+///
+/// Blank lines and lines starting with `#` or `;` are skipped, and a leading
+/// `- ` is accepted. A pattern without a leading `/` matches at any depth, a
+/// leading `/` anchors it to the root, and a trailing `/` excludes only the
+/// contents of matching directories. Include rules (`+ `) are rejected.
+pub fn read_exclude_file(path: &Path) -> anyhow::Result<Vec<String>> {
+    use anyhow::Context;
+
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read exclude file {}", path.display()))?;
+    let mut globs = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with("+ ") {
+            anyhow::bail!(
+                "{}:{}: include rules are not supported",
+                path.display(),
+                n + 1
+            );
+        }
+        let pattern = line.strip_prefix("- ").unwrap_or(line);
+        let (pattern, dir_only) = match pattern.strip_suffix('/') {
+            Some(p) => (p, true),
+            None => (pattern, false),
+        };
+        let base = match pattern.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if pattern.starts_with("**/") => pattern.to_string(),
+            None => format!("**/{}", pattern),
+        };
+        if !dir_only {
+            globs.push(base.clone());
+        }
+        globs.push(format!("{}/**", base));
+    }
+    Ok(globs)
+}
+
+/// Directory-listing threads set by `--walk-threads`; 0 lets the walker choose.
+static WALK_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Limit how many directories the parallel walk lists at once, independently
+/// of the Rayon pool that hashes. Set once from `main`.
+pub fn set_walk_threads(threads: usize) {
+    WALK_THREADS.store(threads.max(1), Ordering::Relaxed);
+}
+
+/// Walker configuration and per-entry filters shared by the buffered
+/// `collect_files` and the sorted `stream_files`.
+#[derive(Clone)]
+struct WalkFilter {
+    root: PathBuf,
+    ignore_set: Option<GlobSet>,
+    /// The patterns of `ignore_set`, for `--explain-skips`.
+    ignore_patterns: Vec<String>,
+    type_filter: Option<HashSet<String>>,
     symlink_mode: SymlinkMode,
-) -> Result<(Vec<FileEntry>, Vec<ErrorEntry>)> {
-    let mut walk_builder = WalkBuilder::new(dir);
-    walk_builder.hidden(!hidden);
+    /// Only files owned by this uid are kept (`--owner-filter`).
+    owner: Option<u32>,
+    /// Stay on the filesystem of the root (`--one-file-system`).
+    one_file_system: bool,
+    /// Followed links a userspace `--sandbox` kept the walk out of.
+    refused: Arc<Mutex<Vec<ErrorEntry>>>,
+}
 
-    if no_recursive {
-        walk_builder.max_depth(Some(1));
-    } else if let Some(d) = depth {
-        walk_builder.max_depth(Some(d));
+impl WalkFilter {
+    fn new(
+        dir: &Path,
+        types: &Option<Vec<String>>,
+        ignore_patterns: &Option<Vec<String>>,
+        symlink_mode: SymlinkMode,
+        owner: Option<u32>,
+        one_file_system: bool,
+    ) -> Result<Self> {
+        Ok(Self {
+            root: dir.to_path_buf(),
+            ignore_set: build_globset(ignore_patterns)?,
+            ignore_patterns: ignore_patterns.clone().unwrap_or_default(),
+            type_filter: types.as_ref().map(|exts| {
+                exts.iter()
+                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                    .collect()
+            }),
+            symlink_mode,
+            owner,
+            one_file_system,
+            refused: Arc::default(),
+        })
     }
 
-    match symlink_mode {
-        SymlinkMode::Follow => {
-            walk_builder.follow_links(true);
+    fn builder(&self, depth: Option<usize>, no_recursive: bool, hidden: bool) -> WalkBuilder {
+        let mut walk_builder = WalkBuilder::new(&self.root);
+        walk_builder
+            .hidden(!hidden)
+            .same_file_system(self.one_file_system)
+            .threads(WALK_THREADS.load(Ordering::Relaxed));
+
+        if no_recursive {
+            walk_builder.max_depth(Some(1));
+        } else if let Some(d) = depth {
+            walk_builder.max_depth(Some(d));
+        }
+
+        match self.symlink_mode {
+            SymlinkMode::Follow => {
+                walk_builder.follow_links(true);
+            }
+            _ => {
+                walk_builder.follow_links(false);
+            }
+        }
+        // Without landlock, a followed link is the one way out of the root.
+        if self.symlink_mode == SymlinkMode::Follow && sandbox::confined() {
+            let refused = self.refused.clone();
+            walk_builder.filter_entry(move |entry| {
+                if !entry.path_is_symlink() {
+                    return true;
+                }
+                match sandbox::check(entry.path()) {
+                    Err(e) if sandbox::refused(&e) => {
+                        refused.lock().unwrap().push(ErrorEntry {
+                            kind: ErrorKind::of(&e),
+                            ..ErrorEntry::new(entry.path().to_path_buf(), e.to_string())
+                        });
+                        false
+                    }
+                    _ => true,
+                }
+            });
+        }
+        walk_builder
+    }
+
+    /// The entry for a walked path, or `None` when it is filtered out.
+    fn accept(&self, entry: &ignore::DirEntry) -> Option<FileEntry> {
+        self.check(entry).ok()
+    }
+
+    /// The entry for a walked path, or the filter that leaves it out.
+    fn check(&self, entry: &ignore::DirEntry) -> Result<FileEntry, Skip> {
+        // Patterns may match the full path or the path relative to the
+        // root, so root-anchored patterns from --exclude-from work too.
+        if let Some(ref set) = self.ignore_set {
+            let rel = entry.path().strip_prefix(&self.root).ok();
+            if set.is_match(entry.path()) || rel.is_some_and(|rel| set.is_match(rel)) {
+                let mut hits = set.matches(entry.path());
+                hits.extend(rel.map(|rel| set.matches(rel)).unwrap_or_default());
+                let first = hits.into_iter().min().unwrap_or(0);
+                return Err(Skip::Pattern(
+                    first + 1,
+                    self.ignore_patterns[first].clone(),
+                ));
+            }
+        }
+
+        let ft = entry.file_type().ok_or(Skip::Unreadable)?;
+        let is_symlink = ft.is_symlink();
+        let is_file = ft.is_file();
+
+        let should_include = match self.symlink_mode {
+            SymlinkMode::Ignore => is_file,
+            SymlinkMode::Follow => is_file,
+            SymlinkMode::Compare => is_file || is_symlink,
+        };
+
+        if !should_include {
+            return Err(if is_symlink {
+                Skip::Symlink
+            } else {
+                Skip::NotFile
+            });
         }
-        _ => {
-            walk_builder.follow_links(false);
+
+        if let Some(ref exts) = self.type_filter
+            && !entry
+                .path()
+                .extension()
+                .and_then(|s| s.to_str())
+                .is_some_and(|s| exts.contains(&s.to_lowercase()))
+        {
+            return Err(Skip::Type);
         }
+
+        let mut symlink_target = None;
+        if is_symlink
+            && matches!(self.symlink_mode, SymlinkMode::Compare)
+            && let Ok(target) = fs::read_link(entry.path())
+        {
+            symlink_target = Some(target.to_string_lossy().to_string());
+        }
+
+        let meta = entry.metadata().map_err(|_| Skip::Unreadable)?;
+        let file = FileEntry {
+            path: entry.path().to_path_buf(),
+            size: meta.len(),
+            modified: meta.modified().ok(),
+            symlink_target,
+            link_id: link_id(&meta),
+            ..FileEntry::default()
+        }
+        .with_ownership(&meta);
+        if self.owner.is_some_and(|uid| file.uid != Some(uid)) {
+            return Err(Skip::Owner(file.uid));
+        }
+        Ok(file)
     }
+}
 
-    let custom_ignore_set = if let Some(patterns) = ignore_patterns {
-        let mut builder = GlobSetBuilder::new();
-        for p in patterns {
-            builder.add(Glob::new(p)?);
+/// Why [`WalkFilter::check`] leaves an entry out.
+enum Skip {
+    /// Matched `--ignore` pattern number `.0` (from 1), `.1`.
+    Pattern(usize, String),
+    /// Directories, sockets, devices; directories are still walked into.
+    NotFile,
+    Symlink,
+    Type,
+    Owner(Option<u32>),
+    /// Its type or metadata could not be read.
+    Unreadable,
+}
+
+impl Skip {
+    fn reason(&self, owner: Option<u32>) -> String {
+        match self {
+            Skip::Pattern(n, pattern) => format!("--ignore pattern #{} `{}`", n, pattern),
+            Skip::NotFile => "not a regular file".to_string(),
+            Skip::Symlink => "symbolic link (--symlinks ignore)".to_string(),
+            Skip::Type => "extension not in --type".to_string(),
+            Skip::Owner(uid) => format!(
+                "owned by uid {}, not --owner-filter {}",
+                uid.map_or("?".to_string(), |u| u.to_string()),
+                owner.unwrap_or_default()
+            ),
+            Skip::Unreadable => "metadata unreadable".to_string(),
+        }
+    }
+}
+
+/// The ignore-file rule (`.ignore` or `.gitignore` in the walked tree) that
+/// excludes `path`, nearest directory first.
+fn ignore_file_rule(root: &Path, path: &Path, is_dir: bool) -> Option<String> {
+    for dir in path.ancestors().skip(1) {
+        for name in [".ignore", ".gitignore"] {
+            let file = dir.join(name);
+            if !file.is_file() {
+                continue;
+            }
+            let (rules, _) = ignore::gitignore::Gitignore::new(&file);
+            if let ignore::Match::Ignore(glob) = rules.matched_path_or_any_parents(path, is_dir) {
+                return Some(format!("`{}` in {}", glob.original(), file.display()));
+            }
         }
-        Some(builder.build()?)
+        if dir == root {
+            break;
+        }
+    }
+    None
+}
+
+/// `--explain-skips`: every path a walk with these options leaves out, with
+/// the first rule that excludes it. Directories the walk does not enter are
+/// listed once rather than with their contents. Walks the tree a second time
+/// without the walker's own filters, so it costs an extra listing.
+#[allow(clippy::too_many_arguments)]
+pub fn explain_skips(
+    dir: &Path,
+    depth: Option<usize>,
+    no_recursive: bool,
+    hidden: bool,
+    types: &Option<Vec<String>>,
+    ignore_patterns: &Option<Vec<String>>,
+    symlink_mode: SymlinkMode,
+    owner: Option<u32>,
+    one_file_system: bool,
+) -> Result<Vec<(PathBuf, String)>> {
+    let filter = WalkFilter::new(
+        dir,
+        types,
+        ignore_patterns,
+        symlink_mode,
+        owner,
+        one_file_system,
+    )?;
+    // Entries the walker itself yields; anything else it skipped on its own.
+    let walked: HashSet<PathBuf> = filter
+        .builder(depth, no_recursive, hidden)
+        .build()
+        .filter_map(|e| e.ok().map(ignore::DirEntry::into_path))
+        .collect();
+    let max_depth = if no_recursive { Some(1) } else { depth };
+    let depth_flag = if no_recursive {
+        "--no-recursive".to_string()
     } else {
-        None
+        format!("--depth {}", depth.unwrap_or_default())
     };
+    let root_dev = fs::metadata(dir).ok().map(|m| device(&m));
 
-    let type_filter: Option<HashSet<String>> = types.as_ref().map(|exts| {
-        exts.iter()
-            .map(|ext| ext.trim_start_matches('.').to_lowercase())
-            .collect()
+    let skips = Arc::new(Mutex::new(Vec::new()));
+    let mut raw = WalkBuilder::new(dir);
+    raw.standard_filters(false)
+        .follow_links(symlink_mode == SymlinkMode::Follow);
+    let found = skips.clone();
+    raw.filter_entry(move |entry| {
+        let path = entry.path();
+        let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+        let reason = if entry.depth() == 0 {
+            return true;
+        } else if symlink_mode == SymlinkMode::Follow
+            && entry.path_is_symlink()
+            && sandbox::check(path).is_err_and(|e| sandbox::refused(&e))
+        {
+            "link leading outside the folders being read (--sandbox)".to_string()
+        } else if one_file_system && is_dir && entry.metadata().ok().map(|m| device(&m)) != root_dev
+        {
+            "on another filesystem (--one-file-system)".to_string()
+        } else if walked.contains(path) {
+            // Filters drop directories from the results but still enter them,
+            // unless the depth limit stops there.
+            if is_dir && max_depth != Some(entry.depth()) {
+                return true;
+            }
+            if is_dir {
+                if fs::read_dir(path).is_ok_and(|mut d| d.next().is_none()) {
+                    return false;
+                }
+                format!("contents not walked ({})", depth_flag)
+            } else {
+                match filter.check(entry) {
+                    Ok(_) => return false,
+                    Err(skip) => skip.reason(owner),
+                }
+            }
+        } else if !hidden && entry.file_name().to_string_lossy().starts_with('.') {
+            "hidden (--hidden includes it)".to_string()
+        } else {
+            ignore_file_rule(&filter.root, path, is_dir)
+                .unwrap_or_else(|| "ignore file or git exclude rules".to_string())
+        };
+        let shown = if is_dir {
+            path.join("")
+        } else {
+            path.to_path_buf()
+        };
+        found.lock().unwrap().push((shown, reason));
+        false
     });
+    for _ in raw.build() {}
+
+    let mut skips = std::mem::take(&mut *skips.lock().unwrap());
+    skips.sort();
+    Ok(skips)
+}
+
+#[cfg(unix)]
+fn device(meta: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.dev()
+}
+
+#[cfg(not(unix))]
+fn device(_meta: &fs::Metadata) -> u64 {
+    0
+}
+
+fn walk_error(err: ignore::Error) -> ErrorEntry {
+    // The path goes into its own field so errors can be grouped by message.
+    fn split(err: &ignore::Error) -> (Option<&Path>, String) {
+        match err {
+            ignore::Error::WithDepth { err, .. } => split(err),
+            ignore::Error::WithPath { path, err } => (Some(path), err.to_string()),
+            _ => (None, err.to_string()),
+        }
+    }
+    let (path, message) = split(&err);
+    ErrorEntry {
+        kind: err.io_error().map_or(ErrorKind::Io, ErrorKind::of),
+        ..ErrorEntry::new(
+            path.map_or_else(|| PathBuf::from("?"), Path::to_path_buf),
+            message,
+        )
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn collect_files(
+    dir: &Path,
+    depth: Option<usize>,
+    no_recursive: bool,
+    hidden: bool,
+    types: &Option<Vec<String>>,
+    ignore_patterns: &Option<Vec<String>>,
+    symlink_mode: SymlinkMode,
+    owner: Option<u32>,
+    one_file_system: bool,
+) -> Result<(Vec<FileEntry>, Vec<ErrorEntry>)> {
+    let filter = WalkFilter::new(
+        dir,
+        types,
+        ignore_patterns,
+        symlink_mode,
+        owner,
+        one_file_system,
+    )?;
+    let walker = filter.builder(depth, no_recursive, hidden).build_parallel();
+    let refused = filter.refused.clone();
 
     let (tx, rx) = mpsc::channel();
     let (tx_err, rx_err) = mpsc::channel();
 
-    let walker = walk_builder.build_parallel();
-
     std::thread::spawn(move || {
         walker.run(|| {
             let tx = tx.clone();
             let tx_err = tx_err.clone();
-            let type_filter = type_filter.clone();
-            let custom_ignore_set = custom_ignore_set.clone();
+            let filter = filter.clone();
 
             Box::new(move |result| {
-                let entry = match result {
-                    Ok(e) => e,
+                match result {
+                    Ok(entry) => {
+                        if let Some(entry_data) = filter.accept(&entry) {
+                            let _ = tx.send(entry_data);
+                        }
+                    }
                     Err(err) => {
-                        let _ = tx_err.send(ErrorEntry {
-                            path: PathBuf::from("?"),
-                            error: err.to_string(),
-                        });
-                        return ignore::WalkState::Continue;
+                        let _ = tx_err.send(walk_error(err));
                     }
-                };
-
-                if let Some(ref set) = custom_ignore_set
-                    && set.is_match(entry.path())
-                {
-                    return ignore::WalkState::Continue;
                 }
+                ignore::WalkState::Continue
+            })
+        });
+    });
 
-                let ft = match entry.file_type() {
-                    Some(ft) => ft,
-                    None => return ignore::WalkState::Continue,
-                };
-
-                let is_symlink = ft.is_symlink();
-                let is_file = ft.is_file();
+    let final_files: Vec<FileEntry> = rx.into_iter().collect();
+    let mut final_errors: Vec<ErrorEntry> = rx_err.into_iter().collect();
+    final_errors.append(&mut refused.lock().unwrap());
+    Ok((final_files, final_errors))
+}
 
-                let should_include = match symlink_mode {
-                    SymlinkMode::Ignore => is_file,
-                    SymlinkMode::Follow => is_file,
-                    SymlinkMode::Compare => is_file || is_symlink,
-                };
+/// What the sorted walk of `stream_files` yields.
+pub enum Walked {
+    File(Box<FileEntry>),
+    Error(ErrorEntry),
+}
 
-                if !should_include {
-                    return ignore::WalkState::Continue;
-                }
+/// Walk `dir` like `collect_files`, but in path order and on a background
+/// thread that can run at most `bound` entries ahead of the consumer. Memory
+/// stays proportional to the widest directory rather than the whole tree.
+///
+/// Entries come in `Path` order (component by component), so the walks of two
+/// folders can be merged by comparing relative paths.
+#[allow(clippy::too_many_arguments)]
+pub fn stream_files(
+    dir: &Path,
+    depth: Option<usize>,
+    no_recursive: bool,
+    hidden: bool,
+    types: &Option<Vec<String>>,
+    ignore_patterns: &Option<Vec<String>>,
+    symlink_mode: SymlinkMode,
+    owner: Option<u32>,
+    one_file_system: bool,
+    bound: usize,
+) -> Result<mpsc::Receiver<Walked>> {
+    let filter = WalkFilter::new(
+        dir,
+        types,
+        ignore_patterns,
+        symlink_mode,
+        owner,
+        one_file_system,
+    )?;
+    let mut builder = filter.builder(depth, no_recursive, hidden);
+    builder.sort_by_file_name(|a, b| a.cmp(b));
+    let walker = builder.build();
 
-                if let Some(ref exts) = type_filter
-                    && !entry
-                        .path()
-                        .extension()
-                        .and_then(|s| s.to_str())
-                        .is_some_and(|s| exts.contains(&s.to_lowercase()))
-                {
-                    return ignore::WalkState::Continue;
-                }
+    let (tx, rx) = mpsc::sync_channel(bound);
+    std::thread::spawn(move || {
+        for result in walker {
+            let item = match result {
+                Ok(entry) => match filter.accept(&entry) {
+                    Some(file) => Walked::File(Box::new(file)),
+                    None => continue,
+                },
+                Err(err) => Walked::Error(walk_error(err)),
+            };
+            // The consumer hung up; nothing left to do.
+            if tx.send(item).is_err() {
+                return;
+            }
+        }
+        for err in filter.refused.lock().unwrap().drain(..) {
+            if tx.send(Walked::Error(err)).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(rx)
+}
 
-                let mut symlink_target = None;
-                if is_symlink
-                    && matches!(symlink_mode, SymlinkMode::Compare)
-                    && let Ok(target) = fs::read_link(entry.path())
-                {
-                    symlink_target = Some(target.to_string_lossy().to_string());
-                }
+/// Key collected entries by their path relative to `root`. Entries the walker
+/// yielded outside `root` (e.g. through a followed symlink that escapes it) are
+/// returned separately so callers can report them instead of panicking.
+pub fn key_by_relative_path(
+    files: Vec<FileEntry>,
+    root: &Path,
+) -> (HashMap<PathBuf, FileEntry>, Vec<PathBuf>) {
+    files
+        .into_par_iter()
+        .partition_map(|f| match f.path.strip_prefix(root) {
+            Ok(rel) => Either::Left((rel.to_path_buf(), f)),
+            Err(_) => Either::Right(f.path),
+        })
+}
 
-                if let Ok(meta) = entry.metadata() {
-                    let entry_data = FileEntry {
-                        path: entry.path().to_path_buf(),
-                        size: meta.len(),
-                        modified: meta.modified().ok(),
-                        symlink_target,
-                    };
-                    let _ = tx.send(entry_data);
-                }
+/// With `--symlinks compare`, the relative paths that are a symbolic link on
+/// one side and a directory holding listed entries on the other. Such a link
+/// is a single entry while the directory contributes one entry per file, so
+/// the caller folds both into one `TYPE_MISMATCH` result.
+pub fn link_dir_mismatches<'a>(
+    side1: impl Iterator<Item = (&'a Path, &'a FileEntry)> + Clone,
+    side2: impl Iterator<Item = (&'a Path, &'a FileEntry)> + Clone,
+) -> Vec<PathBuf> {
+    fn dirs<'a>(side: impl Iterator<Item = (&'a Path, &'a FileEntry)>) -> HashSet<&'a Path> {
+        side.flat_map(|(path, _)| path.ancestors().skip(1))
+            .collect()
+    }
+    fn links_into<'a>(
+        side: impl Iterator<Item = (&'a Path, &'a FileEntry)>,
+        dirs: &HashSet<&Path>,
+    ) -> impl Iterator<Item = PathBuf> {
+        side.filter(|(path, e)| e.symlink_target.is_some() && dirs.contains(path))
+            .map(|(path, _)| path.to_path_buf())
+    }
+    let (dirs1, dirs2) = (dirs(side1.clone()), dirs(side2.clone()));
+    let mut mismatches: Vec<PathBuf> = links_into(side1, &dirs2)
+        .chain(links_into(side2, &dirs1))
+        .collect();
+    mismatches.sort();
+    mismatches
+}
 
-                ignore::WalkState::Continue
-            })
-        });
-    });
+/// Fix #11: split a command string respecting single- and double-quoted segments
+/// so that paths containing spaces (e.g. "/my tools/code --diff") are handled
+/// correctly instead of being naively split on every whitespace character.
+pub fn split_command(cmd: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut in_single = false;
+    let mut in_double = false;
 
-    let final_files: Vec<FileEntry> = rx.into_iter().collect();
-    let final_errors: Vec<ErrorEntry> = rx_err.into_iter().collect();
-    Ok((final_files, final_errors))
+    for ch in cmd.chars() {
+        match ch {
+            '\'' if !in_double => in_single = !in_single,
+            '"' if !in_single => in_double = !in_double,
+            ' ' | '\t' if !in_single && !in_double => {
+                if !current.is_empty() {
+                    parts.push(current.clone());
+                    current.clear();
+                }
+            }
+            _ => current.push(ch),
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
 }
@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::process::{Command, Stdio};
+
+use crate::cleanup::Cleanup;
+
+/// A Volume Shadow Copy created for `--vss`, deleted again when dropped or
+/// when the run is interrupted.
+struct ShadowCopy {
+    /// Volume root as `C:\`.
+    volume: String,
+    /// `\\?\GLOBALROOT\Device\HarddiskVolumeShadowCopyN`.
+    device: String,
+    _cleanup: Cleanup,
+}
+
+fn delete_shadow_copy(id: &str, volume: &str) {
+    let script = format!(
+        "Get-CimInstance Win32_ShadowCopy -Filter \"ID='{}'\" | Remove-CimInstance",
+        id
+    );
+    if let Err(e) = powershell(&script) {
+        eprintln!(
+            "Warning: could not delete shadow copy {} of {}: {:#}",
+            id, volume, e
+        );
+    }
+}
+
+fn powershell(script: &str) -> Result<String> {
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", script])
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to run powershell")?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The drive letter of an absolute Windows path and the rest of it.
+fn split_volume(path: &Path) -> Option<(char, PathBuf)> {
+    let mut components = path.components();
+    let letter = match components.next()? {
+        Component::Prefix(p) => match p.kind() {
+            Prefix::Disk(d) | Prefix::VerbatimDisk(d) => char::from(d).to_ascii_uppercase(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((
+        letter,
+        components.filter(|c| *c != Component::RootDir).collect(),
+    ))
+}
+
+/// Shadow copies of the volumes holding the compared folders, for reading
+/// files other programs hold open or locked (`--vss`). Needs Windows and an
+/// elevated prompt; the copies are deleted when this is dropped.
+#[derive(Default)]
+pub struct Shadows {
+    copies: Vec<ShadowCopy>,
+}
+
+impl Shadows {
+    /// Create one shadow copy per local volume among `folders`.
+    pub fn create(folders: &[PathBuf]) -> Result<Self> {
+        if !cfg!(windows) {
+            anyhow::bail!("--vss reads Volume Shadow Copies and needs Windows");
+        }
+        let mut shadows = Shadows::default();
+        for folder in folders {
+            let path = std::fs::canonicalize(folder)
+                .with_context(|| format!("Cannot access {}", folder.display()))?;
+            let (letter, _) = split_volume(&path).with_context(|| {
+                format!("--vss: {} is not on a lettered volume", folder.display())
+            })?;
+            let volume = format!("{}:\\", letter);
+            if shadows.copies.iter().any(|c| c.volume == volume) {
+                continue;
+            }
+            shadows.copies.push(shadow_copy(volume)?);
+        }
+        Ok(shadows)
+    }
+
+    /// `path` inside the shadow copy of its volume; unchanged when no copy
+    /// covers it.
+    pub fn translate(&self, path: &Path) -> PathBuf {
+        let Some((letter, rest)) = std::fs::canonicalize(path)
+            .ok()
+            .and_then(|p| split_volume(&p))
+        else {
+            return path.to_path_buf();
+        };
+        let volume = format!("{}:\\", letter);
+        match self.copies.iter().find(|c| c.volume == volume) {
+            Some(copy) => PathBuf::from(format!("{}\\", copy.device)).join(rest),
+            None => path.to_path_buf(),
+        }
+    }
+}
+
+fn shadow_copy(volume: String) -> Result<ShadowCopy> {
+    let script = format!(
+        "$r = Invoke-CimMethod -ClassName Win32_ShadowCopy -MethodName Create \
+         -Arguments @{{Volume='{}'; Context='ClientAccessible'}}; \
+         if ($r.ReturnValue -ne 0) {{ throw \"Win32_ShadowCopy.Create returned $($r.ReturnValue)\" }}; \
+         $s = Get-CimInstance Win32_ShadowCopy -Filter \"ID='$($r.ShadowID)'\"; \
+         $s.ID; $s.DeviceObject",
+        volume
+    );
+    let output = powershell(&script)
+        .with_context(|| format!("Failed to create a shadow copy of {}", volume))?;
+    let mut lines = output.lines().map(str::trim).filter(|l| !l.is_empty());
+    let (Some(id), Some(device)) = (lines.next(), lines.next()) else {
+        anyhow::bail!("Unexpected output creating a shadow copy of {}", volume);
+    };
+    eprintln!("Reading {} from shadow copy {}", volume, device);
+    let (id, owner) = (id.to_string(), volume.clone());
+    Ok(ShadowCopy {
+        volume,
+        device: device.to_string(),
+        _cleanup: Cleanup::register(move || delete_shadow_copy(&id, &owner)),
+    })
+}
@@ -0,0 +1,236 @@
+use chrono::{Local, TimeZone};
+use flate2::read::DeflateDecoder;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Extensions of files compared as zip archives under `--zip-members`.
+pub const ZIP_EXTENSIONS: &[&str] = &["zip", "jar", "war", "ear"];
+
+/// End of central directory record, and the most its comment can add.
+const EOCD_LEN: u64 = 22;
+const MAX_COMMENT: u64 = 0xFFFF;
+
+const EOCD_SIG: u32 = 0x0605_4b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x0706_4b50;
+const ZIP64_EOCD_SIG: u32 = 0x0606_4b50;
+const CENTRAL_SIG: u32 = 0x0201_4b50;
+const LOCAL_SIG: u32 = 0x0403_4b50;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// What a member contributes to the comparison: its name, and the CRC-32 and
+/// size of its uncompressed content. Timestamps, extra fields, compression
+/// method and member order are left out.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ZipMember {
+    pub name: Vec<u8>,
+    pub crc32: u32,
+    pub size: u64,
+}
+
+pub fn is_zip_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| ZIP_EXTENSIONS.iter().any(|z| e.eq_ignore_ascii_case(z)))
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("not a zip archive: {}", what),
+    )
+}
+
+fn u16_at(buf: &[u8], at: usize) -> io::Result<u16> {
+    buf.get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid("truncated record"))
+}
+
+fn u32_at(buf: &[u8], at: usize) -> io::Result<u32> {
+    buf.get(at..at + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("truncated record"))
+}
+
+fn u64_at(buf: &[u8], at: usize) -> io::Result<u64> {
+    buf.get(at..at + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("truncated record"))
+}
+
+fn read_at<R: Read + Seek>(file: &mut R, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buf = Vec::new();
+    file.take(len).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Offset and size of the central directory, from the end of central
+/// directory record (or its Zip64 counterpart).
+fn central_directory<R: Read + Seek>(file: &mut R, file_len: u64) -> io::Result<(u64, u64)> {
+    let tail_len = file_len.min(EOCD_LEN + MAX_COMMENT);
+    let tail_start = file_len - tail_len;
+    let tail = read_at(file, tail_start, tail_len)?;
+    let eocd = (0..tail.len().saturating_sub(EOCD_LEN as usize - 1))
+        .rev()
+        .find(|&i| u32_at(&tail, i).is_ok_and(|sig| sig == EOCD_SIG))
+        .ok_or_else(|| invalid("no end of central directory"))?;
+
+    let size = u32_at(&tail, eocd + 12)?;
+    let offset = u32_at(&tail, eocd + 16)?;
+    if size != u32::MAX && offset != u32::MAX {
+        return Ok((u64::from(offset), u64::from(size)));
+    }
+
+    // Zip64: the locator sits right before the classic record.
+    let locator = eocd
+        .checked_sub(20)
+        .filter(|&at| u32_at(&tail, at).is_ok_and(|sig| sig == ZIP64_LOCATOR_SIG))
+        .ok_or_else(|| invalid("missing Zip64 locator"))?;
+    let record_at = u64_at(&tail, locator + 8)?;
+    let record = read_at(file, record_at, 56)?;
+    if u32_at(&record, 0)? != ZIP64_EOCD_SIG {
+        return Err(invalid("bad Zip64 end of central directory"));
+    }
+    Ok((u64_at(&record, 48)?, u64_at(&record, 40)?))
+}
+
+/// A central directory record: what `zip_members` reports, and where the
+/// member's data is and how it is stored.
+struct CentralEntry {
+    member: ZipMember,
+    method: u16,
+    compressed_size: u64,
+    local_offset: u64,
+    /// MS-DOS date and time, in local time.
+    dos_time: (u16, u16),
+}
+
+/// The file entries of the central directory, in stored order. Directory
+/// entries are skipped, since archivers differ in whether they write them.
+fn central_entries<R: Read + Seek>(file: &mut R) -> io::Result<Vec<CentralEntry>> {
+    let file_len = file.seek(SeekFrom::End(0))?;
+    let (offset, size) = central_directory(file, file_len)?;
+    if offset.saturating_add(size) > file_len {
+        return Err(invalid("central directory beyond end of file"));
+    }
+    let dir = read_at(file, offset, size)?;
+
+    let mut entries = Vec::new();
+    let mut at = 0;
+    while at < dir.len() {
+        if u32_at(&dir, at)? != CENTRAL_SIG {
+            return Err(invalid("bad central directory entry"));
+        }
+        let method = u16_at(&dir, at + 10)?;
+        let dos_time = (u16_at(&dir, at + 14)?, u16_at(&dir, at + 12)?);
+        let crc32 = u32_at(&dir, at + 16)?;
+        let mut compressed_size = u64::from(u32_at(&dir, at + 20)?);
+        let mut size = u64::from(u32_at(&dir, at + 24)?);
+        let name_len = usize::from(u16_at(&dir, at + 28)?);
+        let extra_len = usize::from(u16_at(&dir, at + 30)?);
+        let comment_len = usize::from(u16_at(&dir, at + 32)?);
+        let mut local_offset = u64::from(u32_at(&dir, at + 42)?);
+        let name_at = at + 46;
+        let extra_at = name_at + name_len;
+        let name = dir
+            .get(name_at..extra_at)
+            .ok_or_else(|| invalid("truncated name"))?
+            .to_vec();
+        let extra = dir
+            .get(extra_at..extra_at + extra_len)
+            .ok_or_else(|| invalid("truncated extra field"))?;
+
+        // A Zip64 extra field holds the real values of the saturated fields,
+        // in this order.
+        let mut field = 0;
+        while field + 4 <= extra.len() {
+            let id = u16_at(extra, field)?;
+            let len = usize::from(u16_at(extra, field + 2)?);
+            if id == 0x0001 {
+                let mut value = field + 4;
+                for v in [&mut size, &mut compressed_size, &mut local_offset] {
+                    if *v == u64::from(u32::MAX) {
+                        *v = u64_at(extra, value)?;
+                        value += 8;
+                    }
+                }
+                break;
+            }
+            field += 4 + len;
+        }
+
+        if !name.ends_with(b"/") {
+            entries.push(CentralEntry {
+                member: ZipMember { name, crc32, size },
+                method,
+                compressed_size,
+                local_offset,
+                dos_time,
+            });
+        }
+        at = extra_at + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// The file members of the zip archive at `path`, sorted by name.
+/// Only the central directory is read; CRCs are taken as recorded.
+pub fn zip_members(path: &Path) -> io::Result<Vec<ZipMember>> {
+    let mut members: Vec<ZipMember> = central_entries(&mut crate::sandbox::open(path)?)?
+        .into_iter()
+        .map(|e| e.member)
+        .collect();
+    members.sort();
+    Ok(members)
+}
+
+fn dos_to_system_time((date, time): (u16, u16)) -> Option<SystemTime> {
+    Local
+        .with_ymd_and_hms(
+            1980 + i32::from(date >> 9),
+            u32::from((date >> 5) & 0x0f),
+            u32::from(date & 0x1f),
+            u32::from(time >> 11),
+            u32::from((time >> 5) & 0x3f),
+            u32::from(time & 0x1f) * 2,
+        )
+        .single()
+        .map(SystemTime::from)
+}
+
+/// Call `visit` with each file member of a zip archive, its modification time
+/// and a reader of its uncompressed content, in stored order. Only stored and
+/// deflated members can be read; any other method is an error.
+pub fn for_each_member<R: Read + Seek>(
+    file: &mut R,
+    mut visit: impl FnMut(&ZipMember, Option<SystemTime>, &mut dyn Read) -> io::Result<()>,
+) -> io::Result<()> {
+    for entry in central_entries(file)? {
+        let local = read_at(file, entry.local_offset, 30)?;
+        if u32_at(&local, 0)? != LOCAL_SIG {
+            return Err(invalid("bad local file header"));
+        }
+        let data_at = entry.local_offset
+            + 30
+            + u64::from(u16_at(&local, 26)?)
+            + u64::from(u16_at(&local, 28)?);
+        file.seek(SeekFrom::Start(data_at))?;
+        let data = Read::by_ref(file).take(entry.compressed_size);
+        let modified = dos_to_system_time(entry.dos_time);
+        match entry.method {
+            STORED => visit(&entry.member, modified, &mut { data })?,
+            DEFLATED => visit(&entry.member, modified, &mut DeflateDecoder::new(data))?,
+            method => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("zip compression method {} is not supported", method),
+                ));
+            }
+        }
+    }
+    Ok(())
+}